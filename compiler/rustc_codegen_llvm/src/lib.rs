@@ -87,7 +87,16 @@ pub mod llvm {
 rustc_fluent_macro::fluent_messages! { "../messages.ftl" }
 
 #[derive(Clone)]
-pub struct LlvmCodegenBackend(());
+pub struct LlvmCodegenBackend(Option<CodegenHooks>);
+
+/// Codegen done by a backend that builds on this one: it generates the LLVM IR of some or all
+/// codegen units itself, and leaves optimizing and emitting them to this backend. Each hook
+/// returns `None` for the modules this backend should generate as usual.
+#[derive(Clone, Copy)]
+pub struct CodegenHooks {
+    pub compile_codegen_unit: fn(TyCtxt<'_>, Symbol) -> Option<(ModuleCodegen<ModuleLlvm>, u64)>,
+    pub codegen_allocator: fn(TyCtxt<'_>, &str, AllocatorKind, AllocatorKind) -> Option<ModuleLlvm>,
+}
 
 struct TimeTraceProfiler {
     enabled: bool,
@@ -118,6 +127,12 @@ fn codegen_allocator<'tcx>(
         kind: AllocatorKind,
         alloc_error_handler_kind: AllocatorKind,
     ) -> ModuleLlvm {
+        if let Some(hooks) = self.0
+            && let Some(module_llvm) =
+                (hooks.codegen_allocator)(tcx, module_name, kind, alloc_error_handler_kind)
+        {
+            return module_llvm;
+        }
         let mut module_llvm = ModuleLlvm::new_metadata(tcx, module_name);
         unsafe {
             allocator::codegen(tcx, &mut module_llvm, module_name, kind, alloc_error_handler_kind);
@@ -129,6 +144,11 @@ fn compile_codegen_unit(
         tcx: TyCtxt<'_>,
         cgu_name: Symbol,
     ) -> (ModuleCodegen<ModuleLlvm>, u64) {
+        if let Some(hooks) = self.0
+            && let Some(module) = (hooks.compile_codegen_unit)(tcx, cgu_name)
+        {
+            return module;
+        }
         base::compile_codegen_unit(tcx, cgu_name)
     }
     fn target_machine_factory(
@@ -257,7 +277,12 @@ unsafe impl Sync for LlvmCodegenBackend {}
 
 impl LlvmCodegenBackend {
     pub fn new() -> Box<dyn CodegenBackend> {
-        Box::new(LlvmCodegenBackend(()))
+        Box::new(LlvmCodegenBackend(None))
+    }
+
+    /// A backend that generates LLVM IR with `hooks` where they return a module.
+    pub fn with_hooks(hooks: CodegenHooks) -> Box<dyn CodegenBackend> {
+        Box::new(LlvmCodegenBackend(Some(hooks)))
     }
 }
 
@@ -360,7 +385,7 @@ fn codegen_crate<'tcx>(
         need_metadata_module: bool,
     ) -> Box<dyn Any> {
         Box::new(rustc_codegen_ssa::base::codegen_crate(
-            LlvmCodegenBackend(()),
+            self.clone(),
             tcx,
             crate::llvm_util::target_cpu(tcx.sess).to_string(),
             metadata,
@@ -418,7 +443,7 @@ unsafe impl Send for ModuleLlvm {}
 unsafe impl Sync for ModuleLlvm {}
 
 impl ModuleLlvm {
-    fn new(tcx: TyCtxt<'_>, mod_name: &str) -> Self {
+    pub fn new(tcx: TyCtxt<'_>, mod_name: &str) -> Self {
         unsafe {
             let llcx = llvm::LLVMRustContextCreate(tcx.sess.fewer_names());
             let llmod_raw = context::create_module(tcx, llcx, mod_name) as *const _;
@@ -463,7 +488,11 @@ fn parse(
         }
     }
 
-    fn llmod(&self) -> &llvm::Module {
+    pub fn llcx(&self) -> &llvm::Context {
+        self.llcx
+    }
+
+    pub fn llmod(&self) -> &llvm::Module {
         unsafe { &*self.llmod_raw }
     }
 }
@@ -828,6 +828,8 @@ pub struct AllocKindFlags : u64 {
     pub fn LLVMModuleCreateWithNameInContext(ModuleID: *const c_char, C: &Context) -> &Module;
     pub fn LLVMGetModuleContext(M: &Module) -> &Context;
     pub fn LLVMCloneModule(M: &Module) -> &Module;
+    /// Links `Src` into `Dest`, returning true on error. `Src` is destroyed either way.
+    pub fn LLVMLinkModules2<'a>(Dest: &'a Module, Src: &'a Module) -> Bool;
 
     /// Data layout. See Module::getDataLayout.
     pub fn LLVMGetDataLayoutStr(M: &Module) -> *const c_char;
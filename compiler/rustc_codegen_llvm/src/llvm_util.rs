@@ -5,6 +5,7 @@
 use std::{ptr, slice, str};
 
 use libc::c_int;
+use rustc_attr::InstructionSetAttr;
 use rustc_codegen_ssa::base::wants_wasm_eh;
 use rustc_codegen_ssa::codegen_attrs::check_tied_features;
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
@@ -12,6 +13,8 @@
 use rustc_data_structures::unord::UnordSet;
 use rustc_fs_util::path_to_c_string;
 use rustc_middle::bug;
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrs;
+use rustc_middle::ty::TyCtxt;
 use rustc_session::Session;
 use rustc_session::config::{PrintKind, PrintRequest};
 use rustc_span::symbol::Symbol;
@@ -279,6 +282,35 @@ pub(crate) fn to_llvm_features<'a>(sess: &Session, s: &'a str) -> Option<LLVMFea
     }
 }
 
+/// The `target-features` attribute of a function with `codegen_fn_attrs`: the global features of
+/// the session followed by the ones the function enables.
+pub fn function_target_features(tcx: TyCtxt<'_>, codegen_fn_attrs: &CodegenFnAttrs) -> String {
+    let function_features =
+        codegen_fn_attrs.target_features.iter().map(|f| f.name.as_str()).collect::<Vec<&str>>();
+
+    let function_features = function_features
+        .iter()
+        // Convert to LLVMFeatures and filter out unavailable ones
+        .flat_map(|feat| to_llvm_features(tcx.sess, feat))
+        // Convert LLVMFeatures & dependencies to +<feats>s
+        .flat_map(|feat| feat.into_iter().map(|f| format!("+{f}")))
+        .chain(codegen_fn_attrs.instruction_set.iter().map(|x| match x {
+            InstructionSetAttr::ArmA32 => "-thumb-mode".to_string(),
+            InstructionSetAttr::ArmT32 => "+thumb-mode".to_string(),
+        }))
+        // HACK: LLVM versions 19+ do not have the FPMR feature and treat it as always enabled
+        // It only exists as a feature in LLVM 18, cannot be passed down for any other version
+        .chain(match &*tcx.sess.target.arch {
+            "aarch64" if get_version().0 == 18 => vec!["+fpmr".to_string()],
+            _ => vec![],
+        })
+        .collect::<Vec<String>>();
+
+    let global_features = tcx.global_backend_features(()).iter().map(|s| s.as_str());
+    let function_features = function_features.iter().map(|s| s.as_str());
+    global_features.chain(function_features).intersperse(",").collect()
+}
+
 /// Used to generate cfg variables and apply features
 /// Must express features in the way Rust understands them
 pub fn target_features(sess: &Session, allow_unstable: bool) -> Vec<Symbol> {
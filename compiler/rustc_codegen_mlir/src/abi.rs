@@ -0,0 +1,786 @@
+//! Function signatures, the function prelude, calls and returns.
+
+use melior::Context;
+use melior::ir::operation::OperationLike;
+use melior::ir::r#type::{FunctionType, IntegerType};
+use melior::ir::{Attribute, BlockLike, Type, Value, ValueLike};
+use rustc_abi::{Abi, FieldIdx, Size};
+use rustc_codegen_ssa::base::is_call_from_compiler_builtins_to_upstream_monomorphization;
+use rustc_hir::LangItem;
+use rustc_middle::bug;
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
+use rustc_middle::mir::{self, BasicBlock, RETURN_PLACE, UnwindAction};
+use rustc_middle::ty::layout::{FnAbiOf, LayoutOf, TyAndLayout};
+use rustc_middle::ty::{self, Instance, InstanceKind, Ty, TyCtxt};
+use rustc_target::abi::call::{
+    ArgAbi, ArgAttribute, ArgAttributes, CastTarget, FnAbi, PassMode, Reg, RegKind,
+};
+use rustc_target::spec::abi::Abi as SpecAbi;
+use smallvec::{SmallVec, smallvec};
+
+use crate::builder::IntCC;
+use crate::common::FunctionCx;
+use crate::type_of::{pointer_ty, scalar_to_mlir_type};
+use crate::unwind::{Callee, codegen_call_with_unwind_action};
+use crate::value_and_place::{MPlace, MValue};
+
+fn reg_to_mlir_type<'ml>(context: &'ml Context, reg: Reg) -> Type<'ml> {
+    match (reg.kind, reg.size.bytes()) {
+        (RegKind::Integer, size) => IntegerType::new(context, (size * 8) as u32).into(),
+        (RegKind::Float, 2) => Type::float16(context),
+        (RegKind::Float, 4) => Type::float32(context),
+        (RegKind::Float, 8) => Type::float64(context),
+        (RegKind::Float, 16) => Type::parse(context, "f128").unwrap(),
+        (RegKind::Vector, size) => Type::vector(&[size], IntegerType::new(context, 8).into()),
+        _ => bug!("unsupported register {:?}", reg),
+    }
+}
+
+/// Splits a `CastTarget` into the individual registers it is passed in, together with the size
+/// of each register.
+fn cast_target_to_mlir_types<'ml>(
+    context: &'ml Context,
+    cast: &CastTarget,
+) -> SmallVec<[(Type<'ml>, Size); 2]> {
+    let (rest_count, rem_bytes) = if cast.rest.unit.size.bytes() == 0 {
+        (0, 0)
+    } else {
+        (
+            cast.rest.total.bytes() / cast.rest.unit.size.bytes(),
+            cast.rest.total.bytes() % cast.rest.unit.size.bytes(),
+        )
+    };
+
+    let mut regs = cast
+        .prefix
+        .iter()
+        .flatten()
+        .copied()
+        .chain((0..rest_count).map(|_| cast.rest.unit))
+        .map(|reg| (reg_to_mlir_type(context, reg), reg.size))
+        .collect::<SmallVec<_>>();
+
+    if rem_bytes != 0 {
+        // Only integers can be really split further.
+        assert_eq!(cast.rest.unit.kind, RegKind::Integer);
+        let size = Size::from_bytes(rem_bytes);
+        regs.push((IntegerType::new(context, size.bits() as u32).into(), size));
+    }
+
+    regs
+}
+
+fn cast_target_size(cast: &CastTarget) -> u64 {
+    cast.prefix.iter().flatten().map(|reg| reg.size.bytes()).sum::<u64>() + cast.rest.total.bytes()
+}
+
+pub(crate) trait ArgAbiExt<'tcx> {
+    fn mlir_params<'ml>(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        context: &'ml Context,
+    ) -> SmallVec<[Type<'ml>; 2]>;
+    fn mlir_return<'ml>(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        context: &'ml Context,
+    ) -> (Option<Type<'ml>>, SmallVec<[Type<'ml>; 2]>);
+}
+
+impl<'tcx> ArgAbiExt<'tcx> for ArgAbi<'tcx, Ty<'tcx>> {
+    fn mlir_params<'ml>(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        context: &'ml Context,
+    ) -> SmallVec<[Type<'ml>; 2]> {
+        match self.mode {
+            PassMode::Ignore => smallvec![],
+            PassMode::Direct(_) => match self.layout.abi {
+                Abi::Scalar(scalar) => smallvec![scalar_to_mlir_type(tcx, context, scalar)],
+                _ => bug!("unsupported direct argument abi {:?}", self.layout.abi),
+            },
+            PassMode::Pair(_, _) => match self.layout.abi {
+                Abi::ScalarPair(a, b) => smallvec![
+                    scalar_to_mlir_type(tcx, context, a),
+                    scalar_to_mlir_type(tcx, context, b),
+                ],
+                _ => bug!("{:?}", self.layout.abi),
+            },
+            PassMode::Cast { ref cast, pad_i32 } => {
+                assert!(!pad_i32, "padding support not yet implemented");
+                cast_target_to_mlir_types(context, cast).into_iter().map(|(ty, _)| ty).collect()
+            }
+            PassMode::Indirect { meta_attrs: None, .. } => smallvec![pointer_ty(context)],
+            PassMode::Indirect { meta_attrs: Some(_), on_stack, .. } => {
+                assert!(!on_stack);
+                smallvec![pointer_ty(context), pointer_ty(context)]
+            }
+        }
+    }
+
+    fn mlir_return<'ml>(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        context: &'ml Context,
+    ) -> (Option<Type<'ml>>, SmallVec<[Type<'ml>; 2]>) {
+        match self.mode {
+            PassMode::Indirect { meta_attrs: None, .. } => (Some(pointer_ty(context)), smallvec![]),
+            PassMode::Indirect { meta_attrs: Some(_), .. } => bug!("unsized return value"),
+            _ => (None, self.mlir_params(tcx, context)),
+        }
+    }
+}
+
+/// Returns the MLIR `FunctionType` used for a function with the given ABI.
+pub(crate) fn mlir_fn_type<'ml, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    context: &'ml Context,
+    fn_abi: &FnAbi<'tcx, Ty<'tcx>>,
+) -> Type<'ml> {
+    let (ret_ptr, results) = fn_abi.ret.mlir_return(tcx, context);
+    let inputs = ret_ptr
+        .into_iter()
+        .chain(fn_abi.args.iter().flat_map(|arg_abi| arg_abi.mlir_params(tcx, context)))
+        .collect::<Vec<_>>();
+    FunctionType::new(context, &inputs, &results).into()
+}
+
+pub(crate) fn fn_abi_param_types<'ml, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    context: &'ml Context,
+    fn_abi: &FnAbi<'tcx, Ty<'tcx>>,
+) -> Vec<Type<'ml>> {
+    let (ret_ptr, _) = fn_abi.ret.mlir_return(tcx, context);
+    ret_ptr
+        .into_iter()
+        .chain(fn_abi.args.iter().flat_map(|arg_abi| arg_abi.mlir_params(tcx, context)))
+        .collect()
+}
+
+/// The LLVM dialect argument attributes corresponding to `attrs`. Only the ones later passes can
+/// make use of are kept, in particular `noalias`, which the vectorizer relies on.
+fn mlir_arg_attrs(attrs: &ArgAttributes) -> String {
+    let mut names = vec![];
+    if attrs.regular.contains(ArgAttribute::NoAlias) {
+        names.push("llvm.noalias");
+    }
+    if attrs.regular.contains(ArgAttribute::ReadOnly) {
+        names.push("llvm.readonly");
+    }
+    format!("{{{}}}", names.join(", "))
+}
+
+/// Returns the `arg_attrs` of the `func.func` for a function with the given ABI, matching
+/// [`fn_abi_param_types`], or `None` if none of its parameters has any attributes.
+pub(crate) fn fn_abi_arg_attrs<'ml, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    context: &'ml Context,
+    fn_abi: &FnAbi<'tcx, Ty<'tcx>>,
+) -> Option<Attribute<'ml>> {
+    let mut dicts = vec![];
+    if let PassMode::Indirect { ref attrs, meta_attrs: None, .. } = fn_abi.ret.mode {
+        dicts.push(mlir_arg_attrs(attrs));
+    }
+    for arg_abi in fn_abi.args.iter() {
+        match arg_abi.mode {
+            PassMode::Direct(ref attrs) => dicts.push(mlir_arg_attrs(attrs)),
+            PassMode::Pair(ref a, ref b) => {
+                dicts.push(mlir_arg_attrs(a));
+                dicts.push(mlir_arg_attrs(b));
+            }
+            PassMode::Indirect { ref attrs, ref meta_attrs, .. } => {
+                dicts.push(mlir_arg_attrs(attrs));
+                dicts.extend(meta_attrs.as_ref().map(mlir_arg_attrs));
+            }
+            PassMode::Ignore | PassMode::Cast { .. } => {
+                dicts.extend(arg_abi.mlir_params(tcx, context).iter().map(|_| String::from("{}")))
+            }
+        }
+    }
+    if dicts.iter().all(|dict| dict == "{}") {
+        return None;
+    }
+    Some(
+        Attribute::parse(context, &format!("[{}]", dicts.join(", ")))
+            .unwrap_or_else(|| bug!("invalid argument attributes {dicts:?}")),
+    )
+}
+
+/// Reads the block arguments for a single `ArgAbi` and returns the value they represent.
+fn mvalue_for_param<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    arg_abi: &ArgAbi<'tcx, Ty<'tcx>>,
+    block_params: &mut impl Iterator<Item = Value<'ml, 'a>>,
+) -> Option<MValue<'ml, 'a, 'tcx>> {
+    let mut next = || block_params.next().unwrap();
+    match arg_abi.mode {
+        PassMode::Ignore => None,
+        PassMode::Direct(_) => Some(MValue::by_val(next(), arg_abi.layout)),
+        PassMode::Pair(_, _) => {
+            let (a, b) = (next(), next());
+            Some(MValue::by_val_pair(a, b, arg_abi.layout))
+        }
+        PassMode::Cast { ref cast, .. } => {
+            let regs = cast_target_to_mlir_types(fx.context, cast);
+            let size = std::cmp::max(cast_target_size(cast), arg_abi.layout.size.bytes());
+            let ptr = fx.create_stack_slot(size, arg_abi.layout.align.abi.bytes());
+            let mut offset = 0;
+            for (_, reg_size) in regs {
+                let reg_ptr = fx.bx.ptr_offset_imm(ptr, offset as i64);
+                fx.bx.store(next(), reg_ptr, 1);
+                offset += reg_size.bytes();
+            }
+            Some(MValue::by_ref(ptr, arg_abi.layout))
+        }
+        PassMode::Indirect { meta_attrs: None, .. } => Some(MValue::by_ref(next(), arg_abi.layout)),
+        PassMode::Indirect { meta_attrs: Some(_), .. } => {
+            let (ptr, meta) = (next(), next());
+            Some(MValue::by_ref_unsized(ptr, meta, arg_abi.layout))
+        }
+    }
+}
+
+/// Sets up the return place and all locals, and stores the incoming arguments into them.
+pub(crate) fn codegen_fn_prelude<'a, 'ml, 'tcx>(fx: &mut FunctionCx<'a, 'ml, 'tcx>) {
+    let entry_block = fx.entry_block;
+    fx.switch_to_block(entry_block);
+
+    // The body of a naked function is a single `naked_asm!`, which finds the arguments where the
+    // calling convention put them. Nothing may be spilled to the stack before it.
+    if fx.tcx.codegen_fn_attrs(fx.instance.def_id()).flags.contains(CodegenFnAttrFlags::NAKED) {
+        let start_block = fx.get_block(mir::START_BLOCK);
+        fx.bx.br(&start_block, &[]);
+        return;
+    }
+
+    let mut block_params = (0..entry_block.argument_count())
+        .map(|i| -> Value<'ml, 'a> { entry_block.argument(i).unwrap().into() });
+
+    let ret_layout = fx.fn_abi.ret.layout;
+    let ret_place = match fx.fn_abi.ret.mode {
+        PassMode::Indirect { meta_attrs: None, .. } => {
+            MPlace::for_ptr(block_params.next().unwrap(), ret_layout)
+        }
+        PassMode::Cast { ref cast, .. } => {
+            let size = std::cmp::max(cast_target_size(cast), ret_layout.size.bytes());
+            let ptr = fx.create_stack_slot(size, ret_layout.align.abi.bytes());
+            MPlace::for_ptr(ptr, ret_layout)
+        }
+        _ => MPlace::new_stack_slot(fx, ret_layout),
+    };
+    assert_eq!(fx.local_map.push(ret_place), RETURN_PLACE);
+
+    if fx.fn_abi.c_variadic {
+        fx.tcx.dcx().span_fatal(
+            fx.mir.span,
+            "defining variadic functions is not yet supported by the MLIR backend",
+        );
+    }
+
+    enum ArgKind<'ml, 'a, 'tcx> {
+        Normal(Option<MValue<'ml, 'a, 'tcx>>),
+        Spread(Vec<Option<MValue<'ml, 'a, 'tcx>>>),
+    }
+
+    let fn_abi = fx.fn_abi;
+    let mut arg_abis_iter = fn_abi.args.iter();
+
+    let mut func_params = Vec::with_capacity(fx.mir.arg_count);
+    for local in fx.mir.args_iter() {
+        let arg_ty = fx.monomorphize(fx.mir.local_decls[local].ty);
+        if Some(local) == fx.mir.spread_arg {
+            // This argument (e.g. the last argument in the "rust-call" ABI) is a tuple that was
+            // spread at the ABI level and now we have to reconstruct it.
+            let tupled_arg_tys = match arg_ty.kind() {
+                ty::Tuple(tys) => tys,
+                _ => bug!("spread argument isn't a tuple?! but {:?}", arg_ty),
+            };
+            let params = tupled_arg_tys
+                .iter()
+                .map(|_| mvalue_for_param(fx, arg_abis_iter.next().unwrap(), &mut block_params))
+                .collect();
+            func_params.push((local, ArgKind::Spread(params), arg_ty));
+        } else {
+            let param = mvalue_for_param(fx, arg_abis_iter.next().unwrap(), &mut block_params);
+            func_params.push((local, ArgKind::Normal(param), arg_ty));
+        }
+    }
+
+    if fx.instance.def.requires_caller_location(fx.tcx) {
+        // Store caller location for `#[track_caller]`.
+        let arg_abi = arg_abis_iter.next().unwrap();
+        fx.caller_location = Some(mvalue_for_param(fx, arg_abi, &mut block_params).unwrap());
+    }
+
+    assert!(arg_abis_iter.next().is_none(), "ArgAbi left behind");
+    assert!(block_params.next().is_none(), "block param left behind");
+
+    for (local, arg_kind, ty) in func_params {
+        // Ownership of the value at the backing storage for an argument is passed to the callee
+        // per the ABI, so it is fine to use the backing storage of an indirect argument directly.
+        if let ArgKind::Normal(Some(val)) = arg_kind {
+            if let Some((addr, meta)) = val.try_to_ptr() {
+                let place = match meta {
+                    Some(meta) => MPlace::for_ptr_with_extra(addr, meta, val.layout()),
+                    None => MPlace::for_ptr(addr, val.layout()),
+                };
+                assert_eq!(fx.local_map.push(place), local);
+                continue;
+            }
+        }
+
+        let layout = fx.layout_of(ty);
+        let place = make_local_place(fx, local, layout);
+        assert_eq!(fx.local_map.push(place), local);
+
+        match arg_kind {
+            ArgKind::Normal(param) => {
+                if let Some(param) = param {
+                    place.write_mvalue(fx, param);
+                }
+            }
+            ArgKind::Spread(params) => {
+                for (i, param) in params.into_iter().enumerate() {
+                    if let Some(param) = param {
+                        place.place_field(fx, FieldIdx::new(i)).write_mvalue(fx, param);
+                    }
+                }
+            }
+        }
+    }
+
+    for local in fx.mir.vars_and_temps_iter() {
+        let ty = fx.monomorphize(fx.mir.local_decls[local].ty);
+        let layout = fx.layout_of(ty);
+        let place = make_local_place(fx, local, layout);
+        assert_eq!(fx.local_map.push(place), local);
+    }
+
+    crate::debuginfo::declare_vars(fx);
+
+    let start_block = fx.get_block(mir::START_BLOCK);
+    fx.bx.br(&start_block, &[]);
+}
+
+fn make_local_place<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    local: mir::Local,
+    layout: TyAndLayout<'tcx>,
+) -> MPlace<'ml, 'a, 'tcx> {
+    if layout.is_unsized() {
+        fx.tcx.dcx().span_fatal(
+            fx.mir.local_decls[local].source_info.span,
+            "unsized locals are not yet supported by the MLIR backend",
+        );
+    }
+    MPlace::new_stack_slot(fx, layout)
+}
+
+pub(crate) fn codegen_return(fx: &mut FunctionCx<'_, '_, '_>) {
+    let ret_place = fx.get_local_place(RETURN_PLACE);
+    let ret_abi = &fx.fn_abi.ret;
+    match ret_abi.mode {
+        PassMode::Ignore | PassMode::Indirect { .. } => fx.bx.ret(&[]),
+        PassMode::Direct(_) => {
+            let val = ret_place.to_mvalue(fx).load_scalar(fx);
+            fx.bx.ret(&[val]);
+        }
+        PassMode::Pair(_, _) => {
+            let (a, b) = ret_place.to_mvalue(fx).load_scalar_pair(fx);
+            fx.bx.ret(&[a, b]);
+        }
+        PassMode::Cast { ref cast, .. } => {
+            let vals = to_casted_value(fx, ret_place.to_ptr(), cast);
+            fx.bx.ret(&vals);
+        }
+    }
+}
+
+fn to_casted_value<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    ptr: Value<'ml, 'a>,
+    cast: &CastTarget,
+) -> SmallVec<[Value<'ml, 'a>; 2]> {
+    let mut offset = 0;
+    cast_target_to_mlir_types(fx.context, cast)
+        .into_iter()
+        .map(|(ty, size)| {
+            let reg_ptr = fx.bx.ptr_offset_imm(ptr, offset as i64);
+            offset += size.bytes();
+            fx.bx.load(ty, reg_ptr, 1)
+        })
+        .collect()
+}
+
+/// Get the set of values to be passed as function arguments.
+fn adjust_arg_for_abi<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    arg: MValue<'ml, 'a, 'tcx>,
+    arg_abi: &ArgAbi<'tcx, Ty<'tcx>>,
+    is_owned: bool,
+) -> SmallVec<[Value<'ml, 'a>; 2]> {
+    match arg_abi.mode {
+        PassMode::Ignore => smallvec![],
+        PassMode::Direct(_) => smallvec![arg.load_scalar(fx)],
+        PassMode::Pair(_, _) => {
+            let (a, b) = arg.load_scalar_pair(fx);
+            smallvec![a, b]
+        }
+        PassMode::Cast { ref cast, .. } => {
+            let size = std::cmp::max(cast_target_size(cast), arg.layout().size.bytes());
+            let ptr = fx.create_stack_slot(size, arg.layout().align.abi.bytes());
+            MPlace::for_ptr(ptr, arg.layout()).write_mvalue(fx, arg);
+            to_casted_value(fx, ptr, cast)
+        }
+        PassMode::Indirect { meta_attrs: None, .. } => {
+            if is_owned {
+                smallvec![arg.force_stack(fx)]
+            } else {
+                // Ownership of the value at the backing storage for an argument is passed to the
+                // callee per the ABI, so we must make a copy of the argument unless the argument
+                // local is moved.
+                let place = MPlace::new_stack_slot(fx, arg.layout());
+                place.write_mvalue(fx, arg);
+                smallvec![place.to_ptr()]
+            }
+        }
+        PassMode::Indirect { meta_attrs: Some(_), .. } => {
+            let (ptr, meta) = arg.try_to_ptr().unwrap();
+            smallvec![ptr, meta.unwrap()]
+        }
+    }
+}
+
+pub(crate) struct CallArgument<'ml, 'a, 'tcx> {
+    pub(crate) value: MValue<'ml, 'a, 'tcx>,
+    /// Whether the argument was passed by move, in which case an indirectly passed argument
+    /// doesn't need to be copied first.
+    pub(crate) is_owned: bool,
+    /// The constant the argument was created from, if any. Some intrinsics need the value of an
+    /// argument at compile time.
+    pub(crate) constant: Option<mir::ConstOperand<'tcx>>,
+}
+
+pub(crate) fn codegen_terminator_call<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    source_info: mir::SourceInfo,
+    func: MValue<'ml, 'a, 'tcx>,
+    args: Vec<CallArgument<'ml, 'a, 'tcx>>,
+    ret_place: MPlace<'ml, 'a, 'tcx>,
+    target: Option<BasicBlock>,
+    unwind: UnwindAction,
+) {
+    codegen_call(fx, source_info, func, args, ret_place, target, unwind, false);
+}
+
+/// Codegens a `become` of `func`, which returns the result of the call from the current function.
+pub(crate) fn codegen_tail_call<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    source_info: mir::SourceInfo,
+    func: MValue<'ml, 'a, 'tcx>,
+    args: Vec<CallArgument<'ml, 'a, 'tcx>>,
+) {
+    // All locals have already been dropped, so the callee unwinds straight to our caller.
+    let ret_place = fx.get_local_place(RETURN_PLACE);
+    codegen_call(fx, source_info, func, args, ret_place, None, UnwindAction::Continue, true);
+}
+
+/// Whether a tail call to a function with the ABI `fn_abi` can be emitted as a `musttail` call.
+/// LLVM only allows this if the callee has the same signature as the caller, and it is only sound
+/// if no argument points into the stack frame the callee reuses. Multiple results are returned as
+/// a struct by `convert-func-to-llvm`, which puts instructions between the call and the return.
+fn can_musttail<'tcx>(fx: &FunctionCx<'_, '_, 'tcx>, fn_abi: &FnAbi<'tcx, Ty<'tcx>>) -> bool {
+    let (_, result_tys) = fn_abi.ret.mlir_return(fx.tcx, fx.context);
+    fn_abi.conv == fx.fn_abi.conv
+        && !fn_abi.c_variadic
+        && result_tys.len() <= 1
+        && !fn_abi.args.iter().any(|arg| matches!(arg.mode, PassMode::Indirect { .. }))
+        && mlir_fn_type(fx.tcx, fx.context, fn_abi) == mlir_fn_type(fx.tcx, fx.context, fx.fn_abi)
+}
+
+fn codegen_call<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    source_info: mir::SourceInfo,
+    func: MValue<'ml, 'a, 'tcx>,
+    mut args: Vec<CallArgument<'ml, 'a, 'tcx>>,
+    ret_place: MPlace<'ml, 'a, 'tcx>,
+    target: Option<BasicBlock>,
+    unwind: UnwindAction,
+    tail: bool,
+) {
+    let fn_sig = func.layout().ty.fn_sig(fx.tcx);
+
+    // Handle special calls like intrinsics and empty drop glue.
+    let instance = if let ty::FnDef(def_id, fn_args) = *func.layout().ty.kind() {
+        let instance = ty::Instance::expect_resolve(
+            fx.tcx,
+            ty::ParamEnv::reveal_all(),
+            def_id,
+            fn_args,
+            source_info.span,
+        )
+        .polymorphize(fx.tcx);
+
+        if is_call_from_compiler_builtins_to_upstream_monomorphization(fx.tcx, instance) {
+            fx.bx.trap();
+            return;
+        }
+
+        match instance.def {
+            InstanceKind::Intrinsic(_) if tail => {
+                fx.tcx.dcx().span_fatal(
+                    source_info.span,
+                    "tail calls of intrinsics are not yet supported by the MLIR backend",
+                );
+            }
+            InstanceKind::Intrinsic(_) => {
+                match crate::intrinsics::codegen_intrinsic_call(
+                    fx,
+                    instance,
+                    &args,
+                    ret_place,
+                    target,
+                    unwind,
+                    source_info,
+                ) {
+                    Ok(()) => return,
+                    Err(instance) => Some(instance),
+                }
+            }
+            InstanceKind::DropGlue(_, None) | InstanceKind::AsyncDropGlueCtorShim(_, None) => {
+                // empty drop glue - a nop.
+                if tail {
+                    codegen_return(fx);
+                    return;
+                }
+                let dest = target.expect("Non terminating drop_in_place_real???");
+                let ret_block = fx.get_block(dest);
+                fx.bx.br(&ret_block, &[]);
+                return;
+            }
+            _ => Some(instance),
+        }
+    } else {
+        None
+    };
+
+    let extra_args = &args[fn_sig.inputs().skip_binder().len()..];
+    let extra_args =
+        fx.tcx.mk_type_list_from_iter(extra_args.iter().map(|arg| arg.value.layout().ty));
+    let fn_abi = if let Some(instance) = instance {
+        fx.fn_abi_of_instance(instance, extra_args)
+    } else {
+        fx.fn_abi_of_fn_ptr(fn_sig, extra_args)
+    };
+
+    if fn_abi.c_variadic {
+        fx.tcx.dcx().span_fatal(
+            source_info.span,
+            "variadic calls are not yet supported by the MLIR backend",
+        );
+    }
+
+    // Unpack arguments tuple for closures
+    if fn_sig.abi() == SpecAbi::RustCall {
+        let pack_arg = match args.len() {
+            1 | 2 => args.pop().unwrap(),
+            _ => bug!("rust-call abi requires one or two arguments"),
+        };
+
+        let tupled_arguments = match pack_arg.value.layout().ty.kind() {
+            ty::Tuple(tupled_arguments) => tupled_arguments,
+            _ => bug!("argument to function with \"rust-call\" ABI is not a tuple"),
+        };
+
+        for i in 0..tupled_arguments.len() {
+            args.push(CallArgument {
+                value: pack_arg.value.value_field(fx, FieldIdx::new(i)),
+                is_owned: pack_arg.is_owned,
+                constant: None,
+            });
+        }
+    }
+
+    // Pass the caller location for `#[track_caller]`.
+    if instance.is_some_and(|inst| inst.def.requires_caller_location(fx.tcx)) {
+        let caller_location = fx.get_caller_location(source_info);
+        args.push(CallArgument { value: caller_location, is_owned: false, constant: None });
+    }
+
+    assert_eq!(fn_abi.args.len(), args.len());
+
+    // For virtual calls the receiver is replaced by its data pointer, and the callee is loaded
+    // from the vtable.
+    let virtual_call = match instance {
+        Some(Instance { def: InstanceKind::Virtual(_, idx), .. }) => {
+            Some(crate::vtable::get_ptr_and_method_ref(fx, args[0].value, idx))
+        }
+        _ => None,
+    };
+
+    let (ret_ptr_ty, result_tys) = fn_abi.ret.mlir_return(fx.tcx, fx.context);
+    let mut call_args: Vec<Value<'ml, 'a>> = Vec::new();
+    if ret_ptr_ty.is_some() {
+        call_args.push(ret_place.to_ptr());
+    }
+    for (i, (arg, arg_abi)) in args.into_iter().zip(fn_abi.args.iter()).enumerate() {
+        match virtual_call {
+            Some((ptr, _)) if i == 0 => call_args.push(ptr),
+            _ => call_args.extend(adjust_arg_for_abi(fx, arg.value, arg_abi, arg.is_owned)),
+        }
+    }
+
+    let callee = match (virtual_call, instance) {
+        (Some((_, method)), _) => Callee::Indirect(method),
+        (None, Some(instance)) => {
+            let symbol_name = fx.tcx.symbol_name(instance).name;
+            let fn_ty = mlir_fn_type(fx.tcx, fx.context, fn_abi);
+            fx.cx.reference_fn(symbol_name, fn_ty);
+            Callee::Direct { symbol: symbol_name, fn_ty }
+        }
+        (None, None) => Callee::Indirect(func.load_scalar(fx)),
+    };
+
+    if tail && can_musttail(fx, fn_abi) {
+        let func_ptr = match callee {
+            Callee::Direct { symbol, fn_ty } => fx.bx.func_addr(symbol, fn_ty),
+            Callee::Indirect(func_ptr) => func_ptr,
+        };
+        let call = fx.bx.musttail_call(func_ptr, &call_args, &result_tys);
+        let results = (0..call.result_count())
+            .map(|i| call.result(i).unwrap().into())
+            .collect::<Vec<Value<'ml, 'a>>>();
+        fx.bx.ret(&results);
+        return;
+    }
+
+    let results = codegen_call_with_unwind_action(fx, callee, &call_args, &result_tys, unwind);
+    match fn_abi.ret.mode {
+        PassMode::Ignore | PassMode::Indirect { .. } => {}
+        PassMode::Direct(_) => {
+            ret_place.write_mvalue(fx, MValue::by_val(results[0], fn_abi.ret.layout));
+        }
+        PassMode::Pair(_, _) => {
+            ret_place
+                .write_mvalue(fx, MValue::by_val_pair(results[0], results[1], fn_abi.ret.layout));
+        }
+        PassMode::Cast { ref cast, .. } => {
+            let size = std::cmp::max(cast_target_size(cast), fn_abi.ret.layout.size.bytes());
+            let ptr = fx.create_stack_slot(size, fn_abi.ret.layout.align.abi.bytes());
+            let mut offset = 0;
+            for ((_, reg_size), val) in
+                cast_target_to_mlir_types(fx.context, cast).into_iter().zip(results)
+            {
+                let reg_ptr = fx.bx.ptr_offset_imm(ptr, offset as i64);
+                fx.bx.store(val, reg_ptr, 1);
+                offset += reg_size.bytes();
+            }
+            ret_place.write_mvalue(fx, MValue::by_ref(ptr, fn_abi.ret.layout));
+        }
+    }
+
+    if tail {
+        codegen_return(fx);
+    } else if let Some(dest) = target {
+        let ret_block = fx.get_block(dest);
+        fx.bx.br(&ret_block, &[]);
+    } else {
+        fx.bx.unreachable();
+    }
+}
+
+pub(crate) fn codegen_drop<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    source_info: mir::SourceInfo,
+    drop_place: MPlace<'ml, 'a, 'tcx>,
+    target: BasicBlock,
+    unwind: UnwindAction,
+) {
+    let ty = drop_place.layout().ty;
+    let drop_instance = Instance::resolve_drop_in_place(fx.tcx, ty).polymorphize(fx.tcx);
+
+    if let InstanceKind::DropGlue(_, None) | InstanceKind::AsyncDropGlueCtorShim(_, None) =
+        drop_instance.def
+    {
+        // we don't actually need to drop anything
+    } else {
+        match ty.kind() {
+            ty::Dynamic(_, _, ty::Dyn) => {
+                // The place is a `(data, vtable)` pair; the drop glue of the concrete type is
+                // found in the vtable.
+                let (ptr, vtable) = drop_place.to_ptr_unsized();
+                let drop_fn = crate::vtable::drop_fn_of_obj(fx, vtable);
+
+                // The vtable has a null drop function if the type doesn't need dropping.
+                let usize_ty = fx.usize_type();
+                let drop_fn_addr = fx.bx.cast("llvm.ptrtoint", drop_fn, usize_ty);
+                let is_null = fx.bx.icmp_imm(IntCC::Equal, drop_fn_addr, 0);
+                let target_block = fx.get_block(target);
+                let call_block = fx.create_block();
+                fx.bx.cond_br(is_null, &target_block, &call_block);
+                fx.switch_to_block(call_block);
+
+                // `drop_in_place::<T>` takes the data pointer as a thin `*mut T`.
+                codegen_call_with_unwind_action(fx, Callee::Indirect(drop_fn), &[ptr], &[], unwind);
+            }
+            ty::Dynamic(_, _, ty::DynStar) => {
+                fx.tcx.dcx().span_fatal(
+                    source_info.span,
+                    "dropping dyn* is not yet supported by the MLIR backend",
+                );
+            }
+            _ => {
+                assert!(!matches!(drop_instance.def, InstanceKind::Virtual(_, _)));
+
+                let fn_abi = fx.fn_abi_of_instance(drop_instance, ty::List::empty());
+                let arg_value = drop_place.place_ref(
+                    fx,
+                    fx.layout_of(Ty::new_mut_ref(fx.tcx, fx.tcx.lifetimes.re_erased, ty)),
+                );
+                let arg = adjust_arg_for_abi(fx, arg_value, &fn_abi.args[0], true);
+
+                let symbol_name = fx.tcx.symbol_name(drop_instance).name;
+                let fn_ty = mlir_fn_type(fx.tcx, fx.context, fn_abi);
+                fx.cx.reference_fn(symbol_name, fn_ty);
+                let callee = Callee::Direct { symbol: symbol_name, fn_ty };
+                codegen_call_with_unwind_action(fx, callee, &arg, &[], unwind);
+            }
+        }
+    }
+
+    let target_block = fx.get_block(target);
+    fx.bx.br(&target_block, &[]);
+}
+
+/// Codegens a call to the panic lang item `lang_item` with `args`, followed by the caller location
+/// if the lang item is `#[track_caller]`. The call never returns.
+pub(crate) fn codegen_panic_lang_item<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    source_info: mir::SourceInfo,
+    lang_item: LangItem,
+    args: &[MValue<'ml, 'a, 'tcx>],
+    unwind: UnwindAction,
+) {
+    let def_id = fx.tcx.require_lang_item(lang_item, Some(source_info.span));
+    let instance = Instance::mono(fx.tcx, def_id).polymorphize(fx.tcx);
+    let fn_abi = fx.fn_abi_of_instance(instance, ty::List::empty());
+
+    let mut args = args.to_vec();
+    if instance.def.requires_caller_location(fx.tcx) {
+        args.push(fx.get_caller_location(source_info));
+    }
+    assert_eq!(fn_abi.args.len(), args.len());
+
+    let mut call_args: Vec<Value<'ml, 'a>> = Vec::new();
+    for (arg, arg_abi) in args.into_iter().zip(fn_abi.args.iter()) {
+        call_args.extend(adjust_arg_for_abi(fx, arg, arg_abi, false));
+    }
+
+    let symbol_name = fx.tcx.symbol_name(instance).name;
+    let fn_ty = mlir_fn_type(fx.tcx, fx.context, fn_abi);
+    fx.cx.reference_fn(symbol_name, fn_ty);
+    let (_, result_tys) = fn_abi.ret.mlir_return(fx.tcx, fx.context);
+    let callee = Callee::Direct { symbol: symbol_name, fn_ty };
+    codegen_call_with_unwind_action(fx, callee, &call_args, &result_tys, unwind);
+    fx.bx.unreachable();
+}
@@ -0,0 +1,214 @@
+//! The allocator shim, which forwards `__rust_alloc` and friends to the `#[global_allocator]` or
+//! to the default allocator of std, and `__rust_alloc_error_handler` to the alloc error handler.
+//!
+//! See `rustc_codegen_llvm/src/allocator.rs`.
+
+use melior::Context;
+use melior::ir::attribute::{IntegerAttribute, StringAttribute, TypeAttribute};
+use melior::ir::r#type::{FunctionType, IntegerType};
+use melior::ir::{Attribute, Block, BlockLike, Identifier, Location, Module, Region, Type, Value};
+use rustc_ast::expand::allocator::{
+    ALLOCATOR_METHODS, AllocatorKind, AllocatorTy, NO_ALLOC_SHIM_IS_UNSTABLE,
+    alloc_error_handler_name, default_fn_name, global_fn_name,
+};
+use rustc_codegen_llvm::ModuleLlvm;
+use rustc_codegen_ssa::{ModuleCodegen, ModuleKind};
+use rustc_middle::mir::mono::{Linkage, Visibility};
+use rustc_middle::ty::TyCtxt;
+use rustc_session::config::OomStrategy;
+
+use crate::ModuleMlir;
+use crate::builder::Builder;
+use crate::type_of::pointer_ty;
+
+/// Codegens the allocator shim into a module of its own and translates it to LLVM IR. In
+/// mixed-backend builds, the allocator shim is left to `rustc_codegen_llvm`.
+pub(crate) fn codegen_allocator(
+    tcx: TyCtxt<'_>,
+    module_name: &str,
+    kind: AllocatorKind,
+    alloc_error_handler_kind: AllocatorKind,
+) -> Option<ModuleLlvm> {
+    if crate::mixed::is_mixed(tcx.sess) {
+        return None;
+    }
+
+    let module_mlir = ModuleMlir::new(module_name);
+    codegen(tcx, module_mlir.context(), module_mlir.module(), kind, alloc_error_handler_kind);
+
+    crate::dump::dump_after(
+        tcx.sess,
+        tcx.output_filenames(()),
+        module_name,
+        "lower",
+        module_mlir.module(),
+    );
+
+    let module = ModuleCodegen {
+        name: module_name.to_owned(),
+        module_llvm: module_mlir,
+        kind: ModuleKind::Allocator,
+    };
+    Some(crate::write::lower_to_llvm(tcx, module).module_llvm)
+}
+
+fn codegen<'ml>(
+    tcx: TyCtxt<'_>,
+    context: &'ml Context,
+    module: &Module<'ml>,
+    kind: AllocatorKind,
+    alloc_error_handler_kind: AllocatorKind,
+) {
+    let usize = IntegerType::new(context, tcx.data_layout.pointer_size.bits() as u32).into();
+    let ptr = pointer_ty(context);
+
+    if kind == AllocatorKind::Default {
+        for method in ALLOCATOR_METHODS {
+            let mut args = Vec::with_capacity(method.inputs.len());
+            for input in method.inputs.iter() {
+                match input.ty {
+                    AllocatorTy::Layout => {
+                        args.push(usize); // size
+                        args.push(usize); // align
+                    }
+                    AllocatorTy::Ptr => args.push(ptr),
+                    AllocatorTy::Usize => args.push(usize),
+
+                    AllocatorTy::ResultPtr | AllocatorTy::Unit => panic!("invalid allocator arg"),
+                }
+            }
+            let output = match method.output {
+                AllocatorTy::ResultPtr => Some(ptr),
+                AllocatorTy::Unit => None,
+
+                AllocatorTy::Layout | AllocatorTy::Usize | AllocatorTy::Ptr => {
+                    panic!("invalid allocator output")
+                }
+            };
+
+            let from_name = global_fn_name(method.name);
+            let to_name = default_fn_name(method.name);
+
+            create_wrapper_function(
+                tcx, context, module, &from_name, &to_name, &args, output, false,
+            );
+        }
+    }
+
+    // rust alloc error handler
+    create_wrapper_function(
+        tcx,
+        context,
+        module,
+        "__rust_alloc_error_handler",
+        alloc_error_handler_name(alloc_error_handler_kind),
+        &[usize, usize], // size, align
+        None,
+        true,
+    );
+
+    // __rust_alloc_error_handler_should_panic
+    let val = tcx.sess.opts.unstable_opts.oom.should_panic();
+    define_u8_global(tcx, context, module, OomStrategy::SYMBOL, val as u8);
+
+    define_u8_global(tcx, context, module, NO_ALLOC_SHIM_IS_UNSTABLE, 0);
+}
+
+/// Defines `from_name` as a function forwarding its arguments to `to_name`.
+fn create_wrapper_function<'ml>(
+    tcx: TyCtxt<'_>,
+    context: &'ml Context,
+    module: &Module<'ml>,
+    from_name: &str,
+    to_name: &str,
+    args: &[Type<'ml>],
+    output: Option<Type<'ml>>,
+    no_return: bool,
+) {
+    let location = Location::unknown(context);
+    let fn_ty = FunctionType::new(context, args, output.as_slice());
+    let ident = |name| Identifier::new(context, name);
+
+    let mut attributes = crate::linkage::symbol_attrs(
+        tcx,
+        context,
+        "llvm.linkage",
+        Linkage::External,
+        Visibility::from(tcx.sess.default_visibility()),
+        false,
+    );
+    let mut callee_attributes = vec![
+        (ident("sym_visibility"), StringAttribute::new(context, "private").into()),
+        // The callee is defined in std or by `#[global_allocator]`, but always in the same linkage
+        // unit as the shim.
+        (ident("visibility_"), Attribute::parse(context, "1 : i64").unwrap()),
+    ];
+    if no_return {
+        let passthrough = Attribute::parse(context, "[\"noreturn\"]").unwrap();
+        attributes.push((ident("passthrough"), passthrough));
+        callee_attributes.push((ident("passthrough"), passthrough));
+    }
+
+    let body = Region::new();
+    let block =
+        body.append_block(Block::new(&args.iter().map(|&ty| (ty, location)).collect::<Vec<_>>()));
+    let bx = Builder::new(context, block, location);
+    let params: Vec<Value<'_, '_>> =
+        (0..args.len()).map(|i| block.argument(i).unwrap().into()).collect();
+    let call = bx.call(to_name, &params, output.as_slice());
+    if output.is_some() {
+        bx.ret(&[call.result(0).unwrap().into()]);
+    } else {
+        bx.ret(&[]);
+    }
+
+    module.body().append_operation(melior::dialect::func::func(
+        context,
+        StringAttribute::new(context, from_name),
+        TypeAttribute::new(fn_ty.into()),
+        body,
+        &attributes,
+        location,
+    ));
+    module.body().append_operation(melior::dialect::func::func(
+        context,
+        StringAttribute::new(context, to_name),
+        TypeAttribute::new(fn_ty.into()),
+        Region::new(),
+        &callee_attributes,
+        location,
+    ));
+}
+
+/// Defines the `u8` global `symbol` with initial value `val`.
+fn define_u8_global<'ml>(
+    tcx: TyCtxt<'_>,
+    context: &'ml Context,
+    module: &Module<'ml>,
+    symbol: &str,
+    val: u8,
+) {
+    let bx = Builder::new(context, module.body(), Location::unknown(context));
+    let i8 = bx.int_type(8);
+    let mut attributes = vec![
+        (bx.ident("sym_name"), StringAttribute::new(context, symbol).into()),
+        (bx.ident("global_type"), TypeAttribute::new(i8).into()),
+        (bx.ident("value"), IntegerAttribute::new(i8, i64::from(val)).into()),
+        (bx.ident("alignment"), bx.i64_attr(1)),
+    ];
+    attributes.extend(crate::linkage::symbol_attrs(
+        tcx,
+        context,
+        "linkage",
+        Linkage::External,
+        Visibility::from(tcx.sess.default_visibility()),
+        false,
+    ));
+    bx.append(
+        bx.op("llvm.mlir.global")
+            .add_attributes(&attributes)
+            .add_regions([Region::new()])
+            .build()
+            .unwrap(),
+    );
+}
@@ -0,0 +1,720 @@
+//! Codegen of `global_asm!` and `asm!`.
+//!
+//! See `rustc_codegen_llvm/src/asm.rs` for reference. Inline assembly is lowered to
+//! `llvm.inline_asm`, which takes the same template and constraint strings as LLVM's `InlineAsm`,
+//! and module level assembly is collected in [`CodegenCx::global_asm`] and appended to the LLVM
+//! module once it has been translated.
+
+use std::fmt::Write;
+
+use melior::ir::attribute::StringAttribute;
+use melior::ir::{Attribute, Type, Value};
+use rustc_abi::{Abi, Float, Integer, Primitive};
+use rustc_ast::{InlineAsmOptions, InlineAsmTemplatePiece};
+use rustc_codegen_ssa::common::asm_const_to_str;
+use rustc_hir as hir;
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::BasicBlock;
+use rustc_middle::mir::interpret::ErrorHandled;
+use rustc_middle::ty::layout::{LayoutOf, TyAndLayout};
+use rustc_middle::ty::{self, Instance};
+use rustc_middle::{bug, span_bug};
+use rustc_span::Span;
+use rustc_target::asm::*;
+
+use crate::common::FunctionCx;
+use crate::context::CodegenCx;
+use crate::type_of::{mlir_type, scalar_to_mlir_type};
+use crate::value_and_place::{MPlace, MValue};
+
+/// An operand of an `asm!`, with its values and places already lowered.
+pub(crate) enum AsmOperand<'ml, 'a, 'tcx> {
+    In {
+        reg: InlineAsmRegOrRegClass,
+        value: MValue<'ml, 'a, 'tcx>,
+    },
+    Out {
+        reg: InlineAsmRegOrRegClass,
+        late: bool,
+        place: Option<MPlace<'ml, 'a, 'tcx>>,
+    },
+    InOut {
+        reg: InlineAsmRegOrRegClass,
+        late: bool,
+        in_value: MValue<'ml, 'a, 'tcx>,
+        out_place: Option<MPlace<'ml, 'a, 'tcx>>,
+    },
+    Const {
+        string: String,
+    },
+    SymFn {
+        instance: Instance<'tcx>,
+    },
+    SymStatic {
+        def_id: DefId,
+    },
+    Label {
+        target: BasicBlock,
+    },
+}
+
+/// An operand of a `global_asm!`.
+enum GlobalAsmOperand<'tcx> {
+    Const { string: String },
+    SymFn { instance: Instance<'tcx> },
+    SymStatic { def_id: DefId },
+}
+
+fn check_asm_arch(tcx: ty::TyCtxt<'_>, span: Span) -> InlineAsmArch {
+    let asm_arch = tcx.sess.asm_arch.unwrap();
+    match asm_arch {
+        InlineAsmArch::X86
+        | InlineAsmArch::X86_64
+        | InlineAsmArch::AArch64
+        | InlineAsmArch::RiscV32
+        | InlineAsmArch::RiscV64 => asm_arch,
+        _ => tcx.dcx().span_fatal(
+            span,
+            format!("inline assembly for {asm_arch:?} is not yet supported by the MLIR backend"),
+        ),
+    }
+}
+
+/// Appends the `global_asm!` `item_id` to the module level assembly of `cx`.
+pub(crate) fn codegen_global_asm(cx: &CodegenCx<'_, '_>, item_id: hir::ItemId) {
+    let tcx = cx.tcx;
+    let item = tcx.hir().item(item_id);
+    let hir::ItemKind::GlobalAsm(asm) = item.kind else {
+        span_bug!(item.span, "Mismatch between hir::Item type and MonoItem type")
+    };
+    let asm_arch = check_asm_arch(tcx, item.span);
+
+    let operands = asm
+        .operands
+        .iter()
+        .map(|(op, op_sp)| match *op {
+            hir::InlineAsmOperand::Const { ref anon_const } => {
+                match tcx.const_eval_poly(anon_const.def_id.to_def_id()) {
+                    Ok(const_value) => {
+                        let ty = tcx.typeck_body(anon_const.body).node_type(anon_const.hir_id);
+                        let string = asm_const_to_str(tcx, *op_sp, const_value, cx.layout_of(ty));
+                        GlobalAsmOperand::Const { string }
+                    }
+                    // An error has already been reported and compilation is guaranteed to fail,
+                    // so any string will do.
+                    Err(ErrorHandled::Reported { .. }) => {
+                        GlobalAsmOperand::Const { string: String::new() }
+                    }
+                    Err(ErrorHandled::TooGeneric(_)) => {
+                        span_bug!(*op_sp, "asm const cannot be resolved; too generic")
+                    }
+                }
+            }
+            hir::InlineAsmOperand::SymFn { ref anon_const } => {
+                let ty = tcx.typeck_body(anon_const.body).node_type(anon_const.hir_id);
+                let instance = match ty.kind() {
+                    &ty::FnDef(def_id, args) => Instance::new(def_id, args),
+                    _ => span_bug!(*op_sp, "asm sym is not a function"),
+                };
+                GlobalAsmOperand::SymFn { instance }
+            }
+            hir::InlineAsmOperand::SymStatic { path: _, def_id } => {
+                GlobalAsmOperand::SymStatic { def_id }
+            }
+            hir::InlineAsmOperand::In { .. }
+            | hir::InlineAsmOperand::Out { .. }
+            | hir::InlineAsmOperand::InOut { .. }
+            | hir::InlineAsmOperand::SplitInOut { .. }
+            | hir::InlineAsmOperand::Label { .. } => {
+                span_bug!(*op_sp, "invalid operand type for global_asm!")
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Default to Intel syntax on x86
+    let intel_syntax = matches!(asm_arch, InlineAsmArch::X86 | InlineAsmArch::X86_64)
+        && !asm.options.contains(InlineAsmOptions::ATT_SYNTAX);
+
+    let mut global_asm = cx.global_asm.borrow_mut();
+    if intel_syntax {
+        global_asm.push_str(".intel_syntax\n");
+    }
+    for piece in asm.template {
+        match *piece {
+            InlineAsmTemplatePiece::String(ref s) => global_asm.push_str(s),
+            InlineAsmTemplatePiece::Placeholder { operand_idx, modifier: _, span: _ } => {
+                match operands[operand_idx] {
+                    // Note that we don't need to escape $ here unlike normal inline assembly.
+                    GlobalAsmOperand::Const { ref string } => global_asm.push_str(string),
+                    GlobalAsmOperand::SymFn { instance } => {
+                        let instance = instance.polymorphize(tcx);
+                        global_asm.push_str(&asm_symbol_name(cx, tcx.symbol_name(instance).name));
+                    }
+                    GlobalAsmOperand::SymStatic { def_id } => {
+                        let symbol = tcx.symbol_name(Instance::mono(tcx, def_id)).name;
+                        global_asm.push_str(&asm_symbol_name(cx, symbol));
+                    }
+                }
+            }
+        }
+    }
+    if intel_syntax {
+        global_asm.push_str("\n.att_syntax\n");
+    }
+    global_asm.push('\n');
+}
+
+/// The name `symbol` has at the assembly level, which on Apple targets includes the global
+/// prefix.
+fn asm_symbol_name(cx: &CodegenCx<'_, '_>, symbol: &str) -> String {
+    if cx.tcx.sess.target.is_like_osx { format!("_{symbol}") } else { symbol.to_owned() }
+}
+
+/// Lowers an `asm!` to an `llvm.inline_asm`, writes its outputs and then branches to `dest`, or
+/// to an `llvm.unreachable` if the assembly doesn't return.
+pub(crate) fn codegen_inline_asm<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    template: &[InlineAsmTemplatePiece],
+    operands: &[AsmOperand<'ml, 'a, 'tcx>],
+    options: InlineAsmOptions,
+    line_spans: &[Span],
+    dest: Option<BasicBlock>,
+) {
+    let span = line_spans.first().copied().unwrap_or(fx.mir.span);
+    let asm_arch = check_asm_arch(fx.tcx, span);
+
+    if operands.iter().any(|op| matches!(op, AsmOperand::Label { .. })) {
+        fx.tcx.dcx().span_fatal(span, "asm goto is not yet supported by the MLIR backend");
+    }
+    // `llvm.inline_asm` can't be invoked, so there is no unwind edge to lower this to.
+    if options.contains(InlineAsmOptions::MAY_UNWIND) {
+        fx.tcx.dcx().span_fatal(
+            span,
+            "inline assembly with `options(may_unwind)` is not yet supported by the MLIR backend",
+        );
+    }
+    for op in operands {
+        match *op {
+            AsmOperand::In { reg, value } => {
+                check_operand(fx, span, reg.reg_class(), value.layout())
+            }
+            AsmOperand::Out { reg, place: Some(place), .. } => {
+                check_operand(fx, span, reg.reg_class(), place.layout())
+            }
+            AsmOperand::InOut { reg, in_value, out_place, .. } => {
+                check_operand(fx, span, reg.reg_class(), in_value.layout());
+                if let Some(place) = out_place {
+                    check_operand(fx, span, reg.reg_class(), place.layout());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Collect the types of output operands
+    let mut constraints = vec![];
+    let mut clobbers = vec![];
+    let mut output_types = vec![];
+    let mut op_idx = vec![None; operands.len()];
+    let mut clobbered_x87 = false;
+    for (idx, op) in operands.iter().enumerate() {
+        let (reg, late, layout) = match *op {
+            AsmOperand::Out { reg, late, place } => (reg, late, place.map(|place| place.layout())),
+            // LLVM requires tied operands to have the same type, so if the output is discarded we
+            // just use the type of the input.
+            AsmOperand::InOut { reg, late, in_value, out_place } => {
+                (reg, late, Some(out_place.map_or(in_value.layout(), |place| place.layout())))
+            }
+            _ => continue,
+        };
+        let ty = if let Some(layout) = layout {
+            fixup_output_type(fx, reg.reg_class(), layout)
+        } else if matches!(
+            reg.reg_class(),
+            InlineAsmRegClass::X86(X86InlineAsmRegClass::mmx_reg | X86InlineAsmRegClass::x87_reg)
+        ) {
+            // LLVM handles the FP stack as a whole, so clobbering one register clobbers all of
+            // them.
+            if !clobbered_x87 {
+                clobbered_x87 = true;
+                clobbers.push("~{st}".to_string());
+                for i in 1..=7 {
+                    clobbers.push(format!("~{{st({})}}", i));
+                }
+            }
+            continue;
+        } else if !is_target_supported(fx, asm_arch, reg.reg_class())
+            || reg.reg_class().is_clobber_only(asm_arch)
+        {
+            // Discarded outputs turn into clobbers if the register class can't be allocated, as
+            // LLVM would otherwise try to allocate a register for the dummy output.
+            assert!(matches!(reg, InlineAsmRegOrRegClass::Reg(_)));
+            clobbers.push(format!("~{}", reg_to_llvm(reg, None)));
+            continue;
+        } else {
+            // The output is discarded, the type only reserves the register.
+            dummy_output_type(fx, reg.reg_class())
+        };
+        output_types.push(ty);
+        op_idx[idx] = Some(constraints.len());
+        let prefix = if late { "=" } else { "=&" };
+        constraints.push(format!("{}{}", prefix, reg_to_llvm(reg, layout)));
+    }
+
+    // Collect input operands
+    let mut inputs = vec![];
+    for (idx, op) in operands.iter().enumerate() {
+        match *op {
+            AsmOperand::In { reg, value } => {
+                let layout = value.layout();
+                inputs.push(fixup_input(fx, value, reg.reg_class()));
+                op_idx[idx] = Some(constraints.len());
+                constraints.push(reg_to_llvm(reg, Some(layout)));
+            }
+            AsmOperand::InOut { reg, late, in_value, out_place: _ } => {
+                inputs.push(fixup_input(fx, in_value, reg.reg_class()));
+                // For fixed registers, duplicating the constraint is preferred over a tied
+                // operand, matching the behavior of Clang.
+                if late && matches!(reg, InlineAsmRegOrRegClass::Reg(_)) {
+                    constraints.push(reg_to_llvm(reg, Some(in_value.layout())));
+                } else {
+                    constraints.push(format!("{}", op_idx[idx].unwrap()));
+                }
+            }
+            AsmOperand::SymFn { instance } => {
+                inputs.push(crate::base::codegen_fn_addr(fx, instance));
+                op_idx[idx] = Some(constraints.len());
+                constraints.push("s".to_string());
+            }
+            AsmOperand::SymStatic { def_id } => {
+                let symbol = crate::constant::static_symbol(fx.cx, def_id);
+                inputs.push(fx.bx.global_addr(&symbol));
+                op_idx[idx] = Some(constraints.len());
+                constraints.push("s".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    // Build the template string
+    let mut template_str = String::new();
+    for piece in template {
+        match *piece {
+            InlineAsmTemplatePiece::String(ref s) => template_str.push_str(&s.replace('$', "$$")),
+            InlineAsmTemplatePiece::Placeholder { operand_idx, modifier, span: _ } => {
+                match operands[operand_idx] {
+                    AsmOperand::In { reg, .. }
+                    | AsmOperand::Out { reg, .. }
+                    | AsmOperand::InOut { reg, .. } => {
+                        let idx = op_idx[operand_idx].unwrap();
+                        match modifier_to_llvm(asm_arch, reg.reg_class(), modifier) {
+                            Some(modifier) => write!(template_str, "${{{idx}:{modifier}}}"),
+                            None => write!(template_str, "${{{idx}}}"),
+                        }
+                        .unwrap();
+                    }
+                    // Const operands get injected directly into the template
+                    AsmOperand::Const { ref string } => template_str.push_str(string),
+                    // Only emit the raw symbol name
+                    AsmOperand::SymFn { .. } | AsmOperand::SymStatic { .. } => {
+                        write!(template_str, "${{{}:c}}", op_idx[operand_idx].unwrap()).unwrap();
+                    }
+                    AsmOperand::Label { .. } => unreachable!(),
+                }
+            }
+        }
+    }
+
+    constraints.append(&mut clobbers);
+    if !options.contains(InlineAsmOptions::PRESERVES_FLAGS) {
+        match asm_arch {
+            InlineAsmArch::AArch64 => constraints.push("~{cc}".to_string()),
+            InlineAsmArch::X86 | InlineAsmArch::X86_64 => {
+                constraints.extend(["~{dirflag}", "~{fpsr}", "~{flags}"].map(str::to_string));
+            }
+            InlineAsmArch::RiscV32 | InlineAsmArch::RiscV64 => {
+                constraints
+                    .extend(["~{vtype}", "~{vl}", "~{vxsat}", "~{vxrm}"].map(str::to_string));
+            }
+            _ => unreachable!(),
+        }
+    }
+    if !options.contains(InlineAsmOptions::NOMEM) {
+        constraints.push("~{memory}".to_string());
+    }
+
+    let bx = fx.bx;
+    let mut attributes = vec![
+        (bx.ident("asm_string"), StringAttribute::new(fx.context, &template_str).into()),
+        (bx.ident("constraints"), StringAttribute::new(fx.context, &constraints.join(",")).into()),
+    ];
+    if !options.contains(InlineAsmOptions::PURE) {
+        attributes.push((bx.ident("has_side_effects"), Attribute::unit(fx.context)));
+    }
+    if !options.contains(InlineAsmOptions::NOSTACK) {
+        attributes.push((bx.ident("is_align_stack"), Attribute::unit(fx.context)));
+    }
+    if matches!(asm_arch, InlineAsmArch::X86 | InlineAsmArch::X86_64)
+        && !options.contains(InlineAsmOptions::ATT_SYNTAX)
+    {
+        attributes.push((bx.ident("asm_dialect"), bx.parse_attr("#llvm.asm_dialect<intel>")));
+    }
+    let output_type = match &output_types[..] {
+        [] => None,
+        &[ty] => Some(ty),
+        tys => Some(melior::dialect::llvm::r#type::r#struct(fx.context, tys, false)),
+    };
+    let op = bx.append(
+        bx.op("llvm.inline_asm")
+            .add_operands(&inputs)
+            .add_attributes(&attributes)
+            .add_results(output_type.as_slice())
+            .build()
+            .unwrap(),
+    );
+
+    // Write results to outputs
+    for (idx, op_ref) in operands.iter().enumerate() {
+        if let AsmOperand::Out { reg, place: Some(place), .. }
+        | AsmOperand::InOut { reg, out_place: Some(place), .. } = *op_ref
+        {
+            let result: Value<'ml, 'a> = op.result(0).unwrap().into();
+            let value = if output_types.len() == 1 {
+                result
+            } else {
+                let output_idx = op_idx[idx].unwrap();
+                fx.bx.extract_value(result, output_idx, output_types[output_idx])
+            };
+            let value = fixup_output(fx, value, reg.reg_class(), place.layout());
+            place.write_mvalue(fx, MValue::by_val(value, place.layout()));
+        }
+    }
+
+    match dest {
+        Some(dest) => {
+            let dest = fx.get_block(dest);
+            fx.bx.br(&dest, &[]);
+        }
+        None => fx.bx.unreachable(),
+    }
+}
+
+fn is_target_supported(
+    fx: &FunctionCx<'_, '_, '_>,
+    asm_arch: InlineAsmArch,
+    reg_class: InlineAsmRegClass,
+) -> bool {
+    reg_class.supported_types(asm_arch).iter().any(|&(_, feature)| match feature {
+        Some(feature) => fx.tcx.asm_target_features(fx.instance.def_id()).contains(&feature),
+        // Register class is unconditionally supported
+        None => true,
+    })
+}
+
+/// If the register is an xmm/ymm/zmm register then return its index.
+fn xmm_reg_index(reg: InlineAsmReg) -> Option<u32> {
+    use X86InlineAsmReg::*;
+    match reg {
+        InlineAsmReg::X86(reg) if reg as u32 >= xmm0 as u32 && reg as u32 <= xmm15 as u32 => {
+            Some(reg as u32 - xmm0 as u32)
+        }
+        InlineAsmReg::X86(reg) if reg as u32 >= ymm0 as u32 && reg as u32 <= ymm15 as u32 => {
+            Some(reg as u32 - ymm0 as u32)
+        }
+        InlineAsmReg::X86(reg) if reg as u32 >= zmm0 as u32 && reg as u32 <= zmm31 as u32 => {
+            Some(reg as u32 - zmm0 as u32)
+        }
+        _ => None,
+    }
+}
+
+/// If the register is an AArch64 integer register then return its index.
+fn a64_reg_index(reg: InlineAsmReg) -> Option<u32> {
+    match reg {
+        InlineAsmReg::AArch64(r) => r.reg_index(),
+        _ => None,
+    }
+}
+
+/// If the register is an AArch64 vector register then return its index.
+fn a64_vreg_index(reg: InlineAsmReg) -> Option<u32> {
+    match reg {
+        InlineAsmReg::AArch64(reg) => reg.vreg_index(),
+        _ => None,
+    }
+}
+
+/// Converts a register class to an LLVM constraint code.
+fn reg_to_llvm(reg: InlineAsmRegOrRegClass, layout: Option<TyAndLayout<'_>>) -> String {
+    use InlineAsmRegClass::*;
+    match reg {
+        // For vector registers LLVM wants the register name to match the type size.
+        InlineAsmRegOrRegClass::Reg(reg) => {
+            if let Some(idx) = xmm_reg_index(reg) {
+                let class = match layout.map(|layout| layout.size.bytes()) {
+                    Some(64) => 'z',
+                    Some(32) => 'y',
+                    // We use f32 as the type for discarded outputs
+                    _ => 'x',
+                };
+                format!("{{{}mm{}}}", class, idx)
+            } else if let Some(idx) = a64_reg_index(reg) {
+                // We use i32 as the type for discarded outputs
+                let class =
+                    if layout.is_some_and(|layout| layout.size.bytes() == 8) { 'x' } else { 'w' };
+                if class == 'x' && reg == InlineAsmReg::AArch64(AArch64InlineAsmReg::x30) {
+                    // LLVM doesn't recognize x30. use lr instead.
+                    "{lr}".to_string()
+                } else {
+                    format!("{{{}{}}}", class, idx)
+                }
+            } else if let Some(idx) = a64_vreg_index(reg) {
+                let class = match layout.map(|layout| layout.size.bytes()) {
+                    Some(16) => 'q',
+                    Some(8) => 'd',
+                    Some(4) => 's',
+                    Some(2) => 'h',
+                    Some(1) => 'd', // We fixup i8 to i8x8
+                    Some(_) => unreachable!(),
+                    // We use i64x2 as the type for discarded outputs
+                    None => 'q',
+                };
+                format!("{{{}{}}}", class, idx)
+            } else {
+                format!("{{{}}}", reg.name())
+            }
+        }
+        // The constraints can be retrieved from
+        // https://llvm.org/docs/LangRef.html#supported-constraint-code-list
+        InlineAsmRegOrRegClass::RegClass(reg) => match reg {
+            AArch64(AArch64InlineAsmRegClass::reg) => "r",
+            AArch64(AArch64InlineAsmRegClass::vreg) => "w",
+            AArch64(AArch64InlineAsmRegClass::vreg_low16) => "x",
+            RiscV(RiscVInlineAsmRegClass::reg) => "r",
+            RiscV(RiscVInlineAsmRegClass::freg) => "f",
+            X86(X86InlineAsmRegClass::reg) => "r",
+            X86(X86InlineAsmRegClass::reg_abcd) => "Q",
+            X86(X86InlineAsmRegClass::reg_byte) => "q",
+            X86(X86InlineAsmRegClass::xmm_reg) | X86(X86InlineAsmRegClass::ymm_reg) => "x",
+            X86(X86InlineAsmRegClass::zmm_reg) => "v",
+            X86(X86InlineAsmRegClass::kreg) => "^Yk",
+            AArch64(AArch64InlineAsmRegClass::preg)
+            | RiscV(RiscVInlineAsmRegClass::vreg)
+            | X86(
+                X86InlineAsmRegClass::x87_reg
+                | X86InlineAsmRegClass::mmx_reg
+                | X86InlineAsmRegClass::kreg0
+                | X86InlineAsmRegClass::tmm_reg,
+            ) => unreachable!("clobber-only"),
+            _ => unreachable!("unsupported asm arch"),
+        }
+        .to_string(),
+    }
+}
+
+/// Converts a modifier into LLVM's equivalent modifier.
+fn modifier_to_llvm(
+    arch: InlineAsmArch,
+    reg: InlineAsmRegClass,
+    modifier: Option<char>,
+) -> Option<char> {
+    use InlineAsmRegClass::*;
+    // The modifiers can be retrieved from
+    // https://llvm.org/docs/LangRef.html#asm-template-argument-modifiers
+    match reg {
+        AArch64(AArch64InlineAsmRegClass::reg) => modifier,
+        AArch64(AArch64InlineAsmRegClass::vreg) | AArch64(AArch64InlineAsmRegClass::vreg_low16) => {
+            if modifier == Some('v') {
+                None
+            } else {
+                modifier
+            }
+        }
+        RiscV(RiscVInlineAsmRegClass::reg) | RiscV(RiscVInlineAsmRegClass::freg) => None,
+        X86(X86InlineAsmRegClass::reg) | X86(X86InlineAsmRegClass::reg_abcd) => match modifier {
+            None if arch == InlineAsmArch::X86_64 => Some('q'),
+            None => Some('k'),
+            Some('l') => Some('b'),
+            Some('h') => Some('h'),
+            Some('x') => Some('w'),
+            Some('e') => Some('k'),
+            Some('r') => Some('q'),
+            _ => unreachable!(),
+        },
+        X86(X86InlineAsmRegClass::reg_byte) => None,
+        X86(reg @ X86InlineAsmRegClass::xmm_reg)
+        | X86(reg @ X86InlineAsmRegClass::ymm_reg)
+        | X86(reg @ X86InlineAsmRegClass::zmm_reg) => match (reg, modifier) {
+            (X86InlineAsmRegClass::xmm_reg, None) => Some('x'),
+            (X86InlineAsmRegClass::ymm_reg, None) => Some('t'),
+            (X86InlineAsmRegClass::zmm_reg, None) => Some('g'),
+            (_, Some('x')) => Some('x'),
+            (_, Some('y')) => Some('t'),
+            (_, Some('z')) => Some('g'),
+            _ => unreachable!(),
+        },
+        X86(X86InlineAsmRegClass::kreg) => None,
+        AArch64(AArch64InlineAsmRegClass::preg)
+        | RiscV(RiscVInlineAsmRegClass::vreg)
+        | X86(
+            X86InlineAsmRegClass::x87_reg
+            | X86InlineAsmRegClass::mmx_reg
+            | X86InlineAsmRegClass::kreg0
+            | X86InlineAsmRegClass::tmm_reg,
+        ) => unreachable!("clobber-only"),
+        _ => unreachable!("unsupported asm arch"),
+    }
+}
+
+/// The type of a discarded output in a register of class `reg`.
+fn dummy_output_type<'ml>(fx: &FunctionCx<'_, 'ml, '_>, reg: InlineAsmRegClass) -> Type<'ml> {
+    use InlineAsmRegClass::*;
+    let ty = match reg {
+        AArch64(AArch64InlineAsmRegClass::reg) => "i32",
+        AArch64(AArch64InlineAsmRegClass::vreg) | AArch64(AArch64InlineAsmRegClass::vreg_low16) => {
+            "vector<2xi64>"
+        }
+        RiscV(RiscVInlineAsmRegClass::reg) => "i32",
+        RiscV(RiscVInlineAsmRegClass::freg) => "f32",
+        X86(X86InlineAsmRegClass::reg) | X86(X86InlineAsmRegClass::reg_abcd) => "i32",
+        X86(X86InlineAsmRegClass::reg_byte) => "i8",
+        X86(X86InlineAsmRegClass::xmm_reg)
+        | X86(X86InlineAsmRegClass::ymm_reg)
+        | X86(X86InlineAsmRegClass::zmm_reg) => "f32",
+        X86(X86InlineAsmRegClass::kreg) => "i16",
+        AArch64(AArch64InlineAsmRegClass::preg)
+        | RiscV(RiscVInlineAsmRegClass::vreg)
+        | X86(
+            X86InlineAsmRegClass::x87_reg
+            | X86InlineAsmRegClass::mmx_reg
+            | X86InlineAsmRegClass::kreg0
+            | X86InlineAsmRegClass::tmm_reg,
+        ) => unreachable!("clobber-only"),
+        _ => unreachable!("unsupported asm arch"),
+    };
+    Type::parse(fx.context, ty).unwrap()
+}
+
+/// Errors out on operands of `layout` in registers of class `reg` that this backend can't pass to
+/// LLVM: values that aren't a scalar or a vector, and values that `llvm_fixup_input` in
+/// `rustc_codegen_llvm` converts to another type to work around LLVM bugs. Of those conversions,
+/// only the one of `f64` in `reg_abcd` registers is done by [`fixup_input`].
+fn check_operand(
+    fx: &FunctionCx<'_, '_, '_>,
+    span: Span,
+    reg: InlineAsmRegClass,
+    layout: TyAndLayout<'_>,
+) {
+    use InlineAsmRegClass::*;
+    let unsupported = match (reg, layout.abi) {
+        (_, Abi::Uninhabited | Abi::ScalarPair(..) | Abi::Aggregate { .. }) => true,
+        (AArch64(AArch64InlineAsmRegClass::vreg), Abi::Scalar(s)) => {
+            matches!(s.primitive(), Primitive::Int(Integer::I8, _))
+        }
+        (AArch64(AArch64InlineAsmRegClass::vreg_low16), Abi::Scalar(s)) => {
+            s.primitive() != Primitive::Float(Float::F128)
+        }
+        (AArch64(AArch64InlineAsmRegClass::vreg_low16), Abi::Vector { .. }) => {
+            layout.size.bytes() == 8
+        }
+        (
+            X86(
+                X86InlineAsmRegClass::xmm_reg
+                | X86InlineAsmRegClass::ymm_reg
+                | X86InlineAsmRegClass::zmm_reg,
+            ),
+            Abi::Scalar(s),
+        ) => {
+            s.primitive() == Primitive::Float(Float::F16)
+                || (fx.tcx.sess.asm_arch == Some(InlineAsmArch::X86)
+                    && s.primitive() == Primitive::Float(Float::F128))
+        }
+        (
+            X86(
+                X86InlineAsmRegClass::xmm_reg
+                | X86InlineAsmRegClass::ymm_reg
+                | X86InlineAsmRegClass::zmm_reg,
+            ),
+            Abi::Vector { element, .. },
+        ) => element.primitive() == Primitive::Float(Float::F16) || layout.size.bytes() == 64,
+        // NaN-boxed into an `f32` unless the `zfh` or `zfhmin` target feature is enabled.
+        (RiscV(RiscVInlineAsmRegClass::freg), Abi::Scalar(s)) => {
+            s.primitive() == Primitive::Float(Float::F16)
+        }
+        _ => false,
+    };
+    if unsupported {
+        fx.tcx.dcx().span_fatal(
+            span,
+            format!(
+                "asm operands of type `{}` in `{}` registers are not yet supported by the MLIR \
+                 backend",
+                layout.ty,
+                reg.name()
+            ),
+        );
+    }
+}
+
+/// Loads `value` as an input operand for a register of class `reg`.
+fn fixup_input<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    value: MValue<'ml, 'a, 'tcx>,
+    reg: InlineAsmRegClass,
+) -> Value<'ml, 'a> {
+    use InlineAsmRegClass::*;
+    let layout = value.layout();
+    match (reg, layout.abi) {
+        (X86(X86InlineAsmRegClass::reg_abcd), Abi::Scalar(s))
+            if s.primitive() == Primitive::Float(Float::F64) =>
+        {
+            let value = value.load_scalar(fx);
+            let i64_ty = fx.bx.int_type(64);
+            fx.bx.cast("arith.bitcast", value, i64_ty)
+        }
+        (_, Abi::Scalar(_)) => value.load_scalar(fx),
+        (_, Abi::Vector { .. }) => {
+            let ptr = value.force_stack(fx);
+            fx.bx.load(mlir_type(fx.cx, layout), ptr, layout.align.abi.bytes())
+        }
+        _ => bug!("asm operand of type `{}` with abi {:?}", layout.ty, layout.abi),
+    }
+}
+
+/// The type of the output operand for a register of class `reg` written to a place of `layout`.
+fn fixup_output_type<'ml>(
+    fx: &FunctionCx<'_, 'ml, '_>,
+    reg: InlineAsmRegClass,
+    layout: TyAndLayout<'_>,
+) -> Type<'ml> {
+    use InlineAsmRegClass::*;
+    match (reg, layout.abi) {
+        (X86(X86InlineAsmRegClass::reg_abcd), Abi::Scalar(s))
+            if s.primitive() == Primitive::Float(Float::F64) =>
+        {
+            Type::parse(fx.context, "i64").unwrap()
+        }
+        (_, Abi::Scalar(s)) => scalar_to_mlir_type(fx.tcx, fx.context, s),
+        (_, Abi::Vector { .. }) => mlir_type(fx.cx, layout),
+        _ => bug!("asm operand of type `{}` with abi {:?}", layout.ty, layout.abi),
+    }
+}
+
+/// Converts an output operand back to the type of the place of `layout` it is written to.
+fn fixup_output<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    value: Value<'ml, 'a>,
+    reg: InlineAsmRegClass,
+    layout: TyAndLayout<'tcx>,
+) -> Value<'ml, 'a> {
+    use InlineAsmRegClass::*;
+    match (reg, layout.abi) {
+        (X86(X86InlineAsmRegClass::reg_abcd), Abi::Scalar(s))
+            if s.primitive() == Primitive::Float(Float::F64) =>
+        {
+            let f64_ty = scalar_to_mlir_type(fx.tcx, fx.context, s);
+            fx.bx.cast("arith.bitcast", value, f64_ty)
+        }
+        _ => value,
+    }
+}
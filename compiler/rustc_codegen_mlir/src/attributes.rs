@@ -0,0 +1,130 @@
+//! Function attributes derived from the `CodegenFnAttrs` of an instance, matching the ones
+//! `rustc_codegen_llvm` puts on the functions it defines.
+//!
+//! They are attached to the `func.func`, from which `convert-func-to-llvm` carries them over to
+//! the `llvm.func`. LLVM attributes without a counterpart in the LLVM dialect are listed in its
+//! `passthrough` attribute.
+
+use melior::Context;
+use melior::ir::{Attribute, Identifier};
+use rustc_attr::{InlineAttr, OptimizeAttr};
+use rustc_middle::bug;
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
+use rustc_middle::ty::{Instance, Ty, TyCtxt};
+use rustc_session::config::OptLevel;
+use rustc_target::abi::call::FnAbi;
+use rustc_target::spec::SanitizerSet;
+
+/// The LLVM attribute enabling the instrumentation of each set of sanitizers.
+const SANITIZER_ATTRS: &[(SanitizerSet, &str)] = &[
+    (SanitizerSet::ADDRESS.union(SanitizerSet::KERNELADDRESS), "sanitize_address"),
+    (SanitizerSet::MEMORY, "sanitize_memory"),
+    (SanitizerSet::THREAD, "sanitize_thread"),
+    (SanitizerSet::HWADDRESS, "sanitize_hwaddress"),
+    (SanitizerSet::MEMTAG, "sanitize_memtag"),
+    (SanitizerSet::SHADOWCALLSTACK, "shadowcallstack"),
+    (SanitizerSet::SAFESTACK, "safestack"),
+];
+
+/// Returns the attributes of the `func.func` defining `instance`.
+pub(crate) fn fn_attributes<'ml, 'tcx>(
+    tcx: TyCtxt<'tcx>,
+    context: &'ml Context,
+    instance: Instance<'tcx>,
+    fn_abi: &FnAbi<'tcx, Ty<'tcx>>,
+) -> Vec<(Identifier<'ml>, Attribute<'ml>)> {
+    let sess = tcx.sess;
+    let codegen_fn_attrs = tcx.codegen_fn_attrs(instance.def_id());
+    let mut passthrough = vec![];
+
+    match codegen_fn_attrs.optimize {
+        OptimizeAttr::None => match sess.opts.optimize {
+            OptLevel::Size => passthrough.push("optsize"),
+            OptLevel::SizeMin => passthrough.extend(["minsize", "optsize"]),
+            _ => {}
+        },
+        OptimizeAttr::Size => passthrough.extend(["minsize", "optsize"]),
+        OptimizeAttr::Speed => {}
+    }
+
+    let inline = if codegen_fn_attrs.inline == InlineAttr::None && instance.def.requires_inline(tcx)
+    {
+        InlineAttr::Hint
+    } else {
+        codegen_fn_attrs.inline
+    };
+    if !sess.opts.unstable_opts.inline_llvm {
+        passthrough.push("noinline");
+    } else {
+        match inline {
+            InlineAttr::Hint => passthrough.push("inlinehint"),
+            InlineAttr::Always => passthrough.push("alwaysinline"),
+            InlineAttr::Never => passthrough.push("noinline"),
+            InlineAttr::None => {}
+        }
+    }
+
+    if !fn_abi.can_unwind {
+        passthrough.push("nounwind");
+    }
+    if codegen_fn_attrs.flags.contains(CodegenFnAttrFlags::NO_BUILTINS) {
+        passthrough.push("no-builtins");
+    }
+    if codegen_fn_attrs.flags.contains(CodegenFnAttrFlags::COLD) {
+        passthrough.push("cold");
+    }
+    if codegen_fn_attrs.flags.contains(CodegenFnAttrFlags::NAKED) {
+        // Indirect branch tracking would add an `endbr` instruction in front of the assembly.
+        passthrough.extend(["naked", "nocf_check"]);
+    } else {
+        let enabled = sess.opts.unstable_opts.sanitizer - codegen_fn_attrs.no_sanitize;
+        for &(sanitizer, attr) in SANITIZER_ATTRS {
+            if enabled.intersects(sanitizer) {
+                passthrough.push(attr);
+            }
+        }
+    }
+
+    let mut passthrough =
+        passthrough.into_iter().map(|attr| format!("\"{attr}\"")).collect::<Vec<_>>();
+
+    let target_features =
+        rustc_codegen_llvm::llvm_util::function_target_features(tcx, codegen_fn_attrs);
+    if !target_features.is_empty() {
+        passthrough.push(format!("[\"target-features\", \"{target_features}\"]"));
+    }
+
+    let mut attributes = vec![];
+    let mut push = |name: &str, attr: String| {
+        let parsed = Attribute::parse(context, &attr)
+            .unwrap_or_else(|| bug!("invalid function attribute `{name} = {attr}`"));
+        attributes.push((Identifier::new(context, name), parsed));
+    };
+
+    if !passthrough.is_empty() {
+        push("passthrough", format!("[{}]", passthrough.join(", ")));
+    }
+
+    let memory = if codegen_fn_attrs.flags.contains(CodegenFnAttrFlags::FFI_CONST) {
+        Some("none")
+    } else if codegen_fn_attrs.flags.contains(CodegenFnAttrFlags::FFI_PURE) {
+        Some("read")
+    } else {
+        None
+    };
+    if let Some(memory) = memory {
+        push(
+            "memory_effects",
+            format!(
+                "#llvm.memory_effects<other = {memory}, argMem = {memory}, \
+                 inaccessibleMem = {memory}>"
+            ),
+        );
+    }
+
+    if let Some(align) = codegen_fn_attrs.alignment {
+        push("alignment", format!("{} : i64", align.bytes()));
+    }
+
+    attributes
+}
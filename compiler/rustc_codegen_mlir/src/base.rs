@@ -1,66 +1,490 @@
-use rustc_middle::ty::{self, Ty, TyCtxt};
+//! Codegen of a codegen unit, and of MIR statements once lowered out of the `rust` dialect.
+
+use std::time::Instant;
+
+use rustc_abi::{Abi, FIRST_VARIANT, FieldIdx};
+use rustc_codegen_llvm::ModuleLlvm;
+use rustc_codegen_ssa::{ModuleCodegen, ModuleKind};
+use rustc_hir::def_id::DefId;
+use rustc_index::IndexVec;
+use rustc_middle::bug;
+use rustc_middle::dep_graph;
+use rustc_middle::mir::mono::MonoItem;
+use rustc_middle::mir::{AggregateKind, BinOp, CastKind, NullOp, SourceInfo, UnOp};
+use rustc_middle::ty::adjustment::PointerCoercion;
+use rustc_middle::ty::layout::{FnAbiOf, LayoutOf};
+use rustc_middle::ty::{self, Instance, ParamEnv, Ty, TyCtxt};
 use rustc_span::Symbol;
+use tracing::debug;
+
+use crate::ModuleMlir;
+use crate::abi::mlir_fn_type;
+use crate::builder::IntCC;
+use crate::common::FunctionCx;
+use crate::context::CodegenCx;
+use crate::type_of::{has_ptr_meta, type_sign};
+use crate::value_and_place::{MPlace, MValue, immediate_type};
+
+/// Codegens `cgu_name` and translates it to LLVM IR, for `rustc_codegen_llvm` to optimize and
+/// emit on the worker threads of the `rustc_codegen_ssa` coordinator. The MLIR module is built in a
+/// dep-graph task, so that the result can be reused by later incremental sessions.
+///
+/// In mixed-backend builds, only the codegen unit of the functions selected with
+/// `-Zmlir-only-functions` is compiled here; the others are left to `rustc_codegen_llvm`.
+pub(crate) fn compile_codegen_unit(
+    tcx: TyCtxt<'_>,
+    cgu_name: Symbol,
+) -> Option<(ModuleCodegen<ModuleLlvm>, u64)> {
+    if crate::mixed::is_mixed(tcx.sess) && !tcx.codegen_unit(cgu_name).is_mlir() {
+        return None;
+    }
+
+    let start_time = Instant::now();
+
+    let dep_node = tcx.codegen_unit(cgu_name).codegen_dep_node(tcx);
+    let (module, _) = tcx.dep_graph.with_task(
+        dep_node,
+        tcx,
+        cgu_name,
+        module_codegen,
+        Some(dep_graph::hash_result),
+    );
+    let module = crate::write::lower_to_llvm(tcx, module);
+
+    // Like `rustc_codegen_llvm`, assume that the cost to run LLVM on a codegen unit is
+    // proportional to the time needed to generate it.
+    let cost = start_time.elapsed().as_nanos() as u64;
+
+    Some((module, cost))
+}
 
-pub fn module_codegen(tcx: TyCtxt<'_>, cgu_name: Symbol) -> () {
+fn module_codegen(tcx: TyCtxt<'_>, cgu_name: Symbol) -> ModuleCodegen<ModuleMlir> {
     let cgu = tcx.codegen_unit(cgu_name);
+    let mono_items = cgu.items_in_deterministic_order(tcx);
 
-    for (mono_item, data) in cgu.items() {
-        match mono_item {
-            rustc_middle::mir::mono::MonoItem::Fn(instance) => {
-                let mir = tcx.instance_mir(instance.def);
+    let mut module_mlir = ModuleMlir::new(cgu_name.as_str());
+    let module_asm = {
+        let cx = CodegenCx::new(
+            tcx,
+            cgu,
+            module_mlir.context(),
+            module_mlir.module(),
+            module_mlir.rust_module(),
+        );
 
-                println!("Got mir: {mir:?}");
+        for &(mono_item, data) in &mono_items {
+            match mono_item {
+                MonoItem::Fn(instance) => {
+                    debug!("emit fn {}", tcx.symbol_name(instance).name);
+                    crate::dialect::emit::emit_fn(&cx, instance);
+                }
+                MonoItem::Static(def_id) => {
+                    debug!("emit static {}", tcx.def_path_str(def_id));
+                    crate::constant::codegen_static(&cx, def_id, data.linkage, data.visibility);
+                }
+                MonoItem::GlobalAsm(item_id) => {
+                    crate::asm::codegen_global_asm(&cx, item_id);
+                }
+            }
+        }
 
-                //instance.def_id().
-                println!("fn instance is: {:?}", instance.def_id());
-                match instance.def {
-                    ty::InstanceKind::Item(def_id) => {
-                        println!("item");
-                    }
-                    ty::InstanceKind::Intrinsic(def_id) => {
-                        println!("intrinsic");
-                    }
-                    ty::InstanceKind::VTableShim(def_id) => {
-                        println!("vtable shim");
-                    }
-                    ty::InstanceKind::ReifyShim(def_id, reify_reason) => {
-                        println!("reify shim");
-                    }
-                    ty::InstanceKind::FnPtrShim(def_id, ty) => {
-                        println!("fnptr shim");
-                    }
-                    ty::InstanceKind::Virtual(def_id, _) => todo!(),
-                    ty::InstanceKind::ClosureOnceShim { call_once, track_caller } => {
-                        println!("closure once shim");
-                    }
-                    ty::InstanceKind::ConstructCoroutineInClosureShim {
-                        coroutine_closure_def_id,
-                        receiver_by_ref,
-                    } => {
-                        println!("construct coroutine in closure shim");
-                    }
-                    ty::InstanceKind::ThreadLocalShim(def_id) => {
-                        println!("thread local shim");
-                    }
-                    ty::InstanceKind::DropGlue(def_id, ty) => {
-                        println!("drop glue");
+        crate::dump::dump_after(
+            tcx.sess,
+            tcx.output_filenames(()),
+            cgu_name.as_str(),
+            "rust",
+            module_mlir.rust_module(),
+        );
+        crate::write::run_rust_passes(
+            tcx.sess,
+            tcx.output_filenames(()),
+            &module_mlir,
+            cgu_name.as_str(),
+        );
+
+        crate::dialect::lower::lower_module(&cx);
+        crate::main_shim::maybe_create_entry_wrapper(&cx);
+        cx.finalize();
+        cx.global_asm.take()
+    };
+    module_mlir.module_asm = module_asm;
+
+    crate::dump::dump_after(
+        tcx.sess,
+        tcx.output_filenames(()),
+        cgu_name.as_str(),
+        "lower",
+        module_mlir.module(),
+    );
+
+    ModuleCodegen {
+        name: cgu_name.to_string(),
+        module_llvm: module_mlir,
+        kind: ModuleKind::Regular,
+    }
+}
+
+/// An `Rvalue` whose operands and places have already been lowered.
+pub(crate) enum LoweredRvalue<'ml, 'a, 'tcx> {
+    Use(MValue<'ml, 'a, 'tcx>),
+    Repeat(MValue<'ml, 'a, 'tcx>, ty::Const<'tcx>),
+    /// Both `Rvalue::Ref` and `Rvalue::RawPtr`.
+    Ref(MPlace<'ml, 'a, 'tcx>),
+    ThreadLocalRef(DefId),
+    Len(MPlace<'ml, 'a, 'tcx>),
+    Cast(CastKind, MValue<'ml, 'a, 'tcx>, Ty<'tcx>),
+    BinaryOp(BinOp, MValue<'ml, 'a, 'tcx>, MValue<'ml, 'a, 'tcx>),
+    NullaryOp(NullOp<'tcx>, Ty<'tcx>),
+    UnaryOp(UnOp, MValue<'ml, 'a, 'tcx>),
+    Discriminant(MPlace<'ml, 'a, 'tcx>),
+    Aggregate(AggregateKind<'tcx>, IndexVec<FieldIdx, MValue<'ml, 'a, 'tcx>>),
+    ShallowInitBox(MValue<'ml, 'a, 'tcx>, Ty<'tcx>),
+    CopyForDeref(MPlace<'ml, 'a, 'tcx>),
+}
+
+pub(crate) fn codegen_assign<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    source_info: SourceInfo,
+    lval: MPlace<'ml, 'a, 'tcx>,
+    rvalue: LoweredRvalue<'ml, 'a, 'tcx>,
+) {
+    let dest_layout = lval.layout();
+    match rvalue {
+        LoweredRvalue::Use(val) => {
+            lval.write_mvalue(fx, val);
+        }
+        LoweredRvalue::CopyForDeref(place) => {
+            let val = place.to_mvalue(fx);
+            lval.write_mvalue(fx, val)
+        }
+        LoweredRvalue::Ref(place) => {
+            let ref_ = place.place_ref(fx, lval.layout());
+            lval.write_mvalue(fx, ref_);
+        }
+        LoweredRvalue::ThreadLocalRef(def_id) => {
+            let addr = codegen_thread_local_addr(fx, def_id);
+            lval.write_mvalue(fx, MValue::by_val(addr, dest_layout));
+        }
+        LoweredRvalue::BinaryOp(bin_op, lhs, rhs) => {
+            let res = if let Some(bin_op) = bin_op.overflowing_to_wrapping() {
+                crate::num::codegen_checked_int_binop(fx, bin_op, lhs, rhs)
+            } else {
+                crate::num::codegen_binop(fx, bin_op, lhs, rhs)
+            };
+            lval.write_mvalue(fx, res);
+        }
+        LoweredRvalue::UnaryOp(un_op, operand) => {
+            let layout = operand.layout();
+            let res = match un_op {
+                UnOp::Not => {
+                    let val = operand.load_scalar(fx);
+                    match layout.ty.kind() {
+                        ty::Bool => {
+                            let res = fx.bx.icmp_imm(IntCC::Equal, val, 0);
+                            MValue::by_val(fx.bx.bool_to_i8(res), layout)
+                        }
+                        ty::Uint(_) | ty::Int(_) => {
+                            let all_ones = fx.bx.iconst(immediate_type(fx, layout).unwrap(), -1);
+                            MValue::by_val(fx.bx.binary("arith.xori", val, all_ones), layout)
+                        }
+                        _ => unreachable!("un op Not for {:?}", layout.ty),
                     }
-                    ty::InstanceKind::CloneShim(def_id, ty) => {
-                        println!("clone shim");
+                }
+                UnOp::Neg => {
+                    let val = operand.load_scalar(fx);
+                    match layout.ty.kind() {
+                        ty::Int(_) => {
+                            let zero = fx.bx.iconst(immediate_type(fx, layout).unwrap(), 0);
+                            MValue::by_val(fx.bx.binary("arith.subi", zero, val), layout)
+                        }
+                        ty::Float(_) => MValue::by_val(fx.bx.unary("arith.negf", val), layout),
+                        _ => unreachable!("un op Neg for {:?}", layout.ty),
                     }
-                    ty::InstanceKind::FnPtrAddrShim(def_id, ty) => {
-                        println!("fn ptr addr shim");
+                }
+                UnOp::PtrMetadata => match layout.abi {
+                    Abi::Scalar(_) => MValue::zst(dest_layout),
+                    Abi::ScalarPair(_, _) => {
+                        MValue::by_val(operand.load_scalar_pair(fx).1, dest_layout)
                     }
-                    ty::InstanceKind::AsyncDropGlueCtorShim(def_id, ty) => todo!(),
+                    _ => bug!("Unexpected `PtrToMetadata` operand: {operand:?}"),
+                },
+            };
+            lval.write_mvalue(fx, res);
+        }
+        LoweredRvalue::Cast(
+            CastKind::PointerCoercion(PointerCoercion::ReifyFnPointer, _),
+            operand,
+            to_ty,
+        ) => {
+            let from_ty = operand.layout().ty;
+            let to_layout = fx.layout_of(fx.monomorphize(to_ty));
+            match *from_ty.kind() {
+                ty::FnDef(def_id, args) => {
+                    let instance =
+                        Instance::resolve_for_fn_ptr(fx.tcx, ParamEnv::reveal_all(), def_id, args)
+                            .unwrap()
+                            .polymorphize(fx.tcx);
+                    let func_addr = codegen_fn_addr(fx, instance);
+                    lval.write_mvalue(fx, MValue::by_val(func_addr, to_layout));
                 }
-                println!("got fn!");
+                _ => bug!("Trying to ReifyFnPointer on non FnDef {:?}", from_ty),
             }
-            rustc_middle::mir::mono::MonoItem::Static(def_id) => {
-                println!("got static item");
+        }
+        LoweredRvalue::Cast(
+            CastKind::PointerCoercion(PointerCoercion::UnsafeFnPointer, _),
+            operand,
+            to_ty,
+        ) => {
+            let to_layout = fx.layout_of(fx.monomorphize(to_ty));
+            lval.write_mvalue(fx, operand.cast_pointer_to(to_layout));
+        }
+        LoweredRvalue::Cast(
+            kind @ CastKind::PointerCoercion(
+                PointerCoercion::MutToConstPointer | PointerCoercion::ArrayToPointer,
+                _,
+            ),
+            ..,
+        ) => {
+            bug!("{:?} is for borrowck, and should never appear in codegen", kind);
+        }
+        LoweredRvalue::Cast(
+            CastKind::IntToInt
+            | CastKind::FloatToFloat
+            | CastKind::FloatToInt
+            | CastKind::IntToFloat
+            | CastKind::FnPtrToPtr
+            | CastKind::PtrToPtr
+            | CastKind::PointerExposeProvenance
+            | CastKind::PointerWithExposedProvenance,
+            operand,
+            to_ty,
+        ) => {
+            let from_ty = operand.layout().ty;
+            let to_ty = fx.monomorphize(to_ty);
+
+            fn is_wide_ptr<'tcx>(fx: &FunctionCx<'_, '_, 'tcx>, ty: Ty<'tcx>) -> bool {
+                ty.builtin_deref(true).is_some_and(|pointee_ty| has_ptr_meta(fx.tcx, pointee_ty))
+            }
+
+            if is_wide_ptr(fx, from_ty) {
+                if is_wide_ptr(fx, to_ty) {
+                    // wide-ptr -> wide-ptr
+                    lval.write_mvalue(fx, operand.cast_pointer_to(dest_layout));
+                } else {
+                    // wide-ptr -> thin-ptr
+                    let (ptr, _extra) = operand.load_scalar_pair(fx);
+                    lval.write_mvalue(fx, MValue::by_val(ptr, dest_layout))
+                }
+            } else {
+                let to_mlir_ty = immediate_type(fx, dest_layout).unwrap();
+                let from = operand.load_scalar(fx);
+
+                let res = crate::cast::int_or_float_cast(
+                    fx,
+                    from,
+                    type_sign(from_ty),
+                    to_mlir_ty,
+                    type_sign(to_ty),
+                );
+                lval.write_mvalue(fx, MValue::by_val(res, dest_layout));
+            }
+        }
+        LoweredRvalue::Cast(
+            CastKind::PointerCoercion(PointerCoercion::ClosureFnPointer(_), _),
+            operand,
+            _to_ty,
+        ) => match *operand.layout().ty.kind() {
+            ty::Closure(def_id, args) => {
+                let instance =
+                    Instance::resolve_closure(fx.tcx, def_id, args, ty::ClosureKind::FnOnce)
+                        .polymorphize(fx.tcx);
+                let func_addr = codegen_fn_addr(fx, instance);
+                lval.write_mvalue(fx, MValue::by_val(func_addr, lval.layout()));
+            }
+            _ => bug!("{} cannot be cast to a fn ptr", operand.layout().ty),
+        },
+        LoweredRvalue::Cast(CastKind::PointerCoercion(PointerCoercion::Unsize, _), operand, _) => {
+            crate::unsize::coerce_unsized_into(fx, operand, lval);
+        }
+        LoweredRvalue::Cast(CastKind::PointerCoercion(PointerCoercion::DynStar, _), _, _) => {
+            fx.tcx
+                .dcx()
+                .span_fatal(source_info.span, "dyn* is not yet supported by the MLIR backend");
+        }
+        LoweredRvalue::Cast(CastKind::Transmute, operand, _to_ty) => {
+            lval.write_mvalue_transmute(fx, operand);
+        }
+        LoweredRvalue::Discriminant(place) => {
+            let value = place.to_mvalue(fx);
+            crate::discriminant::codegen_get_discriminant(fx, lval, value, dest_layout);
+        }
+        LoweredRvalue::Repeat(operand, times) => {
+            let times = fx
+                .monomorphize(times)
+                .try_to_target_usize(fx.tcx)
+                .expect("expected monomorphic const in codegen");
+            if operand.layout().size.bytes() == 0 {
+                // Do nothing for ZST's
+            } else if operand.layout().size.bytes() == 1
+                && matches!(operand.layout().abi, Abi::Scalar(_))
+            {
+                let times = fx.bx.iconst(fx.usize_type(), times as i128);
+                let val = operand.load_scalar(fx);
+                fx.bx.memset(lval.to_ptr(), val, times);
+            } else {
+                codegen_repeat_loop(fx, lval, operand, times);
+            }
+        }
+        LoweredRvalue::Len(place) => {
+            let usize_layout = fx.layout_of(fx.tcx.types.usize);
+            let len = codegen_array_len(fx, place);
+            lval.write_mvalue(fx, MValue::by_val(len, usize_layout));
+        }
+        LoweredRvalue::ShallowInitBox(operand, content_ty) => {
+            let content_ty = fx.monomorphize(content_ty);
+            let box_layout = fx.layout_of(Ty::new_box(fx.tcx, content_ty));
+            let operand = operand.load_scalar(fx);
+            lval.write_mvalue(fx, MValue::by_val(operand, box_layout));
+        }
+        LoweredRvalue::NullaryOp(null_op, ty) => {
+            assert!(lval.layout().ty.is_sized(fx.tcx, ParamEnv::reveal_all()));
+            let layout = fx.layout_of(fx.monomorphize(ty));
+            let val = match null_op {
+                NullOp::SizeOf => layout.size.bytes(),
+                NullOp::AlignOf => layout.align.abi.bytes(),
+                NullOp::OffsetOf(fields) => {
+                    fx.tcx.offset_of_subfield(ParamEnv::reveal_all(), layout, fields.iter()).bytes()
+                }
+                NullOp::UbChecks => {
+                    let val = fx.tcx.sess.ub_checks();
+                    let val = MValue::by_val(
+                        fx.bx.iconst(fx.bx.int_type(8), i128::from(val)),
+                        fx.layout_of(fx.tcx.types.bool),
+                    );
+                    lval.write_mvalue(fx, val);
+                    return;
+                }
+            };
+            let val = MValue::by_val(
+                fx.bx.iconst(fx.usize_type(), i128::from(val)),
+                fx.layout_of(fx.tcx.types.usize),
+            );
+            lval.write_mvalue(fx, val);
+        }
+        LoweredRvalue::Aggregate(AggregateKind::RawPtr(..), operands) => {
+            let layout = dest_layout;
+            let [data, meta] = &*operands.raw else {
+                bug!("RawPtr fields: {operands:?}");
+            };
+            let (data, meta) = (*data, *meta);
+            assert!(data.layout().ty.is_unsafe_ptr());
+            assert!(layout.ty.is_unsafe_ptr());
+            let ptr_val = if meta.layout().is_zst() {
+                data.cast_pointer_to(layout)
+            } else {
+                MValue::by_val_pair(data.load_scalar(fx), meta.load_scalar(fx), layout)
+            };
+            lval.write_mvalue(fx, ptr_val);
+        }
+        LoweredRvalue::Aggregate(kind, operands) => {
+            let (variant_index, variant_dest, active_field_index) = match kind {
+                AggregateKind::Adt(_, variant_index, _, _, active_field_index) => {
+                    let variant_dest = lval.downcast_variant(fx, variant_index);
+                    (variant_index, variant_dest, active_field_index)
+                }
+                _ => (FIRST_VARIANT, lval, None),
+            };
+            if active_field_index.is_some() {
+                assert_eq!(operands.len(), 1);
             }
-            rustc_middle::mir::mono::MonoItem::GlobalAsm(item_id) => {
-                println!("got global asm");
+            for (i, operand) in operands.into_iter_enumerated() {
+                let field_index = active_field_index.unwrap_or(i);
+                let to = if let AggregateKind::Array(_) = kind {
+                    let index = fx.bx.iconst(fx.usize_type(), field_index.as_u32().into());
+                    variant_dest.place_index(fx, index)
+                } else {
+                    variant_dest.place_field(fx, field_index)
+                };
+                to.write_mvalue(fx, operand);
             }
+            crate::discriminant::codegen_set_discriminant(fx, lval, variant_index);
+        }
+    }
+}
+
+/// Writes `times` copies of `operand` into the array at `lval` using an explicit loop.
+fn codegen_repeat_loop<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    lval: MPlace<'ml, 'a, 'tcx>,
+    operand: MValue<'ml, 'a, 'tcx>,
+    times: u64,
+) {
+    let usize_ty = fx.usize_type();
+    let usize_layout = fx.layout_of(fx.tcx.types.usize);
+    let align = usize_layout.align.abi.bytes();
+    let index_slot = fx.create_stack_slot(usize_layout.size.bytes(), align);
+    let zero = fx.bx.iconst(usize_ty, 0);
+    fx.bx.store(zero, index_slot, align);
+
+    let loop_block = fx.create_block();
+    let loop_block2 = fx.create_block();
+    let done_block = fx.create_block();
+    fx.bx.br(&loop_block, &[]);
+
+    fx.switch_to_block(loop_block);
+    let index = fx.bx.load(usize_ty, index_slot, align);
+    let done = fx.bx.icmp_imm(IntCC::Equal, index, i128::from(times));
+    fx.bx.cond_br(done, &done_block, &loop_block2);
+
+    fx.switch_to_block(loop_block2);
+    let to = lval.place_index(fx, index);
+    to.write_mvalue(fx, operand);
+    let index = fx.bx.iadd_imm(index, 1);
+    fx.bx.store(index, index_slot, align);
+    fx.bx.br(&loop_block, &[]);
+
+    fx.switch_to_block(done_block);
+}
+
+/// Takes the address of `instance`, declaring it in the current module if needed.
+pub(crate) fn codegen_fn_addr<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    instance: Instance<'tcx>,
+) -> melior::ir::Value<'ml, 'a> {
+    let fn_abi = fx.fn_abi_of_instance(instance, ty::List::empty());
+    let fn_ty = mlir_fn_type(fx.tcx, fx.context, fn_abi);
+    let symbol_name = fx.tcx.symbol_name(instance).name;
+    fx.cx.reference_fn(symbol_name, fn_ty);
+    fx.bx.func_addr(symbol_name, fn_ty)
+}
+
+/// Returns the address of the current thread's instance of the thread local static `def_id`.
+fn codegen_thread_local_addr<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    def_id: DefId,
+) -> melior::ir::Value<'ml, 'a> {
+    let instance = Instance::mono(fx.tcx, def_id);
+    let symbol_name = fx.tcx.symbol_name(instance).name;
+    let layout = fx.layout_of(fx.tcx.type_of(def_id).instantiate_identity());
+    let ty = crate::type_of::mlir_type(fx.cx, layout);
+    fx.cx.reference_global(symbol_name, ty, true);
+    let global = fx.bx.global_addr(symbol_name);
+    fx.bx.threadlocal_addr(global)
+}
+
+pub(crate) fn codegen_array_len<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    place: MPlace<'ml, 'a, 'tcx>,
+) -> melior::ir::Value<'ml, 'a> {
+    match *place.layout().ty.kind() {
+        ty::Array(_elem_ty, len) => {
+            let len = fx
+                .monomorphize(len)
+                .try_to_target_usize(fx.tcx)
+                .expect("expected monomorphic const in codegen");
+            fx.bx.iconst(fx.usize_type(), i128::from(len))
         }
+        ty::Slice(_elem_ty) => place.to_ptr_unsized().1,
+        _ => bug!("Rvalue::Len({:?})", place),
     }
 }
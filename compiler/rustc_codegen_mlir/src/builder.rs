@@ -0,0 +1,819 @@
+//! A thin instruction builder over `melior`'s `OperationBuilder`.
+//!
+//! All ops are built in their generic form so that we don't depend on which convenience
+//! constructors a given `melior` revision happens to expose. The builder always appends to the end
+//! of `block`; callers that need to insert elsewhere (e.g. allocas in the entry block) do so
+//! explicitly.
+
+use melior::Context;
+use melior::ir::attribute::{
+    ArrayAttribute, DenseI32ArrayAttribute, DenseI64ArrayAttribute, FlatSymbolRefAttribute,
+    IntegerAttribute, StringAttribute, TypeAttribute,
+};
+use melior::ir::operation::{OperationBuilder, OperationLike, OperationRef};
+use melior::ir::r#type::IntegerType;
+use melior::ir::{
+    Attribute, Block, BlockLike, BlockRef, Identifier, Location, Operation, Type, Value, ValueLike,
+};
+use rustc_codegen_ssa::common::AtomicOrdering;
+
+/// Marker used in `rawConstantIndices` of `llvm.getelementptr` for a dynamic index.
+const GEP_DYNAMIC_INDEX: i32 = i32::MIN;
+
+/// Integer comparison predicates of `arith.cmpi`, in the order of `arith::CmpIPredicate`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum IntCC {
+    Equal = 0,
+    NotEqual = 1,
+    SignedLessThan = 2,
+    SignedLessThanOrEqual = 3,
+    SignedGreaterThan = 4,
+    SignedGreaterThanOrEqual = 5,
+    UnsignedLessThan = 6,
+    UnsignedLessThanOrEqual = 7,
+    UnsignedGreaterThan = 8,
+    UnsignedGreaterThanOrEqual = 9,
+}
+
+/// Float comparison predicates of `arith.cmpf`, in the order of `arith::CmpFPredicate`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum FloatCC {
+    Equal = 1,
+    GreaterThan = 2,
+    GreaterThanOrEqual = 3,
+    LessThan = 4,
+    LessThanOrEqual = 5,
+    Ordered = 7,
+    NotEqual = 13,
+    Unordered = 14,
+}
+
+/// Ops of `llvm.atomicrmw`, in the order of `LLVM::AtomicBinOp`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum AtomicRmwOp {
+    Xchg = 0,
+    Add = 1,
+    Sub = 2,
+    And = 3,
+    Nand = 4,
+    Or = 5,
+    Xor = 6,
+    Max = 7,
+    Min = 8,
+    UMax = 9,
+    UMin = 10,
+}
+
+/// The value of an `LLVM::AtomicOrdering` attribute.
+fn atomic_ordering(ordering: AtomicOrdering) -> i64 {
+    match ordering {
+        AtomicOrdering::Unordered => 1,
+        AtomicOrdering::Relaxed => 2,
+        AtomicOrdering::Acquire => 4,
+        AtomicOrdering::Release => 5,
+        AtomicOrdering::AcquireRelease => 6,
+        AtomicOrdering::SequentiallyConsistent => 7,
+    }
+}
+
+#[derive(Copy, Clone)]
+pub(crate) struct Builder<'a, 'ml> {
+    pub(crate) context: &'ml Context,
+    pub(crate) block: BlockRef<'ml, 'a>,
+    pub(crate) location: Location<'ml>,
+}
+
+impl<'a, 'ml> Builder<'a, 'ml> {
+    pub(crate) fn new(
+        context: &'ml Context,
+        block: BlockRef<'ml, 'a>,
+        location: Location<'ml>,
+    ) -> Self {
+        Builder { context, block, location }
+    }
+
+    pub(crate) fn at(self, block: BlockRef<'ml, 'a>) -> Self {
+        Builder { block, ..self }
+    }
+
+    pub(crate) fn append(&self, op: Operation<'ml>) -> OperationRef<'ml, 'a> {
+        self.block.append_operation(op)
+    }
+
+    pub(crate) fn append_value(&self, op: Operation<'ml>) -> Value<'ml, 'a> {
+        self.append(op).result(0).unwrap().into()
+    }
+
+    pub(crate) fn ident(&self, name: &str) -> Identifier<'ml> {
+        Identifier::new(self.context, name)
+    }
+
+    pub(crate) fn parse_attr(&self, source: &str) -> Attribute<'ml> {
+        Attribute::parse(self.context, source)
+            .unwrap_or_else(|| panic!("invalid MLIR attribute `{source}`"))
+    }
+
+    pub(crate) fn i64_attr(&self, value: i64) -> Attribute<'ml> {
+        IntegerAttribute::new(self.int_type(64), value).into()
+    }
+
+    pub(crate) fn op(&self, name: &str) -> OperationBuilder<'ml> {
+        OperationBuilder::new(name, self.location)
+    }
+
+    // Types
+
+    pub(crate) fn int_type(&self, bits: u64) -> Type<'ml> {
+        IntegerType::new(self.context, bits as u32).into()
+    }
+
+    pub(crate) fn ptr_type(&self) -> Type<'ml> {
+        melior::dialect::llvm::r#type::pointer(self.context, 0)
+    }
+
+    pub(crate) fn byte_array_type(&self, size: u64) -> Type<'ml> {
+        melior::dialect::llvm::r#type::array(self.int_type(8), size as u32)
+    }
+
+    // Constants
+
+    pub(crate) fn iconst(&self, ty: Type<'ml>, value: i128) -> Value<'ml, 'a> {
+        let value = self.parse_attr(&format!("{value} : {ty}"));
+        self.append_value(
+            self.op("arith.constant")
+                .add_attributes(&[(self.ident("value"), value)])
+                .add_results(&[ty])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// Materializes a float constant from its raw bit pattern.
+    pub(crate) fn fconst_bits(&self, ty: Type<'ml>, bits: u64, raw: u128) -> Value<'ml, 'a> {
+        let int = self.iconst(self.int_type(bits), raw as i128);
+        self.cast("arith.bitcast", int, ty)
+    }
+
+    pub(crate) fn undef(&self, ty: Type<'ml>) -> Value<'ml, 'a> {
+        self.append_value(self.op("llvm.mlir.undef").add_results(&[ty]).build().unwrap())
+    }
+
+    pub(crate) fn null_ptr(&self) -> Value<'ml, 'a> {
+        self.zero(self.ptr_type())
+    }
+
+    pub(crate) fn zero(&self, ty: Type<'ml>) -> Value<'ml, 'a> {
+        self.append_value(self.op("llvm.mlir.zero").add_results(&[ty]).build().unwrap())
+    }
+
+    // Arithmetic
+
+    pub(crate) fn binary(
+        &self,
+        name: &str,
+        lhs: Value<'ml, '_>,
+        rhs: Value<'ml, '_>,
+    ) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op(name).add_operands(&[lhs, rhs]).add_results(&[lhs.r#type()]).build().unwrap(),
+        )
+    }
+
+    /// Emits an op such as `math.fma` or `llvm.intr.fshl` taking `args` and producing a `result`.
+    pub(crate) fn intrinsic(
+        &self,
+        name: &str,
+        args: &[Value<'ml, '_>],
+        result: Type<'ml>,
+    ) -> Value<'ml, 'a> {
+        self.append_value(self.op(name).add_operands(args).add_results(&[result]).build().unwrap())
+    }
+
+    pub(crate) fn unary(&self, name: &str, val: Value<'ml, '_>) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op(name).add_operands(&[val]).add_results(&[val.r#type()]).build().unwrap(),
+        )
+    }
+
+    pub(crate) fn iadd_imm(&self, val: Value<'ml, '_>, imm: i128) -> Value<'ml, 'a> {
+        let imm = self.iconst(val.r#type(), imm);
+        self.binary("arith.addi", val, imm)
+    }
+
+    pub(crate) fn imul_imm(&self, val: Value<'ml, 'a>, imm: i128) -> Value<'ml, 'a> {
+        if imm == 1 {
+            return val;
+        }
+        let imm = self.iconst(val.r#type(), imm);
+        self.binary("arith.muli", val, imm)
+    }
+
+    pub(crate) fn icmp(
+        &self,
+        cc: IntCC,
+        lhs: Value<'ml, '_>,
+        rhs: Value<'ml, '_>,
+    ) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("arith.cmpi")
+                .add_operands(&[lhs, rhs])
+                .add_attributes(&[(self.ident("predicate"), self.i64_attr(cc as i64))])
+                .add_results(&[self.int_type(1)])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    pub(crate) fn icmp_imm(&self, cc: IntCC, lhs: Value<'ml, '_>, rhs: i128) -> Value<'ml, 'a> {
+        let rhs = self.iconst(lhs.r#type(), rhs);
+        self.icmp(cc, lhs, rhs)
+    }
+
+    pub(crate) fn fcmp(
+        &self,
+        cc: FloatCC,
+        lhs: Value<'ml, '_>,
+        rhs: Value<'ml, '_>,
+    ) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("arith.cmpf")
+                .add_operands(&[lhs, rhs])
+                .add_attributes(&[(self.ident("predicate"), self.i64_attr(cc as i64))])
+                .add_results(&[self.int_type(1)])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    pub(crate) fn select(
+        &self,
+        cond: Value<'ml, '_>,
+        then_val: Value<'ml, '_>,
+        else_val: Value<'ml, '_>,
+    ) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("arith.select")
+                .add_operands(&[cond, then_val, else_val])
+                .add_results(&[then_val.r#type()])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// Emits a single-operand conversion op such as `arith.extui` or `llvm.ptrtoint`.
+    pub(crate) fn cast(&self, name: &str, val: Value<'ml, '_>, to: Type<'ml>) -> Value<'ml, 'a> {
+        self.append_value(self.op(name).add_operands(&[val]).add_results(&[to]).build().unwrap())
+    }
+
+    /// Converts an `i1` into the `i8` representation used for `bool` values.
+    pub(crate) fn bool_to_i8(&self, val: Value<'ml, '_>) -> Value<'ml, 'a> {
+        self.cast("arith.extui", val, self.int_type(8))
+    }
+
+    /// Converts any integer into an `i1` truth value.
+    pub(crate) fn to_i1(&self, val: Value<'ml, '_>) -> Value<'ml, 'a> {
+        self.icmp_imm(IntCC::NotEqual, val, 0)
+    }
+
+    // Memory
+
+    /// Builds (but doesn't insert) an `llvm.alloca` of one `elem_ty`. `one` must be an `i64`
+    /// constant 1 that dominates the alloca.
+    pub(crate) fn alloca(
+        &self,
+        one: Value<'ml, '_>,
+        elem_ty: Type<'ml>,
+        align: u64,
+    ) -> Operation<'ml> {
+        self.op("llvm.alloca")
+            .add_operands(&[one])
+            .add_attributes(&[
+                (self.ident("elem_type"), TypeAttribute::new(elem_ty).into()),
+                (self.ident("alignment"), self.i64_attr(align as i64)),
+            ])
+            .add_results(&[self.ptr_type()])
+            .build()
+            .unwrap()
+    }
+
+    pub(crate) fn load(&self, ty: Type<'ml>, ptr: Value<'ml, '_>, align: u64) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("llvm.load")
+                .add_operands(&[ptr])
+                .add_attributes(&[(self.ident("alignment"), self.i64_attr(align as i64))])
+                .add_results(&[ty])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    pub(crate) fn store(&self, val: Value<'ml, '_>, ptr: Value<'ml, '_>, align: u64) {
+        self.append(
+            self.op("llvm.store")
+                .add_operands(&[val, ptr])
+                .add_attributes(&[(self.ident("alignment"), self.i64_attr(align as i64))])
+                .build()
+                .unwrap(),
+        );
+    }
+
+    /// Offsets `ptr` by a constant number of bytes.
+    pub(crate) fn ptr_offset_imm(&self, ptr: Value<'ml, 'a>, offset: i64) -> Value<'ml, 'a> {
+        if offset == 0 {
+            return ptr;
+        }
+        if let Ok(offset) = i32::try_from(offset) {
+            if offset != GEP_DYNAMIC_INDEX {
+                return self.gep(ptr, None, offset, self.int_type(8));
+            }
+        }
+        let offset = self.iconst(self.int_type(64), offset as i128);
+        self.ptr_offset(ptr, offset)
+    }
+
+    /// Offsets `ptr` by a dynamic number of bytes.
+    pub(crate) fn ptr_offset(&self, ptr: Value<'ml, '_>, offset: Value<'ml, '_>) -> Value<'ml, 'a> {
+        self.gep(ptr, Some(offset), GEP_DYNAMIC_INDEX, self.int_type(8))
+    }
+
+    fn gep(
+        &self,
+        ptr: Value<'ml, '_>,
+        dynamic_index: Option<Value<'ml, '_>>,
+        raw_index: i32,
+        elem_ty: Type<'ml>,
+    ) -> Value<'ml, 'a> {
+        let mut operands = vec![ptr];
+        operands.extend(dynamic_index);
+        self.append_value(
+            self.op("llvm.getelementptr")
+                .add_operands(&operands)
+                .add_attributes(&[
+                    (
+                        self.ident("rawConstantIndices"),
+                        DenseI32ArrayAttribute::new(self.context, &[raw_index]).into(),
+                    ),
+                    (self.ident("elem_type"), TypeAttribute::new(elem_ty).into()),
+                ])
+                .add_results(&[self.ptr_type()])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    pub(crate) fn load_volatile(
+        &self,
+        ty: Type<'ml>,
+        ptr: Value<'ml, '_>,
+        align: u64,
+    ) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("llvm.load")
+                .add_operands(&[ptr])
+                .add_attributes(&[
+                    (self.ident("alignment"), self.i64_attr(align as i64)),
+                    (self.ident("volatile_"), Attribute::unit(self.context)),
+                ])
+                .add_results(&[ty])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// Stores `val`, which is either volatile or non-temporal.
+    pub(crate) fn store_with_flag(
+        &self,
+        val: Value<'ml, '_>,
+        ptr: Value<'ml, '_>,
+        align: u64,
+        flag: &str,
+    ) {
+        self.append(
+            self.op("llvm.store")
+                .add_operands(&[val, ptr])
+                .add_attributes(&[
+                    (self.ident("alignment"), self.i64_attr(align as i64)),
+                    (self.ident(flag), Attribute::unit(self.context)),
+                ])
+                .build()
+                .unwrap(),
+        );
+    }
+
+    pub(crate) fn memcpy(&self, dst: Value<'ml, '_>, src: Value<'ml, '_>, len: Value<'ml, '_>) {
+        self.mem_intrinsic("llvm.intr.memcpy", dst, src, len, false);
+    }
+
+    pub(crate) fn memset(&self, dst: Value<'ml, '_>, val: Value<'ml, '_>, len: Value<'ml, '_>) {
+        self.mem_intrinsic("llvm.intr.memset", dst, val, len, false);
+    }
+
+    /// Emits `llvm.intr.memcpy`, `llvm.intr.memmove` or `llvm.intr.memset`.
+    pub(crate) fn mem_intrinsic(
+        &self,
+        name: &str,
+        dst: Value<'ml, '_>,
+        src: Value<'ml, '_>,
+        len: Value<'ml, '_>,
+        volatile: bool,
+    ) {
+        let volatile = self.parse_attr(if volatile { "true" } else { "false" });
+        self.append(
+            self.op(name)
+                .add_operands(&[dst, src, len])
+                .add_attributes(&[(self.ident("isVolatile"), volatile)])
+                .build()
+                .unwrap(),
+        );
+    }
+
+    // Atomics
+
+    pub(crate) fn atomic_load(
+        &self,
+        ty: Type<'ml>,
+        ptr: Value<'ml, '_>,
+        ordering: AtomicOrdering,
+        align: u64,
+    ) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("llvm.load")
+                .add_operands(&[ptr])
+                .add_attributes(&[
+                    (self.ident("alignment"), self.i64_attr(align as i64)),
+                    (self.ident("ordering"), self.i64_attr(atomic_ordering(ordering))),
+                ])
+                .add_results(&[ty])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    pub(crate) fn atomic_store(
+        &self,
+        val: Value<'ml, '_>,
+        ptr: Value<'ml, '_>,
+        ordering: AtomicOrdering,
+        align: u64,
+    ) {
+        self.append(
+            self.op("llvm.store")
+                .add_operands(&[val, ptr])
+                .add_attributes(&[
+                    (self.ident("alignment"), self.i64_attr(align as i64)),
+                    (self.ident("ordering"), self.i64_attr(atomic_ordering(ordering))),
+                ])
+                .build()
+                .unwrap(),
+        );
+    }
+
+    pub(crate) fn atomic_rmw(
+        &self,
+        op: AtomicRmwOp,
+        ptr: Value<'ml, '_>,
+        val: Value<'ml, '_>,
+        ordering: AtomicOrdering,
+    ) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("llvm.atomicrmw")
+                .add_operands(&[ptr, val])
+                .add_attributes(&[
+                    (self.ident("bin_op"), self.i64_attr(op as i64)),
+                    (self.ident("ordering"), self.i64_attr(atomic_ordering(ordering))),
+                ])
+                .add_results(&[val.r#type()])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// Emits an `llvm.cmpxchg`, returning the previous value and whether it was replaced.
+    pub(crate) fn atomic_cmpxchg(
+        &self,
+        ptr: Value<'ml, '_>,
+        cmp: Value<'ml, '_>,
+        new: Value<'ml, '_>,
+        success: AtomicOrdering,
+        failure: AtomicOrdering,
+        weak: bool,
+    ) -> (Value<'ml, 'a>, Value<'ml, 'a>) {
+        let bool_ty = self.int_type(1);
+        let pair_ty =
+            melior::dialect::llvm::r#type::r#struct(self.context, &[cmp.r#type(), bool_ty], false);
+        let mut attributes = vec![
+            (self.ident("success_ordering"), self.i64_attr(atomic_ordering(success))),
+            (self.ident("failure_ordering"), self.i64_attr(atomic_ordering(failure))),
+        ];
+        if weak {
+            attributes.push((self.ident("weak"), Attribute::unit(self.context)));
+        }
+        let pair = self.append_value(
+            self.op("llvm.cmpxchg")
+                .add_operands(&[ptr, cmp, new])
+                .add_attributes(&attributes)
+                .add_results(&[pair_ty])
+                .build()
+                .unwrap(),
+        );
+        (self.extract_value(pair, 0, cmp.r#type()), self.extract_value(pair, 1, bool_ty))
+    }
+
+    pub(crate) fn fence(&self, ordering: AtomicOrdering, single_thread: bool) {
+        let mut attributes =
+            vec![(self.ident("ordering"), self.i64_attr(atomic_ordering(ordering)))];
+        if single_thread {
+            attributes.push((
+                self.ident("syncscope"),
+                StringAttribute::new(self.context, "singlethread").into(),
+            ));
+        }
+        self.append(self.op("llvm.fence").add_attributes(&attributes).build().unwrap());
+    }
+
+    // Calls
+
+    pub(crate) fn call(
+        &self,
+        symbol: &str,
+        args: &[Value<'ml, '_>],
+        results: &[Type<'ml>],
+    ) -> OperationRef<'ml, 'a> {
+        self.append(
+            self.op("func.call")
+                .add_attributes(&[(
+                    self.ident("callee"),
+                    FlatSymbolRefAttribute::new(self.context, symbol).into(),
+                )])
+                .add_operands(args)
+                .add_results(results)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    pub(crate) fn call_indirect(
+        &self,
+        callee: Value<'ml, '_>,
+        args: &[Value<'ml, '_>],
+        results: &[Type<'ml>],
+    ) -> OperationRef<'ml, 'a> {
+        self.append(self.indirect_call_op(callee, args, results).build().unwrap())
+    }
+
+    /// Like [`Self::call_indirect`], but the call is marked `musttail`: the callee reuses the
+    /// stack frame of the caller, so the call must be followed by a return of its result.
+    pub(crate) fn musttail_call(
+        &self,
+        callee: Value<'ml, '_>,
+        args: &[Value<'ml, '_>],
+        results: &[Type<'ml>],
+    ) -> OperationRef<'ml, 'a> {
+        self.append(
+            self.indirect_call_op(callee, args, results)
+                .add_attributes(&[(
+                    self.ident("TailCallKind"),
+                    self.parse_attr("#llvm.tailcallkind<musttail>"),
+                )])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    fn indirect_call_op(
+        &self,
+        callee: Value<'ml, '_>,
+        args: &[Value<'ml, '_>],
+        results: &[Type<'ml>],
+    ) -> OperationBuilder<'ml> {
+        let mut operands = vec![callee];
+        operands.extend_from_slice(args);
+        self.op("llvm.call")
+            .add_operands(&operands)
+            .add_attributes(&[
+                (
+                    self.ident("operandSegmentSizes"),
+                    DenseI32ArrayAttribute::new(self.context, &[operands.len() as i32, 0]).into(),
+                ),
+                (
+                    self.ident("op_bundle_sizes"),
+                    DenseI32ArrayAttribute::new(self.context, &[]).into(),
+                ),
+                (self.ident("op_bundle_tags"), ArrayAttribute::new(self.context, &[]).into()),
+            ])
+            .add_results(results)
+    }
+
+    /// Takes the address of a `func.func`, producing an `!llvm.ptr`.
+    pub(crate) fn func_addr(&self, symbol: &str, fn_ty: Type<'ml>) -> Value<'ml, 'a> {
+        let func = self.append_value(
+            self.op("func.constant")
+                .add_attributes(&[(
+                    self.ident("value"),
+                    FlatSymbolRefAttribute::new(self.context, symbol).into(),
+                )])
+                .add_results(&[fn_ty])
+                .build()
+                .unwrap(),
+        );
+        self.cast("builtin.unrealized_conversion_cast", func, self.ptr_type())
+    }
+
+    /// Takes the address of an `llvm.mlir.global`.
+    pub(crate) fn global_addr(&self, symbol: &str) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("llvm.mlir.addressof")
+                .add_attributes(&[(
+                    self.ident("global_name"),
+                    FlatSymbolRefAttribute::new(self.context, symbol).into(),
+                )])
+                .add_results(&[self.ptr_type()])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// Resolves the address of a thread local global for the current thread.
+    pub(crate) fn threadlocal_addr(&self, global: Value<'ml, '_>) -> Value<'ml, 'a> {
+        self.cast("llvm.intr.threadlocal.address", global, self.ptr_type())
+    }
+
+    // Aggregates
+
+    pub(crate) fn insert_value(
+        &self,
+        container: Value<'ml, '_>,
+        value: Value<'ml, '_>,
+        index: usize,
+    ) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("llvm.insertvalue")
+                .add_operands(&[container, value])
+                .add_attributes(&[(
+                    self.ident("position"),
+                    DenseI64ArrayAttribute::new(self.context, &[index as i64]).into(),
+                )])
+                .add_results(&[container.r#type()])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    pub(crate) fn extract_value(
+        &self,
+        container: Value<'ml, '_>,
+        index: usize,
+        ty: Type<'ml>,
+    ) -> Value<'ml, 'a> {
+        self.append_value(
+            self.op("llvm.extractvalue")
+                .add_operands(&[container])
+                .add_attributes(&[(
+                    self.ident("position"),
+                    DenseI64ArrayAttribute::new(self.context, &[index as i64]).into(),
+                )])
+                .add_results(&[ty])
+                .build()
+                .unwrap(),
+        )
+    }
+
+    // Terminators
+
+    pub(crate) fn br(&self, dest: &Block<'ml>, args: &[Value<'ml, '_>]) {
+        self.append(melior::dialect::cf::br(dest, args, self.location));
+    }
+
+    pub(crate) fn cond_br(
+        &self,
+        cond: Value<'ml, '_>,
+        then_block: &Block<'ml>,
+        else_block: &Block<'ml>,
+    ) {
+        self.append(melior::dialect::cf::cond_br(
+            self.context,
+            cond,
+            then_block,
+            else_block,
+            &[],
+            &[],
+            self.location,
+        ));
+    }
+
+    /// Emits a `cf.switch` on an integer of any width.
+    pub(crate) fn switch(
+        &self,
+        flag: Value<'ml, '_>,
+        otherwise: &Block<'ml>,
+        cases: &[(u128, &Block<'ml>)],
+    ) {
+        if cases.is_empty() {
+            return self.br(otherwise, &[]);
+        }
+
+        let flag_ty = flag.r#type();
+        let values = cases
+            .iter()
+            .map(|&(value, _)| (value as i128).to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let case_values =
+            self.parse_attr(&format!("dense<[{values}]> : vector<{}x{flag_ty}>", cases.len()));
+
+        let mut successors = vec![otherwise];
+        successors.extend(cases.iter().map(|&(_, block)| block));
+
+        self.append(
+            self.op("cf.switch")
+                .add_operands(&[flag])
+                .add_successors(&successors)
+                .add_attributes(&[
+                    (self.ident("case_values"), case_values),
+                    (
+                        self.ident("case_operand_segments"),
+                        DenseI32ArrayAttribute::new(self.context, &vec![0; cases.len()]).into(),
+                    ),
+                    (
+                        self.ident("operandSegmentSizes"),
+                        DenseI32ArrayAttribute::new(self.context, &[1, 0, 0]).into(),
+                    ),
+                ])
+                .build()
+                .unwrap(),
+        );
+    }
+
+    /// Calls the function pointer `callee`, continuing in `normal` or, if it unwinds, in the
+    /// landing pad `unwind`.
+    pub(crate) fn invoke(
+        &self,
+        callee: Value<'ml, '_>,
+        args: &[Value<'ml, '_>],
+        result: Option<Type<'ml>>,
+        normal: &Block<'ml>,
+        unwind: &Block<'ml>,
+    ) -> OperationRef<'ml, 'a> {
+        let mut operands = vec![callee];
+        operands.extend_from_slice(args);
+        self.append(
+            self.op("llvm.invoke")
+                .add_operands(&operands)
+                .add_successors(&[normal, unwind])
+                .add_attributes(&[
+                    (
+                        self.ident("operandSegmentSizes"),
+                        DenseI32ArrayAttribute::new(
+                            self.context,
+                            &[operands.len() as i32, 0, 0, 0],
+                        )
+                        .into(),
+                    ),
+                    (
+                        self.ident("op_bundle_sizes"),
+                        DenseI32ArrayAttribute::new(self.context, &[]).into(),
+                    ),
+                    (self.ident("op_bundle_tags"), ArrayAttribute::new(self.context, &[]).into()),
+                ])
+                .add_results(result.as_slice())
+                .build()
+                .unwrap(),
+        )
+    }
+
+    /// Emits an `llvm.landingpad` with the given catch or filter `clauses`, which must be the
+    /// first op of its block.
+    pub(crate) fn landing_pad(
+        &self,
+        ty: Type<'ml>,
+        clauses: &[Value<'ml, '_>],
+        cleanup: bool,
+    ) -> Value<'ml, 'a> {
+        let mut op = self.op("llvm.landingpad").add_operands(clauses).add_results(&[ty]);
+        if cleanup {
+            op = op.add_attributes(&[(self.ident("cleanup"), Attribute::unit(self.context))]);
+        }
+        self.append_value(op.build().unwrap())
+    }
+
+    pub(crate) fn resume(&self, exception: Value<'ml, '_>) {
+        self.append(self.op("llvm.resume").add_operands(&[exception]).build().unwrap());
+    }
+
+    pub(crate) fn ret(&self, vals: &[Value<'ml, '_>]) {
+        self.append(melior::dialect::func::r#return(vals, self.location));
+    }
+
+    pub(crate) fn unreachable(&self) {
+        self.append(self.op("llvm.unreachable").build().unwrap());
+    }
+
+    pub(crate) fn trap(&self) {
+        self.append(self.op("llvm.intr.trap").build().unwrap());
+        self.unreachable();
+    }
+
+    pub(crate) fn debug_trap(&self) {
+        self.append(self.op("llvm.intr.debugtrap").build().unwrap());
+    }
+}
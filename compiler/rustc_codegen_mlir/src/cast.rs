@@ -0,0 +1,114 @@
+//! Various number casting functions
+
+use melior::ir::r#type::TypeLike;
+use melior::ir::{Type, Value, ValueLike};
+
+use crate::common::FunctionCx;
+use crate::num::int_bits;
+
+pub(crate) fn intcast<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    val: Value<'ml, 'a>,
+    to: Type<'ml>,
+    signed: bool,
+) -> Value<'ml, 'a> {
+    let from = val.r#type();
+    if from == to {
+        return val;
+    }
+
+    let from_bits = int_bits(val);
+    let to_bits = melior::ir::r#type::IntegerType::try_from(to).unwrap().width();
+    if to_bits > from_bits {
+        if signed { fx.bx.cast("arith.extsi", val, to) } else { fx.bx.cast("arith.extui", val, to) }
+    } else {
+        fx.bx.cast("arith.trunci", val, to)
+    }
+}
+
+fn float_width(context: &melior::Context, ty: Type<'_>) -> u32 {
+    if ty == Type::float16(context) {
+        16
+    } else if ty == Type::float32(context) {
+        32
+    } else if ty == Type::float64(context) {
+        64
+    } else {
+        128
+    }
+}
+
+pub(crate) fn int_or_float_cast<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    from: Value<'ml, 'a>,
+    from_signed: bool,
+    to_ty: Type<'ml>,
+    to_signed: bool,
+) -> Value<'ml, 'a> {
+    let from_ty = from.r#type();
+
+    if from_ty.is_llvm_pointer_type() || to_ty.is_llvm_pointer_type() {
+        return match (from_ty.is_llvm_pointer_type(), to_ty.is_llvm_pointer_type()) {
+            // ptr -> ptr
+            (true, true) => from,
+            // ptr -> int
+            (true, false) => {
+                let addr = fx.bx.cast("llvm.ptrtoint", from, fx.usize_type());
+                intcast(fx, addr, to_ty, false)
+            }
+            // int -> ptr
+            (false, true) => {
+                let addr = intcast(fx, from, fx.usize_type(), false);
+                fx.bx.cast("llvm.inttoptr", addr, to_ty)
+            }
+            (false, false) => unreachable!(),
+        };
+    }
+
+    if from_ty.is_integer() && to_ty.is_integer() {
+        // int-like -> int-like
+        intcast(
+            fx,
+            from,
+            to_ty,
+            // This is correct as either from_signed == to_signed (=> this is trivially correct)
+            // Or from_ty == to_ty, which means this is a no-op.
+            from_signed,
+        )
+    } else if from_ty.is_integer() && to_ty.is_float() {
+        // int-like -> float
+        if from_signed {
+            fx.bx.cast("arith.sitofp", from, to_ty)
+        } else {
+            fx.bx.cast("arith.uitofp", from, to_ty)
+        }
+    } else if from_ty.is_float() && to_ty.is_integer() {
+        // Rust float to int casts saturate and map NaN to zero, which is exactly what the LLVM
+        // saturating conversion intrinsics do.
+        if let Some(false) = fx.tcx.sess.opts.unstable_opts.saturating_float_casts {
+            return if to_signed {
+                fx.bx.cast("arith.fptosi", from, to_ty)
+            } else {
+                fx.bx.cast("arith.fptoui", from, to_ty)
+            };
+        }
+        if to_signed {
+            fx.bx.cast("llvm.intr.fptosi.sat", from, to_ty)
+        } else {
+            fx.bx.cast("llvm.intr.fptoui.sat", from, to_ty)
+        }
+    } else if from_ty.is_float() && to_ty.is_float() {
+        // float -> float
+        let from_width = float_width(fx.context, from_ty);
+        let to_width = float_width(fx.context, to_ty);
+        if from_width < to_width {
+            fx.bx.cast("arith.extf", from, to_ty)
+        } else if from_width > to_width {
+            fx.bx.cast("arith.truncf", from, to_ty)
+        } else {
+            from
+        }
+    } else {
+        unreachable!("cast value from {} to {}", from_ty, to_ty);
+    }
+}
@@ -0,0 +1,177 @@
+use melior::Context;
+use melior::ir::operation::OperationLike;
+use melior::ir::{Attribute, Block, BlockLike, BlockRef, Region, RegionLike, Type, Value};
+use rustc_abi::{HasDataLayout, TargetDataLayout};
+use rustc_data_structures::fx::FxHashMap;
+use rustc_index::IndexVec;
+use rustc_middle::mir::{BasicBlock, Body, Local, SourceInfo, UnwindTerminateReason};
+use rustc_middle::ty::layout::{
+    FnAbiError, FnAbiOfHelpers, FnAbiRequest, HasParamEnv, HasTyCtxt, LayoutError, LayoutOfHelpers,
+};
+use rustc_middle::ty::{self, Instance, ParamEnv, Ty, TyCtxt, TypeFoldable};
+use rustc_span::Span;
+use rustc_target::abi::call::FnAbi;
+use rustc_target::spec::{HasTargetSpec, Target};
+
+use crate::builder::Builder;
+use crate::context::CodegenCx;
+use crate::type_of::{pointer_ty, usize_ty};
+use crate::value_and_place::{MPlace, MValue};
+
+pub(crate) struct FunctionCx<'a, 'ml, 'tcx> {
+    pub(crate) cx: &'a CodegenCx<'ml, 'tcx>,
+    pub(crate) tcx: TyCtxt<'tcx>,
+    pub(crate) context: &'ml Context,
+
+    pub(crate) instance: Instance<'tcx>,
+    pub(crate) symbol_name: String,
+    pub(crate) mir: &'tcx Body<'tcx>,
+    pub(crate) fn_abi: &'tcx FnAbi<'tcx, Ty<'tcx>>,
+
+    /// The function body. Owned by the caller so that it can be moved into the `func.func` once
+    /// we are done with it.
+    pub(crate) region: &'a Region<'ml>,
+    /// The entry block, which holds the function arguments and all stack slots and then jumps to
+    /// the block for `START_BLOCK`.
+    pub(crate) entry_block: BlockRef<'ml, 'a>,
+    pub(crate) block_map: IndexVec<BasicBlock, BlockRef<'ml, 'a>>,
+    pub(crate) local_map: IndexVec<Local, MPlace<'ml, 'a, 'tcx>>,
+
+    /// The `&'static Location` passed to a `#[track_caller]` function by its caller.
+    pub(crate) caller_location: Option<MValue<'ml, 'a, 'tcx>>,
+
+    /// Landing pads of the cleanup blocks unwound to so far, see [`crate::unwind`].
+    pub(crate) landing_pads: FxHashMap<BasicBlock, BlockRef<'ml, 'a>>,
+    /// Landing pads for calls that must not unwind, by the reason they must not.
+    pub(crate) terminate_blocks: FxHashMap<UnwindTerminateReason, BlockRef<'ml, 'a>>,
+    /// The stack slot the exception being unwound is kept in between landing pads and resumes.
+    pub(crate) personality_slot: Option<Value<'ml, 'a>>,
+    /// Whether the function catches unwinds through `catch_unwind`.
+    pub(crate) catches_unwind: bool,
+
+    /// The `#llvm.di_subprogram` of the function, if debug info is enabled.
+    pub(crate) subprogram: Option<Attribute<'ml>>,
+
+    /// Builder positioned at the end of the block currently being filled.
+    pub(crate) bx: Builder<'a, 'ml>,
+}
+
+impl<'tcx> LayoutOfHelpers<'tcx> for FunctionCx<'_, '_, 'tcx> {
+    #[inline]
+    fn handle_layout_err(&self, err: LayoutError<'tcx>, span: Span, ty: Ty<'tcx>) -> ! {
+        self.cx.handle_layout_err(err, span, ty)
+    }
+}
+
+impl<'tcx> FnAbiOfHelpers<'tcx> for FunctionCx<'_, '_, 'tcx> {
+    #[inline]
+    fn handle_fn_abi_err(
+        &self,
+        err: FnAbiError<'tcx>,
+        span: Span,
+        fn_abi_request: FnAbiRequest<'tcx>,
+    ) -> ! {
+        self.cx.handle_fn_abi_err(err, span, fn_abi_request)
+    }
+}
+
+impl<'tcx> HasTyCtxt<'tcx> for FunctionCx<'_, '_, 'tcx> {
+    fn tcx(&self) -> TyCtxt<'tcx> {
+        self.tcx
+    }
+}
+
+impl HasDataLayout for FunctionCx<'_, '_, '_> {
+    fn data_layout(&self) -> &TargetDataLayout {
+        &self.tcx.data_layout
+    }
+}
+
+impl<'tcx> HasParamEnv<'tcx> for FunctionCx<'_, '_, 'tcx> {
+    fn param_env(&self) -> ParamEnv<'tcx> {
+        ParamEnv::reveal_all()
+    }
+}
+
+impl HasTargetSpec for FunctionCx<'_, '_, '_> {
+    fn target_spec(&self) -> &Target {
+        &self.tcx.sess.target
+    }
+}
+
+impl<'a, 'ml, 'tcx> FunctionCx<'a, 'ml, 'tcx> {
+    pub(crate) fn monomorphize<T>(&self, value: T) -> T
+    where
+        T: TypeFoldable<TyCtxt<'tcx>> + Copy,
+    {
+        self.instance.instantiate_mir_and_normalize_erasing_regions(
+            self.tcx,
+            ty::ParamEnv::reveal_all(),
+            ty::EarlyBinder::bind(value),
+        )
+    }
+
+    pub(crate) fn pointer_type(&self) -> Type<'ml> {
+        pointer_ty(self.context)
+    }
+
+    pub(crate) fn usize_type(&self) -> Type<'ml> {
+        usize_ty(self.tcx, self.context)
+    }
+
+    pub(crate) fn get_block(&self, bb: BasicBlock) -> BlockRef<'ml, 'a> {
+        self.block_map[bb]
+    }
+
+    pub(crate) fn get_local_place(&self, local: Local) -> MPlace<'ml, 'a, 'tcx> {
+        *self.local_map.get(local).unwrap_or_else(|| {
+            panic!("Local {:?} doesn't exist", local);
+        })
+    }
+
+    /// The `&'static Location` a `#[track_caller]` callee or the `caller_location` intrinsic at
+    /// `source_info` receives.
+    pub(crate) fn get_caller_location(&mut self, source_info: SourceInfo) -> MValue<'ml, 'a, 'tcx> {
+        self.mir.caller_location_span(source_info, self.caller_location, self.tcx, |span| {
+            let const_loc = self.tcx.span_as_caller_location(span);
+            crate::constant::codegen_const_value(self, const_loc, self.tcx.caller_location_ty())
+        })
+    }
+
+    /// Creates a new, empty block at the end of the function body.
+    pub(crate) fn create_block(&self) -> BlockRef<'ml, 'a> {
+        self.region.append_block(Block::new(&[]))
+    }
+
+    pub(crate) fn switch_to_block(&mut self, block: BlockRef<'ml, 'a>) {
+        self.bx = self.bx.at(block);
+    }
+
+    /// Allocates `size` bytes of stack memory at the start of the entry block.
+    pub(crate) fn create_stack_slot(&mut self, size: u64, align: u64) -> Value<'ml, 'a> {
+        let ty = self.bx.byte_array_type(size);
+        self.create_typed_stack_slot(ty, align)
+    }
+
+    /// Materializes a zero of type `ty` at the start of the entry block. Landing pads use this for
+    /// their clauses, as they have to be the first op of their block.
+    pub(crate) fn create_entry_zero(&mut self, ty: Type<'ml>) -> Value<'ml, 'a> {
+        let zero = self.bx.op("llvm.mlir.zero").add_results(&[ty]).build().unwrap();
+        self.entry_block.insert_operation(0, zero).result(0).unwrap().into()
+    }
+
+    /// Allocates stack memory for a value of type `ty` at the start of the entry block.
+    pub(crate) fn create_typed_stack_slot(&mut self, ty: Type<'ml>, align: u64) -> Value<'ml, 'a> {
+        let bx = self.bx;
+        let one = bx
+            .op("arith.constant")
+            .add_attributes(&[(bx.ident("value"), bx.parse_attr("1 : i64"))])
+            .add_results(&[bx.int_type(64)])
+            .build()
+            .unwrap();
+        let one = self.entry_block.insert_operation(0, one);
+        let one: Value<'ml, 'a> = one.result(0).unwrap().into();
+        let alloca = self.entry_block.insert_operation(1, bx.alloca(one, ty, align));
+        alloca.result(0).unwrap().into()
+    }
+}
@@ -0,0 +1,347 @@
+//! Handling of `const`s, statics and promoted allocations
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use melior::ir::{Block, Region, RegionLike, Value, ValueLike};
+use rustc_abi::{Abi, Align, Primitive};
+use rustc_hir::def_id::DefId;
+use rustc_middle::bug;
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
+use rustc_middle::mir::interpret::{
+    AllocId, Allocation, ConstAllocation, GlobalAlloc, InitChunk, Scalar, read_target_uint,
+};
+use rustc_middle::mir::mono::{Linkage, Visibility};
+use rustc_middle::mir::{ConstOperand, ConstValue};
+use rustc_middle::ty::layout::LayoutOf;
+use rustc_middle::ty::{self, Instance, ScalarInt, Ty};
+
+use crate::builder::Builder;
+use crate::common::FunctionCx;
+use crate::context::{CodegenCx, GlobalAttrs};
+use crate::type_of::{mlir_type, scalar_to_mlir_type};
+use crate::value_and_place::{MPlace, MValue};
+
+pub(crate) fn eval_mir_constant<'tcx>(
+    fx: &FunctionCx<'_, '_, 'tcx>,
+    constant: &ConstOperand<'tcx>,
+) -> (ConstValue<'tcx>, Ty<'tcx>) {
+    let cv = fx.monomorphize(constant.const_);
+    // This cannot fail because we checked all required_consts in advance.
+    let val = cv
+        .eval(fx.tcx, ty::ParamEnv::reveal_all(), constant.span)
+        .expect("erroneous constant missed by mono item collection");
+    (val, cv.ty())
+}
+
+pub(crate) fn codegen_constant_operand<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    constant: &ConstOperand<'tcx>,
+) -> MValue<'ml, 'a, 'tcx> {
+    let (const_val, ty) = eval_mir_constant(fx, constant);
+    codegen_const_value(fx, const_val, ty)
+}
+
+/// Materializes a `ScalarInt` with the given layout.
+fn codegen_scalar_int<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    layout: ty::layout::TyAndLayout<'tcx>,
+    int: ScalarInt,
+) -> MValue<'ml, 'a, 'tcx> {
+    let raw_val = int.size().truncate(int.to_bits(int.size()));
+    match layout.abi {
+        Abi::Scalar(scalar) => {
+            let ty = scalar_to_mlir_type(fx.tcx, fx.context, scalar);
+            let val = match scalar.primitive() {
+                Primitive::Int(..) => fx.bx.iconst(ty, raw_val as i128),
+                Primitive::Float(float) => fx.bx.fconst_bits(ty, float.size().bits(), raw_val),
+                Primitive::Pointer(_) => {
+                    let addr = fx.bx.iconst(fx.usize_type(), raw_val as i128);
+                    fx.bx.cast("llvm.inttoptr", addr, ty)
+                }
+            };
+            MValue::by_val(val, layout)
+        }
+        _ => {
+            // FIXME avoid this extra copy to the stack and directly write to the final
+            // destination
+            let int_ty = fx.bx.int_type(int.size().bits());
+            let val = fx.bx.iconst(int_ty, raw_val as i128);
+            let place = MPlace::new_stack_slot(fx, layout);
+            fx.bx.store(val, place.to_ptr(), layout.align.abi.bytes());
+            place.to_mvalue(fx)
+        }
+    }
+}
+
+pub(crate) fn codegen_const_value<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    const_val: ConstValue<'tcx>,
+    ty: Ty<'tcx>,
+) -> MValue<'ml, 'a, 'tcx> {
+    let layout = fx.layout_of(ty);
+    assert!(layout.is_sized(), "unsized const value");
+
+    if layout.is_zst() {
+        return MValue::zst(layout);
+    }
+
+    match const_val {
+        ConstValue::ZeroSized => unreachable!(), // we already handled ZST above
+        ConstValue::Scalar(Scalar::Int(int)) => codegen_scalar_int(fx, layout, int),
+        ConstValue::Scalar(Scalar::Ptr(ptr, _size)) => {
+            let (prov, offset) = ptr.into_parts(); // we know the `offset` is relative
+            let alloc_id = prov.alloc_id();
+            let base_addr = match fx.tcx.global_alloc(alloc_id) {
+                GlobalAlloc::Function { instance, .. } => {
+                    crate::base::codegen_fn_addr(fx, instance.polymorphize(fx.tcx))
+                }
+                GlobalAlloc::Memory(_) | GlobalAlloc::VTable(..) | GlobalAlloc::Static(_) => {
+                    let symbol = alloc_symbol(fx.cx, alloc_id);
+                    fx.bx.global_addr(&symbol)
+                }
+            };
+            let val = fx.bx.ptr_offset_imm(base_addr, offset.bytes() as i64);
+            let val = match layout.abi {
+                Abi::Scalar(scalar) if !matches!(scalar.primitive(), Primitive::Pointer(_)) => {
+                    let ty = scalar_to_mlir_type(fx.tcx, fx.context, scalar);
+                    fx.bx.cast("llvm.ptrtoint", val, ty)
+                }
+                _ => val,
+            };
+            MValue::by_val(val, layout)
+        }
+        ConstValue::Indirect { alloc_id, offset } => {
+            let symbol = alloc_symbol(fx.cx, alloc_id);
+            let base_addr = fx.bx.global_addr(&symbol);
+            MValue::by_ref(fx.bx.ptr_offset_imm(base_addr, offset.bytes() as i64), layout)
+        }
+        ConstValue::Slice { data, meta } => {
+            let alloc_id = fx.tcx.reserve_and_set_memory_alloc(data);
+            let symbol = alloc_symbol(fx.cx, alloc_id);
+            let ptr = fx.bx.global_addr(&symbol);
+            let len = fx.bx.iconst(fx.usize_type(), i128::from(meta));
+            MValue::by_val_pair(ptr, len, layout)
+        }
+    }
+}
+
+/// Codegens a `&'static str` constant with the contents `s`.
+pub(crate) fn codegen_const_str<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    s: &str,
+) -> MValue<'ml, 'a, 'tcx> {
+    let alloc = Allocation::from_bytes_byte_aligned_immutable(s.as_bytes());
+    let const_val = ConstValue::Slice { data: fx.tcx.mk_const_alloc(alloc), meta: s.len() as u64 };
+    codegen_const_value(fx, const_val, Ty::new_static_str(fx.tcx))
+}
+
+/// Returns the symbol of the global backing `alloc_id`, emitting it into the module first if it
+/// is an anonymous allocation that hasn't been used in this codegen unit yet.
+pub(crate) fn alloc_symbol<'ml, 'tcx>(cx: &CodegenCx<'ml, 'tcx>, alloc_id: AllocId) -> String {
+    let tcx = cx.tcx;
+    match tcx.global_alloc(alloc_id) {
+        GlobalAlloc::Memory(alloc) => {
+            if let Some(symbol) = cx.anon_allocs.borrow().get(&alloc_id) {
+                return symbol.clone();
+            }
+            let symbol = format!("alloc.{}", cx.anon_allocs.borrow().len());
+            // Allocations may point to themselves through statics, so the symbol has to be
+            // reserved before lowering the provenance.
+            cx.anon_allocs.borrow_mut().insert(alloc_id, symbol.clone());
+            let attrs = GlobalAttrs {
+                linkage: Linkage::Private,
+                visibility: Visibility::Default,
+                constant: alloc.inner().mutability.is_not(),
+                thread_local: false,
+                align: global_align(cx, alloc.inner().align),
+                section: None,
+            };
+            define_alloc(cx, &symbol, alloc, attrs);
+            symbol
+        }
+        GlobalAlloc::VTable(ty, dyn_ty) => crate::vtable::vtable_symbol(cx, ty, dyn_ty.principal()),
+        GlobalAlloc::Static(def_id) => static_symbol(cx, def_id),
+        GlobalAlloc::Function { .. } => bug!("function {alloc_id:?} is not backed by a global"),
+    }
+}
+
+/// Returns the symbol of the static `def_id`, which must not be thread local, declaring it in
+/// the module if needed.
+pub(crate) fn static_symbol<'ml, 'tcx>(cx: &CodegenCx<'ml, 'tcx>, def_id: DefId) -> String {
+    let tcx = cx.tcx;
+    assert!(tcx.is_static(def_id));
+    assert!(!tcx.is_thread_local_static(def_id));
+    let instance = Instance::mono(tcx, def_id);
+    let symbol = tcx.symbol_name(instance).name;
+    let layout = cx.layout_of(instance.ty(tcx, ty::ParamEnv::reveal_all()));
+    cx.reference_global(symbol, mlir_type(cx, layout), false);
+    symbol.to_owned()
+}
+
+/// Defines the static `def_id` with the allocation produced by its initializer, and the linkage
+/// and visibility chosen by the partitioner.
+pub(crate) fn codegen_static<'ml, 'tcx>(
+    cx: &CodegenCx<'ml, 'tcx>,
+    def_id: DefId,
+    linkage: Linkage,
+    visibility: Visibility,
+) {
+    let tcx = cx.tcx;
+    let Ok(alloc) = tcx.eval_static_initializer(def_id) else {
+        // Error has already been reported
+        return;
+    };
+    let attrs = tcx.codegen_fn_attrs(def_id);
+
+    if tcx.sess.target.is_like_wasm && attrs.link_section.is_some() {
+        tcx.dcx().span_fatal(
+            tcx.def_span(def_id),
+            "custom wasm sections are not yet supported by the MLIR backend",
+        );
+    }
+
+    let symbol = tcx.symbol_name(Instance::mono(tcx, def_id)).name;
+    let section = attrs.link_section.map(|section| section.as_str().to_owned());
+    let global_attrs = GlobalAttrs {
+        linkage,
+        visibility,
+        // The const interner picks the mutability of the allocation, taking interior mutability
+        // into account.
+        constant: alloc.inner().mutability.is_not(),
+        thread_local: attrs.flags.contains(CodegenFnAttrFlags::THREAD_LOCAL),
+        align: global_align(cx, alloc.inner().align),
+        section: section.as_deref(),
+    };
+    define_alloc(cx, symbol, alloc, global_attrs);
+
+    if attrs.flags.contains(CodegenFnAttrFlags::USED) {
+        // `USED` and `USED_LINKER` can't be used together.
+        assert!(!attrs.flags.contains(CodegenFnAttrFlags::USED_LINKER));
+        // See `rustc_codegen_llvm::consts` for why `#[used]` maps to `llvm.compiler.used`.
+        cx.add_compiler_used_global(symbol);
+    }
+    if attrs.flags.contains(CodegenFnAttrFlags::USED_LINKER) {
+        assert!(!attrs.flags.contains(CodegenFnAttrFlags::USED));
+        cx.add_used_global(symbol);
+    }
+}
+
+/// The alignment of a global holding an allocation aligned to `align`, which the target may
+/// require to be larger.
+fn global_align(cx: &CodegenCx<'_, '_>, align: Align) -> u64 {
+    let min = cx.tcx.sess.target.min_global_align.unwrap_or(1);
+    align.bytes().max(min / 8)
+}
+
+/// Defines `symbol` as a global initialized with the contents of `alloc`.
+///
+/// The initial value is a packed `!llvm.struct` of byte arrays for the plain data and pointers for
+/// the provenance, like the constants `rustc_codegen_llvm::consts::const_alloc_to_llvm` builds.
+fn define_alloc<'ml, 'tcx>(
+    cx: &CodegenCx<'ml, 'tcx>,
+    symbol: &str,
+    alloc: ConstAllocation<'tcx>,
+    attrs: GlobalAttrs<'_>,
+) {
+    let tcx = cx.tcx;
+    let alloc = alloc.inner();
+    let pointer_size = tcx.data_layout.pointer_size.bytes() as usize;
+
+    let initializer = Region::new();
+    let block = initializer.append_block(Block::new(&[]));
+    let bx = Builder::new(cx.context, block, cx.unknown_loc());
+
+    let mut fields = Vec::with_capacity(alloc.provenance().ptrs().len() * 2 + 1);
+    let mut next_offset = 0;
+    for &(offset, prov) in alloc.provenance().ptrs().iter() {
+        let offset = offset.bytes() as usize;
+        if offset > next_offset {
+            // This `inspect` is okay since we have checked that there is no provenance, it is
+            // within the bounds of the allocation, and it doesn't affect interpreter execution.
+            append_data_chunks(cx, &bx, &mut fields, alloc, next_offset..offset);
+        }
+        let addend = read_target_uint(
+            tcx.data_layout.endian,
+            // This `inspect` is okay since it is within the bounds of the allocation, and we
+            // properly interpret the provenance as a relocation pointer offset.
+            alloc.inspect_with_uninit_and_ptr_outside_interpreter(offset..(offset + pointer_size)),
+        )
+        .expect("define_alloc: could not read relocation pointer");
+
+        let alloc_id = prov.alloc_id();
+        let base_addr = match tcx.global_alloc(alloc_id) {
+            GlobalAlloc::Function { instance, .. } => {
+                crate::vtable::fn_addr(cx, &bx, instance.polymorphize(tcx))
+            }
+            GlobalAlloc::Memory(_) | GlobalAlloc::VTable(..) | GlobalAlloc::Static(_) => {
+                bx.global_addr(&alloc_symbol(cx, alloc_id))
+            }
+        };
+        fields.push(bx.ptr_offset_imm(base_addr, addend as i64));
+        next_offset = offset + pointer_size;
+    }
+    if alloc.len() > next_offset {
+        append_data_chunks(cx, &bx, &mut fields, alloc, next_offset..alloc.len());
+    }
+
+    let field_tys = fields.iter().map(|field| field.r#type()).collect::<Vec<_>>();
+    let ty = melior::dialect::llvm::r#type::r#struct(cx.context, &field_tys, true);
+    let mut value = bx.undef(ty);
+    for (idx, field) in fields.into_iter().enumerate() {
+        value = bx.insert_value(value, field, idx);
+    }
+    bx.append(bx.op("llvm.return").add_operands(&[value]).build().unwrap());
+
+    cx.define_global(symbol, ty, attrs, initializer);
+}
+
+/// Appends the bytes of `range`, which must not overlap any provenance, to `fields`.
+///
+/// Uninitialized bytes are left undefined, unless that would split the range into too many
+/// chunks, see `-Zuninit-const-chunk-threshold`.
+fn append_data_chunks<'a, 'ml>(
+    cx: &CodegenCx<'ml, '_>,
+    bx: &Builder<'a, 'ml>,
+    fields: &mut Vec<Value<'ml, 'a>>,
+    alloc: &Allocation,
+    range: Range<usize>,
+) {
+    let chunks = alloc.init_mask().range_as_init_chunks(range.clone().into());
+    let max = cx.tcx.sess.opts.unstable_opts.uninit_const_chunk_threshold;
+    if chunks.clone().take(max.saturating_add(1)).count() > max {
+        let bytes = alloc.inspect_with_uninit_and_ptr_outside_interpreter(range);
+        fields.push(const_bytes(bx, bytes));
+        return;
+    }
+
+    for chunk in chunks {
+        let range = chunk.range();
+        let range = (range.start.bytes() as usize)..(range.end.bytes() as usize);
+        match chunk {
+            InitChunk::Init(_) => {
+                let bytes = alloc.inspect_with_uninit_and_ptr_outside_interpreter(range);
+                fields.push(const_bytes(bx, bytes));
+            }
+            InitChunk::Uninit(_) => {
+                fields.push(bx.undef(bx.byte_array_type(range.len() as u64)));
+            }
+        }
+    }
+}
+
+/// Materializes `bytes` as an `!llvm.array` of `i8`.
+fn const_bytes<'a, 'ml>(bx: &Builder<'a, 'ml>, bytes: &[u8]) -> Value<'ml, 'a> {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(hex, "{byte:02X}").unwrap();
+    }
+    let value = bx.parse_attr(&format!("dense<\"0x{hex}\"> : tensor<{}xi8>", bytes.len()));
+    bx.append_value(
+        bx.op("llvm.mlir.constant")
+            .add_attributes(&[(bx.ident("value"), value)])
+            .add_results(&[bx.byte_array_type(bytes.len() as u64)])
+            .build()
+            .unwrap(),
+    )
+}
@@ -0,0 +1,381 @@
+//! Per-codegen-unit state shared by all functions lowered into the same MLIR module.
+
+use std::cell::RefCell;
+
+use melior::Context;
+use melior::ir::attribute::{StringAttribute, TypeAttribute};
+use melior::ir::{
+    Attribute, Block, BlockLike, BlockRef, Identifier, Location, Module, Region, Type,
+};
+use rustc_abi::{HasDataLayout, TargetDataLayout, VariantIdx};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet, FxIndexMap};
+use rustc_middle::mir::interpret::AllocId;
+use rustc_middle::mir::mono::{CodegenUnit, Linkage, Visibility};
+use rustc_middle::span_bug;
+use rustc_middle::ty::layout::{
+    FnAbiError, FnAbiOfHelpers, FnAbiRequest, HasParamEnv, HasTyCtxt, LayoutError, LayoutOfHelpers,
+};
+use rustc_middle::ty::{self, ParamEnv, Ty, TyCtxt};
+use rustc_span::Span;
+use rustc_span::source_map::Spanned;
+use rustc_target::spec::{HasTargetSpec, Target};
+
+use crate::builder::Builder;
+use crate::debuginfo::DebugContext;
+use crate::dialect::MirTables;
+
+pub(crate) struct CodegenCx<'ml, 'tcx> {
+    pub(crate) tcx: TyCtxt<'tcx>,
+    pub(crate) context: &'ml Context,
+    pub(crate) module: &'ml Module<'ml>,
+    /// The `rust` dialect form of this codegen unit, which `module` is lowered from.
+    pub(crate) rust_module: &'ml Module<'ml>,
+    /// Rustc data referenced from the ops in `rust_module`.
+    pub(crate) mir_tables: MirTables<'tcx>,
+    pub(crate) codegen_unit: &'tcx CodegenUnit<'tcx>,
+    pub(crate) debug_context: Option<DebugContext<'ml, 'tcx>>,
+
+    /// Cache of [`crate::type_of::mlir_type`] for non-scalar layouts.
+    pub(crate) type_lowering: RefCell<FxHashMap<(Ty<'tcx>, Option<VariantIdx>), Type<'ml>>>,
+    /// Symbols of the vtables emitted into this module, keyed like `tcx.vtable_allocation`.
+    pub(crate) vtables:
+        RefCell<FxHashMap<(Ty<'tcx>, Option<ty::PolyExistentialTraitRef<'tcx>>), String>>,
+    /// Module level assembly from `global_asm!`s, see [`crate::asm::codegen_global_asm`].
+    pub(crate) global_asm: RefCell<String>,
+    /// Symbols of the anonymous allocations emitted into this module by
+    /// [`crate::constant::alloc_symbol`].
+    pub(crate) anon_allocs: RefCell<FxHashMap<AllocId, String>>,
+
+    /// Functions that have a body in this module.
+    defined_fns: RefCell<FxHashSet<String>>,
+    /// Functions that are referenced from this module, together with their `FunctionType`. Those
+    /// that aren't also defined get a private declaration in [`CodegenCx::finalize`].
+    referenced_fns: RefCell<FxIndexMap<String, Type<'ml>>>,
+    /// Globals that have an initializer in this module.
+    defined_globals: RefCell<FxHashSet<String>>,
+    /// Globals that are referenced from this module, together with their type and whether they
+    /// are thread local. Those that aren't also defined get an external declaration in
+    /// [`CodegenCx::finalize`].
+    referenced_globals: RefCell<FxIndexMap<String, (Type<'ml>, bool)>>,
+    /// Globals marked `#[used(linker)]`, which end up in `llvm.used`.
+    used_globals: RefCell<Vec<String>>,
+    /// Globals marked `#[used]`, which end up in `llvm.compiler.used`.
+    compiler_used_globals: RefCell<Vec<String>>,
+}
+
+/// The properties of an `llvm.mlir.global` defined by [`CodegenCx::define_global`].
+pub(crate) struct GlobalAttrs<'s> {
+    pub(crate) linkage: Linkage,
+    pub(crate) visibility: Visibility,
+    pub(crate) constant: bool,
+    pub(crate) thread_local: bool,
+    pub(crate) align: u64,
+    pub(crate) section: Option<&'s str>,
+}
+
+impl<'ml, 'tcx> CodegenCx<'ml, 'tcx> {
+    pub(crate) fn new(
+        tcx: TyCtxt<'tcx>,
+        codegen_unit: &'tcx CodegenUnit<'tcx>,
+        context: &'ml Context,
+        module: &'ml Module<'ml>,
+        rust_module: &'ml Module<'ml>,
+    ) -> Self {
+        CodegenCx {
+            tcx,
+            context,
+            module,
+            rust_module,
+            mir_tables: MirTables::default(),
+            codegen_unit,
+            debug_context: DebugContext::new(tcx, context),
+            type_lowering: RefCell::default(),
+            vtables: RefCell::default(),
+            global_asm: RefCell::default(),
+            anon_allocs: RefCell::default(),
+            defined_fns: RefCell::default(),
+            referenced_fns: RefCell::default(),
+            defined_globals: RefCell::default(),
+            referenced_globals: RefCell::default(),
+            used_globals: RefCell::default(),
+            compiler_used_globals: RefCell::default(),
+        }
+    }
+
+    pub(crate) fn unknown_loc(&self) -> Location<'ml> {
+        Location::unknown(self.context)
+    }
+
+    pub(crate) fn module_body(&self) -> BlockRef<'ml, 'ml> {
+        self.module.body()
+    }
+
+    /// A builder appending to the module body, for building globals.
+    fn module_builder(&self) -> Builder<'ml, 'ml> {
+        Builder::new(self.context, self.module_body(), self.unknown_loc())
+    }
+
+    /// Records that `symbol` is called or has its address taken in this module.
+    pub(crate) fn reference_fn(&self, symbol: &str, fn_ty: Type<'ml>) {
+        let mut referenced_fns = self.referenced_fns.borrow_mut();
+        if let Some(&prev_ty) = referenced_fns.get(symbol) {
+            if prev_ty != fn_ty {
+                self.tcx.dcx().fatal(format!(
+                    "function `{symbol}` referenced with conflicting signatures {prev_ty} and \
+                     {fn_ty}"
+                ));
+            }
+            return;
+        }
+        referenced_fns.insert(symbol.to_owned(), fn_ty);
+    }
+
+    /// Appends a `func.func` with the given body and extra attributes to the module.
+    pub(crate) fn define_fn(
+        &self,
+        symbol: &str,
+        fn_ty: Type<'ml>,
+        body: Region<'ml>,
+        attributes: &[(Identifier<'ml>, Attribute<'ml>)],
+        location: Location<'ml>,
+    ) {
+        if !self.defined_fns.borrow_mut().insert(symbol.to_owned()) {
+            span_bug!(
+                rustc_span::DUMMY_SP,
+                "function `{symbol}` defined twice in cgu {}",
+                self.codegen_unit.name()
+            );
+        }
+        self.reference_fn(symbol, fn_ty);
+        self.module_body().append_operation(melior::dialect::func::func(
+            self.context,
+            StringAttribute::new(self.context, symbol),
+            TypeAttribute::new(fn_ty),
+            body,
+            attributes,
+            location,
+        ));
+    }
+
+    /// Whether a `func.func` with a body named `symbol` has been defined in this module.
+    pub(crate) fn is_fn_defined(&self, symbol: &str) -> bool {
+        self.defined_fns.borrow().contains(symbol)
+    }
+
+    /// Records that the address of the global `symbol` is taken in this module. `ty` is only used
+    /// to declare the global if it isn't defined in this module, so unlike for functions the first
+    /// type a global is referenced with wins.
+    pub(crate) fn reference_global(&self, symbol: &str, ty: Type<'ml>, thread_local: bool) {
+        let mut referenced_globals = self.referenced_globals.borrow_mut();
+        if let Some(&(_, prev_thread_local)) = referenced_globals.get(symbol) {
+            if prev_thread_local != thread_local {
+                span_bug!(
+                    rustc_span::DUMMY_SP,
+                    "global `{symbol}` referenced both as thread local and as regular global"
+                );
+            }
+            return;
+        }
+        referenced_globals.insert(symbol.to_owned(), (ty, thread_local));
+    }
+
+    /// Appends an `llvm.mlir.global` to the module. `initializer` must end in an `llvm.return`
+    /// of the initial value, which has type `ty`.
+    pub(crate) fn define_global(
+        &self,
+        symbol: &str,
+        ty: Type<'ml>,
+        attrs: GlobalAttrs<'_>,
+        initializer: Region<'ml>,
+    ) {
+        if !self.defined_globals.borrow_mut().insert(symbol.to_owned()) {
+            span_bug!(
+                rustc_span::DUMMY_SP,
+                "global `{symbol}` defined twice in cgu {}",
+                self.codegen_unit.name()
+            );
+        }
+        let bx = self.module_builder();
+        let mut attributes = vec![
+            (bx.ident("sym_name"), StringAttribute::new(self.context, symbol).into()),
+            (bx.ident("global_type"), TypeAttribute::new(ty).into()),
+            (bx.ident("alignment"), bx.i64_attr(attrs.align as i64)),
+        ];
+        attributes.extend(crate::linkage::symbol_attrs(
+            self.tcx,
+            self.context,
+            "linkage",
+            attrs.linkage,
+            attrs.visibility,
+            attrs.thread_local,
+        ));
+        if attrs.constant {
+            attributes.push((bx.ident("constant"), Attribute::unit(self.context)));
+        }
+        if attrs.thread_local {
+            attributes.push((bx.ident("thread_local_"), Attribute::unit(self.context)));
+        }
+        if let Some(section) = attrs.section {
+            attributes
+                .push((bx.ident("section"), StringAttribute::new(self.context, section).into()));
+        }
+        bx.append(
+            bx.op("llvm.mlir.global")
+                .add_attributes(&attributes)
+                .add_regions([initializer])
+                .build()
+                .unwrap(),
+        );
+    }
+
+    /// Adds the global `symbol` to `llvm.used`, keeping it alive until after linking.
+    pub(crate) fn add_used_global(&self, symbol: &str) {
+        self.used_globals.borrow_mut().push(symbol.to_owned());
+    }
+
+    /// Adds the global `symbol` to `llvm.compiler.used`, which allows the linker to strip it.
+    pub(crate) fn add_compiler_used_global(&self, symbol: &str) {
+        self.compiler_used_globals.borrow_mut().push(symbol.to_owned());
+    }
+
+    /// Defines one of the `llvm.used` lists as an appending array of pointers to `symbols`.
+    fn define_used_list(&self, name: &str, symbols: &[String]) {
+        if symbols.is_empty() {
+            return;
+        }
+        let ptr_ty = crate::type_of::pointer_ty(self.context);
+        let array_ty = melior::dialect::llvm::r#type::array(ptr_ty, symbols.len() as u32);
+
+        let initializer = Region::new();
+        let block = initializer.append_block(Block::new(&[]));
+        let bx = Builder::new(self.context, block, self.unknown_loc());
+        let mut array = bx.undef(array_ty);
+        for (idx, symbol) in symbols.iter().enumerate() {
+            let addr = bx.global_addr(symbol);
+            array = bx.insert_value(array, addr, idx);
+        }
+        bx.append(bx.op("llvm.return").add_operands(&[array]).build().unwrap());
+
+        let attrs = GlobalAttrs {
+            linkage: Linkage::Appending,
+            visibility: Visibility::Default,
+            constant: false,
+            thread_local: false,
+            align: self.tcx.data_layout.pointer_align.abi.bytes(),
+            section: Some("llvm.metadata"),
+        };
+        self.define_global(name, array_ty, attrs, initializer);
+    }
+
+    /// Declares all functions and globals that were referenced but not defined in this module,
+    /// and emits the `llvm.used` lists.
+    pub(crate) fn finalize(&self) {
+        self.define_used_list("llvm.used", &self.used_globals.borrow());
+        self.define_used_list("llvm.compiler.used", &self.compiler_used_globals.borrow());
+
+        let defined_fns = self.defined_fns.borrow();
+        for (symbol, &fn_ty) in self.referenced_fns.borrow().iter() {
+            if defined_fns.contains(symbol) {
+                continue;
+            }
+            self.module_body().append_operation(melior::dialect::func::func(
+                self.context,
+                StringAttribute::new(self.context, symbol),
+                TypeAttribute::new(fn_ty),
+                Region::new(),
+                &[(
+                    Identifier::new(self.context, "sym_visibility"),
+                    StringAttribute::new(self.context, "private").into(),
+                )],
+                self.unknown_loc(),
+            ));
+        }
+
+        let defined_globals = self.defined_globals.borrow();
+        let bx = self.module_builder();
+        for (symbol, &(ty, thread_local)) in self.referenced_globals.borrow().iter() {
+            if defined_globals.contains(symbol) {
+                continue;
+            }
+            let mut attributes = vec![
+                (bx.ident("sym_name"), StringAttribute::new(self.context, symbol).into()),
+                (bx.ident("global_type"), TypeAttribute::new(ty).into()),
+                (bx.ident("linkage"), bx.parse_attr("#llvm.linkage<external>")),
+            ];
+            if thread_local {
+                attributes.push((bx.ident("thread_local_"), Attribute::unit(self.context)));
+            }
+            bx.append(
+                bx.op("llvm.mlir.global")
+                    .add_attributes(&attributes)
+                    .add_regions([Region::new()])
+                    .build()
+                    .unwrap(),
+            );
+        }
+    }
+}
+
+impl<'tcx> LayoutOfHelpers<'tcx> for CodegenCx<'_, 'tcx> {
+    #[inline]
+    fn handle_layout_err(&self, err: LayoutError<'tcx>, span: Span, ty: Ty<'tcx>) -> ! {
+        if let LayoutError::SizeOverflow(_) | LayoutError::ReferencesError(_) = err {
+            self.tcx.sess.dcx().span_fatal(span, err.to_string())
+        } else {
+            self.tcx
+                .sess
+                .dcx()
+                .span_fatal(span, format!("failed to get layout for `{}`: {}", ty, err))
+        }
+    }
+}
+
+impl<'tcx> FnAbiOfHelpers<'tcx> for CodegenCx<'_, 'tcx> {
+    #[inline]
+    fn handle_fn_abi_err(
+        &self,
+        err: FnAbiError<'tcx>,
+        span: Span,
+        fn_abi_request: FnAbiRequest<'tcx>,
+    ) -> ! {
+        if let FnAbiError::Layout(LayoutError::SizeOverflow(_)) = err {
+            self.tcx.sess.dcx().emit_fatal(Spanned { span, node: err })
+        } else {
+            match fn_abi_request {
+                FnAbiRequest::OfFnPtr { sig, extra_args } => {
+                    span_bug!(span, "`fn_abi_of_fn_ptr({sig}, {extra_args:?})` failed: {err:?}");
+                }
+                FnAbiRequest::OfInstance { instance, extra_args } => {
+                    span_bug!(
+                        span,
+                        "`fn_abi_of_instance({instance}, {extra_args:?})` failed: {err:?}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl<'tcx> HasTyCtxt<'tcx> for CodegenCx<'_, 'tcx> {
+    fn tcx(&self) -> TyCtxt<'tcx> {
+        self.tcx
+    }
+}
+
+impl HasDataLayout for CodegenCx<'_, '_> {
+    fn data_layout(&self) -> &TargetDataLayout {
+        &self.tcx.data_layout
+    }
+}
+
+impl<'tcx> HasParamEnv<'tcx> for CodegenCx<'_, 'tcx> {
+    fn param_env(&self) -> ParamEnv<'tcx> {
+        ParamEnv::reveal_all()
+    }
+}
+
+impl HasTargetSpec for CodegenCx<'_, '_> {
+    fn target_spec(&self) -> &Target {
+        &self.tcx.sess.target
+    }
+}
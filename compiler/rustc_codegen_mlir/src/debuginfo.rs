@@ -0,0 +1,481 @@
+//! Source locations and DWARF debug info.
+//!
+//! Every op gets a `FileLineColLoc` for the span of the MIR statement or terminator it was lowered
+//! from. With `-Cdebuginfo` enabled, the location of each function is additionally fused with an
+//! `#llvm.di_subprogram`, which is what makes the translation to LLVM IR turn the locations of its
+//! ops into `DILocation`s, and user variables are described by `llvm.intr.dbg.declare`s of their
+//! stack slots.
+//!
+//! The compile unit, subprograms and local variables refer to distinct attributes, which can't be
+//! shared between separately parsed attributes, so they are built through the MLIR C API. All
+//! other debug info attributes are built from their textual form.
+//!
+//! See `rustc_codegen_cranelift/src/debuginfo` for reference.
+
+use std::cell::RefCell;
+use std::ffi::c_uint;
+
+use melior::Context;
+use melior::ir::attribute::StringAttribute;
+use melior::ir::{Attribute, Location};
+use rustc_abi::{Abi, FieldsShape, Variants};
+use rustc_codegen_ssa::debuginfo::type_names;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::sync::Lrc;
+use rustc_middle::mir::{Body, ProjectionElem, SourceInfo, VarDebugInfoContents};
+use rustc_middle::ty::layout::{LayoutOf, TyAndLayout};
+use rustc_middle::ty::{self, Instance, Ty, TyCtxt};
+use rustc_session::config::{DebugInfo, OptLevel, RemapPathScopeComponents};
+use rustc_span::{
+    FileNameDisplayPreference, SourceFile, SourceFileAndLine, Span, StableSourceFileId, hygiene,
+};
+use rustc_target::abi::call::FnAbi;
+
+use crate::builder::Builder;
+use crate::common::FunctionCx;
+use crate::context::CodegenCx;
+use crate::ffi::{
+    EmissionKind, MlirAttribute, NameTableKind, from_raw_attr, mlirDisctinctAttrCreate,
+    mlirLLVMDICompileUnitAttrGet, mlirLLVMDILocalVariableAttrGet, mlirLLVMDISubprogramAttrGet,
+    raw_attr, raw_context,
+};
+
+const DW_LANG_RUST: c_uint = 0x1c;
+
+/// `DISubprogramFlags::Definition`.
+const SUBPROGRAM_DEFINITION: u64 = 1 << 3;
+/// `DISubprogramFlags::Optimized`.
+const SUBPROGRAM_OPTIMIZED: u64 = 1 << 4;
+
+/// Quotes `s` as an MLIR string literal.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Looks up the file, line and column of `span`, with lines and columns starting at 1.
+fn span_file_line_col(
+    tcx: TyCtxt<'_>,
+    function_span: Span,
+    span: Span,
+) -> (Lrc<SourceFile>, u32, u32) {
+    // Match behavior of `FunctionCx::adjusted_span_and_dbg_scope`.
+    let span = hygiene::walk_chain_collapsed(span, function_span);
+    match tcx.sess.source_map().lookup_line(span.lo()) {
+        Ok(SourceFileAndLine { sf: file, line }) => {
+            let line_pos = file.lines()[line];
+            let col = file.relative_position(span.lo()) - line_pos;
+            (file, line as u32 + 1, col.to_u32() + 1)
+        }
+        Err(file) => (file, 0, 0),
+    }
+}
+
+/// Per-codegen-unit debug info state, only present with `-Cdebuginfo` enabled.
+pub(crate) struct DebugContext<'ml, 'tcx> {
+    compile_unit: Attribute<'ml>,
+    /// Whether to describe variables and types, rather than only emitting line tables.
+    full: bool,
+    /// Textual `#llvm.di_file`s of the source files referenced so far.
+    files: RefCell<FxHashMap<StableSourceFileId, String>>,
+    /// Textual debug info types of the types referenced so far.
+    types: RefCell<FxHashMap<Ty<'tcx>, String>>,
+    /// Types whose debug info type is being built, used to break cycles through pointers.
+    types_in_progress: RefCell<FxHashSet<Ty<'tcx>>>,
+}
+
+impl<'ml, 'tcx> DebugContext<'ml, 'tcx> {
+    pub(crate) fn new(tcx: TyCtxt<'tcx>, context: &'ml Context) -> Option<Self> {
+        let emission_kind = match tcx.sess.opts.debuginfo {
+            DebugInfo::None => return None,
+            DebugInfo::LineDirectivesOnly => EmissionKind::DebugDirectivesOnly,
+            DebugInfo::LineTablesOnly => EmissionKind::LineTablesOnly,
+            DebugInfo::Limited | DebugInfo::Full => EmissionKind::Full,
+        };
+
+        let preference = filename_display_preference(tcx);
+        let directory = tcx.sess.opts.working_dir.to_string_lossy(preference).into_owned();
+        let name = match tcx.sess.local_crate_source_file() {
+            Some(path) => path.to_string_lossy(preference).into_owned(),
+            None => tcx.crate_name(rustc_hir::def_id::LOCAL_CRATE).to_string(),
+        };
+        let file = Attribute::parse(
+            context,
+            &format!("#llvm.di_file<{} in {}>", quote(&name), quote(&directory)),
+        )
+        .unwrap();
+        let producer = StringAttribute::new(
+            context,
+            &format!("rustc version {} with MLIR", tcx.sess.cfg_version),
+        );
+
+        let compile_unit = unsafe {
+            let id = mlirDisctinctAttrCreate(raw_attr(Attribute::unit(context)));
+            mlirLLVMDICompileUnitAttrGet(
+                raw_context(context),
+                id,
+                DW_LANG_RUST,
+                raw_attr(file),
+                raw_attr(producer.into()),
+                tcx.sess.opts.optimize != OptLevel::No,
+                emission_kind,
+                NameTableKind::Default,
+            )
+        };
+
+        Some(DebugContext {
+            compile_unit: from_raw_attr(context, compile_unit),
+            full: tcx.sess.opts.debuginfo == DebugInfo::Full,
+            files: RefCell::default(),
+            types: RefCell::default(),
+            types_in_progress: RefCell::default(),
+        })
+    }
+}
+
+fn filename_display_preference(tcx: TyCtxt<'_>) -> FileNameDisplayPreference {
+    tcx.sess.filename_display_preference(RemapPathScopeComponents::DEBUGINFO)
+}
+
+impl<'ml, 'tcx> CodegenCx<'ml, 'tcx> {
+    /// The location of `span` in a function spanning `function_span`.
+    pub(crate) fn span_location(&self, function_span: Span, span: Span) -> Location<'ml> {
+        let (file, line, col) = span_file_line_col(self.tcx, function_span, span);
+        let name = file.name.display(filename_display_preference(self.tcx)).to_string();
+        Location::new(self.context, &name, line as usize, col as usize)
+    }
+
+    /// The textual `#llvm.di_file` for `file`.
+    fn di_file(&self, debug_context: &DebugContext<'ml, 'tcx>, file: &SourceFile) -> String {
+        debug_context
+            .files
+            .borrow_mut()
+            .entry(file.stable_id)
+            .or_insert_with(|| {
+                let path = file.name.display(filename_display_preference(self.tcx)).to_string();
+                let (directory, name) = match path.rsplit_once('/') {
+                    Some((directory, name)) => (directory, name),
+                    None => ("", &*path),
+                };
+                format!("#llvm.di_file<{} in {}>", quote(name), quote(directory))
+            })
+            .clone()
+    }
+
+    /// Creates the `#llvm.di_subprogram` of `instance`, if debug info is enabled.
+    pub(crate) fn define_subprogram(
+        &self,
+        instance: Instance<'tcx>,
+        mir: &Body<'tcx>,
+        fn_abi: &FnAbi<'tcx, Ty<'tcx>>,
+        symbol_name: &str,
+    ) -> Option<Attribute<'ml>> {
+        let debug_context = self.debug_context.as_ref()?;
+        let tcx = self.tcx;
+
+        let (file, line, _) = span_file_line_col(tcx, mir.span, mir.span);
+        let file = Attribute::parse(self.context, &self.di_file(debug_context, &file)).unwrap();
+
+        let mut name = String::new();
+        type_names::push_item_name(tcx, instance.def_id(), false, &mut name);
+        type_names::push_generic_params(
+            tcx,
+            tcx.normalize_erasing_regions(ty::ParamEnv::reveal_all(), instance.args),
+            &mut name,
+        );
+
+        let subroutine_type = if debug_context.full {
+            let ret = if fn_abi.ret.layout.is_zst() {
+                "#llvm.di_null_type".to_owned()
+            } else {
+                self.di_type(debug_context, fn_abi.ret.layout.ty)
+            };
+            let types = std::iter::once(ret)
+                .chain(fn_abi.args.iter().map(|arg| self.di_type(debug_context, arg.layout.ty)))
+                .collect::<Vec<_>>();
+            format!("#llvm.di_subroutine_type<types = {}>", types.join(", "))
+        } else {
+            "#llvm.di_subroutine_type<>".to_owned()
+        };
+        let subroutine_type = Attribute::parse(self.context, &subroutine_type).unwrap();
+
+        let mut flags = SUBPROGRAM_DEFINITION;
+        if tcx.sess.opts.optimize != OptLevel::No {
+            flags |= SUBPROGRAM_OPTIMIZED;
+        }
+
+        let subprogram = unsafe {
+            let id = mlirDisctinctAttrCreate(raw_attr(Attribute::unit(self.context)));
+            mlirLLVMDISubprogramAttrGet(
+                raw_context(self.context),
+                MlirAttribute { ptr: std::ptr::null() },
+                false,
+                id,
+                raw_attr(debug_context.compile_unit),
+                // FIXME: nest functions in `DINamespace`s for their parent modules and impls.
+                raw_attr(file),
+                raw_attr(StringAttribute::new(self.context, &name).into()),
+                raw_attr(StringAttribute::new(self.context, symbol_name).into()),
+                raw_attr(file),
+                line,
+                line,
+                flags,
+                raw_attr(subroutine_type),
+                0,
+                std::ptr::null(),
+                0,
+                std::ptr::null(),
+            )
+        };
+        Some(from_raw_attr(self.context, subprogram))
+    }
+
+    /// The location of the `func.func` for `mir`, which carries its subprogram if it has one.
+    pub(crate) fn fn_location(
+        &self,
+        mir: &Body<'tcx>,
+        subprogram: Option<Attribute<'ml>>,
+    ) -> Location<'ml> {
+        let location = self.span_location(mir.span, mir.span);
+        match subprogram {
+            Some(subprogram) => Location::fused(self.context, &[location], subprogram),
+            None => location,
+        }
+    }
+
+    /// The textual debug info type of `ty`.
+    ///
+    /// Only the layout is described for enums and unions, and generators and closures only get
+    /// their captured fields.
+    fn di_type(&self, debug_context: &DebugContext<'ml, 'tcx>, ty: Ty<'tcx>) -> String {
+        if let Some(di_type) = debug_context.types.borrow().get(&ty) {
+            return di_type.clone();
+        }
+
+        let tcx = self.tcx;
+        let layout = self.layout_of(ty);
+        let name = quote(&type_names::compute_debuginfo_type_name(tcx, ty, false));
+        let size = layout.size.bits();
+        let align = layout.align.abi.bits();
+
+        if !debug_context.types_in_progress.borrow_mut().insert(ty) {
+            // A recursive type, describe it as an opaque struct where it refers to itself.
+            return format!(
+                "#llvm.di_composite_type<tag = DW_TAG_structure_type, name = {name}, \
+                 sizeInBits = {size}, alignInBits = {align}>"
+            );
+        }
+
+        let basic = |encoding: &str| {
+            format!(
+                "#llvm.di_basic_type<tag = DW_TAG_base_type, name = {name}, sizeInBits = {size}, \
+                 encoding = {encoding}>"
+            )
+        };
+        let di_type = match *ty.kind() {
+            ty::Bool => basic("DW_ATE_boolean"),
+            ty::Char => basic("DW_ATE_UTF"),
+            ty::Int(_) => basic("DW_ATE_signed"),
+            ty::Uint(_) => basic("DW_ATE_unsigned"),
+            ty::Float(_) => basic("DW_ATE_float"),
+            ty::RawPtr(pointee, _) | ty::Ref(_, pointee, _)
+                if matches!(layout.abi, Abi::Scalar(_)) =>
+            {
+                self.di_pointer_type(debug_context, &name, pointee, size, align)
+            }
+            ty::Adt(def, args) if def.is_box() && matches!(layout.abi, Abi::Scalar(_)) => {
+                self.di_pointer_type(debug_context, &name, args.type_at(0), size, align)
+            }
+            ty::Array(elem, _) => {
+                let FieldsShape::Array { count, .. } = layout.fields else {
+                    unreachable!("array {ty} without an array layout")
+                };
+                format!(
+                    "#llvm.di_composite_type<tag = DW_TAG_array_type, name = {name}, \
+                     baseType = {}, sizeInBits = {size}, alignInBits = {align}, \
+                     elements = #llvm.di_subrange<count = {count} : i64>>",
+                    self.di_type(debug_context, elem)
+                )
+            }
+            _ => self.di_struct_type(debug_context, &name, layout),
+        };
+
+        debug_context.types_in_progress.borrow_mut().remove(&ty);
+        debug_context.types.borrow_mut().insert(ty, di_type.clone());
+        di_type
+    }
+
+    fn di_pointer_type(
+        &self,
+        debug_context: &DebugContext<'ml, 'tcx>,
+        name: &str,
+        pointee: Ty<'tcx>,
+        size: u64,
+        align: u64,
+    ) -> String {
+        format!(
+            "#llvm.di_derived_type<tag = DW_TAG_pointer_type, name = {name}, baseType = {}, \
+             sizeInBits = {size}, alignInBits = {align}>",
+            self.di_type(debug_context, pointee)
+        )
+    }
+
+    /// Describes `layout` as a struct of its fields, or as an opaque struct of the right size if
+    /// it has no named fields.
+    fn di_struct_type(
+        &self,
+        debug_context: &DebugContext<'ml, 'tcx>,
+        name: &str,
+        layout: TyAndLayout<'tcx>,
+    ) -> String {
+        let size = layout.size.bits();
+        let align = layout.align.abi.bits();
+        let field_names: Vec<String> = match *layout.ty.kind() {
+            // Fat pointers, made up of the data pointer and the metadata.
+            ty::RawPtr(..) | ty::Ref(..) if layout.fields.count() == 2 => {
+                let meta_name =
+                    if layout.field(self, 1).ty.is_any_ptr() { "vtable" } else { "length" };
+                vec!["data_ptr".to_owned(), meta_name.to_owned()]
+            }
+            ty::Adt(def, _) if def.is_struct() => {
+                def.non_enum_variant().fields.iter().map(|field| field.name.to_string()).collect()
+            }
+            ty::Tuple(_) | ty::Closure(..) | ty::Coroutine(..) | ty::CoroutineClosure(..)
+                if matches!(layout.variants, Variants::Single { .. }) =>
+            {
+                (0..layout.fields.count()).map(|i| format!("__{i}")).collect()
+            }
+            _ => vec![],
+        };
+
+        let mut elements = vec![];
+        if field_names.len() == layout.fields.count() {
+            for (i, field_name) in field_names.iter().enumerate() {
+                let field = layout.field(self, i);
+                elements.push(format!(
+                    "#llvm.di_derived_type<tag = DW_TAG_member, name = {}, baseType = {}, \
+                     sizeInBits = {}, alignInBits = {}, offsetInBits = {}>",
+                    quote(field_name),
+                    self.di_type(debug_context, field.ty),
+                    field.size.bits(),
+                    field.align.abi.bits(),
+                    layout.fields.offset(i).bits(),
+                ));
+            }
+        }
+        let elements = if elements.is_empty() {
+            String::new()
+        } else {
+            format!(", elements = {}", elements.join(", "))
+        };
+        format!(
+            "#llvm.di_composite_type<tag = DW_TAG_structure_type, name = {name}, \
+             sizeInBits = {size}, alignInBits = {align}{elements}>"
+        )
+    }
+}
+
+impl<'a, 'ml, 'tcx> FunctionCx<'a, 'ml, 'tcx> {
+    /// Makes the ops built from now on have the location of `source_info`.
+    pub(crate) fn set_debug_loc(&mut self, source_info: SourceInfo) {
+        self.bx.location = self.debug_location(source_info);
+    }
+
+    fn debug_location(&self, source_info: SourceInfo) -> Location<'ml> {
+        // Code inlined by the MIR inliner is attributed to its outermost call site, as it would
+        // otherwise be reported as part of the wrong subprogram.
+        let mut span = source_info.span;
+        let mut scope = Some(source_info.scope);
+        while let Some(current) = scope {
+            let scope_data = &self.mir.source_scopes[current];
+            if let Some((_, call_span)) = scope_data.inlined {
+                span = call_span;
+            }
+            scope = scope_data.inlined_parent_scope;
+        }
+        self.cx.span_location(self.mir.span, span)
+    }
+}
+
+/// Emits an `llvm.intr.dbg.declare` for every user variable that lives in a local or a part of
+/// one.
+///
+/// FIXME: variables that are constants or are split over multiple locals are not described yet.
+pub(crate) fn declare_vars(fx: &mut FunctionCx<'_, '_, '_>) {
+    let Some(debug_context) = fx.cx.debug_context.as_ref() else { return };
+    let Some(subprogram) = fx.subprogram else { return };
+    if !debug_context.full {
+        return;
+    }
+    let tcx = fx.tcx;
+
+    for var in &fx.mir.var_debug_info {
+        let VarDebugInfoContents::Place(place) = var.value else { continue };
+        if var.composite.is_some() {
+            continue;
+        }
+
+        let local_place = fx.get_local_place(place.local);
+        let mut layout = local_place.layout();
+        if layout.is_unsized() {
+            continue;
+        }
+        let mut expression = vec![];
+        let mut supported = true;
+        for elem in place.projection {
+            match elem {
+                ProjectionElem::Deref => {
+                    layout = fx.layout_of(layout.ty.builtin_deref(true).unwrap());
+                    expression.push("DW_OP_deref".to_owned());
+                }
+                ProjectionElem::Field(field, _) => {
+                    let offset = layout.fields.offset(field.as_usize()).bytes();
+                    if offset != 0 {
+                        expression.push(format!("DW_OP_plus_uconst({offset})"));
+                    }
+                    layout = layout.field(fx, field.as_usize());
+                }
+                ProjectionElem::Downcast(_, variant) => layout = layout.for_variant(fx, variant),
+                _ => supported = false,
+            }
+            if !supported || layout.is_unsized() {
+                break;
+            }
+        }
+        if !supported || layout.is_unsized() {
+            continue;
+        }
+
+        let (file, line, _) = span_file_line_col(tcx, fx.mir.span, var.source_info.span);
+        let file = Attribute::parse(fx.context, &fx.cx.di_file(debug_context, &file)).unwrap();
+        let di_type =
+            Attribute::parse(fx.context, &fx.cx.di_type(debug_context, layout.ty)).unwrap();
+        let variable = unsafe {
+            mlirLLVMDILocalVariableAttrGet(
+                raw_context(fx.context),
+                raw_attr(subprogram),
+                raw_attr(StringAttribute::new(fx.context, var.name.as_str()).into()),
+                raw_attr(file),
+                line,
+                var.argument_index.map_or(0, c_uint::from),
+                layout.align.abi.bits() as c_uint,
+                raw_attr(di_type),
+                0,
+            )
+        };
+        let variable = from_raw_attr(fx.context, variable);
+
+        let bx = Builder { location: fx.debug_location(var.source_info), ..fx.bx };
+        let mut attributes = vec![(bx.ident("varInfo"), variable)];
+        if !expression.is_empty() {
+            let expression = format!("#llvm.di_expression<[{}]>", expression.join(", "));
+            attributes.push((bx.ident("locationExpr"), bx.parse_attr(&expression)));
+        }
+        bx.append(
+            bx.op("llvm.intr.dbg.declare")
+                .add_operands(&[local_place.to_ptr()])
+                .add_attributes(&attributes)
+                .build()
+                .unwrap(),
+        );
+    }
+}
@@ -0,0 +1,650 @@
+//! Emission of MIR bodies as `rust.fn` ops.
+//!
+//! This is a direct translation: no layouts are computed and nothing is monomorphized, that is
+//! left to [`super::lower`].
+
+use melior::ir::operation::{OperationBuilder, OperationLike, OperationRef};
+use melior::ir::{
+    Attribute, Block, BlockLike, BlockRef, Identifier, Region, RegionLike, Type, Value,
+};
+use rustc_index::IndexVec;
+use rustc_middle::bug;
+use rustc_middle::mir::{
+    self, AssertKind, BasicBlock, InlineAsmOperand, NonDivergingIntrinsic, Operand, Place,
+    PlaceElem, Rvalue, SourceInfo, Statement, StatementKind, Terminator, TerminatorKind,
+    UnwindAction, traversal,
+};
+use rustc_middle::ty::{Instance, TyCtxt};
+
+use super::{
+    InlineAsmData, MirData, int_attr, operand_type, place_type, rvalue_type, str_attr,
+    terminate_reason_str,
+};
+use crate::builder::Builder;
+use crate::context::CodegenCx;
+
+struct Emitter<'a, 'ml, 'tcx> {
+    cx: &'a CodegenCx<'ml, 'tcx>,
+    tcx: TyCtxt<'tcx>,
+    blocks: IndexVec<BasicBlock, BlockRef<'ml, 'a>>,
+    bx: Builder<'a, 'ml>,
+}
+
+/// Appends a `rust.fn` for `instance` to the `rust` module of `cx`.
+pub(crate) fn emit_fn<'ml, 'tcx>(cx: &CodegenCx<'ml, 'tcx>, instance: Instance<'tcx>) {
+    let tcx = cx.tcx;
+    let mir = tcx.instance_mir(instance.def);
+    let symbol_name = tcx.symbol_name(instance).name;
+
+    let region = Region::new();
+    {
+        let blocks: IndexVec<BasicBlock, _> =
+            mir.basic_blocks.indices().map(|_| region.append_block(Block::new(&[]))).collect();
+        for (bb, &block) in blocks.iter_enumerated() {
+            cx.mir_tables.push_block(block, bb);
+        }
+        let mut emitter = Emitter {
+            cx,
+            tcx,
+            bx: Builder::new(cx.context, blocks[mir::START_BLOCK], cx.unknown_loc()),
+            blocks,
+        };
+
+        let reachable_blocks = traversal::mono_reachable_as_bitset(mir, tcx, instance);
+        for (bb, bb_data) in mir.basic_blocks.iter_enumerated() {
+            emitter.bx = emitter.bx.at(emitter.blocks[bb]);
+
+            if !reachable_blocks.contains(bb) {
+                // Keep the block so that terminators elsewhere can still reference it.
+                emitter.emit("rust.unreachable", &[], &[], None);
+                continue;
+            }
+
+            for stmt in &bb_data.statements {
+                emitter.emit_stmt(stmt);
+            }
+            emitter.emit_terminator(bb_data.terminator());
+        }
+    }
+
+    let instance = cx.mir_tables.push(MirData::Instance(instance));
+    cx.rust_module.body().append_operation(
+        OperationBuilder::new("rust.fn", cx.unknown_loc())
+            .add_attributes(&[
+                (Identifier::new(cx.context, "sym_name"), str_attr(cx.context, symbol_name)),
+                (Identifier::new(cx.context, "instance"), int_attr(cx.context, instance)),
+            ])
+            .add_regions([region])
+            .build()
+            .unwrap(),
+    );
+}
+
+impl<'a, 'ml, 'tcx> Emitter<'a, 'ml, 'tcx> {
+    fn mir_attr(&self, data: MirData<'tcx>) -> Attribute<'ml> {
+        int_attr(self.cx.context, self.cx.mir_tables.push(data))
+    }
+
+    fn source_info_attr(&self, source_info: SourceInfo) -> (&'static str, Attribute<'ml>) {
+        ("source_info", self.mir_attr(MirData::SourceInfo(source_info)))
+    }
+
+    fn emit(
+        &self,
+        name: &str,
+        operands: &[Value<'ml, 'a>],
+        attributes: &[(&str, Attribute<'ml>)],
+        result: Option<Type<'ml>>,
+    ) -> OperationRef<'ml, 'a> {
+        let attributes =
+            attributes.iter().map(|&(name, attr)| (self.bx.ident(name), attr)).collect::<Vec<_>>();
+        let mut op = self.bx.op(name).add_operands(operands).add_attributes(&attributes);
+        if let Some(result) = result {
+            op = op.add_results(&[result]);
+        }
+        self.bx.append(op.build().unwrap())
+    }
+
+    fn emit_value(
+        &self,
+        name: &str,
+        operands: &[Value<'ml, 'a>],
+        attributes: &[(&str, Attribute<'ml>)],
+        result: Type<'ml>,
+    ) -> Value<'ml, 'a> {
+        self.emit(name, operands, attributes, Some(result)).result(0).unwrap().into()
+    }
+
+    /// Emits a terminator with the given successors. If `unwind` is given it is encoded as the
+    /// `unwind` attribute, with a cleanup block appended as the last successor.
+    fn emit_terminator_op(
+        &self,
+        name: &str,
+        operands: &[Value<'ml, 'a>],
+        attributes: &[(&str, Attribute<'ml>)],
+        successors: &[BasicBlock],
+        unwind: Option<UnwindAction>,
+    ) {
+        let mut successors = successors.to_vec();
+        let mut attributes =
+            attributes.iter().map(|&(name, attr)| (self.bx.ident(name), attr)).collect::<Vec<_>>();
+        if let Some(unwind) = unwind {
+            let unwind = match unwind {
+                UnwindAction::Continue => "continue".to_owned(),
+                UnwindAction::Unreachable => "unreachable".to_owned(),
+                UnwindAction::Terminate(reason) => {
+                    format!("terminate_{}", terminate_reason_str(reason))
+                }
+                UnwindAction::Cleanup(cleanup) => {
+                    successors.push(cleanup);
+                    "cleanup".to_owned()
+                }
+            };
+            attributes.push((self.bx.ident("unwind"), str_attr(self.cx.context, &unwind)));
+        }
+        let successors = successors.iter().map(|&bb| &*self.blocks[bb]).collect::<Vec<_>>();
+        self.bx.append(
+            self.bx
+                .op(name)
+                .add_operands(operands)
+                .add_attributes(&attributes)
+                .add_successors(&successors)
+                .build()
+                .unwrap(),
+        );
+    }
+
+    fn emit_place(&self, place: Place<'tcx>) -> Value<'ml, 'a> {
+        let context = self.cx.context;
+        let place_ty = place_type(context);
+        let mut value = self.emit_value(
+            "rust.local",
+            &[],
+            &[("local", int_attr(context, place.local.as_u32().into()))],
+            place_ty,
+        );
+
+        for elem in place.projection {
+            value = match elem {
+                PlaceElem::Deref => self.emit_value("rust.deref", &[value], &[], place_ty),
+                PlaceElem::Field(field, ty) => self.emit_value(
+                    "rust.field",
+                    &[value],
+                    &[
+                        ("field", int_attr(context, field.as_u32().into())),
+                        ("ty", str_attr(context, &ty.to_string())),
+                    ],
+                    place_ty,
+                ),
+                PlaceElem::Index(local) => {
+                    let index = self.emit_place(Place::from(local));
+                    self.emit_value("rust.index", &[value, index], &[], place_ty)
+                }
+                PlaceElem::ConstantIndex { offset, min_length, from_end } => self.emit_value(
+                    "rust.constant_index",
+                    &[value],
+                    &[
+                        ("offset", int_attr(context, offset as i64)),
+                        ("min_length", int_attr(context, min_length as i64)),
+                        ("from_end", int_attr(context, from_end.into())),
+                    ],
+                    place_ty,
+                ),
+                PlaceElem::Subslice { from, to, from_end } => self.emit_value(
+                    "rust.subslice",
+                    &[value],
+                    &[
+                        ("from", int_attr(context, from as i64)),
+                        ("to", int_attr(context, to as i64)),
+                        ("from_end", int_attr(context, from_end.into())),
+                    ],
+                    place_ty,
+                ),
+                PlaceElem::Downcast(_adt_def, variant) => self.emit_value(
+                    "rust.downcast",
+                    &[value],
+                    &[("variant", int_attr(context, variant.as_u32().into()))],
+                    place_ty,
+                ),
+                PlaceElem::OpaqueCast(ty) | PlaceElem::Subtype(ty) => {
+                    let name = match elem {
+                        PlaceElem::OpaqueCast(_) => "rust.opaque_cast",
+                        _ => "rust.subtype",
+                    };
+                    self.emit_value(
+                        name,
+                        &[value],
+                        &[
+                            ("mir", self.mir_attr(MirData::Ty(ty))),
+                            ("ty", str_attr(context, &ty.to_string())),
+                        ],
+                        place_ty,
+                    )
+                }
+            };
+        }
+
+        value
+    }
+
+    fn emit_operand(&self, operand: &Operand<'tcx>) -> Value<'ml, 'a> {
+        let context = self.cx.context;
+        match operand {
+            Operand::Copy(place) => {
+                let place = self.emit_place(*place);
+                self.emit_value("rust.copy", &[place], &[], operand_type(context))
+            }
+            Operand::Move(place) => {
+                let place = self.emit_place(*place);
+                self.emit_value("rust.move", &[place], &[], operand_type(context))
+            }
+            Operand::Constant(constant) => self.emit_value(
+                "rust.constant",
+                &[],
+                &[
+                    ("mir", self.mir_attr(MirData::Const(**constant))),
+                    ("value", str_attr(context, &constant.to_string())),
+                ],
+                operand_type(context),
+            ),
+        }
+    }
+
+    fn emit_rvalue(&self, rvalue: &Rvalue<'tcx>) -> Value<'ml, 'a> {
+        let context = self.cx.context;
+        let rvalue_ty = rvalue_type(context);
+        match rvalue {
+            Rvalue::Use(operand) => {
+                let operand = self.emit_operand(operand);
+                self.emit_value("rust.use", &[operand], &[], rvalue_ty)
+            }
+            Rvalue::Repeat(operand, count) => {
+                let operand = self.emit_operand(operand);
+                self.emit_value(
+                    "rust.repeat",
+                    &[operand],
+                    &[
+                        ("mir", self.mir_attr(MirData::Repeat(*count))),
+                        ("count", str_attr(context, &count.to_string())),
+                    ],
+                    rvalue_ty,
+                )
+            }
+            Rvalue::Ref(_region, kind, place) => {
+                let place = self.emit_place(*place);
+                self.emit_value(
+                    "rust.ref",
+                    &[place],
+                    &[("kind", str_attr(context, &format!("{kind:?}")))],
+                    rvalue_ty,
+                )
+            }
+            Rvalue::RawPtr(mutability, place) => {
+                let place = self.emit_place(*place);
+                self.emit_value(
+                    "rust.raw_ptr",
+                    &[place],
+                    &[("mutability", str_attr(context, &format!("{mutability:?}")))],
+                    rvalue_ty,
+                )
+            }
+            Rvalue::ThreadLocalRef(def_id) => self.emit_value(
+                "rust.thread_local_ref",
+                &[],
+                &[
+                    ("mir", self.mir_attr(MirData::ThreadLocalRef(*def_id))),
+                    ("item", str_attr(context, &self.tcx.def_path_str(*def_id))),
+                ],
+                rvalue_ty,
+            ),
+            Rvalue::Len(place) => {
+                let place = self.emit_place(*place);
+                self.emit_value("rust.len", &[place], &[], rvalue_ty)
+            }
+            Rvalue::Cast(kind, operand, ty) => {
+                let operand = self.emit_operand(operand);
+                self.emit_value(
+                    "rust.cast",
+                    &[operand],
+                    &[
+                        ("mir", self.mir_attr(MirData::Cast(*kind, *ty))),
+                        ("kind", str_attr(context, &format!("{kind:?}"))),
+                        ("ty", str_attr(context, &ty.to_string())),
+                    ],
+                    rvalue_ty,
+                )
+            }
+            Rvalue::BinaryOp(bin_op, operands) => {
+                let (lhs, rhs) = &**operands;
+                let lhs = self.emit_operand(lhs);
+                let rhs = self.emit_operand(rhs);
+                self.emit_value(
+                    "rust.binary_op",
+                    &[lhs, rhs],
+                    &[
+                        ("mir", self.mir_attr(MirData::BinOp(*bin_op))),
+                        ("kind", str_attr(context, &format!("{bin_op:?}"))),
+                    ],
+                    rvalue_ty,
+                )
+            }
+            Rvalue::NullaryOp(null_op, ty) => self.emit_value(
+                "rust.nullary_op",
+                &[],
+                &[
+                    ("mir", self.mir_attr(MirData::NullaryOp(*null_op, *ty))),
+                    ("kind", str_attr(context, &format!("{null_op:?}"))),
+                    ("ty", str_attr(context, &ty.to_string())),
+                ],
+                rvalue_ty,
+            ),
+            Rvalue::UnaryOp(un_op, operand) => {
+                let operand = self.emit_operand(operand);
+                self.emit_value(
+                    "rust.unary_op",
+                    &[operand],
+                    &[
+                        ("mir", self.mir_attr(MirData::UnOp(*un_op))),
+                        ("kind", str_attr(context, &format!("{un_op:?}"))),
+                    ],
+                    rvalue_ty,
+                )
+            }
+            Rvalue::Discriminant(place) => {
+                let place = self.emit_place(*place);
+                self.emit_value("rust.discriminant", &[place], &[], rvalue_ty)
+            }
+            Rvalue::Aggregate(kind, operands) => {
+                let operands =
+                    operands.iter().map(|operand| self.emit_operand(operand)).collect::<Vec<_>>();
+                self.emit_value(
+                    "rust.aggregate",
+                    &operands,
+                    &[
+                        ("mir", self.mir_attr(MirData::Aggregate((**kind).clone()))),
+                        ("kind", str_attr(context, &format!("{kind:?}"))),
+                    ],
+                    rvalue_ty,
+                )
+            }
+            Rvalue::ShallowInitBox(operand, ty) => {
+                let operand = self.emit_operand(operand);
+                self.emit_value(
+                    "rust.shallow_init_box",
+                    &[operand],
+                    &[
+                        ("mir", self.mir_attr(MirData::Ty(*ty))),
+                        ("ty", str_attr(context, &ty.to_string())),
+                    ],
+                    rvalue_ty,
+                )
+            }
+            Rvalue::CopyForDeref(place) => {
+                let place = self.emit_place(*place);
+                self.emit_value("rust.copy_for_deref", &[place], &[], rvalue_ty)
+            }
+        }
+    }
+
+    fn emit_stmt(&self, stmt: &Statement<'tcx>) {
+        let context = self.cx.context;
+        let source_info = self.source_info_attr(stmt.source_info);
+        match &stmt.kind {
+            StatementKind::Assign(assign) => {
+                let (place, rvalue) = &**assign;
+                let rvalue = self.emit_rvalue(rvalue);
+                let place = self.emit_place(*place);
+                self.emit("rust.assign", &[place, rvalue], &[source_info], None);
+            }
+            StatementKind::SetDiscriminant { place, variant_index } => {
+                let place = self.emit_place(**place);
+                self.emit(
+                    "rust.set_discriminant",
+                    &[place],
+                    &[("variant", int_attr(context, variant_index.as_u32().into())), source_info],
+                    None,
+                );
+            }
+            StatementKind::Deinit(place) => {
+                let place = self.emit_place(**place);
+                self.emit("rust.deinit", &[place], &[source_info], None);
+            }
+            StatementKind::StorageLive(local) | StatementKind::StorageDead(local) => {
+                let name = match stmt.kind {
+                    StatementKind::StorageLive(_) => "rust.storage_live",
+                    _ => "rust.storage_dead",
+                };
+                self.emit(
+                    name,
+                    &[],
+                    &[("local", int_attr(context, local.as_u32().into())), source_info],
+                    None,
+                );
+            }
+            StatementKind::Retag(kind, place) => {
+                let place = self.emit_place(**place);
+                self.emit(
+                    "rust.retag",
+                    &[place],
+                    &[("kind", str_attr(context, &format!("{kind:?}"))), source_info],
+                    None,
+                );
+            }
+            StatementKind::FakeRead(fake_read) => {
+                let (cause, place) = &**fake_read;
+                let place = self.emit_place(*place);
+                self.emit(
+                    "rust.fake_read",
+                    &[place],
+                    &[("cause", str_attr(context, &format!("{cause:?}"))), source_info],
+                    None,
+                );
+            }
+            StatementKind::PlaceMention(place) => {
+                let place = self.emit_place(**place);
+                self.emit("rust.place_mention", &[place], &[source_info], None);
+            }
+            StatementKind::Intrinsic(intrinsic) => match &**intrinsic {
+                NonDivergingIntrinsic::Assume(operand) => {
+                    let operand = self.emit_operand(operand);
+                    self.emit("rust.assume", &[operand], &[source_info], None);
+                }
+                NonDivergingIntrinsic::CopyNonOverlapping(mir::CopyNonOverlapping {
+                    src,
+                    dst,
+                    count,
+                }) => {
+                    let src = self.emit_operand(src);
+                    let dst = self.emit_operand(dst);
+                    let count = self.emit_operand(count);
+                    self.emit("rust.copy_nonoverlapping", &[src, dst, count], &[source_info], None);
+                }
+            },
+            // These have no runtime semantics and nothing downstream of codegen is interested in
+            // them.
+            StatementKind::ConstEvalCounter
+            | StatementKind::Nop
+            | StatementKind::AscribeUserType(..) => {}
+            StatementKind::Coverage { .. } => {
+                bug!("coverage instrumentation is rejected by `MLIRCodegenBackend::init`")
+            }
+        }
+    }
+
+    fn emit_terminator(&self, terminator: &Terminator<'tcx>) {
+        let context = self.cx.context;
+        let source_info = self.source_info_attr(terminator.source_info);
+        match &terminator.kind {
+            TerminatorKind::Goto { target } => {
+                self.emit_terminator_op("rust.goto", &[], &[source_info], &[*target], None);
+            }
+            TerminatorKind::SwitchInt { discr, targets } => {
+                let discr = self.emit_operand(discr);
+                let case_values = targets
+                    .iter()
+                    .map(|(value, _)| value.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let successors = targets.all_targets();
+                self.emit_terminator_op(
+                    "rust.switch_int",
+                    &[discr],
+                    &[("case_values", str_attr(context, &case_values)), source_info],
+                    successors,
+                    None,
+                );
+            }
+            TerminatorKind::UnwindResume => {
+                self.emit_terminator_op("rust.unwind_resume", &[], &[source_info], &[], None);
+            }
+            TerminatorKind::UnwindTerminate(reason) => {
+                self.emit_terminator_op(
+                    "rust.unwind_terminate",
+                    &[],
+                    &[("reason", str_attr(context, terminate_reason_str(*reason))), source_info],
+                    &[],
+                    None,
+                );
+            }
+            TerminatorKind::Return => {
+                self.emit_terminator_op("rust.return", &[], &[source_info], &[], None);
+            }
+            TerminatorKind::Unreachable => {
+                self.emit_terminator_op("rust.unreachable", &[], &[source_info], &[], None);
+            }
+            TerminatorKind::Drop { place, target, unwind, replace } => {
+                let place = self.emit_place(*place);
+                self.emit_terminator_op(
+                    "rust.drop",
+                    &[place],
+                    &[("replace", int_attr(context, (*replace).into())), source_info],
+                    &[*target],
+                    Some(*unwind),
+                );
+            }
+            TerminatorKind::Call {
+                func,
+                args,
+                destination,
+                target,
+                unwind,
+                call_source: _,
+                fn_span,
+            } => {
+                let mut operands = vec![self.emit_operand(func), self.emit_place(*destination)];
+                operands.extend(args.iter().map(|arg| self.emit_operand(&arg.node)));
+                let source_info =
+                    self.source_info_attr(SourceInfo { span: *fn_span, ..terminator.source_info });
+                self.emit_terminator_op(
+                    "rust.call",
+                    &operands,
+                    &[source_info],
+                    target.as_slice(),
+                    Some(*unwind),
+                );
+            }
+            TerminatorKind::TailCall { func, args, fn_span } => {
+                let mut operands = vec![self.emit_operand(func)];
+                operands.extend(args.iter().map(|arg| self.emit_operand(&arg.node)));
+                let source_info =
+                    self.source_info_attr(SourceInfo { span: *fn_span, ..terminator.source_info });
+                self.emit_terminator_op("rust.tail_call", &operands, &[source_info], &[], None);
+            }
+            TerminatorKind::Assert { cond, expected, msg, target, unwind } => {
+                let mut operands = vec![self.emit_operand(cond)];
+                let kind = match &**msg {
+                    AssertKind::BoundsCheck { len, index } => {
+                        operands.extend([self.emit_operand(len), self.emit_operand(index)]);
+                        AssertKind::BoundsCheck { len: (), index: () }
+                    }
+                    AssertKind::Overflow(bin_op, lhs, rhs) => {
+                        operands.extend([self.emit_operand(lhs), self.emit_operand(rhs)]);
+                        AssertKind::Overflow(*bin_op, (), ())
+                    }
+                    AssertKind::OverflowNeg(operand) => {
+                        operands.push(self.emit_operand(operand));
+                        AssertKind::OverflowNeg(())
+                    }
+                    AssertKind::DivisionByZero(operand) => {
+                        operands.push(self.emit_operand(operand));
+                        AssertKind::DivisionByZero(())
+                    }
+                    AssertKind::RemainderByZero(operand) => {
+                        operands.push(self.emit_operand(operand));
+                        AssertKind::RemainderByZero(())
+                    }
+                    AssertKind::ResumedAfterReturn(kind) => AssertKind::ResumedAfterReturn(*kind),
+                    AssertKind::ResumedAfterPanic(kind) => AssertKind::ResumedAfterPanic(*kind),
+                    AssertKind::MisalignedPointerDereference { required, found } => {
+                        operands.extend([self.emit_operand(required), self.emit_operand(found)]);
+                        AssertKind::MisalignedPointerDereference { required: (), found: () }
+                    }
+                };
+                self.emit_terminator_op(
+                    "rust.assert",
+                    &operands,
+                    &[
+                        ("expected", int_attr(context, (*expected).into())),
+                        ("msg", str_attr(context, &format!("{:?}", kind))),
+                        ("mir", self.mir_attr(MirData::Assert(kind))),
+                        source_info,
+                    ],
+                    &[*target],
+                    Some(*unwind),
+                );
+            }
+            TerminatorKind::InlineAsm {
+                asm_macro,
+                template,
+                operands,
+                options,
+                line_spans,
+                targets,
+                unwind,
+            } => {
+                let mut values = vec![];
+                for operand in operands.iter() {
+                    match operand {
+                        InlineAsmOperand::In { value, .. } => values.push(self.emit_operand(value)),
+                        InlineAsmOperand::Out { place, .. } => {
+                            values.extend(place.map(|place| self.emit_place(place)));
+                        }
+                        InlineAsmOperand::InOut { in_value, out_place, .. } => {
+                            values.push(self.emit_operand(in_value));
+                            values.extend(out_place.map(|place| self.emit_place(place)));
+                        }
+                        InlineAsmOperand::Const { .. }
+                        | InlineAsmOperand::SymFn { .. }
+                        | InlineAsmOperand::SymStatic { .. }
+                        | InlineAsmOperand::Label { .. } => {}
+                    }
+                }
+                let template_str = rustc_ast::InlineAsmTemplatePiece::to_string(template);
+                let data = InlineAsmData {
+                    asm_macro: *asm_macro,
+                    template,
+                    operands: operands.to_vec(),
+                    options: *options,
+                    line_spans,
+                };
+                self.emit_terminator_op(
+                    "rust.inline_asm",
+                    &values,
+                    &[
+                        ("template", str_attr(context, &template_str)),
+                        ("mir", self.mir_attr(MirData::InlineAsm(data))),
+                        source_info,
+                    ],
+                    targets,
+                    Some(*unwind),
+                );
+            }
+            TerminatorKind::Yield { .. }
+            | TerminatorKind::FalseEdge { .. }
+            | TerminatorKind::FalseUnwind { .. }
+            | TerminatorKind::CoroutineDrop => {
+                bug!("shouldn't exist at codegen {:?}", terminator);
+            }
+        }
+    }
+}
@@ -0,0 +1,653 @@
+//! Lowering of `rust.fn` ops to `func.func`.
+//!
+//! Ops are lowered in order: place and operand ops map to an [`MPlace`] or [`MValue`], rvalue ops
+//! are kept as a [`LoweredRvalue`] until the `rust.assign` consuming them, and statement and
+//! terminator ops emit code into the block corresponding to their `rust.fn` block.
+
+use melior::ir::attribute::{FlatSymbolRefAttribute, StringAttribute};
+use melior::ir::operation::{OperationLike, OperationRef, OperationResult};
+use melior::ir::{Block, BlockLike, BlockRef, Identifier, Region, RegionLike, Value};
+use rustc_abi::{FieldIdx, VariantIdx};
+use rustc_codegen_ssa::common::asm_const_to_str;
+use rustc_data_structures::fx::FxHashMap;
+use rustc_hir::LangItem;
+use rustc_index::IndexVec;
+use rustc_index::bit_set::BitSet;
+use rustc_middle::mir::mono::MonoItem;
+use rustc_middle::mir::{
+    AssertKind, BasicBlock, ConstOperand, InlineAsmOperand, Local, SourceInfo, UnwindAction,
+};
+use rustc_middle::ty::layout::{FnAbiOf, LayoutOf};
+use rustc_middle::ty::{self, Ty, TypeVisitableExt};
+use rustc_middle::{bug, span_bug};
+use rustc_target::spec::PanicStrategy;
+
+use super::{
+    op_name, parse_terminate_reason, read_int_attr, read_str_attr, read_u128_list, value_key,
+};
+use crate::abi::{CallArgument, fn_abi_arg_attrs, fn_abi_param_types, mlir_fn_type};
+use crate::asm::AsmOperand;
+use crate::base::{LoweredRvalue, codegen_array_len, codegen_assign};
+use crate::builder::Builder;
+use crate::common::FunctionCx;
+use crate::context::CodegenCx;
+use crate::value_and_place::{MPlace, MValue};
+
+struct FnLowering<'a, 'ml, 'tcx> {
+    fx: FunctionCx<'a, 'ml, 'tcx>,
+    places: FxHashMap<usize, MPlace<'ml, 'a, 'tcx>>,
+    /// Lowered operands, and whether they were moved.
+    operands: FxHashMap<usize, (MValue<'ml, 'a, 'tcx>, bool)>,
+    rvalues: FxHashMap<usize, LoweredRvalue<'ml, 'a, 'tcx>>,
+}
+
+/// Lowers every `rust.fn` in the `rust` module of `cx` into its final module.
+pub(crate) fn lower_module(cx: &CodegenCx<'_, '_>) {
+    let mut op = cx.rust_module.body().first_operation();
+    while let Some(rust_fn) = op {
+        assert_eq!(op_name(&rust_fn), "rust.fn");
+        lower_fn(cx, rust_fn);
+        op = rust_fn.next_in_block();
+    }
+}
+
+fn lower_fn<'ml, 'tcx>(cx: &CodegenCx<'ml, 'tcx>, rust_fn: OperationRef<'ml, '_>) {
+    let tcx = cx.tcx;
+    let instance = cx.mir_tables.instance(read_int_attr(&rust_fn, "instance"));
+    debug_assert!(!instance.args.has_infer());
+
+    let symbol_name = tcx.symbol_name(instance).name.to_string();
+    let _timer = tcx.prof.generic_activity_with_arg("codegen fn", &*symbol_name);
+
+    let mir = tcx.instance_mir(instance.def);
+    let fn_abi = cx.fn_abi_of_instance(instance, ty::List::empty());
+    let fn_ty = mlir_fn_type(tcx, cx.context, fn_abi);
+    let subprogram = cx.define_subprogram(instance, mir, fn_abi, &symbol_name);
+
+    // The `-Zmlir-passes` pipeline may have erased or merged blocks, but can't add any.
+    let mut rust_blocks = Vec::with_capacity(mir.basic_blocks.len());
+    let rust_region = rust_fn.region(0).unwrap();
+    let mut rust_block = rust_region.first_block();
+    while let Some(block) = rust_block {
+        let Some(bb) = cx.mir_tables.block(block) else {
+            tcx.dcx().fatal(format!(
+                "the MLIR passes added a block to the `rust.fn` of `{symbol_name}`, which has no \
+                corresponding MIR block"
+            ));
+        };
+        rust_blocks.push((bb, block));
+        rust_block = block.next_in_region();
+    }
+
+    // Predefine blocks. The entry block takes the function arguments and holds all stack slots.
+    let region = Region::new();
+    let personality;
+    {
+        let location = cx.unknown_loc();
+        let entry_params = fn_abi_param_types(tcx, cx.context, fn_abi)
+            .into_iter()
+            .map(|ty| (ty, location))
+            .collect::<Vec<_>>();
+        let entry_block = region.append_block(Block::new(&entry_params));
+        let block_map: IndexVec<BasicBlock, _> =
+            mir.basic_blocks.indices().map(|_| region.append_block(Block::new(&[]))).collect();
+
+        let fx = FunctionCx {
+            cx,
+            tcx,
+            context: cx.context,
+
+            instance,
+            symbol_name: symbol_name.clone(),
+            mir,
+            fn_abi,
+
+            region: &region,
+            entry_block,
+            block_map,
+            local_map: IndexVec::with_capacity(mir.local_decls.len()),
+
+            caller_location: None, // set by `codegen_fn_prelude`
+
+            subprogram,
+            landing_pads: FxHashMap::default(),
+            terminate_blocks: FxHashMap::default(),
+            personality_slot: None,
+            catches_unwind: false,
+
+            // The prelude is attributed to the start of the function.
+            bx: Builder::new(cx.context, entry_block, cx.span_location(mir.span, mir.span)),
+        };
+
+        let mut lowering = FnLowering {
+            fx,
+            places: FxHashMap::default(),
+            operands: FxHashMap::default(),
+            rvalues: FxHashMap::default(),
+        };
+        tcx.prof.generic_activity("codegen mlir").run(|| lowering.lower_body(&rust_blocks));
+        personality = crate::unwind::personality_fn(&lowering.fx);
+    }
+
+    let mut attributes = crate::attributes::fn_attributes(tcx, cx.context, instance, fn_abi);
+    let data = cx.codegen_unit.items()[&MonoItem::Fn(instance)];
+    attributes.extend(crate::linkage::symbol_attrs(
+        tcx,
+        cx.context,
+        "llvm.linkage",
+        data.linkage,
+        data.visibility,
+        false,
+    ));
+    if let Some(section) = tcx.codegen_fn_attrs(instance.def_id()).link_section {
+        attributes.push((
+            Identifier::new(cx.context, "section"),
+            StringAttribute::new(cx.context, section.as_str()).into(),
+        ));
+    }
+    if let Some(arg_attrs) = fn_abi_arg_attrs(tcx, cx.context, fn_abi) {
+        attributes.push((Identifier::new(cx.context, "arg_attrs"), arg_attrs));
+    }
+    if let Some(personality) = &personality {
+        attributes.push((
+            Identifier::new(cx.context, "personality"),
+            FlatSymbolRefAttribute::new(cx.context, personality).into(),
+        ));
+    }
+    cx.define_fn(&symbol_name, fn_ty, region, &attributes, cx.fn_location(mir, subprogram));
+}
+
+impl<'a, 'ml, 'tcx> FnLowering<'a, 'ml, 'tcx> {
+    fn lower_body(&mut self, rust_blocks: &[(BasicBlock, BlockRef<'ml, '_>)]) {
+        let fx = &mut self.fx;
+        let arg_uninhabited = fx.mir.args_iter().any(|arg| {
+            fx.layout_of(fx.monomorphize(fx.mir.local_decls[arg].ty)).abi.is_uninhabited()
+        });
+        if arg_uninhabited {
+            fx.bx.trap();
+            for bb in fx.mir.basic_blocks.indices() {
+                let block = fx.get_block(bb);
+                fx.switch_to_block(block);
+                fx.bx.unreachable();
+            }
+            return;
+        }
+        let tcx = fx.tcx;
+        tcx.prof.generic_activity("codegen prelude").run(|| crate::abi::codegen_fn_prelude(fx));
+
+        let mut lowered = BitSet::new_empty(self.fx.mir.basic_blocks.len());
+        for &(bb, rust_block) in rust_blocks {
+            lowered.insert(bb);
+            let block = self.fx.get_block(bb);
+            self.fx.switch_to_block(block);
+
+            // Without unwinding nothing can branch to cleanup blocks.
+            if self.fx.mir.basic_blocks[bb].is_cleanup
+                && self.fx.tcx.sess.panic_strategy() != PanicStrategy::Unwind
+            {
+                self.fx.bx.unreachable();
+                continue;
+            }
+
+            let mut op = rust_block.first_operation();
+            while let Some(rust_op) = op {
+                self.lower_op(rust_op);
+                op = rust_op.next_in_block();
+            }
+        }
+
+        // Blocks erased by the MLIR passes aren't branched to anymore.
+        for bb in self.fx.mir.basic_blocks.indices() {
+            if !lowered.contains(bb) {
+                let block = self.fx.get_block(bb);
+                self.fx.switch_to_block(block);
+                self.fx.bx.unreachable();
+            }
+        }
+    }
+
+    fn place(&self, value: Value<'ml, '_>) -> MPlace<'ml, 'a, 'tcx> {
+        self.places[&value_key(value)]
+    }
+
+    fn operand(&self, value: Value<'ml, '_>) -> MValue<'ml, 'a, 'tcx> {
+        self.operands[&value_key(value)].0
+    }
+
+    fn successor(&self, op: OperationRef<'ml, '_>, index: usize) -> BasicBlock {
+        self.fx.cx.mir_tables.block(op.successor(index).unwrap()).unwrap()
+    }
+
+    /// The constant `value` was created from by a `rust.constant`, if it was.
+    fn constant(&self, value: Value<'ml, '_>) -> Option<ConstOperand<'tcx>> {
+        let op = OperationResult::try_from(value).ok()?.owner();
+        (op_name(&op) == "rust.constant")
+            .then(|| self.fx.cx.mir_tables.constant(read_int_attr(&op, "mir")))
+    }
+
+    /// Decodes the `unwind` attribute of a terminator.
+    fn unwind_action(&self, op: OperationRef<'ml, '_>) -> UnwindAction {
+        match read_str_attr(&op, "unwind") {
+            "continue" => UnwindAction::Continue,
+            "unreachable" => UnwindAction::Unreachable,
+            "cleanup" => UnwindAction::Cleanup(self.successor(op, op.successor_count() - 1)),
+            unwind => match unwind.strip_prefix("terminate_") {
+                Some(reason) => UnwindAction::Terminate(parse_terminate_reason(reason)),
+                None => bug!("unknown unwind action `{unwind}`"),
+            },
+        }
+    }
+
+    fn source_info(&self, op: OperationRef<'ml, '_>) -> SourceInfo {
+        self.fx.cx.mir_tables.source_info(read_int_attr(&op, "source_info"))
+    }
+
+    fn lower_op(&mut self, op: OperationRef<'ml, '_>) {
+        let name = op_name(&op);
+        let cx = self.fx.cx;
+        let tables = &cx.mir_tables;
+        let operand = |i| op.operand(i).unwrap();
+
+        // Places
+        let place = match name {
+            "rust.local" => {
+                Some(self.fx.get_local_place(Local::from_u32(read_int_attr(&op, "local") as u32)))
+            }
+            "rust.deref" => Some(self.place(operand(0)).place_deref(&mut self.fx)),
+            "rust.field" => {
+                let field = FieldIdx::from_u32(read_int_attr(&op, "field") as u32);
+                Some(self.place(operand(0)).place_field(&mut self.fx, field))
+            }
+            "rust.index" => {
+                let base = self.place(operand(0));
+                let index = self.place(operand(1)).to_mvalue(&mut self.fx);
+                let index = index.load_scalar(&mut self.fx);
+                Some(base.place_index(&mut self.fx, index))
+            }
+            "rust.constant_index" => {
+                let base = self.place(operand(0));
+                let offset = read_int_attr(&op, "offset") as u64;
+                let fx = &mut self.fx;
+                let index = if read_int_attr(&op, "from_end") == 0 {
+                    fx.bx.iconst(fx.usize_type(), i128::from(offset))
+                } else {
+                    let len = codegen_array_len(fx, base);
+                    fx.bx.iadd_imm(len, -i128::from(offset))
+                };
+                Some(base.place_index(fx, index))
+            }
+            "rust.subslice" => Some(self.lower_subslice(op)),
+            "rust.downcast" => {
+                let variant = VariantIdx::from_u32(read_int_attr(&op, "variant") as u32);
+                Some(self.place(operand(0)).downcast_variant(&self.fx, variant))
+            }
+            "rust.subtype" => {
+                let ty = self.fx.monomorphize(tables.ty(read_int_attr(&op, "mir")));
+                Some(self.place(operand(0)).place_transmute_type(&self.fx, ty))
+            }
+            "rust.opaque_cast" => {
+                bug!("encountered OpaqueCast({}) in codegen", read_str_attr(&op, "ty"))
+            }
+            _ => None,
+        };
+        if let Some(place) = place {
+            self.places.insert(value_key(op.result(0).unwrap().into()), place);
+            return;
+        }
+
+        // Operands
+        let value = match name {
+            "rust.copy" | "rust.move" => {
+                Some((self.place(operand(0)).to_mvalue(&mut self.fx), name == "rust.move"))
+            }
+            "rust.constant" => {
+                let constant = tables.constant(read_int_attr(&op, "mir"));
+                Some((crate::constant::codegen_constant_operand(&mut self.fx, &constant), false))
+            }
+            _ => None,
+        };
+        if let Some(value) = value {
+            self.operands.insert(value_key(op.result(0).unwrap().into()), value);
+            return;
+        }
+
+        // Rvalues
+        let rvalue = match name {
+            "rust.use" => Some(LoweredRvalue::Use(self.operand(operand(0)))),
+            "rust.repeat" => Some(LoweredRvalue::Repeat(
+                self.operand(operand(0)),
+                tables.repeat(read_int_attr(&op, "mir")),
+            )),
+            "rust.ref" | "rust.raw_ptr" => Some(LoweredRvalue::Ref(self.place(operand(0)))),
+            "rust.thread_local_ref" => Some(LoweredRvalue::ThreadLocalRef(
+                tables.thread_local_ref(read_int_attr(&op, "mir")),
+            )),
+            "rust.len" => Some(LoweredRvalue::Len(self.place(operand(0)))),
+            "rust.cast" => {
+                let (kind, ty) = tables.cast(read_int_attr(&op, "mir"));
+                Some(LoweredRvalue::Cast(kind, self.operand(operand(0)), ty))
+            }
+            "rust.binary_op" => Some(LoweredRvalue::BinaryOp(
+                tables.bin_op(read_int_attr(&op, "mir")),
+                self.operand(operand(0)),
+                self.operand(operand(1)),
+            )),
+            "rust.nullary_op" => {
+                let (null_op, ty) = tables.nullary_op(read_int_attr(&op, "mir"));
+                Some(LoweredRvalue::NullaryOp(null_op, ty))
+            }
+            "rust.unary_op" => Some(LoweredRvalue::UnaryOp(
+                tables.un_op(read_int_attr(&op, "mir")),
+                self.operand(operand(0)),
+            )),
+            "rust.discriminant" => Some(LoweredRvalue::Discriminant(self.place(operand(0)))),
+            "rust.aggregate" => Some(LoweredRvalue::Aggregate(
+                tables.aggregate(read_int_attr(&op, "mir")),
+                (0..op.operand_count()).map(|i| self.operand(operand(i))).collect(),
+            )),
+            "rust.shallow_init_box" => Some(LoweredRvalue::ShallowInitBox(
+                self.operand(operand(0)),
+                tables.ty(read_int_attr(&op, "mir")),
+            )),
+            "rust.copy_for_deref" => Some(LoweredRvalue::CopyForDeref(self.place(operand(0)))),
+            _ => None,
+        };
+        if let Some(rvalue) = rvalue {
+            self.rvalues.insert(value_key(op.result(0).unwrap().into()), rvalue);
+            return;
+        }
+
+        self.lower_stmt_or_terminator(op);
+    }
+
+    fn lower_subslice(&mut self, op: OperationRef<'ml, '_>) -> MPlace<'ml, 'a, 'tcx> {
+        // These indices are generated by slice patterns.
+        // slice[from:-to] in Python terms.
+        let mplace = self.place(op.operand(0).unwrap());
+        let from = read_int_attr(&op, "from") as u64;
+        let to = read_int_attr(&op, "to") as u64;
+        let from_end = read_int_attr(&op, "from_end") != 0;
+        let fx = &mut self.fx;
+
+        match mplace.layout().ty.kind() {
+            ty::Array(elem_ty, _len) => {
+                assert!(!from_end, "array subslices are never `from_end`");
+                let elem_layout = fx.layout_of(*elem_ty);
+                let ptr = mplace.to_ptr();
+                let ptr =
+                    fx.bx.ptr_offset_imm(ptr, elem_layout.size.bytes() as i64 * (from as i64));
+                MPlace::for_ptr(ptr, fx.layout_of(Ty::new_array(fx.tcx, *elem_ty, to - from)))
+            }
+            ty::Slice(elem_ty) => {
+                assert!(from_end, "slice subslices should be `from_end`");
+                let elem_layout = fx.layout_of(*elem_ty);
+                let (ptr, len) = mplace.to_ptr_unsized();
+                let ptr =
+                    fx.bx.ptr_offset_imm(ptr, elem_layout.size.bytes() as i64 * (from as i64));
+                let len = fx.bx.iadd_imm(len, -(i128::from(from) + i128::from(to)));
+                MPlace::for_ptr_with_extra(ptr, len, mplace.layout())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn lower_stmt_or_terminator(&mut self, op: OperationRef<'ml, '_>) {
+        let name = op_name(&op);
+        let operand = |i| op.operand(i).unwrap();
+
+        // Only the `rust.unreachable`s standing in for unreachable blocks lack a source info.
+        if op.attribute("source_info").is_ok() {
+            let source_info = self.source_info(op);
+            self.fx.set_debug_loc(source_info);
+        }
+
+        match name {
+            // Statements
+            "rust.assign" => {
+                let source_info = self.source_info(op);
+                let lval = self.place(operand(0));
+                let rvalue = self.rvalues.remove(&value_key(operand(1))).unwrap();
+                codegen_assign(&mut self.fx, source_info, lval, rvalue);
+            }
+            "rust.set_discriminant" => {
+                let place = self.place(operand(0));
+                let variant_index = VariantIdx::from_u32(read_int_attr(&op, "variant") as u32);
+                crate::discriminant::codegen_set_discriminant(&mut self.fx, place, variant_index);
+            }
+            "rust.copy_nonoverlapping" => {
+                let src = self.operand(operand(0));
+                let dst = self.operand(operand(1));
+                let count = self.operand(operand(2));
+                let fx = &mut self.fx;
+                let pointee = dst
+                    .layout()
+                    .pointee_info_at(fx, rustc_abi::Size::ZERO)
+                    .expect("Expected pointer");
+                let dst = dst.load_scalar(fx);
+                let src = src.load_scalar(fx);
+                let count = count.load_scalar(fx);
+                let bytes = fx.bx.imul_imm(count, pointee.size.bytes() as i128);
+                fx.bx.memcpy(dst, src, bytes);
+            }
+            // We ignore `assume` intrinsics, they are only useful for optimizations
+            "rust.assume" | "rust.deinit" | "rust.storage_live" | "rust.storage_dead"
+            | "rust.retag" | "rust.fake_read" | "rust.place_mention" => {}
+
+            // Terminators
+            "rust.goto" => {
+                let target = self.fx.get_block(self.successor(op, 0));
+                self.fx.bx.br(&target, &[]);
+            }
+            "rust.return" => {
+                crate::abi::codegen_return(&mut self.fx);
+            }
+            "rust.unreachable" => {
+                self.fx.bx.unreachable();
+            }
+            "rust.unwind_resume" => {
+                crate::unwind::codegen_unwind_resume(&mut self.fx);
+            }
+            "rust.unwind_terminate" => {
+                let reason = parse_terminate_reason(read_str_attr(&op, "reason"));
+                crate::unwind::codegen_unwind_terminate(&mut self.fx, reason);
+            }
+            "rust.switch_int" => self.lower_switch_int(op),
+            "rust.assert" => self.lower_assert(op),
+            "rust.drop" => {
+                let source_info = self.source_info(op);
+                let drop_place = self.place(operand(0));
+                let target = self.successor(op, 0);
+                let unwind = self.unwind_action(op);
+                crate::abi::codegen_drop(&mut self.fx, source_info, drop_place, target, unwind);
+            }
+            "rust.call" => {
+                let source_info = self.source_info(op);
+                let func = self.operand(operand(0));
+                let destination = self.place(operand(1));
+                let args = (2..op.operand_count())
+                    .map(|i| {
+                        let (value, is_owned) = self.operands[&value_key(operand(i))];
+                        CallArgument { value, is_owned, constant: self.constant(operand(i)) }
+                    })
+                    .collect::<Vec<_>>();
+                let unwind = self.unwind_action(op);
+                let has_cleanup = matches!(unwind, UnwindAction::Cleanup(_));
+                let target = if op.successor_count() > usize::from(has_cleanup) {
+                    Some(self.successor(op, 0))
+                } else {
+                    None
+                };
+                let fx = &mut self.fx;
+                fx.tcx.prof.generic_activity("codegen call").run(|| {
+                    crate::abi::codegen_terminator_call(
+                        fx,
+                        source_info,
+                        func,
+                        args,
+                        destination,
+                        target,
+                        unwind,
+                    )
+                });
+            }
+            "rust.tail_call" => {
+                let source_info = self.source_info(op);
+                let func = self.operand(operand(0));
+                let args = (1..op.operand_count())
+                    .map(|i| {
+                        let (value, is_owned) = self.operands[&value_key(operand(i))];
+                        CallArgument { value, is_owned, constant: self.constant(operand(i)) }
+                    })
+                    .collect::<Vec<_>>();
+                let fx = &mut self.fx;
+                fx.tcx
+                    .prof
+                    .generic_activity("codegen call")
+                    .run(|| crate::abi::codegen_tail_call(fx, source_info, func, args));
+            }
+            "rust.inline_asm" => self.lower_inline_asm(op),
+            _ => bug!("unexpected op `{name}` in `rust.fn`"),
+        }
+    }
+
+    fn lower_switch_int(&mut self, op: OperationRef<'ml, '_>) {
+        let discr = self.operand(op.operand(0).unwrap());
+        let case_values = read_u128_list(&op, "case_values");
+        let targets = (0..op.successor_count())
+            .map(|i| self.fx.get_block(self.successor(op, i)))
+            .collect::<Vec<_>>();
+        let (otherwise_block, case_blocks) = targets.split_last().unwrap();
+
+        let fx = &mut self.fx;
+        let switch_ty = discr.layout().ty;
+        let discr = discr.load_scalar(fx);
+
+        let use_bool_opt = switch_ty.kind() == fx.tcx.types.bool.kind()
+            || (case_values.len() == 1 && case_values[0] == 0);
+        if use_bool_opt {
+            assert_eq!(case_values.len(), 1);
+            let then_block = case_blocks[0];
+            let test_zero = match case_values[0] {
+                0 => true,
+                1 => false,
+                _ => unreachable!("{:?}", case_values),
+            };
+
+            let discr = fx.bx.to_i1(discr);
+            if test_zero {
+                fx.bx.cond_br(discr, otherwise_block, &then_block);
+            } else {
+                fx.bx.cond_br(discr, &then_block, otherwise_block);
+            }
+        } else {
+            let cases = case_values
+                .iter()
+                .zip(case_blocks)
+                .map(|(&value, block)| (value, &**block))
+                .collect::<Vec<_>>();
+            fx.bx.switch(discr, otherwise_block, &cases);
+        }
+    }
+
+    fn lower_inline_asm(&mut self, op: OperationRef<'ml, '_>) {
+        let data = self.fx.cx.mir_tables.inline_asm(read_int_attr(&op, "mir"));
+        let tcx = self.fx.tcx;
+        let mut values = (0..op.operand_count()).map(|i| op.operand(i).unwrap());
+
+        let operands = data
+            .operands
+            .iter()
+            .map(|operand| match *operand {
+                InlineAsmOperand::In { reg, .. } => {
+                    AsmOperand::In { reg, value: self.operand(values.next().unwrap()) }
+                }
+                InlineAsmOperand::Out { reg, late, ref place } => AsmOperand::Out {
+                    reg,
+                    late,
+                    place: place.map(|_| self.place(values.next().unwrap())),
+                },
+                InlineAsmOperand::InOut { reg, late, ref out_place, .. } => AsmOperand::InOut {
+                    reg,
+                    late,
+                    in_value: self.operand(values.next().unwrap()),
+                    out_place: out_place.map(|_| self.place(values.next().unwrap())),
+                },
+                InlineAsmOperand::Const { ref value } => {
+                    let (const_value, ty) = crate::constant::eval_mir_constant(&self.fx, value);
+                    let string =
+                        asm_const_to_str(tcx, value.span, const_value, self.fx.layout_of(ty));
+                    AsmOperand::Const { string }
+                }
+                InlineAsmOperand::SymFn { ref value } => {
+                    let const_ = self.fx.monomorphize(value.const_);
+                    let ty::FnDef(def_id, args) = *const_.ty().kind() else {
+                        span_bug!(value.span, "invalid type for asm sym (fn)");
+                    };
+                    let instance = ty::Instance::resolve_for_fn_ptr(
+                        tcx,
+                        ty::ParamEnv::reveal_all(),
+                        def_id,
+                        args,
+                    )
+                    .unwrap();
+                    AsmOperand::SymFn { instance }
+                }
+                InlineAsmOperand::SymStatic { def_id } => AsmOperand::SymStatic { def_id },
+                InlineAsmOperand::Label { target_index } => {
+                    AsmOperand::Label { target: self.successor(op, target_index) }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let dest =
+            if data.asm_macro.diverges(data.options) { None } else { Some(self.successor(op, 0)) };
+        crate::asm::codegen_inline_asm(
+            &mut self.fx,
+            data.template,
+            &operands,
+            data.options,
+            data.line_spans,
+            dest,
+        );
+    }
+
+    fn lower_assert(&mut self, op: OperationRef<'ml, '_>) {
+        let msg = self.fx.cx.mir_tables.assert(read_int_attr(&op, "mir"));
+        let target = self.fx.get_block(self.successor(op, 0));
+        if !self.fx.tcx.sess.overflow_checks() && msg.is_optional_overflow_check() {
+            self.fx.bx.br(&target, &[]);
+            return;
+        }
+
+        let cond = self.operand(op.operand(0).unwrap());
+        let msg_args = (1..op.operand_count())
+            .map(|i| self.operand(op.operand(i).unwrap()))
+            .collect::<Vec<_>>();
+        let source_info = self.source_info(op);
+        let unwind = self.unwind_action(op);
+        let fx = &mut self.fx;
+        let cond = cond.load_scalar(fx);
+        let cond = fx.bx.to_i1(cond);
+
+        let failure = fx.create_block();
+        if read_int_attr(&op, "expected") != 0 {
+            fx.bx.cond_br(cond, &target, &failure);
+        } else {
+            fx.bx.cond_br(cond, &failure, &target);
+        };
+
+        // The operands of `rust.assert` are in the order of the fields of `AssertKind`, but
+        // `panic_bounds_check` takes the index first.
+        fx.switch_to_block(failure);
+        let (lang_item, args) = match msg {
+            AssertKind::BoundsCheck { .. } => {
+                (LangItem::PanicBoundsCheck, vec![msg_args[1], msg_args[0]])
+            }
+            AssertKind::MisalignedPointerDereference { .. } => {
+                (LangItem::PanicMisalignedPointerDereference, msg_args)
+            }
+            _ => (msg.panic_function(), vec![]),
+        };
+        crate::abi::codegen_panic_lang_item(fx, source_info, lang_item, &args, unwind);
+    }
+}
@@ -0,0 +1,227 @@
+//! The `rust` MLIR dialect.
+//!
+//! Every function is first emitted as a `rust.fn` whose body mirrors its MIR one-to-one: one MLIR
+//! block per `BasicBlock`, one op per `StatementKind`/`TerminatorKind`, and separate ops for
+//! places, projections, operands and rvalues. This keeps Rust-level semantics (places, drops,
+//! unwind edges, borrows) visible to MLIR passes: the `-Zmlir-passes` pipeline runs on the module
+//! of `rust.fn`s before [`lower`] progressively lowers each of them to a `func.func` using the
+//! `arith`, `cf` and `llvm` dialects.
+//!
+//! Functions aren't lowered through `rustc_codegen_ssa::mir::codegen_mir` like in the other
+//! backends: its builder traits create constants and function values without an insertion point,
+//! while every MLIR value has to be the result of an op in some block.
+//!
+//! The dialect isn't registered with MLIR; ops are built in generic form and the context allows
+//! unregistered dialects. It uses three opaque types:
+//!
+//! * `!rust.place`, produced by `rust.local` and the projection ops (`rust.deref`, `rust.field`,
+//!   `rust.index`, `rust.constant_index`, `rust.subslice`, `rust.downcast`, `rust.opaque_cast`,
+//!   `rust.subtype`).
+//! * `!rust.operand`, produced by `rust.copy`, `rust.move` and `rust.constant`.
+//! * `!rust.rvalue`, produced by one op per `Rvalue` variant (`rust.use`, `rust.binary_op`, ...)
+//!   and consumed by `rust.assign`.
+//!
+//! Data that can't be expressed as MLIR attributes (types, constants, aggregate kinds, spans)
+//! lives in the per-module [`MirTables`] and is referenced through an integer `mir` attribute.
+//! Ops carrying such a reference also get a human readable rendering (`ty`, `kind`, `value`) so
+//! that dumps and analyses can make sense of them without access to the tables.
+//!
+//! Terminators that can unwind carry an `unwind` attribute (`"continue"`, `"unreachable"`,
+//! `"terminate_abi"`, `"terminate_in_cleanup"` or `"cleanup"`); for `"cleanup"` the cleanup block
+//! is the last successor. `rust.unwind_terminate` carries its reason (`"abi"` or `"in_cleanup"`)
+//! as a `reason` attribute.
+
+use std::cell::RefCell;
+
+use melior::Context;
+use melior::ir::attribute::{IntegerAttribute, StringAttribute};
+use melior::ir::operation::OperationLike;
+use melior::ir::r#type::IntegerType;
+use melior::ir::{Attribute, BlockLike, BlockRef, Type, Value, ValueLike};
+use rustc_ast::{InlineAsmOptions, InlineAsmTemplatePiece};
+use rustc_data_structures::fx::FxHashMap;
+use rustc_hir::def_id::DefId;
+use rustc_middle::bug;
+use rustc_middle::mir::{
+    AggregateKind, AssertKind, BasicBlock, BinOp, CastKind, ConstOperand, InlineAsmMacro,
+    InlineAsmOperand, NullOp, SourceInfo, UnOp, UnwindTerminateReason,
+};
+use rustc_middle::ty::{self, Instance, Ty};
+use rustc_span::Span;
+
+pub(crate) mod emit;
+pub(crate) mod lower;
+
+pub(crate) const PLACE_TYPE: &str = "!rust.place";
+pub(crate) const OPERAND_TYPE: &str = "!rust.operand";
+pub(crate) const RVALUE_TYPE: &str = "!rust.rvalue";
+
+/// Rustc data referenced from `rust` dialect ops.
+#[derive(Clone, Debug)]
+pub(crate) enum MirData<'tcx> {
+    Instance(Instance<'tcx>),
+    SourceInfo(SourceInfo),
+    Ty(Ty<'tcx>),
+    Const(ConstOperand<'tcx>),
+    BinOp(BinOp),
+    UnOp(UnOp),
+    Cast(CastKind, Ty<'tcx>),
+    NullaryOp(NullOp<'tcx>, Ty<'tcx>),
+    Repeat(ty::Const<'tcx>),
+    Aggregate(AggregateKind<'tcx>),
+    ThreadLocalRef(DefId),
+    /// The operands of the assert message are operands of the `rust.assert` op instead.
+    Assert(AssertKind<()>),
+    InlineAsm(InlineAsmData<'tcx>),
+}
+
+/// An `InlineAsm` terminator. The values and places of its `operands` are also operands of the
+/// `rust.inline_asm` op, in the same order: the value of an `In`, the place of an `Out` if it has
+/// one, and the value followed by the place, if any, of an `InOut`.
+#[derive(Clone, Debug)]
+pub(crate) struct InlineAsmData<'tcx> {
+    pub(crate) asm_macro: InlineAsmMacro,
+    pub(crate) template: &'tcx [InlineAsmTemplatePiece],
+    pub(crate) operands: Vec<InlineAsmOperand<'tcx>>,
+    pub(crate) options: InlineAsmOptions,
+    pub(crate) line_spans: &'tcx [Span],
+}
+
+/// Side table for the `mir` attribute of `rust` dialect ops in a single module.
+#[derive(Default)]
+pub(crate) struct MirTables<'tcx> {
+    data: RefCell<Vec<MirData<'tcx>>>,
+    /// The MIR block each block of a `rust.fn` was emitted for, by [`block_key`]. Blocks can't
+    /// carry attributes, and passes may erase or merge them, so they can't be matched by position.
+    blocks: RefCell<FxHashMap<usize, BasicBlock>>,
+}
+
+impl<'tcx> MirTables<'tcx> {
+    pub(crate) fn push_block(&self, block: BlockRef<'_, '_>, bb: BasicBlock) {
+        self.blocks.borrow_mut().insert(block_key(block), bb);
+    }
+
+    /// The MIR block `block` was emitted for, or `None` if a pass created it.
+    pub(crate) fn block(&self, block: BlockRef<'_, '_>) -> Option<BasicBlock> {
+        self.blocks.borrow().get(&block_key(block)).copied()
+    }
+
+    pub(crate) fn push(&self, data: MirData<'tcx>) -> i64 {
+        let mut table = self.data.borrow_mut();
+        table.push(data);
+        (table.len() - 1) as i64
+    }
+
+    pub(crate) fn get(&self, index: i64) -> MirData<'tcx> {
+        self.data.borrow()[index as usize].clone()
+    }
+}
+
+macro_rules! mir_data_getters {
+    ($($name:ident($variant:ident) -> $ret:ty { $($pat:ident),* => $val:expr };)*) => {
+        impl<'tcx> MirTables<'tcx> {
+            $(
+                pub(crate) fn $name(&self, index: i64) -> $ret {
+                    match self.get(index) {
+                        MirData::$variant($($pat),*) => $val,
+                        data => bug!("expected {}, found {:?}", stringify!($variant), data),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+mir_data_getters! {
+    instance(Instance) -> Instance<'tcx> { instance => instance };
+    source_info(SourceInfo) -> SourceInfo { source_info => source_info };
+    ty(Ty) -> Ty<'tcx> { ty => ty };
+    constant(Const) -> ConstOperand<'tcx> { constant => constant };
+    bin_op(BinOp) -> BinOp { bin_op => bin_op };
+    un_op(UnOp) -> UnOp { un_op => un_op };
+    cast(Cast) -> (CastKind, Ty<'tcx>) { kind, ty => (kind, ty) };
+    nullary_op(NullaryOp) -> (NullOp<'tcx>, Ty<'tcx>) { null_op, ty => (null_op, ty) };
+    repeat(Repeat) -> ty::Const<'tcx> { count => count };
+    aggregate(Aggregate) -> AggregateKind<'tcx> { kind => kind };
+    thread_local_ref(ThreadLocalRef) -> DefId { def_id => def_id };
+    assert(Assert) -> AssertKind<()> { msg => msg };
+    inline_asm(InlineAsm) -> InlineAsmData<'tcx> { data => data };
+}
+
+pub(crate) fn place_type(context: &Context) -> Type<'_> {
+    Type::parse(context, PLACE_TYPE).unwrap()
+}
+
+pub(crate) fn operand_type(context: &Context) -> Type<'_> {
+    Type::parse(context, OPERAND_TYPE).unwrap()
+}
+
+pub(crate) fn rvalue_type(context: &Context) -> Type<'_> {
+    Type::parse(context, RVALUE_TYPE).unwrap()
+}
+
+pub(crate) fn int_attr(context: &Context, value: i64) -> Attribute<'_> {
+    IntegerAttribute::new(IntegerType::new(context, 64).into(), value).into()
+}
+
+pub(crate) fn str_attr<'ml>(context: &'ml Context, value: &str) -> Attribute<'ml> {
+    StringAttribute::new(context, value).into()
+}
+
+pub(crate) fn op_name<'ml, 'a>(op: &impl OperationLike<'ml, 'a>) -> &'ml str {
+    op.name().as_string_ref().as_str().unwrap()
+}
+
+/// Reads an integer attribute written by [`int_attr`].
+pub(crate) fn read_int_attr<'ml, 'a>(op: &impl OperationLike<'ml, 'a>, name: &str) -> i64 {
+    let attr = op
+        .attribute(name)
+        .unwrap_or_else(|_| bug!("`{}` is missing attribute `{name}`", op_name(op)));
+    IntegerAttribute::try_from(attr)
+        .unwrap_or_else(|_| bug!("attribute `{name}` is not an integer"))
+        .value()
+}
+
+pub(crate) fn read_str_attr<'ml, 'a>(op: &impl OperationLike<'ml, 'a>, name: &str) -> &'ml str {
+    let attr = op
+        .attribute(name)
+        .unwrap_or_else(|_| bug!("`{}` is missing attribute `{name}`", op_name(op)));
+    StringAttribute::try_from(attr)
+        .unwrap_or_else(|_| bug!("attribute `{name}` is not a string"))
+        .value()
+}
+
+/// Reads a list of `u128` values written as decimal strings.
+pub(crate) fn read_u128_list<'ml, 'a>(op: &impl OperationLike<'ml, 'a>, name: &str) -> Vec<u128> {
+    let list = read_str_attr(op, name);
+    if list.is_empty() {
+        return vec![];
+    }
+    list.split(',').map(|value| value.parse().unwrap()).collect()
+}
+
+pub(crate) fn terminate_reason_str(reason: UnwindTerminateReason) -> &'static str {
+    match reason {
+        UnwindTerminateReason::Abi => "abi",
+        UnwindTerminateReason::InCleanup => "in_cleanup",
+    }
+}
+
+/// Parses a reason written by [`terminate_reason_str`].
+pub(crate) fn parse_terminate_reason(reason: &str) -> UnwindTerminateReason {
+    match reason {
+        "abi" => UnwindTerminateReason::Abi,
+        "in_cleanup" => UnwindTerminateReason::InCleanup,
+        _ => bug!("unknown unwind terminate reason `{reason}`"),
+    }
+}
+
+/// Identity of an SSA value, for use as a map key.
+pub(crate) fn value_key(value: Value<'_, '_>) -> usize {
+    value.to_raw().ptr as usize
+}
+
+/// Identity of a block, for use as a map key.
+pub(crate) fn block_key(block: BlockRef<'_, '_>) -> usize {
+    block.to_raw().ptr as usize
+}
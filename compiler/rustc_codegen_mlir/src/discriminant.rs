@@ -0,0 +1,162 @@
+//! Handling of enum discriminants
+//!
+//! Adapted from `rustc_codegen_cranelift/src/discriminant.rs`.
+
+use melior::ir::Value;
+use rustc_abi::Primitive::{Int, Pointer};
+use rustc_abi::{FieldIdx, TagEncoding, VariantIdx, Variants};
+use rustc_middle::ty::layout::TyAndLayout;
+
+use crate::builder::IntCC;
+use crate::cast::intcast;
+use crate::common::FunctionCx;
+use crate::value_and_place::{MPlace, MValue, immediate_type};
+
+pub(crate) fn codegen_set_discriminant<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    place: MPlace<'ml, 'a, 'tcx>,
+    variant_index: VariantIdx,
+) {
+    let layout = place.layout();
+    if layout.for_variant(fx, variant_index).abi.is_uninhabited() {
+        return;
+    }
+    match layout.variants {
+        Variants::Single { index } => {
+            assert_eq!(index, variant_index);
+        }
+        Variants::Multiple {
+            tag: _,
+            tag_field,
+            tag_encoding: TagEncoding::Direct,
+            variants: _,
+        } => {
+            let ptr = place.place_field(fx, FieldIdx::new(tag_field));
+            let to = layout.ty.discriminant_for_variant(fx.tcx, variant_index).unwrap().val;
+            let ty = immediate_type(fx, ptr.layout()).unwrap();
+            let raw_val = ptr.layout().size.truncate(to);
+            let to = fx.bx.iconst(ty, raw_val as i128);
+            ptr.write_mvalue(fx, MValue::by_val(to, ptr.layout()));
+        }
+        Variants::Multiple {
+            tag: _,
+            tag_field,
+            tag_encoding: TagEncoding::Niche { untagged_variant, ref niche_variants, niche_start },
+            variants: _,
+        } => {
+            if variant_index != untagged_variant {
+                let niche = place.place_field(fx, FieldIdx::new(tag_field));
+                let niche_value = variant_index.as_u32() - niche_variants.start().as_u32();
+                let niche_value = (niche_value as u128).wrapping_add(niche_start);
+                let niche_value = match immediate_type(fx, niche.layout()).unwrap() {
+                    // Pointer niches are only ever small integers, like the null pointer.
+                    ty if ty == fx.pointer_type() => {
+                        let int = fx.bx.iconst(fx.usize_type(), niche_value as i128);
+                        fx.bx.cast("llvm.inttoptr", int, ty)
+                    }
+                    ty => fx.bx.iconst(ty, niche.layout().size.truncate(niche_value) as i128),
+                };
+                niche.write_mvalue(fx, MValue::by_val(niche_value, niche.layout()));
+            }
+        }
+    }
+}
+
+pub(crate) fn codegen_get_discriminant<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    dest: MPlace<'ml, 'a, 'tcx>,
+    value: MValue<'ml, 'a, 'tcx>,
+    dest_layout: TyAndLayout<'tcx>,
+) {
+    let layout = value.layout();
+
+    if layout.abi.is_uninhabited() {
+        return;
+    }
+
+    let cast_to = immediate_type(fx, dest_layout).unwrap();
+
+    let (tag_scalar, tag_field, tag_encoding) = match &layout.variants {
+        Variants::Single { index } => {
+            let discr_val = layout
+                .ty
+                .discriminant_for_variant(fx.tcx, *index)
+                .map_or(u128::from(index.as_u32()), |discr| discr.val);
+            let raw_val = dest_layout.size.truncate(discr_val);
+            let val = fx.bx.iconst(cast_to, raw_val as i128);
+            dest.write_mvalue(fx, MValue::by_val(val, dest_layout));
+            return;
+        }
+        Variants::Multiple { tag, tag_field, tag_encoding, variants: _ } => {
+            (tag, *tag_field, tag_encoding)
+        }
+    };
+
+    // Read the tag/niche-encoded discriminant from memory.
+    let tag = value.value_field(fx, FieldIdx::new(tag_field));
+    let tag = tag.load_scalar(fx);
+    // Niches may be in pointers, which are compared as integers.
+    let tag: Value<'ml, 'a> = match tag_scalar.primitive() {
+        Pointer(_) => fx.bx.cast("llvm.ptrtoint", tag, fx.usize_type()),
+        _ => tag,
+    };
+
+    // Decode the discriminant (specifically if it's niche-encoded).
+    match *tag_encoding {
+        TagEncoding::Direct => {
+            let signed = match tag_scalar.primitive() {
+                Int(_, signed) => signed,
+                _ => false,
+            };
+            let val = intcast(fx, tag, cast_to, signed);
+            dest.write_mvalue(fx, MValue::by_val(val, dest_layout));
+        }
+        TagEncoding::Niche { untagged_variant, ref niche_variants, niche_start } => {
+            let relative_max = niche_variants.end().as_u32() - niche_variants.start().as_u32();
+
+            // We have a subrange `niche_start..=niche_end` inside `range`.
+            // If the value of the tag is inside this subrange, it's a
+            // "niche value", an increment of the discriminant. Otherwise it
+            // indicates the untagged variant.
+            // A general algorithm to extract the discriminant from the tag
+            // is:
+            // relative_tag = tag - niche_start
+            // is_niche = relative_tag <= (ule) relative_max
+            // discr = if is_niche {
+            //     cast(relative_tag) + niche_variants.start()
+            // } else {
+            //     untagged_variant
+            // }
+            // However, we will likely be able to emit simpler code.
+            let tag_size = tag_scalar.size(fx).bits();
+            let truncate = |value: u128| (value << (128 - tag_size)) >> (128 - tag_size);
+
+            let (is_niche, tagged_discr, delta) = if relative_max == 0 {
+                // Best case scenario: only one tagged variant. This will
+                // likely become just a comparison and a jump.
+                let is_niche = fx.bx.icmp_imm(IntCC::Equal, tag, truncate(niche_start) as i128);
+                let tagged_discr = fx.bx.iconst(cast_to, niche_variants.start().as_u32() as i128);
+                (is_niche, tagged_discr, 0)
+            } else {
+                // The special cases don't apply, so we'll have to go with
+                // the general algorithm.
+                let relative_discr =
+                    fx.bx.iadd_imm(tag, truncate(niche_start.wrapping_neg()) as i128);
+                let cast_tag = intcast(fx, relative_discr, cast_to, false);
+                let is_niche = fx.bx.icmp_imm(
+                    IntCC::UnsignedLessThanOrEqual,
+                    relative_discr,
+                    i128::from(relative_max),
+                );
+                (is_niche, cast_tag, niche_variants.start().as_u32() as i128)
+            };
+
+            let tagged_discr =
+                if delta == 0 { tagged_discr } else { fx.bx.iadd_imm(tagged_discr, delta) };
+
+            let untagged_variant = fx.bx.iconst(cast_to, i128::from(untagged_variant.as_u32()));
+            let discr = fx.bx.select(is_niche, tagged_discr, untagged_variant);
+            dest.write_mvalue(fx, MValue::by_val(discr, dest_layout));
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Writing MLIR modules to disk, for `--emit=mlir` and `-Zmlir-dump-after`.
+//!
+//! Every module is written twice: as text to the given `.mlir` path, and as MLIR bytecode next to
+//! it with the `.mlirbc` extension.
+
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::{fs, io, slice};
+
+use melior::ir::Module;
+use melior::ir::operation::OperationLike;
+use rustc_session::Session;
+use rustc_session::config::OutputFilenames;
+
+use crate::ffi::{MlirStringRef, mlirOperationWriteBytecode, raw_op};
+
+pub(crate) const BYTECODE_EXTENSION: &str = "mlirbc";
+
+unsafe extern "C" fn append_to_vec(chunk: MlirStringRef, user_data: *mut c_void) {
+    let buffer = unsafe { &mut *(user_data as *mut Vec<u8>) };
+    let chunk = unsafe { slice::from_raw_parts(chunk.data as *const u8, chunk.length) };
+    buffer.extend_from_slice(chunk);
+}
+
+fn to_bytecode(module: &Module<'_>) -> Vec<u8> {
+    let mut bytecode = Vec::new();
+    unsafe {
+        mlirOperationWriteBytecode(
+            raw_op(&module.as_operation()),
+            append_to_vec,
+            &mut bytecode as *mut Vec<u8> as *mut c_void,
+        );
+    }
+    bytecode
+}
+
+/// Writes `module` as text to `path`, and as bytecode to `path` with the `.mlirbc` extension.
+pub(crate) fn write_module(module: &Module<'_>, path: &Path) -> io::Result<()> {
+    fs::write(path, module.as_operation().to_string())?;
+    fs::write(path.with_extension(BYTECODE_EXTENSION), to_bytecode(module))
+}
+
+/// Whether `-Zmlir-dump-after` asks for a dump after `stage`, which is either one of the stages of
+/// the backend (`rust`, `rust-passes`, `lower`, `raise-loops`, `vectorize`, `mlir-passes`) or the
+/// name of an MLIR pass.
+pub(crate) fn should_dump_after(sess: &Session, stage: &str) -> bool {
+    sess.opts.unstable_opts.mlir_dump_after.iter().any(|s| s == stage || s == "all")
+}
+
+fn dump_path(outputs: &OutputFilenames, cgu_name: &str, stage: &str) -> PathBuf {
+    outputs.temp_path_ext(&format!("{stage}.mlir"), Some(cgu_name))
+}
+
+/// Dumps `module` if requested by `-Zmlir-dump-after`, to
+/// `<crate>.<cgu>.<stage>.mlir` and `<crate>.<cgu>.<stage>.mlirbc`.
+pub(crate) fn dump_after(
+    sess: &Session,
+    outputs: &OutputFilenames,
+    cgu_name: &str,
+    stage: &str,
+    module: &Module<'_>,
+) {
+    if !should_dump_after(sess, stage) {
+        return;
+    }
+
+    let path = dump_path(outputs, cgu_name, stage);
+    if let Err(err) = write_module(module, &path) {
+        sess.dcx().fatal(format!("error writing MLIR dump `{}`: {err}", path.display()));
+    }
+}
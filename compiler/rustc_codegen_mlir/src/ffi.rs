@@ -0,0 +1,205 @@
+//! The parts of the MLIR C API that `melior` doesn't wrap.
+//!
+//! The handle types have the same layout as the `mlir_sys` types `melior` wraps, and are created
+//! from the `to_raw` pointer of the corresponding `melior` type.
+
+use std::ffi::{c_char, c_uint, c_void};
+
+use melior::Context;
+use melior::ir::operation::OperationLike;
+use melior::ir::{Attribute, BlockLike, BlockRef, Value, ValueLike};
+use melior::pass::PassManager;
+use rustc_codegen_llvm::llvm;
+
+/// `MlirContext` from the MLIR C API.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct MlirContext {
+    pub(crate) ptr: *mut c_void,
+}
+
+/// `MlirOperation` from the MLIR C API.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) struct MlirOperation {
+    pub(crate) ptr: *mut c_void,
+}
+
+/// `MlirBlock` from the MLIR C API.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) struct MlirBlock {
+    pub(crate) ptr: *mut c_void,
+}
+
+/// `MlirValue` from the MLIR C API.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct MlirValue {
+    pub(crate) ptr: *const c_void,
+}
+
+/// `MlirOpOperand` from the MLIR C API.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct MlirOpOperand {
+    pub(crate) ptr: *const c_void,
+}
+
+/// `MlirAttribute` from the MLIR C API.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct MlirAttribute {
+    pub(crate) ptr: *const c_void,
+}
+
+/// `MlirPassManager` from the MLIR C API.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct MlirPassManager {
+    pub(crate) ptr: *mut c_void,
+}
+
+/// `MlirStringRef` from the MLIR C API.
+#[repr(C)]
+pub(crate) struct MlirStringRef {
+    pub(crate) data: *const c_char,
+    pub(crate) length: usize,
+}
+
+/// `MlirLogicalResult` from the MLIR C API.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct MlirLogicalResult {
+    pub(crate) value: i8,
+}
+
+/// `MlirLLVMDIEmissionKind` from the MLIR C API.
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub(crate) enum EmissionKind {
+    None = 0,
+    Full = 1,
+    LineTablesOnly = 2,
+    DebugDirectivesOnly = 3,
+}
+
+/// `MlirLLVMDINameTableKind` from the MLIR C API.
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub(crate) enum NameTableKind {
+    Default = 0,
+    Gnu = 1,
+    None = 2,
+    Apple = 3,
+}
+
+extern "C" {
+    // Use-lists and IR mutation, for `raise`.
+    pub(crate) fn mlirValueGetFirstUse(value: MlirValue) -> MlirOpOperand;
+    pub(crate) fn mlirOpOperandIsNull(op_operand: MlirOpOperand) -> bool;
+    pub(crate) fn mlirOpOperandGetOwner(op_operand: MlirOpOperand) -> MlirOperation;
+    pub(crate) fn mlirOpOperandGetOperandNumber(op_operand: MlirOpOperand) -> c_uint;
+    pub(crate) fn mlirOpOperandGetNextUse(op_operand: MlirOpOperand) -> MlirOpOperand;
+    pub(crate) fn mlirOperationSetOperand(op: MlirOperation, pos: isize, new_value: MlirValue);
+    pub(crate) fn mlirOperationGetBlock(op: MlirOperation) -> MlirBlock;
+    pub(crate) fn mlirBlockGetParentOperation(block: MlirBlock) -> MlirOperation;
+    pub(crate) fn mlirOperationRemoveFromParent(op: MlirOperation);
+    pub(crate) fn mlirBlockAppendOwnedOperation(block: MlirBlock, op: MlirOperation);
+    pub(crate) fn mlirOperationDestroy(op: MlirOperation);
+    pub(crate) fn mlirBlockDetach(block: MlirBlock);
+    pub(crate) fn mlirBlockDestroy(block: MlirBlock);
+
+    pub(crate) fn mlirPassManagerRunOnOp(
+        pass_manager: MlirPassManager,
+        op: MlirOperation,
+    ) -> MlirLogicalResult;
+
+    pub(crate) fn mlirOperationWriteBytecode(
+        op: MlirOperation,
+        callback: unsafe extern "C" fn(MlirStringRef, *mut c_void),
+        user_data: *mut c_void,
+    );
+
+    pub(crate) fn mlirTranslateModuleToLLVMIR<'ll>(
+        module: MlirOperation,
+        context: &'ll llvm::Context,
+    ) -> Option<&'ll llvm::Module>;
+
+    // Debug info attributes, for `debuginfo`.
+    // Sic, this is how the MLIR C API spells it.
+    pub(crate) fn mlirDisctinctAttrCreate(referenced_attr: MlirAttribute) -> MlirAttribute;
+    pub(crate) fn mlirLLVMDICompileUnitAttrGet(
+        context: MlirContext,
+        id: MlirAttribute,
+        source_language: c_uint,
+        file: MlirAttribute,
+        producer: MlirAttribute,
+        is_optimized: bool,
+        emission_kind: EmissionKind,
+        name_table_kind: NameTableKind,
+    ) -> MlirAttribute;
+    pub(crate) fn mlirLLVMDISubprogramAttrGet(
+        context: MlirContext,
+        rec_id: MlirAttribute,
+        is_rec_self: bool,
+        id: MlirAttribute,
+        compile_unit: MlirAttribute,
+        scope: MlirAttribute,
+        name: MlirAttribute,
+        linkage_name: MlirAttribute,
+        file: MlirAttribute,
+        line: c_uint,
+        scope_line: c_uint,
+        subprogram_flags: u64,
+        ty: MlirAttribute,
+        n_retained_nodes: isize,
+        retained_nodes: *const MlirAttribute,
+        n_annotations: isize,
+        annotations: *const MlirAttribute,
+    ) -> MlirAttribute;
+    pub(crate) fn mlirLLVMDILocalVariableAttrGet(
+        context: MlirContext,
+        scope: MlirAttribute,
+        name: MlirAttribute,
+        file: MlirAttribute,
+        line: c_uint,
+        arg: c_uint,
+        align_in_bits: c_uint,
+        ty: MlirAttribute,
+        flags: i64,
+    ) -> MlirAttribute;
+}
+
+pub(crate) fn raw_context(context: &Context) -> MlirContext {
+    MlirContext { ptr: context.to_raw().ptr as *mut c_void }
+}
+
+pub(crate) fn raw_op<'ml, 'a>(op: &impl OperationLike<'ml, 'a>) -> MlirOperation {
+    MlirOperation { ptr: op.to_raw().ptr as *mut c_void }
+}
+
+pub(crate) fn raw_block(block: BlockRef<'_, '_>) -> MlirBlock {
+    MlirBlock { ptr: block.to_raw().ptr as *mut c_void }
+}
+
+pub(crate) fn raw_value(value: Value<'_, '_>) -> MlirValue {
+    MlirValue { ptr: value.to_raw().ptr as *const c_void }
+}
+
+pub(crate) fn raw_attr(attr: Attribute<'_>) -> MlirAttribute {
+    MlirAttribute { ptr: attr.to_raw().ptr as *const c_void }
+}
+
+pub(crate) fn raw_pass_manager(pass_manager: &PassManager<'_>) -> MlirPassManager {
+    MlirPassManager { ptr: pass_manager.to_raw().ptr as *mut c_void }
+}
+
+/// Wraps an attribute returned by the MLIR C API, which is owned by `_context`.
+pub(crate) fn from_raw_attr(_context: &Context, attr: MlirAttribute) -> Attribute<'_> {
+    assert!(!attr.ptr.is_null(), "failed to create attribute");
+    // SAFETY: `MlirAttribute` has the same layout as the `mlir_sys` type `melior` wraps.
+    unsafe { Attribute::from_raw(std::mem::transmute(attr)) }
+}
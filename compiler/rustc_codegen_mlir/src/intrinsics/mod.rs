@@ -0,0 +1,794 @@
+//! Codegen of intrinsics.
+//!
+//! See `rustc_codegen_cranelift/src/intrinsics` and `rustc_codegen_ssa/src/mir/intrinsic.rs` for
+//! reference. Integer and float intrinsics map onto `arith`, `math` and `llvm.intr.*` ops, memory
+//! and atomic intrinsics onto the `llvm` dialect and `simd_*` intrinsics onto the `vector`
+//! dialect. Intrinsics that aren't handled here are called through their fallback body.
+
+macro_rules! intrinsic_args {
+    ($args:expr => ($($arg:tt),*); $intrinsic:expr) => {
+        #[allow(unused_parens)]
+        let ($($arg),*) = if let [$($arg),*] = $args {
+            ($($arg.value),*)
+        } else {
+            $crate::intrinsics::bug_on_incorrect_arg_count($intrinsic);
+        };
+    }
+}
+
+mod simd;
+
+use melior::ir::attribute::StringAttribute;
+use melior::ir::operation::OperationLike;
+use melior::ir::r#type::FunctionType;
+use melior::ir::{Attribute, Type, Value, ValueLike};
+use rustc_abi::{Abi, Size};
+use rustc_codegen_ssa::common::{AtomicOrdering, AtomicRmwBinOp};
+use rustc_codegen_ssa::errors::{self as ssa_errors, InvalidMonomorphization};
+use rustc_hir::LangItem;
+use rustc_middle::mir::{self, BasicBlock, BinOp, UnwindAction};
+use rustc_middle::ty::layout::{HasParamEnv, LayoutOf, ValidityRequirement};
+use rustc_middle::ty::print::{with_no_trimmed_paths, with_no_visible_paths};
+use rustc_middle::ty::{self, GenericArgsRef, Instance, Ty};
+use rustc_middle::{bug, span_bug};
+use rustc_span::Span;
+use rustc_span::symbol::{Symbol, sym};
+use rustc_target::spec::PanicStrategy;
+
+use crate::abi::CallArgument;
+use crate::builder::{AtomicRmwOp, IntCC};
+use crate::common::FunctionCx;
+use crate::type_of::type_sign;
+use crate::value_and_place::{MPlace, MValue, immediate_type};
+
+fn bug_on_incorrect_arg_count(intrinsic: impl std::fmt::Display) -> ! {
+    bug!("wrong number of args for intrinsic {}", intrinsic);
+}
+
+fn report_atomic_type_validation_error<'tcx>(
+    fx: &mut FunctionCx<'_, '_, 'tcx>,
+    intrinsic: Symbol,
+    span: Span,
+    ty: Ty<'tcx>,
+) {
+    fx.tcx.dcx().emit_err(InvalidMonomorphization::BasicIntegerType { span, name: intrinsic, ty });
+    // Prevent verifier error
+    fx.bx.trap();
+}
+
+/// Calls the C function `name`, declaring it in the module if necessary.
+fn lib_call<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    name: &str,
+    args: &[Value<'ml, 'a>],
+    results: &[Type<'ml>],
+) -> Vec<Value<'ml, 'a>> {
+    let params = args.iter().map(|arg| arg.r#type()).collect::<Vec<_>>();
+    fx.cx.reference_fn(name, FunctionType::new(fx.context, &params, results).into());
+    let call = fx.bx.call(name, args, results);
+    (0..call.result_count()).map(|i| call.result(i).unwrap().into()).collect()
+}
+
+/// Multiplies `count` by the size of `ty`, for intrinsics taking an element count.
+fn count_to_bytes<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    ty: Ty<'tcx>,
+    count: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    let size = fx.layout_of(ty).size.bytes();
+    fx.bx.imul_imm(count, size as i128)
+}
+
+pub(crate) fn codegen_intrinsic_call<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    instance: Instance<'tcx>,
+    args: &[CallArgument<'ml, 'a, 'tcx>],
+    ret: MPlace<'ml, 'a, 'tcx>,
+    target: Option<BasicBlock>,
+    unwind: UnwindAction,
+    source_info: mir::SourceInfo,
+) -> Result<(), Instance<'tcx>> {
+    let intrinsic = fx.tcx.item_name(instance.def_id());
+
+    if intrinsic.as_str().starts_with("simd_") {
+        self::simd::codegen_simd_intrinsic_call(
+            fx,
+            intrinsic,
+            instance.args,
+            args,
+            ret,
+            source_info.span,
+        );
+    } else if !codegen_float_intrinsic_call(fx, intrinsic, args, ret) {
+        match codegen_regular_intrinsic_call(
+            fx,
+            instance,
+            intrinsic,
+            instance.args,
+            args,
+            ret,
+            target,
+            unwind,
+            source_info,
+        )? {
+            IntrinsicResult::Done => {}
+            IntrinsicResult::Diverged => return Ok(()),
+        }
+    }
+
+    match target {
+        Some(target) => {
+            let ret_block = fx.get_block(target);
+            fx.bx.br(&ret_block, &[]);
+        }
+        None => fx.bx.unreachable(),
+    }
+    Ok(())
+}
+
+/// Whether an intrinsic left the current block open for the branch to the return block.
+enum IntrinsicResult {
+    Done,
+    /// The intrinsic terminated the current block itself.
+    Diverged,
+}
+
+fn codegen_float_intrinsic_call<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    intrinsic: Symbol,
+    args: &[CallArgument<'ml, 'a, 'tcx>],
+    ret: MPlace<'ml, 'a, 'tcx>,
+) -> bool {
+    let (name, arg_count) = match intrinsic {
+        sym::expf32 | sym::expf64 => ("math.exp", 1),
+        sym::exp2f32 | sym::exp2f64 => ("math.exp2", 1),
+        sym::sqrtf32 | sym::sqrtf64 => ("math.sqrt", 1),
+        sym::powif32 | sym::powif64 => ("math.fpowi", 2),
+        sym::powf32 | sym::powf64 => ("math.powf", 2),
+        sym::logf32 | sym::logf64 => ("math.log", 1),
+        sym::log2f32 | sym::log2f64 => ("math.log2", 1),
+        sym::log10f32 | sym::log10f64 => ("math.log10", 1),
+        sym::fabsf32 | sym::fabsf64 => ("math.absf", 1),
+        sym::fmaf32 | sym::fmaf64 => ("math.fma", 3),
+        sym::fmuladdf32 | sym::fmuladdf64 => ("llvm.intr.fmuladd", 3),
+        sym::copysignf32 | sym::copysignf64 => ("math.copysign", 2),
+        sym::floorf32 | sym::floorf64 => ("math.floor", 1),
+        sym::ceilf32 | sym::ceilf64 => ("math.ceil", 1),
+        sym::truncf32 | sym::truncf64 => ("math.trunc", 1),
+        sym::rintf32 | sym::rintf64 => ("llvm.intr.rint", 1),
+        sym::roundf32 | sym::roundf64 => ("math.round", 1),
+        sym::roundevenf32 | sym::roundevenf64 => ("math.roundeven", 1),
+        sym::nearbyintf32 | sym::nearbyintf64 => ("llvm.intr.nearbyint", 1),
+        sym::sinf32 | sym::sinf64 => ("math.sin", 1),
+        sym::cosf32 | sym::cosf64 => ("math.cos", 1),
+        sym::minnumf32 | sym::minnumf64 => ("arith.minnumf", 2),
+        sym::maxnumf32 | sym::maxnumf64 => ("arith.maxnumf", 2),
+        _ => return false,
+    };
+
+    if args.len() != arg_count {
+        bug_on_incorrect_arg_count(intrinsic);
+    }
+
+    let args = args.iter().map(|arg| arg.value.load_scalar(fx)).collect::<Vec<_>>();
+    let res = fx.bx.intrinsic(name, &args, args[0].r#type());
+    ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+
+    true
+}
+
+fn codegen_regular_intrinsic_call<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    instance: Instance<'tcx>,
+    intrinsic: Symbol,
+    generic_args: GenericArgsRef<'tcx>,
+    args: &[CallArgument<'ml, 'a, 'tcx>],
+    ret: MPlace<'ml, 'a, 'tcx>,
+    target: Option<BasicBlock>,
+    unwind: UnwindAction,
+    source_info: mir::SourceInfo,
+) -> Result<IntrinsicResult, Instance<'tcx>> {
+    let usize_layout = fx.layout_of(fx.tcx.types.usize);
+
+    match intrinsic {
+        sym::abort => {
+            fx.bx.trap();
+            return Ok(IntrinsicResult::Diverged);
+        }
+        sym::likely | sym::unlikely => {
+            intrinsic_args!(args => (a); intrinsic);
+
+            ret.write_mvalue(fx, a);
+        }
+        sym::breakpoint => {
+            intrinsic_args!(args => (); intrinsic);
+
+            fx.bx.debug_trap();
+        }
+        sym::copy => {
+            intrinsic_args!(args => (src, dst, count); intrinsic);
+            let src = src.load_scalar(fx);
+            let dst = dst.load_scalar(fx);
+            let count = count.load_scalar(fx);
+
+            let bytes = count_to_bytes(fx, generic_args.type_at(0), count);
+            fx.bx.mem_intrinsic("llvm.intr.memmove", dst, src, bytes, false);
+        }
+        sym::volatile_copy_memory | sym::volatile_copy_nonoverlapping_memory => {
+            // NOTE: the volatile variants have src and dst swapped
+            intrinsic_args!(args => (dst, src, count); intrinsic);
+            let dst = dst.load_scalar(fx);
+            let src = src.load_scalar(fx);
+            let count = count.load_scalar(fx);
+
+            let bytes = count_to_bytes(fx, generic_args.type_at(0), count);
+            let name = if intrinsic == sym::volatile_copy_nonoverlapping_memory {
+                "llvm.intr.memcpy"
+            } else {
+                "llvm.intr.memmove"
+            };
+            fx.bx.mem_intrinsic(name, dst, src, bytes, true);
+        }
+        sym::write_bytes | sym::volatile_set_memory => {
+            intrinsic_args!(args => (dst, val, count); intrinsic);
+            let dst = dst.load_scalar(fx);
+            let val = val.load_scalar(fx);
+            let count = count.load_scalar(fx);
+
+            let bytes = count_to_bytes(fx, generic_args.type_at(0), count);
+            let volatile = intrinsic == sym::volatile_set_memory;
+            fx.bx.mem_intrinsic("llvm.intr.memset", dst, val, bytes, volatile);
+        }
+        sym::size_of_val | sym::min_align_of_val => {
+            intrinsic_args!(args => (ptr); intrinsic);
+
+            let layout = fx.layout_of(generic_args.type_at(0));
+            // Note: Can't use is_unsized here as truly unsized types need to take the fixed size
+            // branch
+            let meta = if let Abi::ScalarPair(_, _) = ptr.layout().abi {
+                Some(ptr.load_scalar_pair(fx).1)
+            } else {
+                None
+            };
+            let (size, align) = crate::unsize::size_and_align_of(fx, layout, meta);
+            let res = if intrinsic == sym::size_of_val { size } else { align };
+            ret.write_mvalue(fx, MValue::by_val(res, usize_layout));
+        }
+        sym::vtable_size | sym::vtable_align => {
+            intrinsic_args!(args => (vtable); intrinsic);
+            let vtable = vtable.load_scalar(fx);
+
+            let res = if intrinsic == sym::vtable_size {
+                crate::vtable::size_of_obj(fx, vtable)
+            } else {
+                crate::vtable::min_align_of_obj(fx, vtable)
+            };
+            ret.write_mvalue(fx, MValue::by_val(res, usize_layout));
+        }
+
+        sym::needs_drop
+        | sym::type_id
+        | sym::type_name
+        | sym::variant_count
+        | sym::pref_align_of => {
+            intrinsic_args!(args => (); intrinsic);
+
+            let const_val = fx
+                .tcx
+                .const_eval_instance(ty::ParamEnv::reveal_all(), instance, source_info.span)
+                .unwrap();
+            let val = crate::constant::codegen_const_value(fx, const_val, ret.layout().ty);
+            ret.write_mvalue(fx, val);
+        }
+
+        sym::assert_inhabited | sym::assert_zero_valid | sym::assert_mem_uninitialized_valid => {
+            intrinsic_args!(args => (); intrinsic);
+
+            let ty = generic_args.type_at(0);
+            let requirement = ValidityRequirement::from_intrinsic(intrinsic).unwrap();
+            let is_valid = fx
+                .tcx
+                .check_validity_requirement((requirement, fx.param_env().and(ty)))
+                .expect("expect to have layout during codegen");
+            if !is_valid {
+                let layout = fx.layout_of(ty);
+                let msg = with_no_visible_paths!({
+                    with_no_trimmed_paths!({
+                        if layout.abi.is_uninhabited() {
+                            // Use this error even for the other intrinsics as it is more precise.
+                            format!("attempted to instantiate uninhabited type `{ty}`")
+                        } else if requirement == ValidityRequirement::Zero {
+                            format!("attempted to zero-initialize type `{ty}`, which is invalid")
+                        } else {
+                            format!(
+                                "attempted to leave type `{ty}` uninitialized, which is invalid"
+                            )
+                        }
+                    })
+                });
+                let msg = crate::constant::codegen_const_str(fx, &msg);
+                crate::abi::codegen_panic_lang_item(
+                    fx,
+                    source_info,
+                    LangItem::PanicNounwind,
+                    &[msg],
+                    unwind,
+                );
+                return Ok(IntrinsicResult::Diverged);
+            }
+        }
+
+        sym::add_with_overflow | sym::sub_with_overflow | sym::mul_with_overflow => {
+            intrinsic_args!(args => (x, y); intrinsic);
+
+            let bin_op = match intrinsic {
+                sym::add_with_overflow => BinOp::Add,
+                sym::sub_with_overflow => BinOp::Sub,
+                sym::mul_with_overflow => BinOp::Mul,
+                _ => unreachable!(),
+            };
+            let res = crate::num::codegen_checked_int_binop(fx, bin_op, x, y);
+            ret.write_mvalue(fx, res);
+        }
+        sym::exact_div => {
+            intrinsic_args!(args => (x, y); intrinsic);
+
+            // FIXME mark the division as exact
+            let res = crate::num::codegen_int_binop(fx, BinOp::Div, x, y);
+            ret.write_mvalue(fx, res);
+        }
+        sym::saturating_add | sym::saturating_sub => {
+            intrinsic_args!(args => (lhs, rhs); intrinsic);
+
+            assert_eq!(lhs.layout().ty, rhs.layout().ty);
+            let signed = type_sign(lhs.layout().ty);
+            let name = match (intrinsic, signed) {
+                (sym::saturating_add, false) => "llvm.intr.uadd.sat",
+                (sym::saturating_add, true) => "llvm.intr.sadd.sat",
+                (sym::saturating_sub, false) => "llvm.intr.usub.sat",
+                (sym::saturating_sub, true) => "llvm.intr.ssub.sat",
+                _ => unreachable!(),
+            };
+            let layout = lhs.layout();
+            let lhs = lhs.load_scalar(fx);
+            let rhs = rhs.load_scalar(fx);
+            let res = fx.bx.binary(name, lhs, rhs);
+            ret.write_mvalue(fx, MValue::by_val(res, layout));
+        }
+        sym::rotate_left | sym::rotate_right => {
+            intrinsic_args!(args => (x, y); intrinsic);
+
+            let layout = x.layout();
+            let x = x.load_scalar(fx);
+            let y = y.load_scalar(fx);
+            // The rotate amount is a `u32` regardless of the width of `x`.
+            let y = crate::cast::intcast(fx, y, x.r#type(), false);
+            let name =
+                if intrinsic == sym::rotate_left { "llvm.intr.fshl" } else { "llvm.intr.fshr" };
+            let res = fx.bx.intrinsic(name, &[x, x, y], x.r#type());
+            ret.write_mvalue(fx, MValue::by_val(res, layout));
+        }
+        sym::ctlz | sym::ctlz_nonzero | sym::cttz | sym::cttz_nonzero | sym::ctpop => {
+            intrinsic_args!(args => (arg); intrinsic);
+            let val = arg.load_scalar(fx);
+
+            // FIXME tell the backend that the `_nonzero` variants are never called with zero
+            let name = match intrinsic {
+                sym::ctlz | sym::ctlz_nonzero => "math.ctlz",
+                sym::cttz | sym::cttz_nonzero => "math.cttz",
+                sym::ctpop => "math.ctpop",
+                _ => unreachable!(),
+            };
+            let res = fx.bx.unary(name, val);
+            // The count is a `u32` regardless of the width of the argument.
+            let res =
+                crate::cast::intcast(fx, res, immediate_type(fx, ret.layout()).unwrap(), false);
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+        sym::bswap | sym::bitreverse => {
+            intrinsic_args!(args => (arg); intrinsic);
+
+            let layout = arg.layout();
+            let val = arg.load_scalar(fx);
+            let res = if intrinsic == sym::bswap {
+                // `llvm.bswap` is only defined for an even number of bytes.
+                if layout.size.bytes() == 1 { val } else { fx.bx.unary("llvm.intr.bswap", val) }
+            } else {
+                fx.bx.unary("llvm.intr.bitreverse", val)
+            };
+            ret.write_mvalue(fx, MValue::by_val(res, layout));
+        }
+
+        sym::arith_offset => {
+            intrinsic_args!(args => (base, offset); intrinsic);
+            let offset = offset.load_scalar(fx);
+
+            let pointee_ty = base.layout().ty.builtin_deref(true).unwrap();
+            let bytes = count_to_bytes(fx, pointee_ty, offset);
+            let base_val = base.load_scalar(fx);
+            let res = fx.bx.ptr_offset(base_val, bytes);
+            ret.write_mvalue(fx, MValue::by_val(res, base.layout()));
+        }
+        sym::ptr_mask => {
+            intrinsic_args!(args => (ptr, mask); intrinsic);
+
+            let layout = ptr.layout();
+            let ptr = ptr.load_scalar(fx);
+            let mask = mask.load_scalar(fx);
+            let res = fx.bx.intrinsic("llvm.intr.ptrmask", &[ptr, mask], ptr.r#type());
+            ret.write_mvalue(fx, MValue::by_val(res, layout));
+        }
+        sym::ptr_offset_from | sym::ptr_offset_from_unsigned => {
+            intrinsic_args!(args => (ptr, base); intrinsic);
+
+            let pointee_size = fx.layout_of(generic_args.type_at(0)).size.bytes();
+            let usize_ty = fx.usize_type();
+            let ptr = ptr.load_scalar(fx);
+            let base = base.load_scalar(fx);
+            let ptr = fx.bx.cast("llvm.ptrtoint", ptr, usize_ty);
+            let base = fx.bx.cast("llvm.ptrtoint", base, usize_ty);
+            let diff = fx.bx.binary("arith.subi", ptr, base);
+            let pointee_size = fx.bx.iconst(usize_ty, i128::from(pointee_size));
+            let res = if intrinsic == sym::ptr_offset_from {
+                fx.bx.binary("arith.divsi", diff, pointee_size)
+            } else {
+                fx.bx.binary("arith.divui", diff, pointee_size)
+            };
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::volatile_load | sym::unaligned_volatile_load => {
+            intrinsic_args!(args => (ptr); intrinsic);
+
+            let ptr = ptr.load_scalar(fx);
+            let layout = ret.layout();
+            let align = if intrinsic == sym::unaligned_volatile_load {
+                1
+            } else {
+                layout.align.abi.bytes()
+            };
+            match immediate_type(fx, layout) {
+                Some(ty) => {
+                    let val = fx.bx.load_volatile(ty, ptr, align);
+                    ret.write_mvalue(fx, MValue::by_val(val, layout));
+                }
+                None => {
+                    let size = fx.bx.iconst(fx.usize_type(), i128::from(layout.size.bytes()));
+                    fx.bx.mem_intrinsic("llvm.intr.memcpy", ret.to_ptr(), ptr, size, true);
+                }
+            }
+        }
+        sym::volatile_store | sym::unaligned_volatile_store | sym::nontemporal_store => {
+            intrinsic_args!(args => (ptr, val); intrinsic);
+
+            let ptr = ptr.load_scalar(fx);
+            let layout = val.layout();
+            let align = if intrinsic == sym::unaligned_volatile_store {
+                1
+            } else {
+                layout.align.abi.bytes()
+            };
+            let flag =
+                if intrinsic == sym::nontemporal_store { "nontemporal" } else { "volatile_" };
+            if let Abi::Scalar(_) = layout.abi {
+                let val = val.load_scalar(fx);
+                fx.bx.store_with_flag(val, ptr, align, flag);
+            } else if !layout.is_zst() {
+                let src = val.force_stack(fx);
+                let size = fx.bx.iconst(fx.usize_type(), i128::from(layout.size.bytes()));
+                let volatile = intrinsic != sym::nontemporal_store;
+                fx.bx.mem_intrinsic("llvm.intr.memcpy", ptr, src, size, volatile);
+            }
+        }
+
+        _ if intrinsic.as_str().starts_with("atomic_") => {
+            return codegen_atomic_intrinsic_call(
+                fx,
+                intrinsic,
+                generic_args,
+                args,
+                ret,
+                source_info.span,
+            );
+        }
+
+        sym::fadd_fast
+        | sym::fsub_fast
+        | sym::fmul_fast
+        | sym::fdiv_fast
+        | sym::frem_fast
+        | sym::fadd_algebraic
+        | sym::fsub_algebraic
+        | sym::fmul_algebraic
+        | sym::fdiv_algebraic
+        | sym::frem_algebraic => {
+            intrinsic_args!(args => (x, y); intrinsic);
+
+            let name = match intrinsic {
+                sym::fadd_fast | sym::fadd_algebraic => "arith.addf",
+                sym::fsub_fast | sym::fsub_algebraic => "arith.subf",
+                sym::fmul_fast | sym::fmul_algebraic => "arith.mulf",
+                sym::fdiv_fast | sym::fdiv_algebraic => "arith.divf",
+                sym::frem_fast | sym::frem_algebraic => "arith.remf",
+                _ => unreachable!(),
+            };
+            // The `_fast` variants may assume that neither inputs nor outputs are NaN or infinite,
+            // the `_algebraic` ones only allow algebraic transformations.
+            let fastmath = if intrinsic.as_str().ends_with("_fast") {
+                "#arith.fastmath<fast>"
+            } else {
+                "#arith.fastmath<reassoc,nsz,arcp,contract,afn>"
+            };
+            let layout = x.layout();
+            let x = x.load_scalar(fx);
+            let y = y.load_scalar(fx);
+            let bx = fx.bx;
+            let res = bx.append_value(
+                bx.op(name)
+                    .add_operands(&[x, y])
+                    .add_attributes(&[(bx.ident("fastmath"), bx.parse_attr(fastmath))])
+                    .add_results(&[x.r#type()])
+                    .build()
+                    .unwrap(),
+            );
+            ret.write_mvalue(fx, MValue::by_val(res, layout));
+        }
+        sym::float_to_int_unchecked => {
+            intrinsic_args!(args => (f); intrinsic);
+            let f = f.load_scalar(fx);
+
+            let int_ty = immediate_type(fx, ret.layout()).unwrap();
+            let res = if type_sign(ret.layout().ty) {
+                fx.bx.cast("arith.fptosi", f, int_ty)
+            } else {
+                fx.bx.cast("arith.fptoui", f, int_ty)
+            };
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::raw_eq => {
+            intrinsic_args!(args => (lhs_ref, rhs_ref); intrinsic);
+            let lhs_ref = lhs_ref.load_scalar(fx);
+            let rhs_ref = rhs_ref.load_scalar(fx);
+
+            let size = fx.layout_of(generic_args.type_at(0)).layout.size();
+            let is_eq = if size == Size::ZERO {
+                // No bytes means they're trivially equal
+                fx.bx.iconst(fx.bx.int_type(1), 1)
+            } else if size.bytes() <= 16 && size.bytes().is_power_of_two() {
+                // Can't assume anything about the alignment of the loads.
+                let int_ty = fx.bx.int_type(size.bits());
+                let lhs_val = fx.bx.load(int_ty, lhs_ref, 1);
+                let rhs_val = fx.bx.load(int_ty, rhs_ref, 1);
+                fx.bx.icmp(IntCC::Equal, lhs_val, rhs_val)
+            } else {
+                // Just call `memcmp` (like slices do in core) when the
+                // size is too large or it's not a power-of-two.
+                let bytes = fx.bx.iconst(fx.usize_type(), i128::from(size.bytes()));
+                let i32_ty = fx.bx.int_type(32);
+                let cmp = lib_call(fx, "memcmp", &[lhs_ref, rhs_ref, bytes], &[i32_ty])[0];
+                fx.bx.icmp_imm(IntCC::Equal, cmp, 0)
+            };
+            let is_eq = fx.bx.bool_to_i8(is_eq);
+            ret.write_mvalue(fx, MValue::by_val(is_eq, ret.layout()));
+        }
+        sym::compare_bytes => {
+            intrinsic_args!(args => (lhs_ptr, rhs_ptr, bytes); intrinsic);
+            let lhs_ptr = lhs_ptr.load_scalar(fx);
+            let rhs_ptr = rhs_ptr.load_scalar(fx);
+            let bytes = bytes.load_scalar(fx);
+
+            // Here we assume that the `memcmp` provided by the target is a NOP for size 0.
+            let i32_ty = fx.bx.int_type(32);
+            let cmp = lib_call(fx, "memcmp", &[lhs_ptr, rhs_ptr, bytes], &[i32_ty])[0];
+            ret.write_mvalue(fx, MValue::by_val(cmp, ret.layout()));
+        }
+
+        sym::black_box => {
+            intrinsic_args!(args => (a); intrinsic);
+
+            // Like `rustc_codegen_llvm`, pass the address of the value to an empty asm block that
+            // may read and write any memory, so that the optimizer has to assume both.
+            if !a.layout().is_zst() {
+                let ptr = a.force_stack(fx);
+                let bx = fx.bx;
+                bx.append(
+                    bx.op("llvm.inline_asm")
+                        .add_operands(&[ptr])
+                        .add_attributes(&[
+                            (bx.ident("asm_string"), StringAttribute::new(fx.context, "").into()),
+                            (
+                                bx.ident("constraints"),
+                                StringAttribute::new(fx.context, "r,~{memory}").into(),
+                            ),
+                            (bx.ident("has_side_effects"), Attribute::unit(fx.context)),
+                        ])
+                        .build()
+                        .unwrap(),
+                );
+                ret.write_mvalue(fx, MValue::by_ref(ptr, a.layout()));
+            }
+        }
+
+        sym::catch_unwind => {
+            intrinsic_args!(args => (try_fn, data, catch_fn); intrinsic);
+            let try_fn = try_fn.load_scalar(fx);
+            let data = data.load_scalar(fx);
+            let catch_fn = catch_fn.load_scalar(fx);
+
+            if fx.tcx.sess.panic_strategy() != PanicStrategy::Unwind {
+                fx.bx.call_indirect(try_fn, &[data], &[]);
+                let zero = fx.bx.iconst(fx.bx.int_type(32), 0);
+                ret.write_mvalue(fx, MValue::by_val(zero, ret.layout()));
+            } else {
+                let target = target.expect("catch_unwind always returns");
+                crate::unwind::codegen_catch_unwind(fx, try_fn, data, catch_fn, ret, target);
+                return Ok(IntrinsicResult::Diverged);
+            }
+        }
+
+        sym::caller_location => {
+            intrinsic_args!(args => (); intrinsic);
+
+            let caller_location = fx.get_caller_location(source_info);
+            ret.write_mvalue(fx, caller_location);
+        }
+
+        sym::va_copy | sym::va_arg | sym::va_end => {
+            fx.tcx.dcx().span_fatal(
+                source_info.span,
+                format!("intrinsic `{intrinsic}` is not yet supported by the MLIR backend"),
+            );
+        }
+
+        // Unimplemented intrinsics must have a fallback body. The fallback body is obtained
+        // by converting the `InstanceKind::Intrinsic` to an `InstanceKind::Item`.
+        _ => {
+            let intrinsic = fx.tcx.intrinsic(instance.def_id()).unwrap();
+            if intrinsic.must_be_overridden {
+                span_bug!(
+                    source_info.span,
+                    "intrinsic {} must be overridden by the MLIR backend, but isn't",
+                    intrinsic.name,
+                );
+            }
+            return Err(Instance::new(instance.def_id(), instance.args));
+        }
+    }
+
+    Ok(IntrinsicResult::Done)
+}
+
+/// Codegens an atomic intrinsic. These follow the naming pattern
+/// `atomic_<operation>[_<ordering>]`, which is parsed the same way `rustc_codegen_ssa` does.
+fn codegen_atomic_intrinsic_call<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    intrinsic: Symbol,
+    generic_args: GenericArgsRef<'tcx>,
+    args: &[CallArgument<'ml, 'a, 'tcx>],
+    ret: MPlace<'ml, 'a, 'tcx>,
+    span: Span,
+) -> Result<IntrinsicResult, Instance<'tcx>> {
+    use AtomicOrdering::*;
+
+    let atomic = intrinsic.as_str().strip_prefix("atomic_").unwrap();
+    let Some((instruction, ordering)) = atomic.split_once('_') else {
+        fx.tcx.dcx().emit_fatal(ssa_errors::MissingMemoryOrdering);
+    };
+
+    let parse_ordering = |fx: &FunctionCx<'_, '_, '_>, s| match s {
+        "unordered" => Unordered,
+        "relaxed" => Relaxed,
+        "acquire" => Acquire,
+        "release" => Release,
+        "acqrel" => AcquireRelease,
+        "seqcst" => SequentiallyConsistent,
+        _ => fx.tcx.dcx().emit_fatal(ssa_errors::UnknownAtomicOrdering),
+    };
+
+    match instruction {
+        "fence" | "singlethreadfence" => {
+            intrinsic_args!(args => (); intrinsic);
+
+            let ordering = parse_ordering(fx, ordering);
+            fx.bx.fence(ordering, instruction == "singlethreadfence");
+            return Ok(IntrinsicResult::Done);
+        }
+        _ => {}
+    }
+
+    let ty = generic_args.type_at(0);
+    if !matches!(ty.kind(), ty::Int(_) | ty::Uint(_) | ty::RawPtr(..)) {
+        report_atomic_type_validation_error(fx, intrinsic, span, ty);
+        return Ok(IntrinsicResult::Diverged);
+    }
+    let layout = fx.layout_of(ty);
+    let val_ty = immediate_type(fx, layout).unwrap();
+    // Atomic accesses have to be aligned to their size.
+    let align = layout.size.bytes();
+
+    match instruction {
+        "cxchg" | "cxchgweak" => {
+            intrinsic_args!(args => (ptr, test_old, new); intrinsic);
+
+            let Some((success, failure)) = ordering.split_once('_') else {
+                fx.tcx.dcx().emit_fatal(ssa_errors::AtomicCompareExchange);
+            };
+            let success = parse_ordering(fx, success);
+            let failure = parse_ordering(fx, failure);
+            let ptr = ptr.load_scalar(fx);
+            let test_old = test_old.load_scalar(fx);
+            let new = new.load_scalar(fx);
+
+            let weak = instruction == "cxchgweak";
+            let (old, is_eq) = fx.bx.atomic_cmpxchg(ptr, test_old, new, success, failure, weak);
+            let is_eq = fx.bx.bool_to_i8(is_eq);
+            ret.write_mvalue(fx, MValue::by_val_pair(old, is_eq, ret.layout()));
+        }
+        "load" => {
+            intrinsic_args!(args => (ptr); intrinsic);
+
+            let ordering = parse_ordering(fx, ordering);
+            let ptr = ptr.load_scalar(fx);
+            let val = fx.bx.atomic_load(val_ty, ptr, ordering, align);
+            ret.write_mvalue(fx, MValue::by_val(val, layout));
+        }
+        "store" => {
+            intrinsic_args!(args => (ptr, val); intrinsic);
+
+            let ordering = parse_ordering(fx, ordering);
+            let ptr = ptr.load_scalar(fx);
+            let val = val.load_scalar(fx);
+            fx.bx.atomic_store(val, ptr, ordering, align);
+        }
+        // These are all `llvm.atomicrmw` ops
+        op => {
+            intrinsic_args!(args => (ptr, val); intrinsic);
+
+            let op = match op {
+                "xchg" => AtomicRmwBinOp::AtomicXchg,
+                "xadd" => AtomicRmwBinOp::AtomicAdd,
+                "xsub" => AtomicRmwBinOp::AtomicSub,
+                "and" => AtomicRmwBinOp::AtomicAnd,
+                "nand" => AtomicRmwBinOp::AtomicNand,
+                "or" => AtomicRmwBinOp::AtomicOr,
+                "xor" => AtomicRmwBinOp::AtomicXor,
+                "max" => AtomicRmwBinOp::AtomicMax,
+                "min" => AtomicRmwBinOp::AtomicMin,
+                "umax" => AtomicRmwBinOp::AtomicUMax,
+                "umin" => AtomicRmwBinOp::AtomicUMin,
+                _ => fx.tcx.dcx().emit_fatal(ssa_errors::UnknownAtomicOperation),
+            };
+            let op = match op {
+                AtomicRmwBinOp::AtomicXchg => AtomicRmwOp::Xchg,
+                AtomicRmwBinOp::AtomicAdd => AtomicRmwOp::Add,
+                AtomicRmwBinOp::AtomicSub => AtomicRmwOp::Sub,
+                AtomicRmwBinOp::AtomicAnd => AtomicRmwOp::And,
+                AtomicRmwBinOp::AtomicNand => AtomicRmwOp::Nand,
+                AtomicRmwBinOp::AtomicOr => AtomicRmwOp::Or,
+                AtomicRmwBinOp::AtomicXor => AtomicRmwOp::Xor,
+                AtomicRmwBinOp::AtomicMax => AtomicRmwOp::Max,
+                AtomicRmwBinOp::AtomicMin => AtomicRmwOp::Min,
+                AtomicRmwBinOp::AtomicUMax => AtomicRmwOp::UMax,
+                AtomicRmwBinOp::AtomicUMin => AtomicRmwOp::UMin,
+            };
+            let ordering = parse_ordering(fx, ordering);
+            let ptr = ptr.load_scalar(fx);
+            let val = val.load_scalar(fx);
+
+            // Only `xchg` works on pointers directly, the other ops need an integer.
+            let is_ptr = val.r#type() == fx.pointer_type();
+            let res = if is_ptr && op != AtomicRmwOp::Xchg {
+                let usize_ty = fx.usize_type();
+                let val = fx.bx.cast("llvm.ptrtoint", val, usize_ty);
+                let res = fx.bx.atomic_rmw(op, ptr, val, ordering);
+                fx.bx.cast("llvm.inttoptr", res, val_ty)
+            } else {
+                fx.bx.atomic_rmw(op, ptr, val, ordering)
+            };
+            ret.write_mvalue(fx, MValue::by_val(res, layout));
+        }
+    }
+
+    Ok(IntrinsicResult::Done)
+}
@@ -0,0 +1,525 @@
+//! Codegen `simd_*` intrinsics.
+//!
+//! SIMD types with `Abi::Vector` lower to `vector<N x T>`, so most of these intrinsics are a
+//! single `arith`, `math` or `vector` op applied to the whole vector.
+
+use melior::ir::attribute::DenseI64ArrayAttribute;
+use melior::ir::{Type, Value, ValueLike};
+use rustc_abi::{Abi, Size};
+use rustc_middle::mir::ConstValue;
+use rustc_middle::mir::interpret::{alloc_range, read_target_uint};
+use rustc_middle::span_bug;
+use rustc_middle::ty::layout::{LayoutOf, TyAndLayout};
+use rustc_middle::ty::{self, GenericArgsRef, Ty};
+use rustc_span::Span;
+use rustc_span::symbol::{Symbol, sym};
+
+use super::bug_on_incorrect_arg_count;
+use crate::abi::CallArgument;
+use crate::builder::{FloatCC, IntCC};
+use crate::common::FunctionCx;
+use crate::type_of::{mlir_type, type_sign};
+use crate::value_and_place::{MPlace, MValue};
+
+/// The lane count and lane type of the SIMD type of `layout`, which must have `Abi::Vector`.
+fn simd_lanes<'tcx>(
+    fx: &FunctionCx<'_, '_, 'tcx>,
+    intrinsic: Symbol,
+    span: Span,
+    layout: TyAndLayout<'tcx>,
+) -> (u64, Ty<'tcx>) {
+    if !layout.ty.is_simd() {
+        span_bug!(span, "`{intrinsic}` called with non-SIMD type `{}`", layout.ty);
+    }
+    if !matches!(layout.abi, Abi::Vector { .. }) {
+        fx.tcx.dcx().span_fatal(
+            span,
+            format!(
+                "`{intrinsic}` on `{}`, which isn't passed as a vector, is not yet supported by \
+                 the MLIR backend",
+                layout.ty
+            ),
+        );
+    }
+    layout.ty.simd_size_and_type(fx.tcx)
+}
+
+/// Loads the `vector<N x T>` value of a SIMD argument.
+fn load_vector<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    val: MValue<'ml, 'a, 'tcx>,
+) -> Value<'ml, 'a> {
+    let layout = val.layout();
+    let ty = mlir_type(fx.cx, layout);
+    let ptr = val.force_stack(fx);
+    fx.bx.load(ty, ptr, layout.align.abi.bytes())
+}
+
+/// The `vector<N x i1>` type of masks with `lane_count` lanes.
+fn mask_type<'ml>(fx: &FunctionCx<'_, 'ml, '_>, lane_count: u64) -> Type<'ml> {
+    Type::vector(&[lane_count], fx.bx.int_type(1))
+}
+
+fn vector_icmp<'a, 'ml>(
+    fx: &FunctionCx<'a, 'ml, '_>,
+    cc: IntCC,
+    lane_count: u64,
+    lhs: Value<'ml, 'a>,
+    rhs: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    let bx = fx.bx;
+    bx.append_value(
+        bx.op("arith.cmpi")
+            .add_operands(&[lhs, rhs])
+            .add_attributes(&[(bx.ident("predicate"), bx.i64_attr(cc as i64))])
+            .add_results(&[mask_type(fx, lane_count)])
+            .build()
+            .unwrap(),
+    )
+}
+
+fn vector_fcmp<'a, 'ml>(
+    fx: &FunctionCx<'a, 'ml, '_>,
+    cc: FloatCC,
+    lane_count: u64,
+    lhs: Value<'ml, 'a>,
+    rhs: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    let bx = fx.bx;
+    bx.append_value(
+        bx.op("arith.cmpf")
+            .add_operands(&[lhs, rhs])
+            .add_attributes(&[(bx.ident("predicate"), bx.i64_attr(cc as i64))])
+            .add_results(&[mask_type(fx, lane_count)])
+            .build()
+            .unwrap(),
+    )
+}
+
+/// Converts a SIMD mask, whose lanes are all zeros or all ones, into a `vector<N x i1>`.
+fn mask_to_i1<'a, 'ml>(
+    fx: &FunctionCx<'a, 'ml, '_>,
+    lane_count: u64,
+    mask: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    let zero = fx.bx.zero(mask.r#type());
+    vector_icmp(fx, IntCC::NotEqual, lane_count, mask, zero)
+}
+
+/// Reduces the vector `val` to a single lane with `vector.reduction`, optionally starting from
+/// `acc`.
+fn reduce<'a, 'ml>(
+    fx: &FunctionCx<'a, 'ml, '_>,
+    kind: &str,
+    val: Value<'ml, 'a>,
+    acc: Option<Value<'ml, 'a>>,
+    result: Type<'ml>,
+) -> Value<'ml, 'a> {
+    let bx = fx.bx;
+    let mut operands = vec![val];
+    operands.extend(acc);
+    bx.append_value(
+        bx.op("vector.reduction")
+            .add_operands(&operands)
+            .add_attributes(&[(bx.ident("kind"), bx.parse_attr(&format!("#vector.kind<{kind}>")))])
+            .add_results(&[result])
+            .build()
+            .unwrap(),
+    )
+}
+
+pub(super) fn codegen_simd_intrinsic_call<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    intrinsic: Symbol,
+    generic_args: GenericArgsRef<'tcx>,
+    args: &[CallArgument<'ml, 'a, 'tcx>],
+    ret: MPlace<'ml, 'a, 'tcx>,
+    span: Span,
+) {
+    match intrinsic {
+        sym::simd_add
+        | sym::simd_sub
+        | sym::simd_mul
+        | sym::simd_div
+        | sym::simd_rem
+        | sym::simd_shl
+        | sym::simd_shr
+        | sym::simd_and
+        | sym::simd_or
+        | sym::simd_xor
+        | sym::simd_fmin
+        | sym::simd_fmax
+        | sym::simd_saturating_add
+        | sym::simd_saturating_sub => {
+            intrinsic_args!(args => (x, y); intrinsic);
+
+            let (_, lane_ty) = simd_lanes(fx, intrinsic, span, x.layout());
+            let name = match (lane_ty.kind(), intrinsic) {
+                (ty::Uint(_) | ty::Int(_), sym::simd_add) => "arith.addi",
+                (ty::Uint(_) | ty::Int(_), sym::simd_sub) => "arith.subi",
+                (ty::Uint(_) | ty::Int(_), sym::simd_mul) => "arith.muli",
+                (ty::Uint(_), sym::simd_div) => "arith.divui",
+                (ty::Int(_), sym::simd_div) => "arith.divsi",
+                (ty::Uint(_), sym::simd_rem) => "arith.remui",
+                (ty::Int(_), sym::simd_rem) => "arith.remsi",
+                (ty::Uint(_) | ty::Int(_), sym::simd_shl) => "arith.shli",
+                (ty::Uint(_), sym::simd_shr) => "arith.shrui",
+                (ty::Int(_), sym::simd_shr) => "arith.shrsi",
+                (ty::Uint(_) | ty::Int(_), sym::simd_and) => "arith.andi",
+                (ty::Uint(_) | ty::Int(_), sym::simd_or) => "arith.ori",
+                (ty::Uint(_) | ty::Int(_), sym::simd_xor) => "arith.xori",
+                (ty::Uint(_), sym::simd_saturating_add) => "llvm.intr.uadd.sat",
+                (ty::Int(_), sym::simd_saturating_add) => "llvm.intr.sadd.sat",
+                (ty::Uint(_), sym::simd_saturating_sub) => "llvm.intr.usub.sat",
+                (ty::Int(_), sym::simd_saturating_sub) => "llvm.intr.ssub.sat",
+                (ty::Float(_), sym::simd_add) => "arith.addf",
+                (ty::Float(_), sym::simd_sub) => "arith.subf",
+                (ty::Float(_), sym::simd_mul) => "arith.mulf",
+                (ty::Float(_), sym::simd_div) => "arith.divf",
+                (ty::Float(_), sym::simd_rem) => "arith.remf",
+                (ty::Float(_), sym::simd_fmin) => "arith.minnumf",
+                (ty::Float(_), sym::simd_fmax) => "arith.maxnumf",
+                _ => span_bug!(span, "`{intrinsic}` called on vector of `{lane_ty}`"),
+            };
+            let x = load_vector(fx, x);
+            let y = load_vector(fx, y);
+            let res = fx.bx.binary(name, x, y);
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::simd_neg
+        | sym::simd_fabs
+        | sym::simd_fsqrt
+        | sym::simd_ceil
+        | sym::simd_floor
+        | sym::simd_round
+        | sym::simd_trunc
+        | sym::simd_fsin
+        | sym::simd_fcos
+        | sym::simd_fexp
+        | sym::simd_fexp2
+        | sym::simd_flog
+        | sym::simd_flog2
+        | sym::simd_flog10
+        | sym::simd_bswap
+        | sym::simd_bitreverse
+        | sym::simd_ctlz
+        | sym::simd_cttz
+        | sym::simd_ctpop => {
+            intrinsic_args!(args => (a); intrinsic);
+
+            let (_, lane_ty) = simd_lanes(fx, intrinsic, span, a.layout());
+            let a = load_vector(fx, a);
+            let res = match (lane_ty.kind(), intrinsic) {
+                (ty::Uint(_) | ty::Int(_), sym::simd_neg) => {
+                    let zero = fx.bx.zero(a.r#type());
+                    fx.bx.binary("arith.subi", zero, a)
+                }
+                (ty::Uint(_) | ty::Int(_), sym::simd_bswap) => {
+                    // `llvm.bswap` is only defined for an even number of bytes.
+                    if lane_ty.primitive_size(fx.tcx).bytes() == 1 {
+                        a
+                    } else {
+                        fx.bx.unary("llvm.intr.bswap", a)
+                    }
+                }
+                (ty::Uint(_) | ty::Int(_), _) => {
+                    let name = match intrinsic {
+                        sym::simd_bitreverse => "llvm.intr.bitreverse",
+                        sym::simd_ctlz => "math.ctlz",
+                        sym::simd_cttz => "math.cttz",
+                        sym::simd_ctpop => "math.ctpop",
+                        _ => span_bug!(span, "`{intrinsic}` called on vector of `{lane_ty}`"),
+                    };
+                    fx.bx.unary(name, a)
+                }
+                (ty::Float(_), _) => {
+                    let name = match intrinsic {
+                        sym::simd_neg => "arith.negf",
+                        sym::simd_fabs => "math.absf",
+                        sym::simd_fsqrt => "math.sqrt",
+                        sym::simd_ceil => "math.ceil",
+                        sym::simd_floor => "math.floor",
+                        sym::simd_round => "math.round",
+                        sym::simd_trunc => "math.trunc",
+                        sym::simd_fsin => "math.sin",
+                        sym::simd_fcos => "math.cos",
+                        sym::simd_fexp => "math.exp",
+                        sym::simd_fexp2 => "math.exp2",
+                        sym::simd_flog => "math.log",
+                        sym::simd_flog2 => "math.log2",
+                        sym::simd_flog10 => "math.log10",
+                        _ => span_bug!(span, "`{intrinsic}` called on vector of `{lane_ty}`"),
+                    };
+                    fx.bx.unary(name, a)
+                }
+                _ => span_bug!(span, "`{intrinsic}` called on vector of `{lane_ty}`"),
+            };
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::simd_fma => {
+            intrinsic_args!(args => (a, b, c); intrinsic);
+
+            simd_lanes(fx, intrinsic, span, a.layout());
+            let a = load_vector(fx, a);
+            let b = load_vector(fx, b);
+            let c = load_vector(fx, c);
+            let res = fx.bx.intrinsic("math.fma", &[a, b, c], a.r#type());
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::simd_eq | sym::simd_ne | sym::simd_lt | sym::simd_le | sym::simd_gt | sym::simd_ge => {
+            intrinsic_args!(args => (x, y); intrinsic);
+
+            let (lane_count, lane_ty) = simd_lanes(fx, intrinsic, span, x.layout());
+            let x = load_vector(fx, x);
+            let y = load_vector(fx, y);
+            let res = match lane_ty.kind() {
+                ty::Uint(_) | ty::Int(_) => {
+                    let signed = type_sign(lane_ty);
+                    let cc = match (intrinsic, signed) {
+                        (sym::simd_eq, _) => IntCC::Equal,
+                        (sym::simd_ne, _) => IntCC::NotEqual,
+                        (sym::simd_lt, false) => IntCC::UnsignedLessThan,
+                        (sym::simd_lt, true) => IntCC::SignedLessThan,
+                        (sym::simd_le, false) => IntCC::UnsignedLessThanOrEqual,
+                        (sym::simd_le, true) => IntCC::SignedLessThanOrEqual,
+                        (sym::simd_gt, false) => IntCC::UnsignedGreaterThan,
+                        (sym::simd_gt, true) => IntCC::SignedGreaterThan,
+                        (sym::simd_ge, false) => IntCC::UnsignedGreaterThanOrEqual,
+                        (sym::simd_ge, true) => IntCC::SignedGreaterThanOrEqual,
+                        _ => unreachable!(),
+                    };
+                    vector_icmp(fx, cc, lane_count, x, y)
+                }
+                ty::Float(_) => {
+                    let cc = match intrinsic {
+                        sym::simd_eq => FloatCC::Equal,
+                        sym::simd_ne => FloatCC::NotEqual,
+                        sym::simd_lt => FloatCC::LessThan,
+                        sym::simd_le => FloatCC::LessThanOrEqual,
+                        sym::simd_gt => FloatCC::GreaterThan,
+                        sym::simd_ge => FloatCC::GreaterThanOrEqual,
+                        _ => unreachable!(),
+                    };
+                    vector_fcmp(fx, cc, lane_count, x, y)
+                }
+                _ => span_bug!(span, "`{intrinsic}` called on vector of `{lane_ty}`"),
+            };
+            // Masks are vectors of integers that are either all zeros or all ones.
+            let res = fx.bx.cast("arith.extsi", res, mlir_type(fx.cx, ret.layout()));
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::simd_cast | sym::simd_as => {
+            intrinsic_args!(args => (a); intrinsic);
+
+            let (lane_count, from_lane_ty) = simd_lanes(fx, intrinsic, span, a.layout());
+            let (ret_lane_count, to_lane_ty) = simd_lanes(fx, intrinsic, span, ret.layout());
+            assert_eq!(lane_count, ret_lane_count);
+            let from_signed = type_sign(from_lane_ty);
+            let to_signed = type_sign(to_lane_ty);
+            let to_lane_mlir_ty = mlir_type(fx.cx, fx.layout_of(to_lane_ty));
+            let ret_ty = mlir_type(fx.cx, ret.layout());
+
+            // `simd_cast` float to int casts are UB when out of range, so the saturating cast of
+            // `simd_as` is fine for both.
+            let a = load_vector(fx, a);
+            let from_lane_mlir_ty = mlir_type(fx.cx, fx.layout_of(from_lane_ty));
+            let mut res = fx.bx.undef(ret_ty);
+            for lane in 0..lane_count {
+                let lane = lane as i64;
+                let val = vector_extract(fx, a, lane, from_lane_mlir_ty);
+                let val = crate::cast::int_or_float_cast(
+                    fx,
+                    val,
+                    from_signed,
+                    to_lane_mlir_ty,
+                    to_signed,
+                );
+                res = vector_insert(fx, val, res, lane);
+            }
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::simd_extract => {
+            intrinsic_args!(args => (v, idx); intrinsic);
+
+            let (_, lane_ty) = simd_lanes(fx, intrinsic, span, v.layout());
+            let lane_mlir_ty = mlir_type(fx.cx, fx.layout_of(lane_ty));
+            let v = load_vector(fx, v);
+            let idx = idx.load_scalar(fx);
+            let res = fx.bx.intrinsic("llvm.extractelement", &[v, idx], lane_mlir_ty);
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+        sym::simd_insert => {
+            intrinsic_args!(args => (v, idx, val); intrinsic);
+
+            simd_lanes(fx, intrinsic, span, v.layout());
+            let v = load_vector(fx, v);
+            let idx = idx.load_scalar(fx);
+            let val = val.load_scalar(fx);
+            let res = fx.bx.intrinsic("llvm.insertelement", &[v, val, idx], v.r#type());
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::simd_shuffle => {
+            let [x, y, idx] = args else {
+                bug_on_incorrect_arg_count(intrinsic);
+            };
+
+            simd_lanes(fx, intrinsic, span, x.value.layout());
+            let (ret_lane_count, _) = simd_lanes(fx, intrinsic, span, ret.layout());
+
+            // The indices are a constant array or SIMD vector of `u32`, read straight out of its
+            // allocation like `rustc_codegen_cranelift` does.
+            let Some(idx_const) = &idx.constant else {
+                span_bug!(span, "shuffle indices of `{intrinsic}` must be a constant");
+            };
+            let idx_bytes = match crate::constant::eval_mir_constant(fx, idx_const).0 {
+                ConstValue::Indirect { alloc_id, offset } => {
+                    let alloc = fx.tcx.global_alloc(alloc_id).unwrap_memory();
+                    let size = Size::from_bytes(4 * ret_lane_count);
+                    alloc.inner().get_bytes_strip_provenance(fx, alloc_range(offset, size)).unwrap()
+                }
+                idx_const => span_bug!(span, "unexpected shuffle indices {idx_const:?}"),
+            };
+            let mask = (0..ret_lane_count as usize)
+                .map(|i| {
+                    let idx =
+                        read_target_uint(fx.tcx.data_layout.endian, &idx_bytes[4 * i..4 * i + 4])
+                            .expect("read_target_uint");
+                    i64::try_from(idx).unwrap()
+                })
+                .collect::<Vec<_>>();
+
+            let x = load_vector(fx, x.value);
+            let y = load_vector(fx, y.value);
+            let ret_ty = mlir_type(fx.cx, ret.layout());
+            let bx = fx.bx;
+            let res = bx.append_value(
+                bx.op("vector.shuffle")
+                    .add_operands(&[x, y])
+                    .add_attributes(&[(
+                        bx.ident("mask"),
+                        DenseI64ArrayAttribute::new(fx.context, &mask).into(),
+                    )])
+                    .add_results(&[ret_ty])
+                    .build()
+                    .unwrap(),
+            );
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::simd_select => {
+            intrinsic_args!(args => (m, a, b); intrinsic);
+
+            let (lane_count, _) = simd_lanes(fx, intrinsic, span, m.layout());
+            simd_lanes(fx, intrinsic, span, a.layout());
+            let m = load_vector(fx, m);
+            let a = load_vector(fx, a);
+            let b = load_vector(fx, b);
+            let m = mask_to_i1(fx, lane_count, m);
+            let res = fx.bx.select(m, a, b);
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        sym::simd_reduce_add_ordered
+        | sym::simd_reduce_add_unordered
+        | sym::simd_reduce_mul_ordered
+        | sym::simd_reduce_mul_unordered
+        | sym::simd_reduce_and
+        | sym::simd_reduce_or
+        | sym::simd_reduce_xor
+        | sym::simd_reduce_min
+        | sym::simd_reduce_max => {
+            let (v, acc) = match args {
+                [v, acc] => (v.value, Some(acc.value)),
+                [v] => (v.value, None),
+                _ => bug_on_incorrect_arg_count(intrinsic),
+            };
+
+            let (_, lane_ty) = simd_lanes(fx, intrinsic, span, v.layout());
+            let kind = match (lane_ty.kind(), intrinsic) {
+                (_, sym::simd_reduce_add_ordered | sym::simd_reduce_add_unordered) => "add",
+                (_, sym::simd_reduce_mul_ordered | sym::simd_reduce_mul_unordered) => "mul",
+                (ty::Uint(_) | ty::Int(_), sym::simd_reduce_and) => "and",
+                (ty::Uint(_) | ty::Int(_), sym::simd_reduce_or) => "or",
+                (ty::Uint(_) | ty::Int(_), sym::simd_reduce_xor) => "xor",
+                (ty::Uint(_), sym::simd_reduce_min) => "minui",
+                (ty::Int(_), sym::simd_reduce_min) => "minsi",
+                (ty::Uint(_), sym::simd_reduce_max) => "maxui",
+                (ty::Int(_), sym::simd_reduce_max) => "maxsi",
+                (ty::Float(_), sym::simd_reduce_min) => "minnumf",
+                (ty::Float(_), sym::simd_reduce_max) => "maxnumf",
+                _ => span_bug!(span, "`{intrinsic}` called on vector of `{lane_ty}`"),
+            };
+            let lane_mlir_ty = mlir_type(fx.cx, fx.layout_of(lane_ty));
+            let v = load_vector(fx, v);
+            // Only the ordered float reductions have to start from the accumulator, but it is
+            // the identity of the operation for the unordered ones anyway.
+            let acc = acc.map(|acc| acc.load_scalar(fx));
+            let res = reduce(fx, kind, v, acc, lane_mlir_ty);
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+        sym::simd_reduce_all | sym::simd_reduce_any => {
+            intrinsic_args!(args => (v); intrinsic);
+
+            let (lane_count, _) = simd_lanes(fx, intrinsic, span, v.layout());
+            let v = load_vector(fx, v);
+            let mask = mask_to_i1(fx, lane_count, v);
+            let kind = if intrinsic == sym::simd_reduce_all { "and" } else { "or" };
+            let res = reduce(fx, kind, mask, None, fx.bx.int_type(1));
+            let res = fx.bx.bool_to_i8(res);
+            ret.write_mvalue(fx, MValue::by_val(res, ret.layout()));
+        }
+
+        _ => {
+            fx.tcx.dcx().span_fatal(
+                span,
+                format!("intrinsic `{intrinsic}` is not yet supported by the MLIR backend"),
+            );
+        }
+    }
+}
+
+fn vector_extract<'a, 'ml>(
+    fx: &FunctionCx<'a, 'ml, '_>,
+    vector: Value<'ml, 'a>,
+    lane: i64,
+    lane_ty: Type<'ml>,
+) -> Value<'ml, 'a> {
+    let bx = fx.bx;
+    bx.append_value(
+        bx.op("vector.extract")
+            .add_operands(&[vector])
+            .add_attributes(&[(
+                bx.ident("static_position"),
+                DenseI64ArrayAttribute::new(fx.context, &[lane]).into(),
+            )])
+            .add_results(&[lane_ty])
+            .build()
+            .unwrap(),
+    )
+}
+
+fn vector_insert<'a, 'ml>(
+    fx: &FunctionCx<'a, 'ml, '_>,
+    val: Value<'ml, 'a>,
+    vector: Value<'ml, 'a>,
+    lane: i64,
+) -> Value<'ml, 'a> {
+    let bx = fx.bx;
+    bx.append_value(
+        bx.op("vector.insert")
+            .add_operands(&[val, vector])
+            .add_attributes(&[(
+                bx.ident("static_position"),
+                DenseI64ArrayAttribute::new(fx.context, &[lane]).into(),
+            )])
+            .add_results(&[vector.r#type()])
+            .build()
+            .unwrap(),
+    )
+}
@@ -6,12 +6,14 @@
 extern crate rustc_abi;
 extern crate rustc_ast;
 extern crate rustc_attr;
+extern crate rustc_codegen_llvm;
 extern crate rustc_codegen_ssa;
 extern crate rustc_data_structures;
 extern crate rustc_errors;
 extern crate rustc_fluent_macro;
 extern crate rustc_fs_util;
 extern crate rustc_hir;
+extern crate rustc_incremental;
 extern crate rustc_index;
 extern crate rustc_macros;
 extern crate rustc_metadata;
@@ -23,29 +25,208 @@
 #[allow(unused_extern_crates)]
 extern crate rustc_driver;
 
+use std::mem::ManuallyDrop;
+use std::sync::LazyLock;
+
+use melior::Context;
+use melior::dialect::DialectRegistry;
+use melior::ir::{Location, Module};
+use melior::pass::PassManager;
+use melior::utility::{parse_pass_pipeline, register_all_dialects, register_all_llvm_translations};
+use rustc_codegen_llvm::{CodegenHooks, LlvmCodegenBackend};
+use rustc_codegen_ssa::CodegenResults;
 use rustc_codegen_ssa::traits::CodegenBackend;
-use rustc_codegen_ssa::{CodegenResults, CompiledModule, ModuleCodegen};
 use rustc_data_structures::fx::FxIndexMap;
 use rustc_errors::ErrorGuaranteed;
 use rustc_metadata::EncodedMetadata;
-use rustc_middle::dep_graph::{self, WorkProduct, WorkProductId};
-use rustc_middle::infer::canonical::ir::ConstKind::Value;
+use rustc_middle::dep_graph::{WorkProduct, WorkProductId};
 use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_middle::util::Providers;
 use rustc_session::Session;
-use rustc_session::config::OutputFilenames;
+use rustc_session::config::{OutputFilenames, PrintRequest};
+use rustc_span::Symbol;
+use tracing::debug;
 
 rustc_fluent_macro::fluent_messages! { "../messages.ftl" }
 
+mod abi;
+mod allocator;
+mod asm;
+mod attributes;
 mod base;
+mod builder;
+mod cast;
+mod common;
+mod constant;
+mod context;
+mod debuginfo;
+mod dialect;
+mod discriminant;
+mod dump;
+mod ffi;
+mod intrinsics;
+mod linkage;
+mod llvm;
+mod main_shim;
+mod mixed;
+mod num;
+mod raise;
+mod type_of;
+mod unsize;
+mod unwind;
+mod value_and_place;
+mod vectorize;
+mod vtable;
+mod write;
 
 #[derive(Clone)]
 pub struct MLIRCodegenBackend(());
 
-struct CodegenCarrier {}
+impl MLIRCodegenBackend {
+    pub fn new() -> Box<dyn CodegenBackend> {
+        Box::new(MLIRCodegenBackend(()))
+    }
+}
+
+/// An MLIR `builtin.module` for a single codegen unit, together with the context that owns it.
+pub struct ModuleMlir {
+    // Both modules borrow from `context`; always dropped first (see the `Drop` impl).
+    module: ManuallyDrop<Module<'static>>,
+    /// The `rust` dialect form of the codegen unit, which `module` is lowered from.
+    rust_module: ManuallyDrop<Module<'static>>,
+    /// Module level assembly, which is appended to the LLVM module once `module` has been
+    /// translated.
+    module_asm: String,
+    context: Context,
+}
+
+impl ModuleMlir {
+    fn new(name: &str) -> Self {
+        let context = Context::new();
+        let registry = DialectRegistry::new();
+        register_all_dialects(&registry);
+        context.append_dialect_registry(&registry);
+        context.load_all_available_dialects();
+        register_all_llvm_translations(&context);
+        // Codegen units are already processed in parallel, each with its own context, so don't
+        // spawn a thread pool for every one of them.
+        context.enable_multi_threading(false);
+        // The `rust` dialect is not registered; its ops are built in generic form.
+        context.set_allow_unregistered_dialects(true);
+
+        debug!("creating mlir module for cgu {name}");
+        let module = Module::new(Location::unknown(&context));
+        let rust_module = Module::new(Location::unknown(&context));
+        // SAFETY: the modules never outlive `context`; the lifetime is narrowed back down again
+        // by `ModuleMlir::module` and `ModuleMlir::rust_module`.
+        let (module, rust_module) = unsafe {
+            (
+                std::mem::transmute::<Module<'_>, Module<'static>>(module),
+                std::mem::transmute::<Module<'_>, Module<'static>>(rust_module),
+            )
+        };
+        ModuleMlir {
+            module: ManuallyDrop::new(module),
+            rust_module: ManuallyDrop::new(rust_module),
+            module_asm: String::new(),
+            context,
+        }
+    }
+
+    pub(crate) fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub(crate) fn module<'ml>(&'ml self) -> &'ml Module<'ml> {
+        &self.module
+    }
+
+    pub(crate) fn rust_module<'ml>(&'ml self) -> &'ml Module<'ml> {
+        &self.rust_module
+    }
+
+    /// Runs a textual pass pipeline, e.g. `builtin.module(canonicalize)`, on `module`.
+    pub(crate) fn run_pass_pipeline(&mut self, pipeline: &str) -> Result<(), melior::Error> {
+        let pass_manager = PassManager::new(&self.context);
+        parse_pass_pipeline(pass_manager.as_operation_pass_manager(), pipeline)?;
+        pass_manager.run(&mut self.module)
+    }
+
+    /// Runs a textual pass pipeline on `rust_module`. Unlike [`ModuleMlir::run_pass_pipeline`],
+    /// this only borrows the module, which the `CodegenCx` lowering it still refers to.
+    pub(crate) fn run_rust_pass_pipeline(&self, pipeline: &str) -> Result<(), melior::Error> {
+        let pass_manager = PassManager::new(&self.context);
+        parse_pass_pipeline(pass_manager.as_operation_pass_manager(), pipeline)?;
+        let result = unsafe {
+            ffi::mlirPassManagerRunOnOp(
+                ffi::raw_pass_manager(&pass_manager),
+                ffi::raw_op(&self.rust_module.as_operation()),
+            )
+        };
+        if result.value == 0 { Err(melior::Error::RunPass) } else { Ok(()) }
+    }
+}
+
+impl Drop for ModuleMlir {
+    fn drop(&mut self) {
+        // SAFETY: the modules are never used again, and are dropped before `context`.
+        unsafe {
+            ManuallyDrop::drop(&mut self.module);
+            ManuallyDrop::drop(&mut self.rust_module);
+        }
+    }
+}
+
+/// The backend that optimizes and emits the LLVM IR translated from the MLIR modules on the worker
+/// threads of the `rustc_codegen_ssa` coordinator, and links the crate. In mixed-backend builds, it
+/// also generates the LLVM IR of the codegen units that aren't compiled by this backend. It holds
+/// no state of its own, so a new one is created wherever it is needed.
+fn llvm_backend() -> Box<dyn CodegenBackend> {
+    LlvmCodegenBackend::with_hooks(CodegenHooks {
+        compile_codegen_unit: base::compile_codegen_unit,
+        codegen_allocator: allocator::codegen_allocator,
+    })
+}
+
+/// The result of `codegen_crate`, consumed by `join_codegen`.
+struct OngoingCodegen {
+    llvm: Box<dyn std::any::Any>,
+    /// The codegen units compiled by this backend rather than `rustc_codegen_llvm`.
+    mlir_cgus: Vec<String>,
+}
 
 impl CodegenBackend for MLIRCodegenBackend {
     fn locale_resource(&self) -> &'static str {
-        crate::DEFAULT_LOCALE_RESOURCE
+        // Diagnostics of `rustc_codegen_llvm` may be emitted while it emits the modules.
+        static LOCALE_RESOURCE: LazyLock<String> = LazyLock::new(|| {
+            [crate::DEFAULT_LOCALE_RESOURCE, rustc_codegen_llvm::DEFAULT_LOCALE_RESOURCE].join("\n")
+        });
+        &LOCALE_RESOURCE
+    }
+
+    fn init(&self, sess: &Session) {
+        // Every module ends up in `rustc_codegen_llvm`'s LLVM, not just the LLVM IR of mixed
+        // builds, so this is checked whether or not the build is mixed.
+        llvm::check_llvm_version(sess);
+        if sess.instrument_coverage() {
+            sess.dcx().fatal("`-Cinstrument-coverage` is not supported by the MLIR backend");
+        }
+        llvm_backend().init(sess);
+    }
+
+    fn provide(&self, providers: &mut Providers) {
+        // `rustc_codegen_llvm` optimizes and emits the modules of every build, and its queries
+        // answer for any build, mixed or not.
+        llvm_backend().provide(providers);
+    }
+
+    fn print(&self, req: &PrintRequest, out: &mut String, sess: &Session) {
+        llvm_backend().print(req, out, sess);
+    }
+
+    fn target_features(&self, sess: &Session, allow_unstable: bool) -> Vec<Symbol> {
+        // The target features of `-Ctarget-cpu`, which may be `native`, are known to LLVM.
+        llvm_backend().target_features(sess, allow_unstable)
     }
 
     fn codegen_crate<'tcx>(
@@ -54,34 +235,18 @@ fn codegen_crate<'tcx>(
         metadata: EncodedMetadata,
         need_metadata_module: bool,
     ) -> Box<dyn std::any::Any> {
-        println!("doing crate codegen");
-
-        let (defids, codegen_units) = tcx.collect_and_partition_mono_items(());
-
-        if tcx.dep_graph.is_fully_enabled() {
-            for cgu in codegen_units {
-                // TODO: do I need to use `ensure()`? since demand should be sufficient, no?
-                //let c1 = tcx.codegen_unit(cgu.name());
-                tcx.ensure().codegen_unit(cgu.name());
-            }
-        } else {
-            println!("dep graph not fully enabled");
-        }
+        let (_, codegen_units) = tcx.collect_and_partition_mono_items(());
+        let mlir_cgus = codegen_units
+            .iter()
+            .filter(|cgu| !mixed::is_mixed(tcx.sess) || cgu.is_mlir())
+            .map(|cgu| cgu.name().to_string())
+            .collect();
 
-        for (i, cgu) in codegen_units.iter().enumerate() {
-            let s = cgu.name();
-            println!("looking at cgu: {s}");
-            let dep_node = cgu.codegen_dep_node(tcx);
-            let module = tcx.dep_graph.with_task(
-                dep_node,
-                tcx,
-                cgu.name(),
-                base::module_codegen,
-                Some(dep_graph::hash_result),
-            );
-        }
+        // `rustc_codegen_llvm` calls back into `base::compile_codegen_unit` for the codegen units
+        // compiled by this backend.
+        let llvm = llvm_backend().codegen_crate(tcx, metadata, need_metadata_module);
 
-        todo!()
+        Box::new(OngoingCodegen { llvm, mlir_cgus })
     }
 
     fn join_codegen(
@@ -90,9 +255,16 @@ fn join_codegen(
         sess: &Session,
         outputs: &OutputFilenames,
     ) -> (CodegenResults, FxIndexMap<WorkProductId, WorkProduct>) {
-        println!("doing join codegen");
+        let OngoingCodegen { llvm, mlir_cgus } = *ongoing_codegen
+            .downcast::<OngoingCodegen>()
+            .expect("Expected MLIRCodegenBackend's OngoingCodegen, found Box<Any>");
+
+        let (codegen_results, work_products) = llvm_backend().join_codegen(llvm, sess, outputs);
 
-        todo!()
+        write::produce_final_output_artifacts(sess, &mlir_cgus, outputs);
+        sess.dcx().abort_if_errors();
+
+        (codegen_results, work_products)
     }
 
     fn link(
@@ -101,27 +273,20 @@ fn link(
         codegen_results: CodegenResults,
         outputs: &OutputFilenames,
     ) -> Result<(), ErrorGuaranteed> {
-        println!("doing link codegen");
-
-        //use rustc_codegen_ssa::back::link::link_binary;
-
-        //use crate::back::archive::LlvmArchiveBuilderBuilder;
+        use rustc_codegen_ssa::back::archive::ArArchiveBuilderBuilder;
+        use rustc_codegen_ssa::back::link::link_binary;
 
-        // Run the linker on any artifacts that resulted from the LLVM run.
+        // Run the linker on the object files emitted by `join_codegen`.
         // This should produce either a finished executable or library.
-        //link_binary(sess, &LlvmArchiveBuilderBuilder, &codegen_results, outputs)
-
-        println!("unimplemented linking");
-
-        Ok(())
+        link_binary(sess, &ArArchiveBuilderBuilder, &codegen_results, outputs)
     }
 
     fn supports_parallel(&self) -> bool {
-        false
+        true
     }
 }
 
 #[no_mangle]
 pub fn __rustc_codegen_backend() -> Box<dyn CodegenBackend> {
-    Box::new(MLIRCodegenBackend(()))
+    MLIRCodegenBackend::new()
 }
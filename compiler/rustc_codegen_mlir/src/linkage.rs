@@ -0,0 +1,120 @@
+//! Linkage, visibility and `dso_local` of the functions and globals defined in a codegen unit.
+//!
+//! See `predefine_fn` and `predefine_static` in `rustc_codegen_llvm/src/mono_item.rs`.
+
+use melior::Context;
+use melior::ir::{Attribute, Identifier};
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_middle::bug;
+use rustc_middle::mir::mono::{Linkage, Visibility};
+use rustc_middle::ty::TyCtxt;
+use rustc_session::config::CrateType;
+use rustc_target::spec::RelocModel;
+
+/// The name of `linkage` in an `#llvm.linkage<..>` attribute.
+pub(crate) fn linkage_name(linkage: Linkage) -> &'static str {
+    match linkage {
+        Linkage::External => "external",
+        Linkage::AvailableExternally => "available_externally",
+        Linkage::LinkOnceAny => "linkonce",
+        Linkage::LinkOnceODR => "linkonce_odr",
+        Linkage::WeakAny => "weak",
+        Linkage::WeakODR => "weak_odr",
+        Linkage::Appending => "appending",
+        Linkage::Internal => "internal",
+        Linkage::Private => "private",
+        Linkage::ExternalWeak => "extern_weak",
+        Linkage::Common => "common",
+    }
+}
+
+/// The visibility a symbol defined with `linkage` and `visibility` ends up with.
+pub(crate) fn symbol_visibility(
+    tcx: TyCtxt<'_>,
+    linkage: Linkage,
+    visibility: Visibility,
+) -> Visibility {
+    match linkage {
+        // Local symbols always have default visibility.
+        Linkage::Internal | Linkage::Private => Visibility::Default,
+        // compiler-builtins is linked into everything, but must not export its symbols from
+        // dylibs.
+        _ if tcx.is_compiler_builtins(LOCAL_CRATE) => Visibility::Hidden,
+        _ => visibility,
+    }
+}
+
+/// Whether a symbol defined in this codegen unit can be assumed to be resolved within the same
+/// linkage unit, allowing direct accesses instead of ones going through the GOT or PLT.
+pub(crate) fn assume_dso_local(
+    tcx: TyCtxt<'_>,
+    linkage: Linkage,
+    visibility: Visibility,
+    thread_local: bool,
+) -> bool {
+    if matches!(linkage, Linkage::Internal | Linkage::Private) {
+        return true;
+    }
+    if visibility != Visibility::Default && linkage != Linkage::ExternalWeak {
+        return true;
+    }
+
+    // Symbols from executables can't really be imported any further.
+    let all_exe = tcx.crate_types().iter().all(|ty| *ty == CrateType::Executable);
+    if all_exe && linkage != Linkage::AvailableExternally {
+        return true;
+    }
+
+    // Match clang by only supporting COFF and ELF for now.
+    if tcx.sess.target.is_like_osx {
+        return false;
+    }
+    // With the pie relocation model, uses of symbols defined in the executable can use copy
+    // relocations.
+    if tcx.sess.relocation_model() == RelocModel::Pie {
+        return true;
+    }
+    // Thread-local variables generally don't support copy relocations.
+    if thread_local {
+        return false;
+    }
+    if let Some(direct) = tcx.sess.direct_access_external_data() {
+        return direct;
+    }
+    // The static relocation model forces copy relocations everywhere.
+    tcx.sess.relocation_model() == RelocModel::Static
+}
+
+/// The LLVM dialect attributes giving a symbol defined in this codegen unit its linkage and
+/// visibility. `linkage_attr` is the name the op takes its `#llvm.linkage` under.
+pub(crate) fn symbol_attrs<'ml>(
+    tcx: TyCtxt<'_>,
+    context: &'ml Context,
+    linkage_attr: &str,
+    linkage: Linkage,
+    visibility: Visibility,
+    thread_local: bool,
+) -> Vec<(Identifier<'ml>, Attribute<'ml>)> {
+    let visibility = symbol_visibility(tcx, linkage, visibility);
+    let parse = |source: String| {
+        Attribute::parse(context, &source)
+            .unwrap_or_else(|| bug!("invalid symbol attribute `{source}`"))
+    };
+
+    let mut attrs = vec![(
+        Identifier::new(context, linkage_attr),
+        parse(format!("#llvm.linkage<{}>", linkage_name(linkage))),
+    )];
+    let visibility_attr = match visibility {
+        Visibility::Default => None,
+        Visibility::Hidden => Some(1),
+        Visibility::Protected => Some(2),
+    };
+    if let Some(visibility) = visibility_attr {
+        attrs.push((Identifier::new(context, "visibility_"), parse(format!("{visibility} : i64"))));
+    }
+    if assume_dso_local(tcx, linkage, visibility, thread_local) {
+        attrs.push((Identifier::new(context, "dso_local"), Attribute::unit(context)));
+    }
+    attrs
+}
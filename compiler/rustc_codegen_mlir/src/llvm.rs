@@ -0,0 +1,71 @@
+//! The translation of modules lowered to the LLVM dialect to LLVM IR, which
+//! `rustc_codegen_llvm` then optimizes and emits like the modules it generates itself.
+//!
+//! MLIR translates into the LLVM context of `rustc_codegen_llvm`. A process can only hold one
+//! definition of each symbol of the LLVM C API, so MLIR has to be built against the compiler's
+//! LLVM and link its libraries instead of a copy of its own. Then the MLIR libraries and
+//! `rustc_codegen_llvm` use a single LLVM, and [`check_llvm_version`] rejects an MLIR that was
+//! built against a different one.
+
+use std::ffi::c_uint;
+
+use melior::ir::Module;
+use rustc_codegen_llvm::{ModuleLlvm, llvm};
+use rustc_session::Session;
+
+use crate::ffi::{mlirTranslateModuleToLLVMIR, raw_op};
+
+extern "C" {
+    fn LLVMGetVersion(major: *mut c_uint, minor: *mut c_uint, patch: *mut c_uint);
+}
+
+/// Checks that the LLVM C API the MLIR libraries come with is the LLVM `rustc_codegen_llvm` was
+/// built against, see the module documentation.
+pub(crate) fn check_llvm_version(sess: &Session) {
+    let (mut major, mut minor, mut patch) = (0, 0, 0);
+    unsafe { LLVMGetVersion(&mut major, &mut minor, &mut patch) };
+    let (rustc_major, rustc_minor, rustc_patch) = unsafe {
+        (llvm::LLVMRustVersionMajor(), llvm::LLVMRustVersionMinor(), llvm::LLVMRustVersionPatch())
+    };
+    if (major, minor, patch) != (rustc_major, rustc_minor, rustc_patch) {
+        sess.dcx().fatal(format!(
+            "the MLIR backend is linked against LLVM {major}.{minor}.{patch}, but the compiler \
+            against LLVM {rustc_major}.{rustc_minor}.{rustc_patch}; MLIR has to be built against \
+            the compiler's LLVM"
+        ));
+    }
+}
+
+/// Translates `module`, which must only contain ops of the LLVM dialect, and links the result into
+/// the module of `module_llvm`, which already carries the target triple, data layout and module
+/// flags of the session. Returns `false` if the translation failed; MLIR will have emitted a
+/// diagnostic explaining why.
+pub(crate) fn translate_into(module: &Module<'_>, module_llvm: &ModuleLlvm) -> bool {
+    let operation = raw_op(&module.as_operation());
+    unsafe {
+        let Some(translated) = mlirTranslateModuleToLLVMIR(operation, module_llvm.llcx()) else {
+            return false;
+        };
+        llvm::LLVMLinkModules2(module_llvm.llmod(), translated) == llvm::False
+    }
+}
+
+/// Appends `asm` to the module level inline assembly of `module_llvm`.
+pub(crate) fn append_module_asm(module_llvm: &ModuleLlvm, asm: &str) {
+    if asm.is_empty() {
+        return;
+    }
+    unsafe { llvm::LLVMAppendModuleInlineAsm(module_llvm.llmod(), asm.as_ptr().cast(), asm.len()) };
+}
+
+/// Sets the DWARF version to emit debug info with, which LLVM would otherwise default to 4.
+pub(crate) fn set_dwarf_version(module_llvm: &ModuleLlvm, version: u32) {
+    unsafe {
+        llvm::LLVMRustAddModuleFlagU32(
+            module_llvm.llmod(),
+            llvm::LLVMModFlagBehavior::Warning,
+            c"Dwarf Version".as_ptr(),
+            version,
+        );
+    }
+}
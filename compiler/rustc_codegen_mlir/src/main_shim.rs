@@ -0,0 +1,143 @@
+//! The C `main` function of executables, which calls the `start` lang item with the Rust `main`
+//! function, or a `#[start]` function directly.
+//!
+//! See `maybe_create_entry_wrapper` in `rustc_codegen_ssa/src/base.rs`.
+
+use melior::ir::r#type::{FunctionType, IntegerType};
+use melior::ir::{Block, BlockLike, Region, Type, Value};
+use rustc_hir::LangItem;
+use rustc_middle::mir::mono::{Linkage, MonoItem, Visibility};
+use rustc_middle::ty::layout::FnAbiOf;
+use rustc_middle::ty::{self, Instance, ParamEnv};
+use rustc_session::config::EntryFnType;
+use rustc_span::DUMMY_SP;
+
+use crate::abi::mlir_fn_type;
+use crate::builder::Builder;
+use crate::context::CodegenCx;
+use crate::type_of::pointer_ty;
+
+/// Defines the entry function of the executable if this codegen unit is the one responsible for
+/// it. Must be called once all functions of the codegen unit have been defined.
+pub(crate) fn maybe_create_entry_wrapper(cx: &CodegenCx<'_, '_>) {
+    let tcx = cx.tcx;
+    let Some((main_def_id, entry_type)) = tcx.entry_fn(()) else {
+        return;
+    };
+    let instance = Instance::mono(tcx, main_def_id).polymorphize(tcx);
+
+    if main_def_id.is_local() {
+        // We want to create the wrapper in the same codegen unit as Rust's main function.
+        if !cx.codegen_unit.contains_item(&MonoItem::Fn(instance)) {
+            return;
+        }
+    } else if !cx.codegen_unit.is_primary() {
+        // We want to create the wrapper only when the codegen unit is the primary one.
+        return;
+    }
+
+    let target = &tcx.sess.target;
+    if target.os.contains("uefi") {
+        tcx.dcx().fatal("UEFI entry points are not yet supported by the MLIR backend");
+    }
+    let entry_name = &*target.entry_name;
+    if cx.is_fn_defined(entry_name) {
+        tcx.dcx().span_fatal(
+            tcx.def_span(main_def_id),
+            format!("entry symbol `{entry_name}` declared multiple times"),
+        );
+    }
+
+    let location = cx.unknown_loc();
+    let c_int: Type<'_> = IntegerType::new(cx.context, target.c_int_width.parse().unwrap()).into();
+    let isize = IntegerType::new(cx.context, tcx.data_layout.pointer_size.bits() as u32).into();
+    let ptr = pointer_ty(cx.context);
+
+    // The entry function is either `int main(void)` or `int main(int argc, char **argv)`.
+    let params = if target.main_needs_argc_argv { vec![c_int, ptr] } else { vec![] };
+    let entry_ty = FunctionType::new(cx.context, &params, &[c_int]).into();
+
+    let body = Region::new();
+    let block =
+        body.append_block(Block::new(&params.iter().map(|&ty| (ty, location)).collect::<Vec<_>>()));
+    let bx = Builder::new(cx.context, block, location);
+
+    let (argc, argv) = if target.main_needs_argc_argv {
+        let argc = block.argument(0).unwrap().into();
+        (int_cast(&bx, argc, c_int, isize), block.argument(1).unwrap().into())
+    } else {
+        (bx.iconst(isize, 0), bx.null_ptr())
+    };
+
+    let (start_instance, args) = match entry_type {
+        EntryFnType::Main { sigpipe } => {
+            // Given that `main()` has no arguments, its return type cannot have late-bound
+            // regions, since late-bound regions must appear in the argument listing.
+            let main_ret_ty = tcx.fn_sig(main_def_id).no_bound_vars().unwrap().output();
+            let main_ret_ty = tcx.normalize_erasing_regions(
+                ParamEnv::reveal_all(),
+                main_ret_ty.no_bound_vars().unwrap(),
+            );
+
+            let start_def_id = tcx.require_lang_item(LangItem::Start, None);
+            let start_instance = Instance::expect_resolve(
+                tcx,
+                ParamEnv::reveal_all(),
+                start_def_id,
+                tcx.mk_args(&[main_ret_ty.into()]),
+                DUMMY_SP,
+            )
+            .polymorphize(tcx);
+
+            let main_addr = fn_addr(cx, &bx, instance);
+            let sigpipe = bx.iconst(bx.int_type(8), i128::from(sigpipe));
+            (start_instance, vec![main_addr, argc, argv, sigpipe])
+        }
+        EntryFnType::Start => (instance, vec![argc, argv]),
+    };
+
+    let start_symbol = tcx.symbol_name(start_instance).name;
+    let start_fn_ty =
+        mlir_fn_type(tcx, cx.context, cx.fn_abi_of_instance(start_instance, ty::List::empty()));
+    cx.reference_fn(start_symbol, start_fn_ty);
+    let result = bx.call(start_symbol, &args, &[isize]).result(0).unwrap().into();
+    bx.ret(&[int_cast(&bx, result, isize, c_int)]);
+
+    let attributes = crate::linkage::symbol_attrs(
+        tcx,
+        cx.context,
+        "llvm.linkage",
+        Linkage::External,
+        Visibility::Default,
+        false,
+    );
+    cx.define_fn(entry_name, entry_ty, body, &attributes, location);
+}
+
+/// Takes the address of `instance`, like [`crate::base::codegen_fn_addr`].
+fn fn_addr<'a, 'ml, 'tcx>(
+    cx: &CodegenCx<'ml, 'tcx>,
+    bx: &Builder<'a, 'ml>,
+    instance: Instance<'tcx>,
+) -> Value<'ml, 'a> {
+    let fn_ty =
+        mlir_fn_type(cx.tcx, cx.context, cx.fn_abi_of_instance(instance, ty::List::empty()));
+    let symbol_name = cx.tcx.symbol_name(instance).name;
+    cx.reference_fn(symbol_name, fn_ty);
+    bx.func_addr(symbol_name, fn_ty)
+}
+
+/// Sign extends or truncates `val` from `from` to `to`.
+fn int_cast<'a, 'ml>(
+    bx: &Builder<'a, 'ml>,
+    val: Value<'ml, 'a>,
+    from: Type<'ml>,
+    to: Type<'ml>,
+) -> Value<'ml, 'a> {
+    let bits = |ty: Type<'ml>| IntegerType::try_from(ty).unwrap().width();
+    match bits(from).cmp(&bits(to)) {
+        std::cmp::Ordering::Less => bx.cast("arith.extsi", val, to),
+        std::cmp::Ordering::Equal => val,
+        std::cmp::Ordering::Greater => bx.cast("arith.trunci", val, to),
+    }
+}
@@ -0,0 +1,13 @@
+//! Mixed-backend builds with `-Zmlir-only-functions`.
+//!
+//! The partitioner places the selected functions into a codegen unit of their own, which is
+//! compiled by this backend. The LLVM IR of everything else, including the allocator shim, is
+//! generated by `rustc_codegen_llvm`, which optimizes and emits the modules of both backends like
+//! in any other build.
+
+use rustc_session::Session;
+
+/// Whether only the functions selected with `-Zmlir-only-functions` are compiled by this backend.
+pub(crate) fn is_mixed(sess: &Session) -> bool {
+    !sess.opts.unstable_opts.mlir_only_functions.is_empty()
+}
@@ -0,0 +1,421 @@
+//! Various operations on integer and floating-point numbers
+
+use melior::ir::operation::OperationLike;
+use melior::ir::{Value, ValueLike};
+use rustc_middle::bug;
+use rustc_middle::mir::BinOp;
+use rustc_middle::ty::layout::LayoutOf;
+use rustc_middle::ty::{self, Ty};
+
+use crate::builder::{FloatCC, IntCC};
+use crate::common::FunctionCx;
+use crate::type_of::type_sign;
+use crate::value_and_place::MValue;
+
+fn bin_op_to_intcc(bin_op: BinOp, signed: bool) -> Option<IntCC> {
+    use BinOp::*;
+    use IntCC::*;
+    Some(match bin_op {
+        Eq => Equal,
+        Lt => {
+            if signed {
+                SignedLessThan
+            } else {
+                UnsignedLessThan
+            }
+        }
+        Le => {
+            if signed {
+                SignedLessThanOrEqual
+            } else {
+                UnsignedLessThanOrEqual
+            }
+        }
+        Ne => NotEqual,
+        Ge => {
+            if signed {
+                SignedGreaterThanOrEqual
+            } else {
+                UnsignedGreaterThanOrEqual
+            }
+        }
+        Gt => {
+            if signed {
+                SignedGreaterThan
+            } else {
+                UnsignedGreaterThan
+            }
+        }
+        _ => return None,
+    })
+}
+
+fn codegen_three_way_compare<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    signed: bool,
+    lhs: Value<'ml, 'a>,
+    rhs: Value<'ml, 'a>,
+) -> MValue<'ml, 'a, 'tcx> {
+    let gt_cc = bin_op_to_intcc(BinOp::Gt, signed).unwrap();
+    let lt_cc = bin_op_to_intcc(BinOp::Lt, signed).unwrap();
+    let gt = fx.bx.bool_to_i8(fx.bx.icmp(gt_cc, lhs, rhs));
+    let lt = fx.bx.bool_to_i8(fx.bx.icmp(lt_cc, lhs, rhs));
+    let val = fx.bx.binary("arith.subi", gt, lt);
+    MValue::by_val(val, fx.layout_of(fx.tcx.ty_ordering_enum(Some(fx.mir.span))))
+}
+
+fn codegen_compare_bin_op<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    bin_op: BinOp,
+    signed: bool,
+    lhs: Value<'ml, 'a>,
+    rhs: Value<'ml, 'a>,
+) -> Option<MValue<'ml, 'a, 'tcx>> {
+    let intcc = bin_op_to_intcc(bin_op, signed)?;
+    let val = fx.bx.icmp(intcc, lhs, rhs);
+    let val = fx.bx.bool_to_i8(val);
+    Some(MValue::by_val(val, fx.layout_of(fx.tcx.types.bool)))
+}
+
+pub(crate) fn codegen_binop<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    bin_op: BinOp,
+    in_lhs: MValue<'ml, 'a, 'tcx>,
+    in_rhs: MValue<'ml, 'a, 'tcx>,
+) -> MValue<'ml, 'a, 'tcx> {
+    if let BinOp::Eq | BinOp::Lt | BinOp::Le | BinOp::Ne | BinOp::Ge | BinOp::Gt | BinOp::Cmp =
+        bin_op
+    {
+        match in_lhs.layout().ty.kind() {
+            ty::Bool | ty::Uint(_) | ty::Int(_) | ty::Char => {
+                let signed = type_sign(in_lhs.layout().ty);
+                let lhs = in_lhs.load_scalar(fx);
+                let rhs = in_rhs.load_scalar(fx);
+
+                if let BinOp::Cmp = bin_op {
+                    return codegen_three_way_compare(fx, signed, lhs, rhs);
+                }
+
+                return codegen_compare_bin_op(fx, bin_op, signed, lhs, rhs).unwrap();
+            }
+            _ => {}
+        }
+    }
+
+    match in_lhs.layout().ty.kind() {
+        ty::Bool => codegen_bool_binop(fx, bin_op, in_lhs, in_rhs),
+        ty::Uint(_) | ty::Int(_) => codegen_int_binop(fx, bin_op, in_lhs, in_rhs),
+        ty::Float(_) => codegen_float_binop(fx, bin_op, in_lhs, in_rhs),
+        ty::RawPtr(..) | ty::FnPtr(..) => codegen_ptr_binop(fx, bin_op, in_lhs, in_rhs),
+        _ => unreachable!("{:?}({:?}, {:?})", bin_op, in_lhs.layout().ty, in_rhs.layout().ty),
+    }
+}
+
+pub(crate) fn codegen_bool_binop<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    bin_op: BinOp,
+    in_lhs: MValue<'ml, 'a, 'tcx>,
+    in_rhs: MValue<'ml, 'a, 'tcx>,
+) -> MValue<'ml, 'a, 'tcx> {
+    let lhs = in_lhs.load_scalar(fx);
+    let rhs = in_rhs.load_scalar(fx);
+
+    let name = match bin_op {
+        BinOp::BitXor => "arith.xori",
+        BinOp::BitAnd => "arith.andi",
+        BinOp::BitOr => "arith.ori",
+        _ => unreachable!("{:?}({:?}, {:?})", bin_op, in_lhs, in_rhs),
+    };
+
+    MValue::by_val(fx.bx.binary(name, lhs, rhs), fx.layout_of(fx.tcx.types.bool))
+}
+
+/// Truncates or extends the shift amount to the width of the shifted value and masks it so that
+/// out of range shifts wrap around like MIR requires.
+fn shift_amount<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    lhs: Value<'ml, 'a>,
+    rhs: Value<'ml, 'a>,
+    masked: bool,
+) -> Value<'ml, 'a> {
+    let lhs_bits = int_bits(lhs);
+    let rhs_bits = int_bits(rhs);
+    let rhs = if rhs_bits > lhs_bits {
+        fx.bx.cast("arith.trunci", rhs, lhs.r#type())
+    } else if rhs_bits < lhs_bits {
+        fx.bx.cast("arith.extui", rhs, lhs.r#type())
+    } else {
+        rhs
+    };
+    if masked {
+        let mask = fx.bx.iconst(lhs.r#type(), i128::from(lhs_bits - 1));
+        fx.bx.binary("arith.andi", rhs, mask)
+    } else {
+        rhs
+    }
+}
+
+pub(crate) fn int_bits(val: Value<'_, '_>) -> u32 {
+    melior::ir::r#type::IntegerType::try_from(val.r#type())
+        .unwrap_or_else(|_| bug!("expected an integer, found {}", val.r#type()))
+        .width()
+}
+
+pub(crate) fn codegen_int_binop<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    bin_op: BinOp,
+    in_lhs: MValue<'ml, 'a, 'tcx>,
+    in_rhs: MValue<'ml, 'a, 'tcx>,
+) -> MValue<'ml, 'a, 'tcx> {
+    if !matches!(bin_op, BinOp::Shl | BinOp::ShlUnchecked | BinOp::Shr | BinOp::ShrUnchecked) {
+        assert_eq!(
+            in_lhs.layout().ty,
+            in_rhs.layout().ty,
+            "int binop requires lhs and rhs of same type"
+        );
+    }
+
+    let signed = type_sign(in_lhs.layout().ty);
+
+    let lhs = in_lhs.load_scalar(fx);
+    let rhs = in_rhs.load_scalar(fx);
+
+    let b = fx.bx;
+    let val = match bin_op {
+        BinOp::Add | BinOp::AddUnchecked => b.binary("arith.addi", lhs, rhs),
+        BinOp::Sub | BinOp::SubUnchecked => b.binary("arith.subi", lhs, rhs),
+        BinOp::Mul | BinOp::MulUnchecked => b.binary("arith.muli", lhs, rhs),
+        BinOp::Div => {
+            if signed {
+                b.binary("arith.divsi", lhs, rhs)
+            } else {
+                b.binary("arith.divui", lhs, rhs)
+            }
+        }
+        BinOp::Rem => {
+            if signed {
+                b.binary("arith.remsi", lhs, rhs)
+            } else {
+                b.binary("arith.remui", lhs, rhs)
+            }
+        }
+        BinOp::BitXor => b.binary("arith.xori", lhs, rhs),
+        BinOp::BitAnd => b.binary("arith.andi", lhs, rhs),
+        BinOp::BitOr => b.binary("arith.ori", lhs, rhs),
+        BinOp::Shl | BinOp::ShlUnchecked => {
+            let rhs = shift_amount(fx, lhs, rhs, bin_op == BinOp::Shl);
+            b.binary("arith.shli", lhs, rhs)
+        }
+        BinOp::Shr | BinOp::ShrUnchecked => {
+            let rhs = shift_amount(fx, lhs, rhs, bin_op == BinOp::Shr);
+            if signed {
+                b.binary("arith.shrsi", lhs, rhs)
+            } else {
+                b.binary("arith.shrui", lhs, rhs)
+            }
+        }
+        BinOp::Offset => unreachable!("Offset is not allowed on integers"),
+        BinOp::AddWithOverflow | BinOp::SubWithOverflow | BinOp::MulWithOverflow => {
+            unreachable!("{:?} must use codegen_checked_int_binop", bin_op)
+        }
+        // Compare binops handles by `codegen_binop`.
+        _ => unreachable!("{:?}({:?}, {:?})", bin_op, in_lhs.layout().ty, in_rhs.layout().ty),
+    };
+
+    MValue::by_val(val, in_lhs.layout())
+}
+
+/// Codegens `AddWithOverflow` and friends, producing a `(T, bool)` pair.
+pub(crate) fn codegen_checked_int_binop<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    bin_op: BinOp,
+    in_lhs: MValue<'ml, 'a, 'tcx>,
+    in_rhs: MValue<'ml, 'a, 'tcx>,
+) -> MValue<'ml, 'a, 'tcx> {
+    assert_eq!(
+        in_lhs.layout().ty,
+        in_rhs.layout().ty,
+        "checked int binop requires lhs and rhs of same type"
+    );
+
+    let lhs = in_lhs.load_scalar(fx);
+    let rhs = in_rhs.load_scalar(fx);
+
+    let signed = type_sign(in_lhs.layout().ty);
+    let b = fx.bx;
+
+    let (res, has_overflow) = match bin_op {
+        BinOp::Add => {
+            let val = b.binary("arith.addi", lhs, rhs);
+            let has_overflow = if !signed {
+                b.icmp(IntCC::UnsignedLessThan, val, lhs)
+            } else {
+                // Overflow iff both operands have a sign that differs from the result's.
+                let a = b.binary("arith.xori", lhs, val);
+                let c = b.binary("arith.xori", rhs, val);
+                let both = b.binary("arith.andi", a, c);
+                b.icmp_imm(IntCC::SignedLessThan, both, 0)
+            };
+            (val, has_overflow)
+        }
+        BinOp::Sub => {
+            let val = b.binary("arith.subi", lhs, rhs);
+            let has_overflow = if !signed {
+                b.icmp(IntCC::UnsignedGreaterThan, val, lhs)
+            } else {
+                // Overflow iff the operands differ in sign and the result's sign differs from
+                // the lhs.
+                let a = b.binary("arith.xori", lhs, rhs);
+                let c = b.binary("arith.xori", lhs, val);
+                let both = b.binary("arith.andi", a, c);
+                b.icmp_imm(IntCC::SignedLessThan, both, 0)
+            };
+            (val, has_overflow)
+        }
+        BinOp::Mul => {
+            let ty = lhs.r#type();
+            let op = if signed { "arith.mulsi_extended" } else { "arith.mului_extended" };
+            let extended = b
+                .append(b.op(op).add_operands(&[lhs, rhs]).add_results(&[ty, ty]).build().unwrap());
+            let low: Value<'ml, 'a> = extended.result(0).unwrap().into();
+            let high: Value<'ml, 'a> = extended.result(1).unwrap().into();
+            let has_overflow = if !signed {
+                b.icmp_imm(IntCC::NotEqual, high, 0)
+            } else {
+                let sign_bits = b.iconst(ty, i128::from(int_bits(low) - 1));
+                let low_sign = b.binary("arith.shrsi", low, sign_bits);
+                b.icmp(IntCC::NotEqual, high, low_sign)
+            };
+            (low, has_overflow)
+        }
+        _ => bug!("binop {:?} on checked int/uint lhs: {:?} rhs: {:?}", bin_op, in_lhs, in_rhs),
+    };
+
+    let has_overflow = b.bool_to_i8(has_overflow);
+
+    let out_layout = fx.layout_of(Ty::new_tup(fx.tcx, &[in_lhs.layout().ty, fx.tcx.types.bool]));
+    MValue::by_val_pair(res, has_overflow, out_layout)
+}
+
+pub(crate) fn codegen_float_binop<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    bin_op: BinOp,
+    in_lhs: MValue<'ml, 'a, 'tcx>,
+    in_rhs: MValue<'ml, 'a, 'tcx>,
+) -> MValue<'ml, 'a, 'tcx> {
+    assert_eq!(in_lhs.layout().ty, in_rhs.layout().ty);
+
+    let lhs = in_lhs.load_scalar(fx);
+    let rhs = in_rhs.load_scalar(fx);
+
+    let b = fx.bx;
+    let name = match bin_op {
+        BinOp::Add => "arith.addf",
+        BinOp::Sub => "arith.subf",
+        BinOp::Mul => "arith.mulf",
+        BinOp::Div => "arith.divf",
+        BinOp::Rem => "arith.remf",
+        BinOp::Eq | BinOp::Lt | BinOp::Le | BinOp::Ne | BinOp::Ge | BinOp::Gt => {
+            let fltcc = match bin_op {
+                BinOp::Eq => FloatCC::Equal,
+                BinOp::Lt => FloatCC::LessThan,
+                BinOp::Le => FloatCC::LessThanOrEqual,
+                BinOp::Ne => FloatCC::NotEqual,
+                BinOp::Ge => FloatCC::GreaterThanOrEqual,
+                BinOp::Gt => FloatCC::GreaterThan,
+                _ => unreachable!(),
+            };
+            let val = b.fcmp(fltcc, lhs, rhs);
+            let val = b.bool_to_i8(val);
+            return MValue::by_val(val, fx.layout_of(fx.tcx.types.bool));
+        }
+        _ => unreachable!("{:?}({:?}, {:?})", bin_op, in_lhs, in_rhs),
+    };
+
+    MValue::by_val(b.binary(name, lhs, rhs), in_lhs.layout())
+}
+
+fn codegen_ptr_binop<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    bin_op: BinOp,
+    in_lhs: MValue<'ml, 'a, 'tcx>,
+    in_rhs: MValue<'ml, 'a, 'tcx>,
+) -> MValue<'ml, 'a, 'tcx> {
+    let is_thin_ptr = in_lhs
+        .layout()
+        .ty
+        .builtin_deref(true)
+        .map(|ty| !crate::type_of::has_ptr_meta(fx.tcx, ty))
+        .unwrap_or(true);
+
+    if is_thin_ptr {
+        match bin_op {
+            BinOp::Offset => {
+                let pointee_ty = in_lhs.layout().ty.builtin_deref(true).unwrap();
+                let base = in_lhs.load_scalar(fx);
+                let offset = in_rhs.load_scalar(fx);
+                let pointee_size = fx.layout_of(pointee_ty).size.bytes();
+                let byte_offset = fx.bx.imul_imm(offset, pointee_size as i128);
+                MValue::by_val(fx.bx.ptr_offset(base, byte_offset), in_lhs.layout())
+            }
+            _ => {
+                let usize_ty = fx.usize_type();
+                let lhs = in_lhs.load_scalar(fx);
+                let rhs = in_rhs.load_scalar(fx);
+                let lhs = fx.bx.cast("llvm.ptrtoint", lhs, usize_ty);
+                let rhs = fx.bx.cast("llvm.ptrtoint", rhs, usize_ty);
+                if let BinOp::Cmp = bin_op {
+                    return codegen_three_way_compare(fx, false, lhs, rhs);
+                }
+                codegen_compare_bin_op(fx, bin_op, false, lhs, rhs)
+                    .unwrap_or_else(|| bug!("{:?} on pointers", bin_op))
+            }
+        }
+    } else {
+        let usize_ty = fx.usize_type();
+        let (lhs_ptr, lhs_extra) = in_lhs.load_scalar_pair(fx);
+        let (rhs_ptr, rhs_extra) = in_rhs.load_scalar_pair(fx);
+        let lhs_ptr = fx.bx.cast("llvm.ptrtoint", lhs_ptr, usize_ty);
+        let rhs_ptr = fx.bx.cast("llvm.ptrtoint", rhs_ptr, usize_ty);
+        let lhs_extra = ptr_meta_as_int(fx, lhs_extra);
+        let rhs_extra = ptr_meta_as_int(fx, rhs_extra);
+        let b = fx.bx;
+
+        let res = match bin_op {
+            BinOp::Eq => {
+                let ptr_eq = b.icmp(IntCC::Equal, lhs_ptr, rhs_ptr);
+                let extra_eq = b.icmp(IntCC::Equal, lhs_extra, rhs_extra);
+                b.binary("arith.andi", ptr_eq, extra_eq)
+            }
+            BinOp::Ne => {
+                let ptr_ne = b.icmp(IntCC::NotEqual, lhs_ptr, rhs_ptr);
+                let extra_ne = b.icmp(IntCC::NotEqual, lhs_extra, rhs_extra);
+                b.binary("arith.ori", ptr_ne, extra_ne)
+            }
+            BinOp::Lt | BinOp::Le | BinOp::Ge | BinOp::Gt => {
+                let ptr_eq = b.icmp(IntCC::Equal, lhs_ptr, rhs_ptr);
+
+                let ptr_cmp = b.icmp(bin_op_to_intcc(bin_op, false).unwrap(), lhs_ptr, rhs_ptr);
+                let extra_cmp =
+                    b.icmp(bin_op_to_intcc(bin_op, false).unwrap(), lhs_extra, rhs_extra);
+
+                b.select(ptr_eq, extra_cmp, ptr_cmp)
+            }
+            _ => bug!("unsupported wide pointer binop {:?}", bin_op),
+        };
+
+        MValue::by_val(b.bool_to_i8(res), fx.layout_of(fx.tcx.types.bool))
+    }
+}
+
+/// Pointer metadata is either a `usize` or a vtable pointer. Comparisons are done on integers.
+fn ptr_meta_as_int<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    meta: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    if meta.r#type() == fx.pointer_type() {
+        fx.bx.cast("llvm.ptrtoint", meta, fx.usize_type())
+    } else {
+        meta
+    }
+}
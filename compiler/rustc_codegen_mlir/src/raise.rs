@@ -0,0 +1,786 @@
+//! Raising of loops in the lowered CFG to structured control flow, enabled by
+//! `-Zmlir-raise-loops`.
+//!
+//! Functions are first cleaned up by `mem2reg`, `canonicalize` and `cse`, which turns locals whose
+//! address isn't taken into SSA values carried by block arguments. A natural loop whose header
+//! only computes the loop condition with `arith` ops and `llvm.ptrtoint` and whose body is a
+//! single block then becomes an `scf.while`. Loops counting an integer up to a loop-invariant
+//! bound by a constant step, as `for i in 0..n` does once `Range::next` is inlined, further become
+//! an `scf.for`, and if its bounds are valid affine symbols and its counter fits into a signed
+//! `index`, an `affine.for`. Loops advancing a pointer up to a loop-invariant end pointer, as
+//! `for x in slice` does, become an `scf.for` counting the elements. Innermost loops are raised
+//! first, which turns the bodies of the loops around them into single blocks too.
+//!
+//! Everything raised here is lowered back to `cf` by `lower-affine` and `convert-scf-to-cf`.
+
+use melior::Context;
+use melior::ir::attribute::{DenseI32ArrayAttribute, IntegerAttribute};
+use melior::ir::block::BlockArgument;
+use melior::ir::operation::{OperationLike, OperationRef, OperationResult};
+use melior::ir::r#type::IntegerType;
+use melior::ir::{
+    Attribute, Block, BlockLike, BlockRef, Location, Operation, Region, RegionLike, Type, Value,
+    ValueLike,
+};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+
+use crate::ModuleMlir;
+use crate::builder::{Builder, IntCC};
+use crate::dialect::{block_key, op_name, read_int_attr, value_key};
+use crate::ffi::{
+    MlirBlock, MlirOperation, mlirBlockAppendOwnedOperation, mlirBlockDestroy, mlirBlockDetach,
+    mlirBlockGetParentOperation, mlirOpOperandGetNextUse, mlirOpOperandGetOperandNumber,
+    mlirOpOperandGetOwner, mlirOpOperandIsNull, mlirOperationDestroy, mlirOperationGetBlock,
+    mlirOperationRemoveFromParent, mlirOperationSetOperand, mlirValueGetFirstUse, raw_block,
+    raw_op, raw_value,
+};
+
+/// The passes run before raising, see the module docs.
+pub(crate) const PREPARE_PASSES: &[&str] = &["mem2reg", "canonicalize", "cse"];
+
+/// The ops using `value`, together with the operand number of each use.
+fn uses(value: Value<'_, '_>) -> Vec<(MlirOperation, usize)> {
+    let mut uses = vec![];
+    unsafe {
+        let mut use_ = mlirValueGetFirstUse(raw_value(value));
+        while !mlirOpOperandIsNull(use_) {
+            uses.push((mlirOpOperandGetOwner(use_), mlirOpOperandGetOperandNumber(use_) as usize));
+            use_ = mlirOpOperandGetNextUse(use_);
+        }
+    }
+    uses
+}
+
+/// The block in the body of `func` that `op` is in, possibly nested in other ops.
+fn block_in_func(mut op: MlirOperation, func: MlirOperation) -> Option<MlirBlock> {
+    loop {
+        let block = unsafe { mlirOperationGetBlock(op) };
+        if block.ptr.is_null() {
+            return None;
+        }
+        let parent = unsafe { mlirBlockGetParentOperation(block) };
+        if parent == func {
+            return Some(block);
+        }
+        if parent.ptr.is_null() {
+            return None;
+        }
+        op = parent;
+    }
+}
+
+/// Moves every op but the terminator from `from` to the end of `to`.
+fn move_ops(from: BlockRef<'_, '_>, to: BlockRef<'_, '_>) {
+    let terminator = from.terminator().map(|op| raw_op(&op));
+    let mut op = from.first_operation();
+    while let Some(current) = op {
+        op = current.next_in_block();
+        let current = raw_op(&current);
+        if Some(current) == terminator {
+            break;
+        }
+        unsafe {
+            mlirOperationRemoveFromParent(current);
+            mlirBlockAppendOwnedOperation(raw_block(to), current);
+        }
+    }
+}
+
+/// Erases `op`, which must not have any uses left.
+fn erase_op<'ml, 'a>(op: &impl OperationLike<'ml, 'a>) {
+    unsafe { mlirOperationDestroy(raw_op(op)) }
+}
+
+/// Erases `block`, whose arguments and ops must not have any uses left.
+fn erase_block(block: BlockRef<'_, '_>) {
+    let block = raw_block(block);
+    unsafe {
+        mlirBlockDetach(block);
+        mlirBlockDestroy(block);
+    }
+}
+
+pub(crate) fn block_arguments<'ml, 'a>(block: BlockRef<'ml, 'a>) -> Vec<Value<'ml, 'a>> {
+    (0..block.argument_count()).map(|i| block.argument(i).unwrap().into()).collect()
+}
+
+pub(crate) fn operands<'ml, 'a>(op: &impl OperationLike<'ml, 'a>) -> Vec<Value<'ml, 'a>> {
+    (0..op.operand_count()).map(|i| op.operand(i).unwrap()).collect()
+}
+
+/// The op defining `value`, if it isn't a block argument.
+pub(crate) fn defining_op<'ml, 'a>(value: Value<'ml, 'a>) -> Option<OperationRef<'ml, 'a>> {
+    OperationResult::try_from(value).ok().map(|result| result.owner())
+}
+
+/// A block with arguments of the types of `values`.
+fn block_like(values: &[Value<'_, '_>], location: Location<'_>) -> Block<'_> {
+    Block::new(&values.iter().map(|value| (value.r#type(), location)).collect::<Vec<_>>())
+}
+
+/// A natural loop that can be raised to an `scf.while`: `preheader` unconditionally branches to
+/// `header`, which only computes whether to run `body` again or leave to `exit`, and `body` is a
+/// single block branching back to `header`.
+struct NaturalLoop<'ml, 'a> {
+    preheader: BlockRef<'ml, 'a>,
+    header: BlockRef<'ml, 'a>,
+    body: BlockRef<'ml, 'a>,
+    exit: BlockRef<'ml, 'a>,
+    /// Whether the loop keeps going while the condition of `header` is false.
+    negated: bool,
+}
+
+/// Raises the loops of every function in `module`, see the module docs. Loops are left as
+/// `scf.for` rather than becoming `affine.for` unless `to_affine` is set.
+pub(crate) fn raise_loops(module: &ModuleMlir, to_affine: bool) {
+    let context = module.context();
+    let mut op = module.module().body().first_operation();
+    while let Some(func) = op {
+        if op_name(&func) == "func.func" {
+            let body = func.region(0).unwrap();
+            while let Some(natural_loop) = find_loop(raw_op(&func), body.first_block()) {
+                let while_op = raise_to_while(context, raw_op(&func), natural_loop);
+                let for_op = raise_to_for(context, while_op)
+                    .or_else(|| raise_ptr_loop_to_for(context, while_op));
+                if let Some(for_op) = for_op {
+                    if to_affine {
+                        raise_to_affine(context, body.first_block().unwrap(), for_op);
+                    }
+                }
+            }
+        }
+        op = func.next_in_block();
+    }
+}
+
+/// Finds a loop that can be raised among the blocks starting at `entry`.
+fn find_loop<'ml, 'a>(
+    func: MlirOperation,
+    entry: Option<BlockRef<'ml, 'a>>,
+) -> Option<NaturalLoop<'ml, 'a>> {
+    let mut blocks = vec![];
+    let mut block = entry;
+    while let Some(current) = block {
+        blocks.push(current);
+        block = current.next_in_region();
+    }
+
+    let mut predecessors = FxHashMap::<usize, Vec<BlockRef<'ml, 'a>>>::default();
+    for &block in &blocks {
+        let Some(terminator) = block.terminator() else { continue };
+        for i in 0..terminator.successor_count() {
+            let successor = terminator.successor(i).unwrap();
+            predecessors.entry(block_key(successor)).or_default().push(block);
+        }
+    }
+    let predecessors_of = |block: BlockRef<'ml, 'a>| {
+        predecessors.get(&block_key(block)).map_or(&[][..], |preds| &preds[..])
+    };
+
+    for &header in &blocks {
+        let Some(terminator) = header.terminator() else { continue };
+        // Successor operands would need to become results of the loop.
+        if op_name(&terminator) != "cf.cond_br" || terminator.operand_count() != 1 {
+            continue;
+        }
+        let on_true = terminator.successor(0).unwrap();
+        let on_false = terminator.successor(1).unwrap();
+        for (body, exit, negated) in [(on_true, on_false, false), (on_false, on_true, true)] {
+            let (key_header, key_body, key_exit) =
+                (block_key(header), block_key(body), block_key(exit));
+            if key_body == key_header || key_exit == key_header || key_body == key_exit {
+                continue;
+            }
+            if body.argument_count() != 0 || exit.argument_count() != 0 {
+                continue;
+            }
+
+            // The body is only entered from the header and always branches back to it.
+            if !matches!(predecessors_of(body), [pred] if block_key(*pred) == key_header) {
+                continue;
+            }
+            let body_terminator = body.terminator().unwrap();
+            if op_name(&body_terminator) != "cf.br"
+                || block_key(body_terminator.successor(0).unwrap()) != key_header
+            {
+                continue;
+            }
+
+            // The header is entered from the body and from a single block outside of the loop,
+            // which that block unconditionally branches to.
+            let preheader = match predecessors_of(header) {
+                [a, b] if block_key(*a) == key_body => *b,
+                [a, b] if block_key(*b) == key_body => *a,
+                _ => continue,
+            };
+            let preheader_terminator = preheader.terminator().unwrap();
+            if op_name(&preheader_terminator) != "cf.br" || block_key(preheader) == key_exit {
+                continue;
+            }
+            // Uses of the header arguments after the loop become uses of the results of the
+            // `scf.while` in the preheader, so the preheader must not be dominated by the header.
+            if !reachable_without(blocks[0], preheader, header) {
+                continue;
+            }
+
+            if !header_is_pure(func, header) {
+                continue;
+            }
+
+            return Some(NaturalLoop { preheader, header, body, exit, negated });
+        }
+    }
+    None
+}
+
+/// Whether `target` can be reached from `entry` without going through `avoid`.
+fn reachable_without(
+    entry: BlockRef<'_, '_>,
+    target: BlockRef<'_, '_>,
+    avoid: BlockRef<'_, '_>,
+) -> bool {
+    let mut visited = FxHashSet::default();
+    let mut worklist = vec![entry];
+    while let Some(block) = worklist.pop() {
+        if block_key(block) == block_key(target) {
+            return true;
+        }
+        if block_key(block) == block_key(avoid) || !visited.insert(block_key(block)) {
+            continue;
+        }
+        let Some(terminator) = block.terminator() else { continue };
+        for i in 0..terminator.successor_count() {
+            worklist.push(terminator.successor(i).unwrap());
+        }
+    }
+    false
+}
+
+/// Whether the header of a loop only computes the loop condition, so that it can become the
+/// `before` region of an `scf.while`.
+fn header_is_pure(func: MlirOperation, header: BlockRef<'_, '_>) -> bool {
+    let terminator = raw_op(&header.terminator().unwrap());
+    let mut op = header.first_operation();
+    while let Some(current) = op {
+        op = current.next_in_block();
+        if raw_op(&current) == terminator {
+            break;
+        }
+        // Pointers are compared by their addresses.
+        if !op_name(&current).starts_with("arith.") && op_name(&current) != "llvm.ptrtoint" {
+            return false;
+        }
+        // Values computed in the `before` region aren't visible anywhere else.
+        for i in 0..current.result_count() {
+            let result = current.result(i).unwrap().into();
+            let only_used_in_header = uses(result)
+                .into_iter()
+                .all(|(user, _)| block_in_func(user, func) == Some(raw_block(header)));
+            if !only_used_in_header {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Replaces the loop by an `scf.while` at the end of its preheader.
+fn raise_to_while<'ml, 'a>(
+    context: &'ml Context,
+    func: MlirOperation,
+    natural_loop: NaturalLoop<'ml, 'a>,
+) -> OperationRef<'ml, 'a> {
+    let NaturalLoop { preheader, header, body, exit, negated } = natural_loop;
+    let condition_br = header.terminator().unwrap();
+    let location = condition_br.location();
+    let header_args = block_arguments(header);
+    let result_types = header_args.iter().map(|arg| arg.r#type()).collect::<Vec<_>>();
+
+    let preheader_br = preheader.terminator().unwrap();
+    let inits = operands(&preheader_br);
+    let before = Region::new();
+    before.append_block(block_like(&header_args, location));
+    let after = Region::new();
+    after.append_block(block_like(&header_args, location));
+    let bx = Builder::new(context, preheader, location);
+    let while_op = preheader.insert_operation_before(
+        preheader_br,
+        bx.op("scf.while")
+            .add_operands(&inits)
+            .add_results(&result_types)
+            .add_regions([before, after])
+            .build()
+            .unwrap(),
+    );
+    preheader.insert_operation_before(preheader_br, melior::dialect::cf::br(&exit, &[], location));
+    erase_op(&preheader_br);
+
+    let before_block = while_op.region(0).unwrap().first_block().unwrap();
+    let after_block = while_op.region(1).unwrap().first_block().unwrap();
+    let before_args = block_arguments(before_block);
+    let after_args = block_arguments(after_block);
+
+    // The header arguments are the arguments of both regions inside of the loop, and the results
+    // of the loop after it.
+    for (i, &arg) in header_args.iter().enumerate() {
+        for (user, operand) in uses(arg) {
+            let block = block_in_func(user, func);
+            let replacement = if block == Some(raw_block(header)) {
+                before_args[i]
+            } else if block == Some(raw_block(body)) {
+                after_args[i]
+            } else {
+                while_op.result(i).unwrap().into()
+            };
+            unsafe { mlirOperationSetOperand(user, operand as isize, raw_value(replacement)) };
+        }
+    }
+
+    move_ops(header, before_block);
+    let bx = bx.at(before_block);
+    let mut condition = condition_br.operand(0).unwrap();
+    let mut inverted_cmp = None;
+    if negated {
+        // Comparisons are inverted rather than negated, so that loops leaving once a counter or
+        // pointer reaches its bound are still recognized by `raise_to_for` and the like.
+        let cmp = defining_op(condition).filter(|cmp| op_name(cmp) == "arith.cmpi");
+        match cmp {
+            Some(cmp) if uses(condition).len() == 1 => {
+                let cc = inverse_predicate(read_int_attr(&cmp, "predicate"));
+                condition = bx.icmp(cc, cmp.operand(0).unwrap(), cmp.operand(1).unwrap());
+                inverted_cmp = Some(cmp);
+            }
+            _ => {
+                let i1 = IntegerType::new(context, 1).into();
+                let true_ = bx.iconst(i1, 1);
+                condition = bx.binary("arith.xori", condition, true_);
+            }
+        }
+    }
+    let mut condition_operands = vec![condition];
+    condition_operands.extend(before_args.iter().copied());
+    bx.append(bx.op("scf.condition").add_operands(&condition_operands).build().unwrap());
+    erase_op(&condition_br);
+    if let Some(cmp) = inverted_cmp {
+        erase_op(&cmp);
+    }
+
+    move_ops(body, after_block);
+    let body_br = body.terminator().unwrap();
+    let bx = bx.at(after_block);
+    bx.append(bx.op("scf.yield").add_operands(&operands(&body_br)).build().unwrap());
+    erase_op(&body_br);
+
+    erase_block(header);
+    erase_block(body);
+    while_op
+}
+
+/// The comparison that holds exactly when one with the `arith.cmpi` `predicate` doesn't.
+fn inverse_predicate(predicate: i64) -> IntCC {
+    use IntCC::*;
+    let inverses = [
+        NotEqual,
+        Equal,
+        SignedGreaterThanOrEqual,
+        SignedGreaterThan,
+        SignedLessThanOrEqual,
+        SignedLessThan,
+        UnsignedGreaterThanOrEqual,
+        UnsignedGreaterThan,
+        UnsignedLessThanOrEqual,
+        UnsignedLessThan,
+    ];
+    inverses[predicate as usize]
+}
+
+/// Turns an `scf.while` counting an integer up to a loop-invariant bound by a constant step into
+/// an `scf.for`. The counter must not be used after the loop, as `scf.for` doesn't return it.
+fn raise_to_for<'ml, 'a>(
+    context: &'ml Context,
+    while_op: OperationRef<'ml, 'a>,
+) -> Option<OperationRef<'ml, 'a>> {
+    let before_block = while_op.region(0).unwrap().first_block().unwrap();
+    let after_block = while_op.region(1).unwrap().first_block().unwrap();
+    let before_args = block_arguments(before_block);
+    let after_args = block_arguments(after_block);
+
+    // before: `%c = arith.cmpi slt/ult, %counter, %bound; scf.condition(%c) %args...`
+    let cmp = before_block.first_operation()?;
+    let condition = cmp.next_in_block()?;
+    if op_name(&cmp) != "arith.cmpi" || op_name(&condition) != "scf.condition" {
+        return None;
+    }
+    let condition_operands = operands(&condition);
+    if value_key(condition_operands[0]) != value_key(cmp.result(0).unwrap().into())
+        || condition_operands[1..]
+            .iter()
+            .map(|&v| value_key(v))
+            .ne(before_args.iter().map(|&v| value_key(v)))
+    {
+        return None;
+    }
+    let unsigned = match read_int_attr(&cmp, "predicate") {
+        p if p == IntCC::SignedLessThan as i64 => false,
+        p if p == IntCC::UnsignedLessThan as i64 => true,
+        _ => return None,
+    };
+    let counter = cmp.operand(0).unwrap();
+    let bound = cmp.operand(1).unwrap();
+    let index = before_args.iter().position(|&arg| value_key(arg) == value_key(counter))?;
+    // The bound is defined outside of the loop, as the comparison is the only op in `before`.
+    if before_args.iter().any(|&arg| value_key(arg) == value_key(bound)) {
+        return None;
+    }
+
+    // after: `... %next = arith.addi %counter, %step ... scf.yield %args...`
+    let yield_op = after_block.terminator().unwrap();
+    let next = yield_op.operand(index).unwrap();
+    let add = defining_op(next)?;
+    if op_name(&add) != "arith.addi"
+        || value_key(add.operand(0).unwrap()) != value_key(after_args[index])
+    {
+        return None;
+    }
+    let step_const = defining_op(add.operand(1).unwrap())?;
+    if op_name(&step_const) != "arith.constant" {
+        return None;
+    }
+    let step_attr = step_const.attribute("value").ok()?;
+    if IntegerAttribute::try_from(step_attr).ok()?.value() <= 0 {
+        return None;
+    }
+    if !uses(while_op.result(index).unwrap().into()).is_empty() {
+        return None;
+    }
+
+    let location = while_op.location();
+    let block = while_op.block().unwrap();
+    let bx = Builder::new(context, block, location);
+    let step = block.insert_operation_before(
+        while_op,
+        bx.op("arith.constant")
+            .add_attributes(&[(bx.ident("value"), step_attr)])
+            .add_results(&[counter.r#type()])
+            .build()
+            .unwrap(),
+    );
+    let step: Value<'ml, 'a> = step.result(0).unwrap().into();
+
+    let inits = operands(&while_op);
+    let carried = (0..inits.len()).filter(|&i| i != index).collect::<Vec<_>>();
+    let mut for_operands = vec![inits[index], bound, step];
+    for_operands.extend(carried.iter().map(|&i| inits[i]));
+    let mut body_args = vec![after_args[index]];
+    body_args.extend(carried.iter().map(|&i| after_args[i]));
+    let result_types = carried.iter().map(|&i| inits[i].r#type()).collect::<Vec<_>>();
+    let region = Region::new();
+    region.append_block(block_like(&body_args, location));
+    let mut attributes = vec![];
+    if unsigned {
+        attributes.push((bx.ident("unsignedCmp"), Attribute::unit(context)));
+    }
+    let for_op = block.insert_operation_before(
+        while_op,
+        bx.op("scf.for")
+            .add_operands(&for_operands)
+            .add_attributes(&attributes)
+            .add_results(&result_types)
+            .add_regions([region])
+            .build()
+            .unwrap(),
+    );
+
+    let for_block = for_op.region(0).unwrap().first_block().unwrap();
+    let for_args = block_arguments(for_block);
+    replace_all_uses(after_args[index], for_args[0]);
+    for (new_index, &i) in carried.iter().enumerate() {
+        replace_all_uses(after_args[i], for_args[new_index + 1]);
+        replace_all_uses(
+            while_op.result(i).unwrap().into(),
+            for_op.result(new_index).unwrap().into(),
+        );
+    }
+
+    move_ops(after_block, for_block);
+    let yielded = carried.iter().map(|&i| yield_op.operand(i).unwrap()).collect::<Vec<_>>();
+    let bx = bx.at(for_block);
+    bx.append(bx.op("scf.yield").add_operands(&yielded).build().unwrap());
+    erase_op(&while_op);
+    Some(for_op)
+}
+
+/// Turns an `scf.while` advancing a pointer by a constant number of bytes until it reaches a
+/// loop-invariant end pointer, as `for x in slice` does once `slice::Iter::next` is inlined, into
+/// an `scf.for` counting the elements. The pointer is recomputed from the counter in the body, and
+/// is the end pointer after the loop.
+fn raise_ptr_loop_to_for<'ml, 'a>(
+    context: &'ml Context,
+    while_op: OperationRef<'ml, 'a>,
+) -> Option<OperationRef<'ml, 'a>> {
+    let before_block = while_op.region(0).unwrap().first_block().unwrap();
+    let after_block = while_op.region(1).unwrap().first_block().unwrap();
+    let before_args = block_arguments(before_block);
+    let after_args = block_arguments(after_block);
+
+    // before: `%a = llvm.ptrtoint %ptr; %b = llvm.ptrtoint %end; %c = arith.cmpi ne, %a, %b;
+    // scf.condition(%c) %args...`, with the operands of the comparison in either order.
+    let condition = before_block.terminator().unwrap();
+    let condition_operands = operands(&condition);
+    if condition_operands[1..]
+        .iter()
+        .map(|&v| value_key(v))
+        .ne(before_args.iter().map(|&v| value_key(v)))
+    {
+        return None;
+    }
+    let cmp = defining_op(condition_operands[0])?;
+    if op_name(&cmp) != "arith.cmpi" || read_int_attr(&cmp, "predicate") != IntCC::NotEqual as i64 {
+        return None;
+    }
+    let address_of = |addr: Value<'ml, 'a>| {
+        defining_op(addr)
+            .filter(|op| op_name(op) == "llvm.ptrtoint")
+            .map(|op| op.operand(0).unwrap())
+    };
+    let (lhs, rhs) = (address_of(cmp.operand(0).unwrap())?, address_of(cmp.operand(1).unwrap())?);
+    let position =
+        |ptr: Value<'ml, 'a>| before_args.iter().position(|&arg| value_key(arg) == value_key(ptr));
+    // Nothing in `before` computes a pointer, so the end pointer is defined outside of the loop
+    // unless it is carried by it.
+    let (index, end) = match (position(lhs), position(rhs)) {
+        (Some(index), None) => (index, rhs),
+        (None, Some(index)) => (index, lhs),
+        _ => return None,
+    };
+
+    // after: `... %next = llvm.getelementptr %ptr[size] : i8 ... scf.yield %args...`
+    let yield_op = after_block.terminator().unwrap();
+    let next = yield_op.operand(index).unwrap();
+    let gep = defining_op(next)?;
+    if op_name(&gep) != "llvm.getelementptr"
+        || value_key(gep.operand(0).unwrap()) != value_key(after_args[index])
+        || gep.attribute("elem_type").ok()?.to_string() != "i8"
+        || uses(next).len() != 1
+    {
+        return None;
+    }
+    let raw_indices = gep.attribute("rawConstantIndices").ok()?.to_string();
+    let raw_index =
+        raw_indices.strip_prefix("array<i32: ")?.strip_suffix('>')?.parse::<i32>().ok()?;
+    let size = match (gep.operand_count(), raw_index) {
+        (1, size) => i64::from(size),
+        (2, i32::MIN) => int_constant(gep.operand(1).unwrap())?,
+        _ => return None,
+    };
+    if size <= 0 {
+        return None;
+    }
+
+    let location = while_op.location();
+    let block = while_op.block().unwrap();
+    let bx = Builder::new(context, block, location);
+    let addr_ty = cmp.operand(0).unwrap().r#type();
+    let insert = |op: Operation<'ml>| -> Value<'ml, 'a> {
+        block.insert_operation_before(while_op, op).result(0).unwrap().into()
+    };
+    let constant = |value: i64| {
+        insert(
+            bx.op("arith.constant")
+                .add_attributes(&[(
+                    bx.ident("value"),
+                    IntegerAttribute::new(addr_ty, value).into(),
+                )])
+                .add_results(&[addr_ty])
+                .build()
+                .unwrap(),
+        )
+    };
+    let binary = |name: &str, lhs: Value<'ml, 'a>, rhs: Value<'ml, 'a>| {
+        insert(bx.op(name).add_operands(&[lhs, rhs]).add_results(&[addr_ty]).build().unwrap())
+    };
+    let ptr_to_int = |ptr: Value<'ml, 'a>| {
+        insert(bx.op("llvm.ptrtoint").add_operands(&[ptr]).add_results(&[addr_ty]).build().unwrap())
+    };
+
+    // The distance between the pointers is a multiple of the step, like for `slice::Iter`.
+    let inits = operands(&while_op);
+    let start = inits[index];
+    let len = binary("arith.subi", ptr_to_int(end), ptr_to_int(start));
+    let count = binary("arith.divui", len, constant(size));
+    let mut for_operands = vec![constant(0), count, constant(1)];
+    let carried = (0..inits.len()).filter(|&i| i != index).collect::<Vec<_>>();
+    for_operands.extend(carried.iter().map(|&i| inits[i]));
+    let mut arg_types = vec![(addr_ty, location)];
+    arg_types.extend(carried.iter().map(|&i| (inits[i].r#type(), location)));
+    let result_types = carried.iter().map(|&i| inits[i].r#type()).collect::<Vec<_>>();
+    let region = Region::new();
+    region.append_block(Block::new(&arg_types));
+    let for_op = block.insert_operation_before(
+        while_op,
+        bx.op("scf.for")
+            .add_operands(&for_operands)
+            .add_attributes(&[(bx.ident("unsignedCmp"), Attribute::unit(context))])
+            .add_results(&result_types)
+            .add_regions([region])
+            .build()
+            .unwrap(),
+    );
+
+    let for_block = for_op.region(0).unwrap().first_block().unwrap();
+    let for_args = block_arguments(for_block);
+    let bx = bx.at(for_block);
+    let offset = bx.imul_imm(for_args[0], size as i128);
+    let ptr = bx.ptr_offset(start, offset);
+    replace_all_uses(after_args[index], ptr);
+    replace_all_uses(while_op.result(index).unwrap().into(), end);
+    for (new_index, &i) in carried.iter().enumerate() {
+        replace_all_uses(after_args[i], for_args[new_index + 1]);
+        replace_all_uses(
+            while_op.result(i).unwrap().into(),
+            for_op.result(new_index).unwrap().into(),
+        );
+    }
+
+    move_ops(after_block, for_block);
+    let yielded = carried.iter().map(|&i| yield_op.operand(i).unwrap()).collect::<Vec<_>>();
+    bx.append(bx.op("scf.yield").add_operands(&yielded).build().unwrap());
+    erase_op(&while_op);
+    // The increment was only used by the `scf.yield` of the `scf.while`.
+    erase_op(&gep);
+    Some(for_op)
+}
+
+/// Turns an `scf.for` into an `affine.for` if its bounds can be affine symbols: constants, or
+/// values defined in the entry block `entry` of the function, which is never part of a loop that
+/// may be raised later. The `index` counter of an `affine.for` is signed and 64 bits wide, so
+/// unsigned loops are only raised if their counter is narrower than that.
+fn raise_to_affine<'ml, 'a>(
+    context: &'ml Context,
+    entry: BlockRef<'ml, 'a>,
+    for_op: OperationRef<'ml, 'a>,
+) {
+    let for_operands = operands(&for_op);
+    let (lower, upper, step, inits) =
+        (for_operands[0], for_operands[1], for_operands[2], &for_operands[3..]);
+    let counter_ty = lower.r#type();
+    let Ok(counter_bits) = IntegerType::try_from(counter_ty).map(|ty| ty.width()) else { return };
+    let unsigned = for_op.attribute("unsignedCmp").is_ok();
+    if unsigned && counter_bits >= 64 {
+        return;
+    }
+    let Some(step) = int_constant(step) else { return };
+    let in_entry = |value: Value<'ml, 'a>| match BlockArgument::try_from(value) {
+        Ok(arg) => block_key(arg.owner()) == block_key(entry),
+        Err(_) => defining_op(value)
+            .and_then(|op| op.block())
+            .is_some_and(|block| block_key(block) == block_key(entry)),
+    };
+    let is_symbol = |value: Value<'ml, 'a>| int_constant(value).is_some() || in_entry(value);
+    if !is_symbol(lower) || !is_symbol(upper) {
+        return;
+    }
+
+    let location = for_op.location();
+    let index_ty = Type::index(context);
+    let block = for_op.block().unwrap();
+    let bx = Builder::new(context, block, location);
+    let to_symbol = |value: Value<'ml, 'a>| -> Value<'ml, 'a> {
+        let symbol = if let Some(mut constant) = int_constant(value) {
+            if unsigned {
+                constant &= (1 << counter_bits) - 1;
+            }
+            let op = bx
+                .op("arith.constant")
+                .add_attributes(&[(
+                    bx.ident("value"),
+                    IntegerAttribute::new(index_ty, constant).into(),
+                )])
+                .add_results(&[index_ty])
+                .build()
+                .unwrap();
+            block.insert_operation_before(for_op, op)
+        } else {
+            let cast = if unsigned { "arith.index_castui" } else { "arith.index_cast" };
+            let op = bx.op(cast).add_operands(&[value]).add_results(&[index_ty]).build().unwrap();
+            match defining_op(value) {
+                Some(def) => entry.insert_operation_after(def, op),
+                None => entry.insert_operation(0, op),
+            }
+        };
+        symbol.result(0).unwrap().into()
+    };
+    let lower = to_symbol(lower);
+    let upper = to_symbol(upper);
+
+    let for_block = for_op.region(0).unwrap().first_block().unwrap();
+    let for_args = block_arguments(for_block);
+    let region = Region::new();
+    let mut arg_types = vec![(index_ty, location)];
+    arg_types.extend(for_args[1..].iter().map(|arg| (arg.r#type(), location)));
+    region.append_block(Block::new(&arg_types));
+    let mut affine_operands = vec![lower, upper];
+    affine_operands.extend_from_slice(inits);
+    let result_types =
+        (0..for_op.result_count()).map(|i| for_op.result(i).unwrap().r#type()).collect::<Vec<_>>();
+    let symbol_map = bx.parse_attr("affine_map<()[s0] -> (s0)>");
+    let affine_op = block.insert_operation_before(
+        for_op,
+        bx.op("affine.for")
+            .add_operands(&affine_operands)
+            .add_attributes(&[
+                (bx.ident("lowerBoundMap"), symbol_map),
+                (bx.ident("upperBoundMap"), symbol_map),
+                (bx.ident("step"), IntegerAttribute::new(index_ty, step).into()),
+                (
+                    bx.ident("operandSegmentSizes"),
+                    DenseI32ArrayAttribute::new(context, &[1, 1, inits.len() as i32]).into(),
+                ),
+            ])
+            .add_results(&result_types)
+            .add_regions([region])
+            .build()
+            .unwrap(),
+    );
+
+    let affine_block = affine_op.region(0).unwrap().first_block().unwrap();
+    let affine_args = block_arguments(affine_block);
+    let bx = bx.at(affine_block);
+    let counter = bx.cast("arith.index_cast", affine_args[0], counter_ty);
+    replace_all_uses(for_args[0], counter);
+    for i in 1..for_args.len() {
+        replace_all_uses(for_args[i], affine_args[i]);
+    }
+    for i in 0..for_op.result_count() {
+        replace_all_uses(for_op.result(i).unwrap().into(), affine_op.result(i).unwrap().into());
+    }
+
+    move_ops(for_block, affine_block);
+    let yield_op = for_block.terminator().unwrap();
+    bx.append(bx.op("affine.yield").add_operands(&operands(&yield_op)).build().unwrap());
+    erase_op(&for_op);
+}
+
+/// The value of an integer `arith.constant`.
+pub(crate) fn int_constant(value: Value<'_, '_>) -> Option<i64> {
+    let op = defining_op(value)?;
+    if op_name(&op) != "arith.constant" {
+        return None;
+    }
+    Some(IntegerAttribute::try_from(op.attribute("value").ok()?).ok()?.value())
+}
+
+/// Makes operand `pos` of `op` use `value`.
+pub(crate) fn set_operand<'ml, 'a>(
+    op: &impl OperationLike<'ml, 'a>,
+    pos: usize,
+    value: Value<'_, '_>,
+) {
+    unsafe { mlirOperationSetOperand(raw_op(op), pos as isize, raw_value(value)) }
+}
+
+fn replace_all_uses(from: Value<'_, '_>, to: Value<'_, '_>) {
+    for (user, operand) in uses(from) {
+        unsafe { mlirOperationSetOperand(user, operand as isize, raw_value(to)) };
+    }
+}
@@ -0,0 +1,213 @@
+//! Mapping of rustc types onto MLIR types.
+//!
+//! Values that fit in a scalar or a scalar pair are kept in SSA form using builtin integer and
+//! float types and `!llvm.ptr`; everything else lives in memory and is only ever handled through
+//! a pointer. The in-memory type of every layout is given by [`mlir_type`], which is what stack
+//! slots are allocated with.
+
+use melior::Context;
+use melior::dialect::llvm;
+use melior::ir::Type;
+use melior::ir::r#type::IntegerType;
+use rustc_abi::{Abi, Align, FieldsShape, Float, Integer, Primitive, Scalar, Size, Variants};
+use rustc_middle::bug;
+use rustc_middle::ty::layout::{LayoutOf, TyAndLayout};
+use rustc_middle::ty::{self, Ty, TyCtxt};
+
+use crate::context::CodegenCx;
+
+pub(crate) fn pointer_ty<'ml>(context: &'ml Context) -> Type<'ml> {
+    melior::dialect::llvm::r#type::pointer(context, 0)
+}
+
+pub(crate) fn usize_ty<'ml>(tcx: TyCtxt<'_>, context: &'ml Context) -> Type<'ml> {
+    IntegerType::new(context, tcx.data_layout.pointer_size.bits() as u32).into()
+}
+
+pub(crate) fn scalar_to_mlir_type<'ml>(
+    tcx: TyCtxt<'_>,
+    context: &'ml Context,
+    scalar: Scalar,
+) -> Type<'ml> {
+    primitive_to_mlir_type(tcx, context, scalar.primitive())
+}
+
+pub(crate) fn primitive_to_mlir_type<'ml>(
+    _tcx: TyCtxt<'_>,
+    context: &'ml Context,
+    primitive: Primitive,
+) -> Type<'ml> {
+    match primitive {
+        Primitive::Int(int, _signed) => {
+            let bits = match int {
+                Integer::I8 => 8,
+                Integer::I16 => 16,
+                Integer::I32 => 32,
+                Integer::I64 => 64,
+                Integer::I128 => 128,
+            };
+            IntegerType::new(context, bits).into()
+        }
+        Primitive::Float(float) => match float {
+            Float::F16 => Type::float16(context),
+            Float::F32 => Type::float32(context),
+            Float::F64 => Type::float64(context),
+            Float::F128 => Type::parse(context, "f128").unwrap(),
+        },
+        // FIXME: handle non-default address spaces
+        Primitive::Pointer(_) => pointer_ty(context),
+    }
+}
+
+/// Returns the MLIR type of a value with the given layout in memory.
+///
+/// Like `rustc_codegen_llvm`'s `llvm_type`, this only looks at the layout and not at the type, so
+/// that `repr(transparent)` wrappers lower to the same type as their field:
+///
+/// * scalars lower to builtin integer and float types or `!llvm.ptr`,
+/// * SIMD vectors lower to `vector<N x T>`,
+/// * arrays lower to `!llvm.array`; unsized slices and `str` to an array of length zero,
+/// * structs, tuples, closures and scalar pairs (including fat pointers) lower to an
+///   `!llvm.struct` of their fields in memory order with explicit padding. Enums with more than
+///   one variant only have their tag (or niche) as field; the rest is padding,
+/// * unions lower to a struct holding only padding of the right size.
+///
+/// Field projections don't index into these types; they offset by the byte offsets of the layout,
+/// as `rustc_codegen_ssa` does.
+pub(crate) fn mlir_type<'ml, 'tcx>(
+    cx: &CodegenCx<'ml, 'tcx>,
+    layout: TyAndLayout<'tcx>,
+) -> Type<'ml> {
+    if let Abi::Scalar(scalar) = layout.abi {
+        return scalar_to_mlir_type(cx.tcx, cx.context, scalar);
+    }
+
+    let variant_index = match layout.variants {
+        Variants::Single { index } => Some(index),
+        Variants::Multiple { .. } => None,
+    };
+    if let Some(&ty) = cx.type_lowering.borrow().get(&(layout.ty, variant_index)) {
+        return ty;
+    }
+
+    let ty = uncached_mlir_type(cx, layout);
+    cx.type_lowering.borrow_mut().insert((layout.ty, variant_index), ty);
+    ty
+}
+
+fn uncached_mlir_type<'ml, 'tcx>(
+    cx: &CodegenCx<'ml, 'tcx>,
+    layout: TyAndLayout<'tcx>,
+) -> Type<'ml> {
+    match layout.abi {
+        Abi::Scalar(_) => bug!("handled by `mlir_type`"),
+        Abi::Vector { element, count } => {
+            let element = scalar_to_mlir_type(cx.tcx, cx.context, element);
+            return Type::vector(&[count], element);
+        }
+        Abi::Uninhabited | Abi::Aggregate { .. } | Abi::ScalarPair(..) => {}
+    }
+
+    match layout.fields {
+        FieldsShape::Primitive | FieldsShape::Union(_) => {
+            let fill = padding_filler(cx, layout.size, layout.align.abi);
+            llvm::r#type::r#struct(cx.context, &[fill], false)
+        }
+        FieldsShape::Array { count, .. } => {
+            array_type(cx, mlir_type(cx, layout.field(cx, 0)), count)
+        }
+        FieldsShape::Arbitrary { .. } => {
+            let (fields, packed) = struct_fields(cx, layout);
+            llvm::r#type::r#struct(cx.context, &fields, packed)
+        }
+    }
+}
+
+/// The fields of the `!llvm.struct` for a layout with `FieldsShape::Arbitrary`, in memory order
+/// and with padding inserted such that each field ends up at its offset in the layout.
+///
+/// Adapted from `rustc_codegen_llvm::type_of::struct_llfields`.
+fn struct_fields<'ml, 'tcx>(
+    cx: &CodegenCx<'ml, 'tcx>,
+    layout: TyAndLayout<'tcx>,
+) -> (Vec<Type<'ml>>, bool) {
+    let field_count = layout.fields.count();
+
+    let mut packed = false;
+    let mut offset = Size::ZERO;
+    let mut prev_effective_align = layout.align.abi;
+    let mut result = Vec::with_capacity(1 + field_count * 2);
+    for i in layout.fields.index_by_increasing_offset() {
+        let target_offset = layout.fields.offset(i);
+        let field = layout.field(cx, i);
+        let effective_field_align =
+            layout.align.abi.min(field.align.abi).restrict_for_offset(target_offset);
+        packed |= effective_field_align < field.align.abi;
+
+        assert!(target_offset >= offset);
+        let padding = target_offset - offset;
+        if padding != Size::ZERO {
+            let padding_align = prev_effective_align.min(effective_field_align);
+            assert_eq!(offset.align_to(padding_align) + padding, target_offset);
+            result.push(padding_filler(cx, padding, padding_align));
+        }
+        result.push(mlir_type(cx, field));
+        offset = target_offset + field.size;
+        prev_effective_align = effective_field_align;
+    }
+    if layout.is_sized() && field_count > 0 {
+        if offset > layout.size {
+            bug!("layout: {:#?} stride: {:?} offset: {:?}", layout, layout.size, offset);
+        }
+        let padding = layout.size - offset;
+        if padding != Size::ZERO {
+            let padding_align = prev_effective_align;
+            assert_eq!(offset.align_to(padding_align) + padding, layout.size);
+            result.push(padding_filler(cx, padding, padding_align));
+        }
+    }
+    (result, packed)
+}
+
+/// An array of the largest integer type that is at most `align` aligned, covering `size` bytes.
+fn padding_filler<'ml>(cx: &CodegenCx<'ml, '_>, size: Size, align: Align) -> Type<'ml> {
+    let unit = Integer::approximate_align(cx, align);
+    let unit_size = unit.size().bytes();
+    assert_eq!(size.bytes() % unit_size, 0);
+    let unit = IntegerType::new(cx.context, unit.size().bits() as u32).into();
+    array_type(cx, unit, size.bytes() / unit_size)
+}
+
+fn array_type<'ml>(cx: &CodegenCx<'ml, '_>, element: Type<'ml>, count: u64) -> Type<'ml> {
+    let count = u32::try_from(count).unwrap_or_else(|_| {
+        cx.tcx.dcx().fatal(format!(
+            "arrays of more than {} elements are not supported by the MLIR backend",
+            u32::MAX
+        ))
+    });
+    llvm::r#type::array(element, count)
+}
+
+/// Returns whether values of this type carry pointer metadata (i.e. are fat pointers).
+pub(crate) fn has_ptr_meta<'tcx>(tcx: TyCtxt<'tcx>, ty: Ty<'tcx>) -> bool {
+    if ty.is_sized(tcx, ty::ParamEnv::reveal_all()) {
+        return false;
+    }
+
+    let tail = tcx.struct_tail_for_codegen(ty, ty::ParamEnv::reveal_all());
+    match tail.kind() {
+        ty::Foreign(..) => false,
+        ty::Str | ty::Slice(..) | ty::Dynamic(..) => true,
+        _ => bug!("unexpected unsized tail: {:?}", tail),
+    }
+}
+
+/// Returns whether integer operations on `ty` should be treated as signed.
+pub(crate) fn type_sign(ty: Ty<'_>) -> bool {
+    match ty.kind() {
+        ty::Ref(..) | ty::RawPtr(..) | ty::FnPtr(..) | ty::Char | ty::Uint(..) | ty::Bool => false,
+        ty::Int(..) => true,
+        ty::Float(..) => false, // `signed` is unused for floats
+        _ => bug!("type_sign on non-primitive type {}", ty),
+    }
+}
@@ -0,0 +1,275 @@
+//! Codegen of the [`PointerCoercion::Unsize`] operation.
+//!
+//! [`PointerCoercion::Unsize`]: `rustc_middle::ty::adjustment::PointerCoercion::Unsize`
+
+use melior::ir::Value;
+use rustc_hir::LangItem;
+use rustc_middle::bug;
+use rustc_middle::mir;
+use rustc_middle::ty::layout::{LayoutOf, TyAndLayout};
+use rustc_middle::ty::print::{with_no_trimmed_paths, with_no_visible_paths};
+use rustc_middle::ty::{self, ParamEnv, Ty};
+
+use crate::builder::IntCC;
+use crate::common::FunctionCx;
+use crate::value_and_place::{MPlace, MValue};
+
+/// Retrieve the information we are losing (making dynamic) in an unsizing
+/// adjustment.
+///
+/// The `old_info` argument is a bit funny. It is intended for use
+/// in an upcast, where the new vtable for an object will be derived
+/// from the old one.
+pub(crate) fn unsized_info<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    source: Ty<'tcx>,
+    target: Ty<'tcx>,
+    old_info: Option<Value<'ml, 'a>>,
+) -> Value<'ml, 'a> {
+    let (source, target) =
+        fx.tcx.struct_lockstep_tails_for_codegen(source, target, ParamEnv::reveal_all());
+    match (&source.kind(), &target.kind()) {
+        (&ty::Array(_, len), &ty::Slice(_)) => {
+            let len =
+                len.try_to_target_usize(fx.tcx).expect("expected monomorphic const in codegen");
+            fx.bx.iconst(fx.usize_type(), len as i128)
+        }
+        (&ty::Dynamic(data_a, _, src_dyn_kind), &ty::Dynamic(data_b, _, target_dyn_kind))
+            if src_dyn_kind == target_dyn_kind =>
+        {
+            let old_info =
+                old_info.expect("unsized_info: missing old info for trait upcasting coercion");
+            let b_principal_def_id = data_b.principal_def_id();
+            if data_a.principal_def_id() == b_principal_def_id || b_principal_def_id.is_none() {
+                // A NOP cast that doesn't actually change anything, should be allowed even with
+                // invalid vtables.
+                return old_info;
+            }
+
+            // trait upcasting coercion
+            match fx.tcx.supertrait_vtable_slot((source, target)) {
+                Some(entry_idx) => {
+                    let ptr_size = fx.tcx.data_layout.pointer_size.bytes();
+                    let ptr_align = fx.tcx.data_layout.pointer_align.abi.bytes();
+                    let entry =
+                        fx.bx.ptr_offset_imm(old_info, (entry_idx as u64 * ptr_size) as i64);
+                    fx.bx.load(fx.pointer_type(), entry, ptr_align)
+                }
+                None => old_info,
+            }
+        }
+        (_, ty::Dynamic(data, ..)) => crate::vtable::get_vtable(fx, source, data.principal()),
+        _ => bug!("unsized_info: invalid unsizing {:?} -> {:?}", source, target),
+    }
+}
+
+/// Coerce `src` to `dst_ty`.
+fn unsize_ptr<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    src: Value<'ml, 'a>,
+    src_layout: TyAndLayout<'tcx>,
+    dst_layout: TyAndLayout<'tcx>,
+    old_info: Option<Value<'ml, 'a>>,
+) -> (Value<'ml, 'a>, Value<'ml, 'a>) {
+    match (&src_layout.ty.kind(), &dst_layout.ty.kind()) {
+        (&ty::Ref(_, a, _), &ty::Ref(_, b, _))
+        | (&ty::Ref(_, a, _), &ty::RawPtr(b, _))
+        | (&ty::RawPtr(a, _), &ty::RawPtr(b, _)) => (src, unsized_info(fx, *a, *b, old_info)),
+        (&ty::Adt(def_a, _), &ty::Adt(def_b, _)) => {
+            assert_eq!(def_a, def_b);
+
+            if src_layout == dst_layout {
+                return (src, old_info.unwrap());
+            }
+
+            let mut result = None;
+            for i in 0..src_layout.fields.count() {
+                let src_f = src_layout.field(fx, i);
+                assert_eq!(src_layout.fields.offset(i).bytes(), 0);
+                assert_eq!(dst_layout.fields.offset(i).bytes(), 0);
+                if src_f.is_1zst() {
+                    // We are looking for the one non-1-ZST field; this is not it.
+                    continue;
+                }
+                assert_eq!(src_layout.size, src_f.size);
+
+                let dst_f = dst_layout.field(fx, i);
+                assert_ne!(src_f.ty, dst_f.ty);
+                assert!(result.is_none());
+                result = Some(unsize_ptr(fx, src, src_f, dst_f, old_info));
+            }
+            result.unwrap()
+        }
+        _ => bug!("unsize_ptr: called on bad types"),
+    }
+}
+
+/// Coerce `src`, which is a reference to a value of type `src_ty`,
+/// to a value of type `dst_ty` and store the result in `dst`
+pub(crate) fn coerce_unsized_into<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    src: MValue<'ml, 'a, 'tcx>,
+    dst: MPlace<'ml, 'a, 'tcx>,
+) {
+    let src_ty = src.layout().ty;
+    let dst_ty = dst.layout().ty;
+    let mut coerce_ptr = || {
+        let (base, info) =
+            if fx.layout_of(src.layout().ty.builtin_deref(true).unwrap()).is_unsized() {
+                let (old_base, old_info) = src.load_scalar_pair(fx);
+                unsize_ptr(fx, old_base, src.layout(), dst.layout(), Some(old_info))
+            } else {
+                let base = src.load_scalar(fx);
+                unsize_ptr(fx, base, src.layout(), dst.layout(), None)
+            };
+        dst.write_mvalue(fx, MValue::by_val_pair(base, info, dst.layout()));
+    };
+    match (&src_ty.kind(), &dst_ty.kind()) {
+        (&ty::Ref(..), &ty::Ref(..))
+        | (&ty::Ref(..), &ty::RawPtr(..))
+        | (&ty::RawPtr(..), &ty::RawPtr(..)) => coerce_ptr(),
+        (&ty::Adt(def_a, _), &ty::Adt(def_b, _)) => {
+            assert_eq!(def_a, def_b);
+
+            for i in 0..def_a.variant(rustc_abi::FIRST_VARIANT).fields.len() {
+                let src_f = src.value_field(fx, rustc_abi::FieldIdx::new(i));
+                let dst_f = dst.place_field(fx, rustc_abi::FieldIdx::new(i));
+
+                if dst_f.layout().is_zst() {
+                    continue;
+                }
+
+                if src_f.layout().ty == dst_f.layout().ty {
+                    dst_f.write_mvalue(fx, src_f);
+                } else {
+                    coerce_unsized_into(fx, src_f, dst_f);
+                }
+            }
+        }
+        _ => bug!("coerce_unsized_into: invalid coercion {:?} -> {:?}", src_ty, dst_ty),
+    }
+}
+
+/// Computes the size and alignment of a value of the given layout, which may be unsized with
+/// `info` as its pointer metadata.
+pub(crate) fn size_and_align_of<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    layout: TyAndLayout<'tcx>,
+    info: Option<Value<'ml, 'a>>,
+) -> (Value<'ml, 'a>, Value<'ml, 'a>) {
+    let usize_ty = fx.usize_type();
+    if layout.is_sized() {
+        return (
+            fx.bx.iconst(usize_ty, layout.size.bytes() as i128),
+            fx.bx.iconst(usize_ty, layout.align.abi.bytes() as i128),
+        );
+    }
+
+    let ty = layout.ty;
+    match ty.kind() {
+        ty::Dynamic(..) => {
+            // load size/align from vtable
+            (
+                crate::vtable::size_of_obj(fx, info.unwrap()),
+                crate::vtable::min_align_of_obj(fx, info.unwrap()),
+            )
+        }
+        ty::Slice(_) | ty::Str => {
+            let unit = layout.field(fx, 0);
+            // The info in this case is the length of the str, so the size is that
+            // times the unit size.
+            (
+                fx.bx.imul_imm(info.unwrap(), unit.size.bytes() as i128),
+                fx.bx.iconst(usize_ty, unit.align.abi.bytes() as i128),
+            )
+        }
+        ty::Foreign(_) => {
+            // `extern` type. We cannot compute the size, so panic like the other backends.
+            let msg = with_no_visible_paths!({
+                with_no_trimmed_paths!({
+                    format!("attempted to compute the size or alignment of extern type `{ty}`")
+                })
+            });
+            let msg = crate::constant::codegen_const_str(fx, &msg);
+            crate::abi::codegen_panic_lang_item(
+                fx,
+                mir::SourceInfo::outermost(fx.mir.span),
+                LangItem::PanicNounwind,
+                &[msg],
+                mir::UnwindAction::Unreachable,
+            );
+            let next_block = fx.create_block();
+            fx.switch_to_block(next_block);
+
+            // This block is unreachable, so we can now return whatever we want.
+            (fx.bx.undef(usize_ty), fx.bx.undef(usize_ty))
+        }
+        ty::Adt(..) | ty::Tuple(..) => {
+            // First get the size of all statically known fields.
+            // Don't use size_of because it also rounds up to alignment, which we
+            // want to avoid, as the unsized field's alignment could be smaller.
+            assert!(!layout.ty.is_simd());
+
+            let i = layout.fields.count() - 1;
+            let unsized_offset_unadjusted = layout.fields.offset(i).bytes();
+            let unsized_offset_unadjusted =
+                fx.bx.iconst(usize_ty, unsized_offset_unadjusted as i128);
+            let sized_align = fx.bx.iconst(usize_ty, layout.align.abi.bytes() as i128);
+
+            // Recurse to get the size of the dynamically sized field (must be
+            // the last field).
+            let field_layout = layout.field(fx, i);
+            let (unsized_size, unsized_align) = size_and_align_of(fx, field_layout, info);
+            let unsized_align = cap_align_for_packed(fx, ty, unsized_align);
+
+            // Choose max of two known alignments (combined value must
+            // be aligned according to more restrictive of the two).
+            let cmp = fx.bx.icmp(IntCC::UnsignedGreaterThan, sized_align, unsized_align);
+            let full_align = fx.bx.select(cmp, sized_align, unsized_align);
+
+            // `unsized_size` is a multiple of `unsized_align` and `full_align >= unsized_align`,
+            // so the size is `(unsized_offset_unadjusted + unsized_size).align_to(full_align)`.
+            let full_size = fx.bx.binary("arith.addi", unsized_offset_unadjusted, unsized_size);
+            let full_size = align_to(fx, full_size, full_align);
+
+            (full_size, full_align)
+        }
+        _ => bug!("size_and_align_of_dst: {ty} not supported"),
+    }
+}
+
+/// For packed types, the alignment of an unsized field is capped at the packing.
+pub(crate) fn cap_align_for_packed<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    ty: Ty<'tcx>,
+    align: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    let ty::Adt(def, _) = ty.kind() else {
+        return align;
+    };
+    match def.repr().pack {
+        None => align,
+        // We know this will be capped to 1.
+        Some(packed) if packed.bytes() == 1 => fx.bx.iconst(fx.usize_type(), 1),
+        Some(packed) => {
+            // We have to dynamically compute `min(align, packed)`.
+            let packed = fx.bx.iconst(fx.usize_type(), packed.bytes() as i128);
+            let cmp = fx.bx.icmp(IntCC::UnsignedLessThan, align, packed);
+            fx.bx.select(cmp, align, packed)
+        }
+    }
+}
+
+/// Rounds `value` up to a multiple of `align`, which must be a power of two:
+/// `(value + (align - 1)) & -align`.
+pub(crate) fn align_to<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    value: Value<'ml, 'a>,
+    align: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    let addend = fx.bx.iadd_imm(align, -1);
+    let add = fx.bx.binary("arith.addi", value, addend);
+    let zero = fx.bx.iconst(fx.usize_type(), 0);
+    let neg = fx.bx.binary("arith.subi", zero, align);
+    fx.bx.binary("arith.andi", add, neg)
+}
@@ -0,0 +1,248 @@
+//! Codegen of calls that may unwind, landing pads and `UnwindResume`.
+//!
+//! See `rustc_codegen_ssa/src/mir/block.rs` for reference. Calls that unwind into a cleanup block
+//! or need to terminate when unwinding become `llvm.invoke`s. Every cleanup block unwound to gets
+//! a landing pad of its own, which saves the in-flight exception to a stack slot shared by the
+//! whole function before jumping to the cleanup block, so that `UnwindResume` can resume it.
+
+use melior::ir::{BlockRef, Type, Value};
+use rustc_middle::bug;
+use rustc_middle::mir::{BasicBlock, UnwindAction, UnwindTerminateReason};
+use rustc_middle::ty::layout::FnAbiOf;
+use rustc_middle::ty::{self, Instance};
+use rustc_span::DUMMY_SP;
+use rustc_target::spec::PanicStrategy;
+
+use crate::abi::mlir_fn_type;
+use crate::builder::Builder;
+use crate::common::FunctionCx;
+use crate::context::CodegenCx;
+use crate::value_and_place::{MPlace, MValue};
+
+/// The function called by [`codegen_call_with_unwind_action`].
+pub(crate) enum Callee<'ml, 'a, 's> {
+    /// The `func.func` named `symbol`, which has type `fn_ty`.
+    Direct { symbol: &'s str, fn_ty: Type<'ml> },
+    /// A function pointer.
+    Indirect(Value<'ml, 'a>),
+}
+
+/// The `{ ptr, i32 }` pair produced by a landing pad.
+fn exception_type<'ml>(fx: &FunctionCx<'_, 'ml, '_>) -> Type<'ml> {
+    let ptr_ty = fx.pointer_type();
+    let i32_ty = fx.bx.int_type(32);
+    melior::dialect::llvm::r#type::r#struct(fx.context, &[ptr_ty, i32_ty], false)
+}
+
+/// Calls `callee` and returns its results. Unless `unwind` lets the call unwind straight out of
+/// the function, or it can't unwind at all, the call becomes an `llvm.invoke` and code generation
+/// continues in a new block.
+pub(crate) fn codegen_call_with_unwind_action<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    callee: Callee<'ml, 'a, '_>,
+    args: &[Value<'ml, 'a>],
+    result_tys: &[Type<'ml>],
+    unwind: UnwindAction,
+) -> Vec<Value<'ml, 'a>> {
+    let Some(unwind_block) = unwind_block(fx, unwind) else {
+        let call = match callee {
+            Callee::Direct { symbol, .. } => fx.bx.call(symbol, args, result_tys),
+            Callee::Indirect(func_ptr) => fx.bx.call_indirect(func_ptr, args, result_tys),
+        };
+        return (0..call.result_count()).map(|i| call.result(i).unwrap().into()).collect();
+    };
+
+    let func_ptr = match callee {
+        Callee::Direct { symbol, fn_ty } => fx.bx.func_addr(symbol, fn_ty),
+        Callee::Indirect(func_ptr) => func_ptr,
+    };
+    // `llvm.invoke` has at most one result, so multiple results are returned as a struct like
+    // `convert-func-to-llvm` does for the callee.
+    let result_ty = match result_tys {
+        [] => None,
+        &[ty] => Some(ty),
+        tys => Some(melior::dialect::llvm::r#type::r#struct(fx.context, tys, false)),
+    };
+    let normal_block = fx.create_block();
+    let invoke = fx.bx.invoke(func_ptr, args, result_ty, &normal_block, &unwind_block);
+    let result = (invoke.result_count() == 1).then(|| invoke.result(0).unwrap().into());
+    fx.switch_to_block(normal_block);
+
+    match (result, result_tys) {
+        (None, _) => vec![],
+        (Some(result), [_]) => vec![result],
+        (Some(result), tys) => {
+            tys.iter().enumerate().map(|(i, &ty)| fx.bx.extract_value(result, i, ty)).collect()
+        }
+    }
+}
+
+/// The block a call with `unwind` unwinds to, if it needs a landing pad.
+fn unwind_block<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    unwind: UnwindAction,
+) -> Option<BlockRef<'ml, 'a>> {
+    if fx.tcx.sess.panic_strategy() != PanicStrategy::Unwind {
+        return None;
+    }
+    match unwind {
+        UnwindAction::Continue | UnwindAction::Unreachable => None,
+        UnwindAction::Cleanup(cleanup) => Some(landing_pad_for(fx, cleanup)),
+        UnwindAction::Terminate(reason) => Some(terminate_block(fx, reason)),
+    }
+}
+
+/// The stack slot holding the exception being unwound.
+fn personality_slot<'a, 'ml>(fx: &mut FunctionCx<'a, 'ml, '_>) -> Value<'ml, 'a> {
+    if let Some(slot) = fx.personality_slot {
+        return slot;
+    }
+    let exception_ty = exception_type(fx);
+    let slot =
+        fx.create_typed_stack_slot(exception_ty, fx.tcx.data_layout.pointer_align.abi.bytes());
+    fx.personality_slot = Some(slot);
+    slot
+}
+
+/// The landing pad for unwinding into the cleanup block `cleanup`.
+fn landing_pad_for<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    cleanup: BasicBlock,
+) -> BlockRef<'ml, 'a> {
+    if let Some(&landing_pad) = fx.landing_pads.get(&cleanup) {
+        return landing_pad;
+    }
+
+    let slot = personality_slot(fx);
+    let exception_ty = exception_type(fx);
+    let landing_pad = fx.create_block();
+    let bx = fx.bx.at(landing_pad);
+    let exception = bx.landing_pad(exception_ty, &[], true);
+    bx.store(exception, slot, fx.tcx.data_layout.pointer_align.abi.bytes());
+    bx.br(&fx.get_block(cleanup), &[]);
+
+    fx.landing_pads.insert(cleanup, landing_pad);
+    landing_pad
+}
+
+/// The landing pad for calls that must not unwind, which panics without unwinding any further.
+fn terminate_block<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    reason: UnwindTerminateReason,
+) -> BlockRef<'ml, 'a> {
+    if let Some(&terminate_block) = fx.terminate_blocks.get(&reason) {
+        return terminate_block;
+    }
+
+    let exception_ty = exception_type(fx);
+    // An empty filter catches every exception, so that unwinding stops here rather than the
+    // unwinder giving up on finding a handler.
+    let filter = fx.create_entry_zero(melior::dialect::llvm::r#type::array(fx.pointer_type(), 0));
+    let terminate_block = fx.create_block();
+    let bx = fx.bx.at(terminate_block);
+    bx.landing_pad(exception_ty, &[filter], false);
+    call_terminate_lang_item(fx, &bx, reason);
+
+    fx.terminate_blocks.insert(reason, terminate_block);
+    terminate_block
+}
+
+/// Resumes unwinding with the exception caught by the landing pad that led here.
+pub(crate) fn codegen_unwind_resume(fx: &mut FunctionCx<'_, '_, '_>) {
+    if fx.tcx.sess.panic_strategy() != PanicStrategy::Unwind {
+        fx.bx.unreachable();
+        return;
+    }
+    let slot = personality_slot(fx);
+    let exception_ty = exception_type(fx);
+    let exception = fx.bx.load(exception_ty, slot, fx.tcx.data_layout.pointer_align.abi.bytes());
+    fx.bx.resume(exception);
+}
+
+/// Codegens `UnwindTerminate`, which panics without unwinding when unwinding out of a cleanup
+/// block.
+pub(crate) fn codegen_unwind_terminate(
+    fx: &mut FunctionCx<'_, '_, '_>,
+    reason: UnwindTerminateReason,
+) {
+    let bx = fx.bx;
+    call_terminate_lang_item(fx, &bx, reason);
+}
+
+/// Calls `panic_cannot_unwind` or `panic_in_cleanup` with `bx`, neither of which returns.
+fn call_terminate_lang_item<'a, 'ml>(
+    fx: &FunctionCx<'a, 'ml, '_>,
+    bx: &Builder<'a, 'ml>,
+    reason: UnwindTerminateReason,
+) {
+    let instance = Instance::mono(fx.tcx, fx.tcx.require_lang_item(reason.lang_item(), None));
+    let symbol_name = fx.tcx.symbol_name(instance).name;
+    let fn_abi = fx.fn_abi_of_instance(instance, ty::List::empty());
+    fx.cx.reference_fn(symbol_name, mlir_fn_type(fx.tcx, fx.context, fn_abi));
+    bx.call(symbol_name, &[], &[]);
+    bx.unreachable();
+}
+
+/// Codegens the `catch_unwind` intrinsic, which calls `try_fn(data)` and, if that unwinds, calls
+/// `catch_fn(data, exception)` with the caught exception. Writes 0 to `ret` if `try_fn` returned
+/// normally and 1 if it unwound, then jumps to `target`.
+pub(crate) fn codegen_catch_unwind<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    try_fn: Value<'ml, 'a>,
+    data: Value<'ml, 'a>,
+    catch_fn: Value<'ml, 'a>,
+    ret: MPlace<'ml, 'a, 'tcx>,
+    target: BasicBlock,
+) {
+    fx.catches_unwind = true;
+
+    let exception_ty = exception_type(fx);
+    let ptr_ty = fx.pointer_type();
+    let i32_ty = fx.bx.int_type(32);
+    // A null type info catches every exception.
+    let catch_all = fx.create_entry_zero(ptr_ty);
+    let normal_block = fx.create_block();
+    let catch_block = fx.create_block();
+    fx.bx.invoke(try_fn, &[data], None, &normal_block, &catch_block);
+
+    fx.switch_to_block(normal_block);
+    let zero = fx.bx.iconst(i32_ty, 0);
+    ret.write_mvalue(fx, MValue::by_val(zero, ret.layout()));
+    fx.bx.br(&fx.get_block(target), &[]);
+
+    fx.switch_to_block(catch_block);
+    let exception = fx.bx.landing_pad(exception_ty, &[catch_all], false);
+    let exception = fx.bx.extract_value(exception, 0, ptr_ty);
+    fx.bx.call_indirect(catch_fn, &[data, exception], &[]);
+    let one = fx.bx.iconst(i32_ty, 1);
+    ret.write_mvalue(fx, MValue::by_val(one, ret.layout()));
+    fx.bx.br(&fx.get_block(target), &[]);
+}
+
+/// The personality function of the function being lowered by `fx`, if it has any landing pads or
+/// resumes unwinding.
+pub(crate) fn personality_fn(fx: &FunctionCx<'_, '_, '_>) -> Option<String> {
+    if fx.personality_slot.is_none() && fx.terminate_blocks.is_empty() && !fx.catches_unwind {
+        return None;
+    }
+    Some(eh_personality(fx.cx))
+}
+
+/// The symbol of `rust_eh_personality`, referencing it from the module.
+fn eh_personality(cx: &CodegenCx<'_, '_>) -> String {
+    let tcx = cx.tcx;
+    let Some(def_id) = tcx.lang_items().eh_personality() else {
+        bug!("unwinding without an `eh_personality` lang item");
+    };
+    let instance = Instance::expect_resolve(
+        tcx,
+        ty::ParamEnv::reveal_all(),
+        def_id,
+        ty::List::empty(),
+        DUMMY_SP,
+    );
+    let symbol_name = tcx.symbol_name(instance).name;
+    let fn_abi = cx.fn_abi_of_instance(instance, ty::List::empty());
+    cx.reference_fn(symbol_name, mlir_fn_type(tcx, cx.context, fn_abi));
+    symbol_name.to_owned()
+}
@@ -0,0 +1,381 @@
+//! Definition of [`MValue`] and [`MPlace`]
+//!
+//! Every MIR local lives in memory (an `llvm.alloca` in the entry block); MLIR's `mem2reg` and
+//! SROA passes are responsible for promoting them back into SSA values. Rvalues that fit in one or
+//! two scalars are kept as SSA values while in flight.
+
+use melior::ir::{Type, Value};
+use rustc_abi::{Abi, FieldIdx, Scalar, Size, VariantIdx};
+use rustc_middle::bug;
+use rustc_middle::ty::layout::{LayoutOf, TyAndLayout};
+use rustc_middle::ty::{self, Ty, TyCtxt};
+
+use crate::common::FunctionCx;
+use crate::type_of::{has_ptr_meta, mlir_type, scalar_to_mlir_type};
+
+pub(crate) fn scalar_pair_b_offset(tcx: TyCtxt<'_>, a_scalar: Scalar, b_scalar: Scalar) -> Size {
+    a_scalar.size(&tcx).align_to(b_scalar.align(&tcx).abi)
+}
+
+/// A read-only value
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct MValue<'ml, 'a, 'tcx>(MValueInner<'ml, 'a>, TyAndLayout<'tcx>);
+
+#[derive(Debug, Copy, Clone)]
+enum MValueInner<'ml, 'a> {
+    ByRef(Value<'ml, 'a>, Option<Value<'ml, 'a>>),
+    ByVal(Value<'ml, 'a>),
+    ByValPair(Value<'ml, 'a>, Value<'ml, 'a>),
+    Zst,
+}
+
+impl<'ml, 'a, 'tcx> MValue<'ml, 'a, 'tcx> {
+    pub(crate) fn by_ref(ptr: Value<'ml, 'a>, layout: TyAndLayout<'tcx>) -> Self {
+        MValue(MValueInner::ByRef(ptr, None), layout)
+    }
+
+    pub(crate) fn by_ref_unsized(
+        ptr: Value<'ml, 'a>,
+        meta: Value<'ml, 'a>,
+        layout: TyAndLayout<'tcx>,
+    ) -> Self {
+        MValue(MValueInner::ByRef(ptr, Some(meta)), layout)
+    }
+
+    pub(crate) fn by_val(value: Value<'ml, 'a>, layout: TyAndLayout<'tcx>) -> Self {
+        MValue(MValueInner::ByVal(value), layout)
+    }
+
+    pub(crate) fn by_val_pair(
+        value: Value<'ml, 'a>,
+        extra: Value<'ml, 'a>,
+        layout: TyAndLayout<'tcx>,
+    ) -> Self {
+        MValue(MValueInner::ByValPair(value, extra), layout)
+    }
+
+    pub(crate) fn zst(layout: TyAndLayout<'tcx>) -> Self {
+        assert!(layout.is_zst());
+        MValue(MValueInner::Zst, layout)
+    }
+
+    pub(crate) fn layout(&self) -> TyAndLayout<'tcx> {
+        self.1
+    }
+
+    pub(crate) fn try_to_ptr(self) -> Option<(Value<'ml, 'a>, Option<Value<'ml, 'a>>)> {
+        match self.0 {
+            MValueInner::ByRef(ptr, meta) => Some((ptr, meta)),
+            MValueInner::ByVal(_) | MValueInner::ByValPair(_, _) | MValueInner::Zst => None,
+        }
+    }
+
+    /// Returns a pointer to memory holding this value, spilling it to the stack if necessary.
+    pub(crate) fn force_stack(self, fx: &mut FunctionCx<'a, 'ml, 'tcx>) -> Value<'ml, 'a> {
+        match self.0 {
+            MValueInner::ByRef(ptr, None) => ptr,
+            MValueInner::ByRef(_, Some(_)) => bug!("force_stack for unsized value"),
+            MValueInner::ByVal(_) | MValueInner::ByValPair(_, _) | MValueInner::Zst => {
+                let place = MPlace::new_stack_slot(fx, self.1);
+                place.write_mvalue(fx, self);
+                place.to_ptr()
+            }
+        }
+    }
+
+    /// Load a value with layout.abi of scalar
+    #[track_caller]
+    pub(crate) fn load_scalar(self, fx: &mut FunctionCx<'a, 'ml, 'tcx>) -> Value<'ml, 'a> {
+        let layout = self.1;
+        match self.0 {
+            MValueInner::ByRef(ptr, None) => {
+                let ty = match layout.abi {
+                    Abi::Scalar(scalar) => scalar_to_mlir_type(fx.tcx, fx.context, scalar),
+                    _ => bug!("load_scalar({:?})", layout.ty),
+                };
+                fx.bx.load(ty, ptr, layout.align.abi.bytes())
+            }
+            MValueInner::ByVal(value) => value,
+            MValueInner::ByRef(_, Some(_)) => bug!("load_scalar for unsized value not allowed"),
+            MValueInner::ByValPair(_, _) => bug!("Please use load_scalar_pair for ByValPair"),
+            MValueInner::Zst => bug!("load_scalar for zst {:?}", layout.ty),
+        }
+    }
+
+    /// Load a value pair with layout.abi of scalar pair
+    #[track_caller]
+    pub(crate) fn load_scalar_pair(
+        self,
+        fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    ) -> (Value<'ml, 'a>, Value<'ml, 'a>) {
+        let layout = self.1;
+        match self.0 {
+            MValueInner::ByRef(ptr, None) => {
+                let (a_scalar, b_scalar) = match layout.abi {
+                    Abi::ScalarPair(a, b) => (a, b),
+                    _ => bug!("load_scalar_pair({:?})", self),
+                };
+                let b_offset = scalar_pair_b_offset(fx.tcx, a_scalar, b_scalar);
+                let ty1 = scalar_to_mlir_type(fx.tcx, fx.context, a_scalar);
+                let ty2 = scalar_to_mlir_type(fx.tcx, fx.context, b_scalar);
+                let align = layout.align.abi;
+                let val1 = fx.bx.load(ty1, ptr, align.bytes());
+                let b_ptr = fx.bx.ptr_offset_imm(ptr, b_offset.bytes() as i64);
+                let val2 = fx.bx.load(ty2, b_ptr, align.restrict_for_offset(b_offset).bytes());
+                (val1, val2)
+            }
+            MValueInner::ByRef(_, Some(_)) => {
+                bug!("load_scalar_pair for unsized value not allowed")
+            }
+            MValueInner::ByVal(_) => bug!("Please use load_scalar for ByVal"),
+            MValueInner::ByValPair(val1, val2) => (val1, val2),
+            MValueInner::Zst => bug!("load_scalar_pair for zst {:?}", layout.ty),
+        }
+    }
+
+    pub(crate) fn value_field(
+        self,
+        fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+        field: FieldIdx,
+    ) -> MValue<'ml, 'a, 'tcx> {
+        let layout = self.1;
+        let field_layout = layout.field(&*fx, field.index());
+        if field_layout.is_zst() {
+            return MValue::zst(field_layout);
+        }
+        match self.0 {
+            MValueInner::ByVal(val) => match layout.abi {
+                // A newtype around a scalar
+                Abi::Scalar(_) => MValue::by_val(val, field_layout),
+                _ => bug!("value_field for ByVal with abi {:?}", layout.abi),
+            },
+            MValueInner::ByValPair(val1, val2) => match layout.abi {
+                Abi::ScalarPair(_, _) => {
+                    let val = match layout.fields.offset(field.index()).bytes() {
+                        0 => val1,
+                        _ => val2,
+                    };
+                    MValue::by_val(val, field_layout)
+                }
+                _ => bug!("value_field for ByValPair with abi {:?}", layout.abi),
+            },
+            MValueInner::ByRef(ptr, None) => {
+                let offset = layout.fields.offset(field.index());
+                let ptr = fx.bx.ptr_offset_imm(ptr, offset.bytes() as i64);
+                MValue::by_ref(ptr, field_layout)
+            }
+            MValueInner::ByRef(_, Some(_)) => bug!("value_field for unsized value"),
+            MValueInner::Zst => bug!("value_field for zst {:?}", layout.ty),
+        }
+    }
+
+    /// Reinterprets a (possibly fat) pointer value as another pointer type with the same abi.
+    pub(crate) fn cast_pointer_to(self, layout: TyAndLayout<'tcx>) -> Self {
+        assert!(matches!(self.layout().ty.kind(), ty::Ref(..) | ty::RawPtr(..) | ty::FnPtr(..)));
+        assert!(matches!(layout.ty.kind(), ty::Ref(..) | ty::RawPtr(..) | ty::FnPtr(..)));
+        assert_eq!(self.layout().abi, layout.abi);
+        MValue(self.0, layout)
+    }
+}
+
+/// A place where you can write a value to or read a value from
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct MPlace<'ml, 'a, 'tcx> {
+    ptr: Value<'ml, 'a>,
+    extra: Option<Value<'ml, 'a>>,
+    layout: TyAndLayout<'tcx>,
+}
+
+impl<'ml, 'a, 'tcx> MPlace<'ml, 'a, 'tcx> {
+    pub(crate) fn layout(&self) -> TyAndLayout<'tcx> {
+        self.layout
+    }
+
+    pub(crate) fn new_stack_slot(
+        fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+        layout: TyAndLayout<'tcx>,
+    ) -> Self {
+        assert!(layout.is_sized());
+        // Even ZSTs get a (zero sized) slot so that every place has a valid address.
+        let ty = mlir_type(fx.cx, layout);
+        let ptr = fx.create_typed_stack_slot(ty, layout.align.abi.bytes());
+        MPlace { ptr, extra: None, layout }
+    }
+
+    pub(crate) fn for_ptr(ptr: Value<'ml, 'a>, layout: TyAndLayout<'tcx>) -> Self {
+        MPlace { ptr, extra: None, layout }
+    }
+
+    pub(crate) fn for_ptr_with_extra(
+        ptr: Value<'ml, 'a>,
+        extra: Value<'ml, 'a>,
+        layout: TyAndLayout<'tcx>,
+    ) -> Self {
+        MPlace { ptr, extra: Some(extra), layout }
+    }
+
+    pub(crate) fn to_mvalue(self, _fx: &mut FunctionCx<'a, 'ml, 'tcx>) -> MValue<'ml, 'a, 'tcx> {
+        if let Some(extra) = self.extra {
+            MValue::by_ref_unsized(self.ptr, extra, self.layout)
+        } else if self.layout.is_zst() {
+            MValue::zst(self.layout)
+        } else {
+            MValue::by_ref(self.ptr, self.layout)
+        }
+    }
+
+    #[track_caller]
+    pub(crate) fn to_ptr(self) -> Value<'ml, 'a> {
+        match self.extra {
+            None => self.ptr,
+            Some(_) => bug!("Expected sized mplace, found {:?}", self),
+        }
+    }
+
+    #[track_caller]
+    pub(crate) fn to_ptr_unsized(self) -> (Value<'ml, 'a>, Value<'ml, 'a>) {
+        match self.extra {
+            Some(extra) => (self.ptr, extra),
+            None => bug!("Expected unsized mplace, found {:?}", self),
+        }
+    }
+
+    pub(crate) fn write_mvalue(
+        self,
+        fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+        from: MValue<'ml, 'a, 'tcx>,
+    ) {
+        let layout = self.layout;
+        if layout.is_zst() {
+            return;
+        }
+        let dst = self.to_ptr();
+        let align = layout.align.abi;
+        match from.0 {
+            MValueInner::ByVal(val) => {
+                fx.bx.store(val, dst, align.bytes());
+            }
+            MValueInner::ByValPair(val1, val2) => {
+                let (a_scalar, b_scalar) = match layout.abi {
+                    Abi::ScalarPair(a, b) => (a, b),
+                    _ => bug!("write_mvalue pair into {:?}", layout.abi),
+                };
+                let b_offset = scalar_pair_b_offset(fx.tcx, a_scalar, b_scalar);
+                fx.bx.store(val1, dst, align.bytes());
+                let b_ptr = fx.bx.ptr_offset_imm(dst, b_offset.bytes() as i64);
+                fx.bx.store(val2, b_ptr, align.restrict_for_offset(b_offset).bytes());
+            }
+            MValueInner::ByRef(src, None) => {
+                let size = fx.bx.iconst(fx.usize_type(), layout.size.bytes() as i128);
+                fx.bx.memcpy(dst, src, size);
+            }
+            MValueInner::ByRef(_, Some(_)) => bug!("write_mvalue for unsized value"),
+            MValueInner::Zst => {}
+        }
+    }
+
+    /// Writes `from` into this place reinterpreting its bytes, as for `CastKind::Transmute`.
+    pub(crate) fn write_mvalue_transmute(
+        self,
+        fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+        from: MValue<'ml, 'a, 'tcx>,
+    ) {
+        assert_eq!(
+            self.layout.size,
+            from.layout().size,
+            "transmute between differently sized types"
+        );
+        let src = from.force_stack(fx);
+        let place = MPlace::for_ptr(self.to_ptr(), self.layout);
+        place.write_mvalue(fx, MValue::by_ref(src, self.layout));
+    }
+
+    /// Used for `ProjectionElem::Subtype`, `ty` has to be monomorphized before passed on.
+    pub(crate) fn place_transmute_type(self, fx: &FunctionCx<'a, 'ml, 'tcx>, ty: Ty<'tcx>) -> Self {
+        MPlace { layout: fx.layout_of(ty), ..self }
+    }
+
+    pub(crate) fn place_field(self, fx: &mut FunctionCx<'a, 'ml, 'tcx>, field: FieldIdx) -> Self {
+        let layout = self.layout;
+        let field_layout = layout.field(&*fx, field.index());
+        let offset = layout.fields.offset(field.index());
+
+        // Unsized fields other than `[T]` and `str` only have a dynamically known alignment, so
+        // their offset has to be rounded up at runtime unless it is zero. This is what
+        // `rustc_codegen_ssa::mir::place::PlaceRef::project_field` does.
+        let needs_dynamic_align = field_layout.is_unsized()
+            && !matches!(field_layout.ty.kind(), ty::Slice(..) | ty::Str)
+            && offset.bytes() != 0;
+
+        let ptr = if needs_dynamic_align {
+            let (_, unsized_align) = crate::unsize::size_and_align_of(fx, field_layout, self.extra);
+            let unsized_align = crate::unsize::cap_align_for_packed(fx, layout.ty, unsized_align);
+            let unaligned_offset = fx.bx.iconst(fx.usize_type(), offset.bytes() as i128);
+            let offset = crate::unsize::align_to(fx, unaligned_offset, unsized_align);
+            fx.bx.ptr_offset(self.ptr, offset)
+        } else {
+            fx.bx.ptr_offset_imm(self.ptr, offset.bytes() as i64)
+        };
+        if has_ptr_meta(fx.tcx, field_layout.ty) {
+            MPlace::for_ptr_with_extra(ptr, self.extra.unwrap(), field_layout)
+        } else {
+            MPlace::for_ptr(ptr, field_layout)
+        }
+    }
+
+    pub(crate) fn place_index(
+        self,
+        fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+        index: Value<'ml, 'a>,
+    ) -> Self {
+        let elem_layout = match self.layout.ty.kind() {
+            ty::Array(elem_ty, _) | ty::Slice(elem_ty) => fx.layout_of(*elem_ty),
+            ty::Str => fx.layout_of(fx.tcx.types.u8),
+            _ => bug!("place_index({:?})", self.layout.ty),
+        };
+
+        let offset = fx.bx.imul_imm(index, elem_layout.size.bytes() as i128);
+        MPlace::for_ptr(fx.bx.ptr_offset(self.ptr, offset), elem_layout)
+    }
+
+    pub(crate) fn place_deref(self, fx: &mut FunctionCx<'a, 'ml, 'tcx>) -> Self {
+        let inner_layout = fx.layout_of(self.layout.ty.builtin_deref(true).unwrap());
+        if has_ptr_meta(fx.tcx, inner_layout.ty) {
+            let (addr, extra) = self.to_mvalue(fx).load_scalar_pair(fx);
+            MPlace::for_ptr_with_extra(addr, extra, inner_layout)
+        } else {
+            MPlace::for_ptr(self.to_mvalue(fx).load_scalar(fx), inner_layout)
+        }
+    }
+
+    pub(crate) fn place_ref(
+        self,
+        _fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+        layout: TyAndLayout<'tcx>,
+    ) -> MValue<'ml, 'a, 'tcx> {
+        match self.extra {
+            Some(extra) => MValue::by_val_pair(self.ptr, extra, layout),
+            None => MValue::by_val(self.ptr, layout),
+        }
+    }
+
+    pub(crate) fn downcast_variant(
+        self,
+        fx: &FunctionCx<'a, 'ml, 'tcx>,
+        variant: VariantIdx,
+    ) -> Self {
+        assert!(self.layout.is_sized());
+        MPlace { layout: self.layout.for_variant(fx, variant), ..self }
+    }
+}
+
+/// Returns the MLIR type used for an SSA value of the given layout, if it has one.
+pub(crate) fn immediate_type<'ml>(
+    fx: &FunctionCx<'_, 'ml, '_>,
+    layout: TyAndLayout<'_>,
+) -> Option<Type<'ml>> {
+    match layout.abi {
+        Abi::Scalar(scalar) => Some(scalar_to_mlir_type(fx.tcx, fx.context, scalar)),
+        _ => None,
+    }
+}
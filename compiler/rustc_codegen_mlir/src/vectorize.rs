@@ -0,0 +1,478 @@
+//! Vectorization of element-wise loops over slices with the `vector` dialect, enabled by
+//! `-Zmlir-vectorize`.
+//!
+//! This works on the `scf.for` loops produced by [`crate::raise`]. A loop is vectorized if its
+//! body only
+//!
+//! * loads and stores the elements `base[i]` of pointer arguments of the function, where `i` is
+//!   the loop counter and the element size is the size of the loaded or stored type,
+//! * computes values from those elements and from loop-invariant values with `arith` and `math`
+//!   ops.
+//!
+//! Every stored-to argument must be `noalias`, which `FnAbi` derives from the `&mut [T]` (and
+//! `&[T]` of `Freeze` types) the pointer came from, so no other access in the loop can overlap
+//! the stores. Every iteration then only touches its own elements, and the loop is split into
+//! a loop running `lanes` iterations at once on `vector<lanes x T>` values and the original loop
+//! running the remaining iterations.
+//!
+//! This covers the loops over slice iterators, like `for x in a.iter_mut()`, which `raise` turns
+//! into `scf.for` loops counting the elements, and `for (x, y) in a.iter_mut().zip(b)`, which
+//! counts up to the shorter length. Bounds checks make the body of `for i in 0..n { a[i] = ... }`
+//! branch, so such indexing loops are only vectorized when they use `get_unchecked`.
+
+use melior::Context;
+use melior::ir::attribute::{ArrayAttribute, IntegerAttribute};
+use melior::ir::block::BlockArgument;
+use melior::ir::operation::{Operation, OperationLike, OperationRef};
+use melior::ir::r#type::IntegerType;
+use melior::ir::{
+    Attribute, Block, BlockLike, BlockRef, Region, RegionLike, Type, Value, ValueLike,
+};
+use rustc_data_structures::fx::FxHashMap;
+use rustc_session::Session;
+use rustc_span::Symbol;
+
+use crate::ModuleMlir;
+use crate::builder::{Builder, IntCC};
+use crate::dialect::{block_key, op_name, value_key};
+use crate::raise::{block_arguments, int_constant, operands, set_operand};
+
+/// Attributes of `arith` and `math` ops that are kept when they are vectorized.
+const ELEMENTWISE_ATTRS: &[&str] = &["predicate", "fastmath", "overflowFlags", "roundingmode"];
+
+/// The width in bits of the vector registers of the target, from the target features enabled by
+/// its spec, `-Ctarget-cpu` and `-Ctarget-feature`, or `None` if it has none worth vectorizing for.
+pub(crate) fn vector_width(sess: &Session) -> Option<u64> {
+    // Like `cfg(target_feature)`, these include the features implied by the enabled ones.
+    let has = |feature: &str| sess.unstable_target_features.contains(&Symbol::intern(feature));
+
+    match &*sess.target.arch {
+        "x86" | "x86_64" => {
+            if has("avx512f") {
+                Some(512)
+            } else if has("avx") || has("avx2") {
+                Some(256)
+            } else if has("sse2") || sess.target.arch == "x86_64" {
+                Some(128)
+            } else {
+                None
+            }
+        }
+        // NEON is part of the base AArch64 ISA.
+        "aarch64" | "arm64ec" => Some(128),
+        "arm" if has("neon") => Some(128),
+        "wasm32" | "wasm64" if has("simd128") => Some(128),
+        "riscv32" | "riscv64" if has("v") => Some(128),
+        "powerpc" | "powerpc64" if has("altivec") || has("vsx") => Some(128),
+        _ => None,
+    }
+}
+
+/// Vectorizes the loops of every function in `module` for vector registers of `width` bits, see
+/// the module docs.
+pub(crate) fn vectorize_loops(module: &ModuleMlir, width: u64) {
+    let context = module.context();
+    let mut op = module.module().body().first_operation();
+    while let Some(func) = op {
+        if op_name(&func) == "func.func" {
+            if let Some(entry) = func.region(0).unwrap().first_block() {
+                let mut loops = vec![];
+                collect_loops(entry, &mut loops);
+                for for_op in loops {
+                    if let Some(lanes) = plan_loop(func, entry, for_op, width) {
+                        vectorize_loop(context, for_op, lanes);
+                    }
+                }
+            }
+        }
+        op = func.next_in_block();
+    }
+}
+
+/// Collects the `scf.for` ops in `block` and the blocks nested in it, inner loops first.
+fn collect_loops<'ml, 'a>(block: BlockRef<'ml, 'a>, loops: &mut Vec<OperationRef<'ml, 'a>>) {
+    let mut op = block.first_operation();
+    while let Some(current) = op {
+        for i in 0..current.region_count() {
+            let mut nested = current.region(i).unwrap().first_block();
+            while let Some(nested_block) = nested {
+                collect_loops(nested_block, loops);
+                nested = nested_block.next_in_region();
+            }
+        }
+        if op_name(&current) == "scf.for" {
+            loops.push(current);
+        }
+        op = current.next_in_block();
+    }
+}
+
+/// What a value in the body of a loop is, as far as vectorization is concerned.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    /// The loop counter.
+    Counter,
+    /// The loop counter times a constant element size.
+    Offset(i64),
+    /// The address of the element of size `size` at the counter of function argument `base`.
+    Address { base: usize, size: i64 },
+    /// A value that is the same in every iteration.
+    Invariant,
+    /// A value that becomes a vector with one lane per iteration.
+    Lane,
+}
+
+/// The size in bits of a scalar integer or float type that can be an element of a vector.
+fn scalar_bits(ty: Type<'_>) -> Option<u64> {
+    if let Ok(int) = IntegerType::try_from(ty) {
+        return Some(int.width().into());
+    }
+    match &*ty.to_string() {
+        "f16" | "bf16" => Some(16),
+        "f32" => Some(32),
+        "f64" => Some(64),
+        _ => None,
+    }
+}
+
+fn is_volatile_or_atomic<'ml, 'a>(op: &impl OperationLike<'ml, 'a>) -> bool {
+    op.attribute("volatile_").is_ok() || op.attribute("ordering").is_ok()
+}
+
+/// Whether argument `index` of `func` is `noalias`, see [`crate::abi::fn_abi_arg_attrs`].
+fn arg_is_noalias(func: OperationRef<'_, '_>, index: usize) -> bool {
+    func.attribute("arg_attrs")
+        .ok()
+        .and_then(|attrs| ArrayAttribute::try_from(attrs).ok())
+        .and_then(|attrs| attrs.element(index).ok())
+        .is_some_and(|attrs| attrs.to_string().contains("llvm.noalias"))
+}
+
+/// Checks whether `for_op` can be vectorized, see the module docs, and returns the number of
+/// lanes to vectorize it with.
+fn plan_loop<'ml, 'a>(
+    func: OperationRef<'ml, 'a>,
+    entry: BlockRef<'ml, 'a>,
+    for_op: OperationRef<'ml, 'a>,
+    width: u64,
+) -> Option<u64> {
+    if for_op.result_count() != 0 || step_of(for_op) != Some(1) {
+        return None;
+    }
+    let body = for_op.region(0).unwrap().first_block().unwrap();
+    let counter = body.argument(0).unwrap().into();
+
+    let mut kinds = FxHashMap::default();
+    kinds.insert(value_key(counter), Kind::Counter);
+    let kind_of = |kinds: &FxHashMap<usize, Kind>, value: Value<'_, '_>| {
+        kinds.get(&value_key(value)).copied().unwrap_or(Kind::Invariant)
+    };
+    let mut accessed = FxHashMap::<usize, i64>::default();
+    let mut stored = vec![];
+    let mut max_bits = 0;
+
+    let mut op = body.first_operation();
+    while let Some(current) = op {
+        op = current.next_in_block();
+        // The `scf.yield` terminating the body.
+        if op.is_none() {
+            break;
+        }
+        if current.region_count() != 0 {
+            return None;
+        }
+        let args = operands(&current);
+        let arg_kinds = args.iter().map(|&arg| kind_of(&kinds, arg)).collect::<Vec<_>>();
+        let kind = match op_name(&current) {
+            "arith.constant" => Kind::Invariant,
+            "arith.muli" if arg_kinds.contains(&Kind::Counter) => {
+                let scale = if arg_kinds[0] == Kind::Counter { args[1] } else { args[0] };
+                match (kind_of(&kinds, scale), int_constant(scale)) {
+                    (Kind::Invariant, Some(scale)) if scale > 0 => Kind::Offset(scale),
+                    _ => return None,
+                }
+            }
+            "llvm.getelementptr" => {
+                // `Builder::ptr_offset`, a single dynamic byte offset.
+                let raw_indices = current.attribute("rawConstantIndices").ok()?.to_string();
+                let single_dynamic = raw_indices == format!("array<i32: {}>", i32::MIN);
+                let elem_type = current.attribute("elem_type").ok()?.to_string();
+                if args.len() != 2 || !single_dynamic || elem_type != "i8" {
+                    return None;
+                }
+                let base = BlockArgument::try_from(args[0]).ok()?;
+                if block_key(base.owner()) != block_key(entry) {
+                    return None;
+                }
+                let size = match arg_kinds[1] {
+                    Kind::Counter => 1,
+                    Kind::Offset(size) => size,
+                    _ => return None,
+                };
+                Kind::Address { base: base.argument_number(), size }
+            }
+            "llvm.load" => {
+                let Kind::Address { base, size } = arg_kinds[0] else { return None };
+                let bits = scalar_bits(current.result(0).unwrap().r#type())?;
+                if is_volatile_or_atomic(&current) || bits != size as u64 * 8 {
+                    return None;
+                }
+                if *accessed.entry(base).or_insert(size) != size {
+                    return None;
+                }
+                max_bits = max_bits.max(bits);
+                Kind::Lane
+            }
+            "llvm.store" => {
+                let Kind::Address { base, size } = arg_kinds[1] else { return None };
+                let bits = scalar_bits(args[0].r#type())?;
+                if is_volatile_or_atomic(&current)
+                    || !matches!(arg_kinds[0], Kind::Lane | Kind::Invariant)
+                    || bits != size as u64 * 8
+                {
+                    return None;
+                }
+                if *accessed.entry(base).or_insert(size) != size {
+                    return None;
+                }
+                stored.push(base);
+                max_bits = max_bits.max(bits);
+                continue;
+            }
+            name if name.starts_with("arith.") || name.starts_with("math.") => {
+                if !arg_kinds.iter().all(|&kind| matches!(kind, Kind::Lane | Kind::Invariant)) {
+                    return None;
+                }
+                for i in 0..current.result_count() {
+                    let bits = scalar_bits(current.result(i).unwrap().r#type())?;
+                    if bits != 1 {
+                        max_bits = max_bits.max(bits);
+                    }
+                }
+                for &arg in &args {
+                    scalar_bits(arg.r#type())?;
+                }
+                Kind::Lane
+            }
+            _ => return None,
+        };
+        for i in 0..current.result_count() {
+            kinds.insert(value_key(current.result(i).unwrap().into()), kind);
+        }
+    }
+
+    if stored.is_empty() || !stored.iter().all(|&base| arg_is_noalias(func, base)) {
+        return None;
+    }
+    let lanes = width / max_bits.max(8);
+    if lanes < 2 {
+        return None;
+    }
+    Some(lanes)
+}
+
+/// The step of an `scf.for`, if it is a constant.
+fn step_of(for_op: OperationRef<'_, '_>) -> Option<i64> {
+    int_constant(for_op.operand(2).unwrap())
+}
+
+/// Splits `for_op` into a vectorized loop and the original loop running the remaining
+/// iterations.
+fn vectorize_loop<'ml, 'a>(context: &'ml Context, for_op: OperationRef<'ml, 'a>, lanes: u64) {
+    let for_operands = operands(&for_op);
+    let (lower, upper) = (for_operands[0], for_operands[1]);
+    let counter_ty = lower.r#type();
+    let unsigned = for_op.attribute("unsignedCmp").is_ok();
+
+    let location = for_op.location();
+    let block = for_op.block().unwrap();
+    let bx = Builder::new(context, block, location);
+    let insert = |op: Operation<'ml>| -> Value<'ml, 'a> {
+        block.insert_operation_before(for_op, op).result(0).unwrap().into()
+    };
+    let constant = |value: u64| {
+        insert(
+            bx.op("arith.constant")
+                .add_attributes(&[(
+                    bx.ident("value"),
+                    bx.parse_attr(&format!("{value} : {counter_ty}")),
+                )])
+                .add_results(&[counter_ty])
+                .build()
+                .unwrap(),
+        )
+    };
+    let binary = |name: &str, lhs: Value<'ml, 'a>, rhs: Value<'ml, 'a>| {
+        insert(bx.op(name).add_operands(&[lhs, rhs]).add_results(&[counter_ty]).build().unwrap())
+    };
+
+    // The vectorized loop runs the largest multiple of `lanes` iterations of the original loop.
+    let step = constant(lanes);
+    let zero = constant(0);
+    let cc = if unsigned { IntCC::UnsignedGreaterThan } else { IntCC::SignedGreaterThan };
+    let runs = insert(
+        bx.op("arith.cmpi")
+            .add_operands(&[upper, lower])
+            .add_attributes(&[(bx.ident("predicate"), bx.i64_attr(cc as i64))])
+            .add_results(&[bx.int_type(1)])
+            .build()
+            .unwrap(),
+    );
+    let distance = binary("arith.subi", upper, lower);
+    let trip_count = insert(
+        bx.op("arith.select")
+            .add_operands(&[runs, distance, zero])
+            .add_results(&[counter_ty])
+            .build()
+            .unwrap(),
+    );
+    let remainder = binary("arith.remui", trip_count, step);
+    let vector_trip_count = binary("arith.subi", trip_count, remainder);
+    let vector_upper = binary("arith.addi", lower, vector_trip_count);
+
+    let region = Region::new();
+    region.append_block(Block::new(&[(counter_ty, location)]));
+    let mut attributes = vec![];
+    if unsigned {
+        attributes.push((bx.ident("unsignedCmp"), Attribute::unit(context)));
+    }
+    let vector_for = block.insert_operation_before(
+        for_op,
+        bx.op("scf.for")
+            .add_operands(&[lower, vector_upper, step])
+            .add_attributes(&attributes)
+            .add_regions([region])
+            .build()
+            .unwrap(),
+    );
+    set_operand(&for_op, 0, vector_upper);
+
+    let body = for_op.region(0).unwrap().first_block().unwrap();
+    let vector_body = vector_for.region(0).unwrap().first_block().unwrap();
+    let mut emitter = VectorBody {
+        bx: bx.at(vector_body),
+        lanes,
+        scalars: FxHashMap::default(),
+        vectors: FxHashMap::default(),
+        broadcasts: FxHashMap::default(),
+    };
+    let counter = body.argument(0).unwrap().into();
+    emitter.scalars.insert(value_key(counter), block_arguments(vector_body)[0]);
+
+    let mut op = body.first_operation();
+    while let Some(current) = op {
+        op = current.next_in_block();
+        if op.is_none() {
+            break;
+        }
+        emitter.emit(current);
+    }
+    emitter.bx.append(emitter.bx.op("scf.yield").build().unwrap());
+}
+
+/// Emits the body of a vectorized loop from the body of the original loop.
+struct VectorBody<'a, 'ml> {
+    bx: Builder<'a, 'ml>,
+    lanes: u64,
+    /// Values of the original body that stay scalars: the counter, offsets, addresses and
+    /// constants.
+    scalars: FxHashMap<usize, Value<'ml, 'a>>,
+    /// Values of the original body that become vectors.
+    vectors: FxHashMap<usize, Value<'ml, 'a>>,
+    /// Broadcasts of the scalars to vectors, by the value they stand for.
+    broadcasts: FxHashMap<usize, Value<'ml, 'a>>,
+}
+
+impl<'a, 'ml> VectorBody<'a, 'ml> {
+    fn vector_type(&self, ty: Type<'ml>) -> Type<'ml> {
+        Type::vector(&[self.lanes], ty)
+    }
+
+    /// The scalar `value` of the original body stands for. Values defined outside of the loop
+    /// are visible in the vectorized loop too.
+    fn scalar(&self, value: Value<'ml, 'a>) -> Value<'ml, 'a> {
+        self.scalars.get(&value_key(value)).copied().unwrap_or(value)
+    }
+
+    /// The vector `value` of the original body stands for, broadcasting scalars to every lane.
+    fn vector(&mut self, value: Value<'ml, 'a>) -> Value<'ml, 'a> {
+        let key = value_key(value);
+        if let Some(&vector) = self.vectors.get(&key).or_else(|| self.broadcasts.get(&key)) {
+            return vector;
+        }
+        let scalar = self.scalar(value);
+        let vector = self.bx.cast("vector.broadcast", scalar, self.vector_type(value.r#type()));
+        self.broadcasts.insert(key, vector);
+        vector
+    }
+
+    fn alignment(op: OperationRef<'ml, 'a>) -> u64 {
+        op.attribute("alignment")
+            .ok()
+            .and_then(|attr| IntegerAttribute::try_from(attr).ok())
+            .map_or(1, |align| align.value() as u64)
+    }
+
+    fn emit(&mut self, op: OperationRef<'ml, 'a>) {
+        let bx = self.bx;
+        let args = operands(&op);
+        let result = op.result(0).ok().map(|result| value_key(result.into()));
+        match op_name(&op) {
+            "arith.constant" => {
+                let ty = op.result(0).unwrap().r#type();
+                let value = bx.append_value(
+                    bx.op("arith.constant")
+                        .add_attributes(&[(bx.ident("value"), op.attribute("value").unwrap())])
+                        .add_results(&[ty])
+                        .build()
+                        .unwrap(),
+                );
+                self.scalars.insert(result.unwrap(), value);
+            }
+            "llvm.getelementptr" => {
+                let address = bx.ptr_offset(self.scalar(args[0]), self.scalar(args[1]));
+                self.scalars.insert(result.unwrap(), address);
+            }
+            "llvm.load" => {
+                let ty = self.vector_type(op.result(0).unwrap().r#type());
+                let value = bx.load(ty, self.scalar(args[0]), Self::alignment(op));
+                self.vectors.insert(result.unwrap(), value);
+            }
+            "llvm.store" => {
+                let value = self.vector(args[0]);
+                bx.store(value, self.scalar(args[1]), Self::alignment(op));
+            }
+            name => {
+                // Ops on scalars only, like the offsets of the elements, stay scalar.
+                let vectorized = args.iter().any(|&arg| self.vectors.contains_key(&value_key(arg)));
+                let new_args = args
+                    .iter()
+                    .map(|&arg| if vectorized { self.vector(arg) } else { self.scalar(arg) })
+                    .collect::<Vec<_>>();
+                let result_types = (0..op.result_count())
+                    .map(|i| op.result(i).unwrap().r#type())
+                    .map(|ty| if vectorized { self.vector_type(ty) } else { ty })
+                    .collect::<Vec<_>>();
+                let attributes = ELEMENTWISE_ATTRS
+                    .iter()
+                    .filter_map(|&attr| Some((bx.ident(attr), op.attribute(attr).ok()?)))
+                    .collect::<Vec<_>>();
+                let new_op = bx.append(
+                    bx.op(name)
+                        .add_operands(&new_args)
+                        .add_attributes(&attributes)
+                        .add_results(&result_types)
+                        .build()
+                        .unwrap(),
+                );
+                let values = if vectorized { &mut self.vectors } else { &mut self.scalars };
+                for i in 0..op.result_count() {
+                    values.insert(
+                        value_key(op.result(i).unwrap().into()),
+                        new_op.result(i).unwrap().into(),
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Codegen of vtables and vtable accesses.
+//!
+//! See `rustc_codegen_ssa/src/meth.rs` for reference.
+
+use melior::ir::{Block, Region, RegionLike, Type, Value};
+use rustc_abi::FieldIdx;
+use rustc_middle::bug;
+use rustc_middle::mir::mono::{Linkage, Visibility};
+use rustc_middle::ty::layout::{FnAbiOf, LayoutOf};
+use rustc_middle::ty::{self, Instance, ParamEnv, Ty, TyCtxt, VtblEntry};
+
+use crate::abi::mlir_fn_type;
+use crate::builder::Builder;
+use crate::common::FunctionCx;
+use crate::context::{CodegenCx, GlobalAttrs};
+use crate::type_of::{pointer_ty, usize_ty};
+use crate::value_and_place::MValue;
+
+fn load_vtable_entry<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    vtable: Value<'ml, 'a>,
+    index: usize,
+    ty: Type<'ml>,
+) -> Value<'ml, 'a> {
+    let usize_size = fx.tcx.data_layout.pointer_size.bytes();
+    let usize_align = fx.tcx.data_layout.pointer_align.abi.bytes();
+    let ptr = fx.bx.ptr_offset_imm(vtable, (index as u64 * usize_size) as i64);
+    fx.bx.load(ty, ptr, usize_align)
+}
+
+pub(crate) fn drop_fn_of_obj<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    vtable: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    let ptr_ty = fx.pointer_type();
+    load_vtable_entry(fx, vtable, ty::COMMON_VTABLE_ENTRIES_DROPINPLACE, ptr_ty)
+}
+
+pub(crate) fn size_of_obj<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    vtable: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    let usize_ty = fx.usize_type();
+    load_vtable_entry(fx, vtable, ty::COMMON_VTABLE_ENTRIES_SIZE, usize_ty)
+}
+
+pub(crate) fn min_align_of_obj<'a, 'ml>(
+    fx: &mut FunctionCx<'a, 'ml, '_>,
+    vtable: Value<'ml, 'a>,
+) -> Value<'ml, 'a> {
+    let usize_ty = fx.usize_type();
+    load_vtable_entry(fx, vtable, ty::COMMON_VTABLE_ENTRIES_ALIGN, usize_ty)
+}
+
+/// Loads the vtable pointer of the trait object `arg` and returns the data pointer together with
+/// the method at `idx` of the vtable.
+pub(crate) fn get_ptr_and_method_ref<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    mut arg: MValue<'ml, 'a, 'tcx>,
+    idx: usize,
+) -> (Value<'ml, 'a>, Value<'ml, 'a>) {
+    // Unwrap newtypes like `Pin<Box<dyn Trait>>` that implement `DispatchFromDyn` until we reach
+    // the fat pointer itself.
+    while !arg.layout().ty.is_unsafe_ptr() && !arg.layout().ty.is_ref() {
+        let (idx, _) = arg
+            .layout()
+            .non_1zst_field(fx)
+            .expect("not exactly one non-1-ZST field in a `DispatchFromDyn` type");
+        arg = arg.value_field(fx, FieldIdx::new(idx));
+    }
+
+    let (ptr, vtable) = arg.load_scalar_pair(fx);
+    let ptr_ty = fx.pointer_type();
+    let func_ref = load_vtable_entry(fx, vtable, idx, ptr_ty);
+    (ptr, func_ref)
+}
+
+/// Returns a pointer to the vtable of `ty` for `trait_ref`, emitting it into the module first if
+/// it hasn't been used in this codegen unit yet.
+pub(crate) fn get_vtable<'a, 'ml, 'tcx>(
+    fx: &mut FunctionCx<'a, 'ml, 'tcx>,
+    ty: Ty<'tcx>,
+    trait_ref: Option<ty::PolyExistentialTraitRef<'tcx>>,
+) -> Value<'ml, 'a> {
+    let symbol = vtable_symbol(fx.cx, ty, trait_ref);
+    fx.bx.global_addr(&symbol)
+}
+
+/// Emits the vtable of `ty` for `trait_ref` as a private constant global and returns its symbol.
+///
+/// The entries mirror `rustc_middle::ty::vtable::vtable_allocation_provider`, except that
+/// `Vacant` entries are filled with null pointers rather than left uninitialized.
+pub(crate) fn vtable_symbol<'ml, 'tcx>(
+    cx: &CodegenCx<'ml, 'tcx>,
+    ty: Ty<'tcx>,
+    trait_ref: Option<ty::PolyExistentialTraitRef<'tcx>>,
+) -> String {
+    if let Some(symbol) = cx.vtables.borrow().get(&(ty, trait_ref)) {
+        return symbol.clone();
+    }
+
+    let tcx = cx.tcx;
+    let symbol = format!("vtable.{}", cx.vtables.borrow().len());
+    // Supertrait vtables are emitted while building this one, so the symbol has to be reserved
+    // before looking at the entries.
+    cx.vtables.borrow_mut().insert((ty, trait_ref), symbol.clone());
+
+    let entries = if let Some(trait_ref) = trait_ref {
+        let trait_ref = tcx.erase_regions(trait_ref.with_self_ty(tcx, ty));
+        tcx.vtable_entries(trait_ref)
+    } else {
+        TyCtxt::COMMON_VTABLE_ENTRIES
+    };
+
+    let layout = cx.layout_of(ty);
+    if layout.is_unsized() {
+        bug!("can't create a vtable for the unsized type {ty}");
+    }
+
+    let ptr_ty = pointer_ty(cx.context);
+    let usize_ty = usize_ty(tcx, cx.context);
+    let vtable_ty = melior::dialect::llvm::r#type::array(ptr_ty, entries.len() as u32);
+
+    let initializer = Region::new();
+    let block = initializer.append_block(Block::new(&[]));
+    let bx = Builder::new(cx.context, block, cx.unknown_loc());
+
+    let mut vtable = bx.undef(vtable_ty);
+    for (idx, entry) in entries.iter().enumerate() {
+        let value = match *entry {
+            VtblEntry::MetadataDropInPlace => {
+                if ty.needs_drop(tcx, ParamEnv::reveal_all()) {
+                    let instance = Instance::resolve_drop_in_place(tcx, ty).polymorphize(tcx);
+                    fn_addr(cx, &bx, instance)
+                } else {
+                    bx.null_ptr()
+                }
+            }
+            VtblEntry::MetadataSize => {
+                let size = bx.iconst(usize_ty, layout.size.bytes() as i128);
+                bx.cast("llvm.inttoptr", size, ptr_ty)
+            }
+            VtblEntry::MetadataAlign => {
+                let align = bx.iconst(usize_ty, layout.align.abi.bytes() as i128);
+                bx.cast("llvm.inttoptr", align, ptr_ty)
+            }
+            VtblEntry::Vacant => bx.null_ptr(),
+            VtblEntry::Method(instance) => fn_addr(cx, &bx, instance.polymorphize(tcx)),
+            VtblEntry::TraitVPtr(trait_ref) => {
+                let super_trait_ref = trait_ref
+                    .map_bound(|trait_ref| ty::ExistentialTraitRef::erase_self_ty(tcx, trait_ref));
+                let super_symbol = vtable_symbol(cx, ty, Some(super_trait_ref));
+                bx.global_addr(&super_symbol)
+            }
+        };
+        vtable = bx.insert_value(vtable, value, idx);
+    }
+    bx.append(bx.op("llvm.return").add_operands(&[vtable]).build().unwrap());
+
+    let attrs = GlobalAttrs {
+        linkage: Linkage::Private,
+        visibility: Visibility::Default,
+        constant: true,
+        thread_local: false,
+        align: tcx.data_layout.pointer_align.abi.bytes(),
+        section: None,
+    };
+    cx.define_global(&symbol, vtable_ty, attrs, initializer);
+    symbol
+}
+
+/// Takes the address of `instance` with `bx`, which may be building a global initializer rather
+/// than a function body.
+pub(crate) fn fn_addr<'a, 'ml, 'tcx>(
+    cx: &CodegenCx<'ml, 'tcx>,
+    bx: &Builder<'a, 'ml>,
+    instance: Instance<'tcx>,
+) -> Value<'ml, 'a> {
+    let fn_abi = cx.fn_abi_of_instance(instance, ty::List::empty());
+    let fn_ty = mlir_fn_type(cx.tcx, cx.context, fn_abi);
+    let symbol_name = cx.tcx.symbol_name(instance).name;
+    cx.reference_fn(symbol_name, fn_ty);
+    bx.func_addr(symbol_name, fn_ty)
+}
@@ -0,0 +1,226 @@
+//! Lowering of codegen units to the LLVM dialect and their translation to LLVM IR.
+
+use std::fs;
+use std::path::Path;
+
+use rustc_codegen_llvm::ModuleLlvm;
+use rustc_codegen_ssa::back::link::ensure_removed;
+use rustc_codegen_ssa::{ModuleCodegen, ModuleKind, errors as ssa_errors};
+use rustc_metadata::fs::copy_to_stdout;
+use rustc_middle::ty::TyCtxt;
+use rustc_session::Session;
+use rustc_session::config::{DebugInfo, OptLevel, OutFileName, OutputFilenames, OutputType};
+
+use crate::dump::{self, BYTECODE_EXTENSION};
+use crate::{ModuleMlir, llvm, raise, vectorize};
+
+/// The passes converting everything emitted by `base` to the LLVM dialect.
+pub(crate) const LOWER_TO_LLVM_PASSES: &[&str] = &[
+    "lower-affine",
+    "convert-scf-to-cf",
+    "convert-vector-to-llvm",
+    "convert-math-to-llvm",
+    "convert-arith-to-llvm",
+    "convert-cf-to-llvm",
+    "convert-func-to-llvm",
+    "reconcile-unrealized-casts",
+];
+
+/// The passes run before lowering to the LLVM dialect when `-Zmlir-passes` isn't given.
+fn default_passes(opt_level: OptLevel) -> &'static [&'static str] {
+    match opt_level {
+        OptLevel::No => &[],
+        OptLevel::Less | OptLevel::Size | OptLevel::SizeMin => &["canonicalize", "cse"],
+        OptLevel::Default | OptLevel::Aggressive => {
+            &["canonicalize", "cse", "loop-invariant-code-motion", "canonicalize"]
+        }
+    }
+}
+
+fn write_error(sess: &Session, path: &Path, err: impl std::fmt::Display) -> ! {
+    sess.dcx().fatal(format!("error writing `{}`: {err}", path.display()))
+}
+
+/// Runs `passes` on the module, dumping it after each pass named by `-Zmlir-dump-after`.
+fn run_passes(
+    sess: &Session,
+    outputs: &OutputFilenames,
+    module: &mut ModuleCodegen<ModuleMlir>,
+    passes: &[&str],
+) {
+    // Passes are only run one at a time if a dump is requested in between them.
+    let dump_requested = passes.iter().any(|pass| dump::should_dump_after(sess, pass));
+    let groups: Vec<&[&str]> =
+        if dump_requested { passes.chunks(1).collect() } else { vec![passes] };
+
+    for group in groups {
+        run_pipeline(sess, module, &group.join(","));
+        if let [pass] = group {
+            dump::dump_after(sess, outputs, &module.name, pass, module.module_llvm.module());
+        }
+    }
+}
+
+/// Runs the textual pass `pipeline`, which is nested in `builtin.module`, on the module.
+fn run_pipeline(sess: &Session, module: &mut ModuleCodegen<ModuleMlir>, pipeline: &str) {
+    let pipeline = format!("builtin.module({pipeline})");
+    if let Err(err) = module.module_llvm.run_pass_pipeline(&pipeline) {
+        sess.dcx().fatal(format!("failed to run `{pipeline}` on `{}`: {err}", module.name));
+    }
+}
+
+/// Runs the `-Zmlir-passes` pipeline on the `rust` dialect form of the codegen unit `name`,
+/// before it is lowered. Passes may erase or merge blocks of a `rust.fn`, but not add any.
+pub(crate) fn run_rust_passes(
+    sess: &Session,
+    outputs: &OutputFilenames,
+    module: &ModuleMlir,
+    name: &str,
+) {
+    let Some(pipeline) = &sess.opts.unstable_opts.mlir_passes else { return };
+    if !pipeline.is_empty() {
+        let pipeline = format!("builtin.module({pipeline})");
+        sess.prof.generic_activity("run mlir passes on rust dialect").run(|| {
+            if let Err(err) = module.run_rust_pass_pipeline(&pipeline) {
+                sess.dcx().fatal(format!("failed to run `{pipeline}` on `{name}`: {err}"));
+            }
+        });
+    }
+    dump::dump_after(sess, outputs, name, "rust-passes", module.rust_module());
+}
+
+/// Lowers `module` to the LLVM dialect and translates it to LLVM IR, for `rustc_codegen_llvm` to
+/// optimize and emit.
+pub(crate) fn lower_to_llvm(
+    tcx: TyCtxt<'_>,
+    mut module: ModuleCodegen<ModuleMlir>,
+) -> ModuleCodegen<ModuleLlvm> {
+    let sess = tcx.sess;
+    let outputs = tcx.output_filenames(());
+    let name = module.name.clone();
+    let _timer = sess.prof.generic_activity_with_arg("lower module to llvm", &*name);
+
+    // Like the other per-cgu outputs, `--emit=mlir` doesn't cover the allocator shim.
+    if module.kind == ModuleKind::Regular && sess.opts.output_types.contains_key(&OutputType::Mlir)
+    {
+        let path = outputs.temp_path(OutputType::Mlir, Some(&name));
+        dump::write_module(module.module_llvm.module(), &path)
+            .unwrap_or_else(|err| write_error(sess, &path, err));
+    }
+
+    let opts = &sess.opts.unstable_opts;
+    if opts.mlir_raise_loops || opts.mlir_vectorize {
+        sess.prof.generic_activity("raise loops").run(|| {
+            run_passes(sess, outputs, &mut module, raise::PREPARE_PASSES);
+            // The vectorizer only handles `scf.for`.
+            raise::raise_loops(&module.module_llvm, !opts.mlir_vectorize);
+        });
+        dump::dump_after(sess, outputs, &name, "raise-loops", module.module_llvm.module());
+    }
+
+    if opts.mlir_vectorize {
+        if let Some(width) = vectorize::vector_width(sess) {
+            sess.prof
+                .generic_activity("vectorize loops")
+                .run(|| vectorize::vectorize_loops(&module.module_llvm, width));
+        }
+        dump::dump_after(sess, outputs, &name, "vectorize", module.module_llvm.module());
+    }
+
+    // A `-Zmlir-passes` pipeline may nest passes in parentheses, so unlike the default passes it
+    // can't be split into single passes to dump after each of them.
+    sess.prof.generic_activity("run mlir passes").run(|| {
+        match &sess.opts.unstable_opts.mlir_passes {
+            Some(pipeline) => {
+                if !pipeline.is_empty() {
+                    run_pipeline(sess, &mut module, pipeline);
+                }
+                dump::dump_after(sess, outputs, &name, "mlir-passes", module.module_llvm.module());
+            }
+            None => run_passes(sess, outputs, &mut module, default_passes(sess.opts.optimize)),
+        }
+    });
+
+    sess.prof
+        .generic_activity("lower to llvm dialect")
+        .run(|| run_passes(sess, outputs, &mut module, LOWER_TO_LLVM_PASSES));
+
+    let module_llvm = ModuleLlvm::new(tcx, &name);
+    let translated = sess
+        .prof
+        .generic_activity("translate to llvm ir")
+        .run(|| llvm::translate_into(module.module_llvm.module(), &module_llvm));
+    if !translated {
+        sess.dcx().fatal(format!("failed to translate `{name}` to LLVM IR"));
+    }
+    llvm::append_module_asm(&module_llvm, &module.module_llvm.module_asm);
+    if sess.opts.debuginfo != DebugInfo::None {
+        llvm::set_dwarf_version(&module_llvm, sess.dwarf_version());
+    }
+
+    ModuleCodegen { name, module_llvm, kind: module.kind }
+}
+
+/// Copies the `--emit=mlir` output of the codegen unit `module_name`, both as text and as bytecode,
+/// to the crate's output, for when it is the only codegen unit producing such an output.
+fn copy_mlir_output(sess: &Session, crate_output: &OutputFilenames, module_name: &str) {
+    let copy_gracefully = |from: &Path, to: &OutFileName| match to {
+        OutFileName::Stdout => {
+            if let Err(e) = copy_to_stdout(from) {
+                sess.dcx().emit_err(ssa_errors::CopyPath::new(from, to.as_path(), e));
+            }
+        }
+        OutFileName::Real(path) => {
+            if let Err(e) = fs::copy(from, path) {
+                sess.dcx().emit_err(ssa_errors::CopyPath::new(from, path, e));
+            }
+        }
+    };
+
+    let path = crate_output.temp_path(OutputType::Mlir, Some(module_name));
+    let output = crate_output.path(OutputType::Mlir);
+    copy_gracefully(&path, &output);
+    let bytecode_path = path.with_extension(BYTECODE_EXTENSION);
+    if let OutFileName::Real(output) = &output {
+        let bytecode_output = OutFileName::Real(output.with_extension(BYTECODE_EXTENSION));
+        copy_gracefully(&bytecode_path, &bytecode_output);
+    }
+    if !sess.opts.cg.save_temps {
+        // The user just wants `foo.mlir`, not `foo.#module-name#.mlir`.
+        ensure_removed(sess.dcx(), &path);
+        ensure_removed(sess.dcx(), &bytecode_path);
+    }
+}
+
+/// Copies the `--emit=mlir` output of the codegen units in `mlir_cgus`, which were compiled by
+/// this backend, to the crate's output, like `rustc_codegen_llvm` does for the outputs it knows
+/// about.
+///
+/// Adapted from `rustc_codegen_ssa::back::write::produce_final_output_artifacts`.
+pub(crate) fn produce_final_output_artifacts(
+    sess: &Session,
+    mlir_cgus: &[String],
+    crate_output: &OutputFilenames,
+) {
+    if !crate_output.outputs.contains_key(&OutputType::Mlir) {
+        return;
+    }
+
+    if let [module_name] = mlir_cgus {
+        // 1) Only one codegen unit. In this case it's no difficulty
+        //    to copy `foo.0.mlir` to `foo.mlir`.
+        copy_mlir_output(sess, crate_output, module_name);
+    } else if crate_output.outputs.contains_explicit_name(&OutputType::Mlir) {
+        // 2) Multiple codegen units, with `--emit mlir=some_name`. We have
+        //    no good solution for this case, so warn the user.
+        sess.dcx().emit_warn(ssa_errors::IgnoringEmitPath { extension: "mlir".to_owned() });
+    } else if crate_output.single_output_file.is_some() {
+        // 3) Multiple codegen units, with `-o some_name`. We have
+        //    no good solution for this case, so warn the user.
+        sess.dcx().emit_warn(ssa_errors::IgnoringOutput { extension: "mlir".to_owned() });
+    } else {
+        // 4) Multiple codegen units, but no explicit name. We
+        //    just leave the `foo.0.mlir` files in place.
+        // (We don't have to do any work in this case.)
+    }
+}
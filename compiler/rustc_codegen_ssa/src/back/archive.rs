@@ -293,6 +293,16 @@ fn add_archive(
     fn build(self: Box<Self>, output: &Path) -> bool;
 }
 
+/// The [`ArchiveBuilderBuilder`] of backends that build archives with [`ArArchiveBuilder`] and
+/// read object files with the default object reader.
+pub struct ArArchiveBuilderBuilder;
+
+impl ArchiveBuilderBuilder for ArArchiveBuilderBuilder {
+    fn new_archive_builder<'a>(&self, sess: &'a Session) -> Box<dyn ArchiveBuilder + 'a> {
+        Box::new(ArArchiveBuilder::new(sess, &DEFAULT_OBJECT_READER))
+    }
+}
+
 #[must_use = "must call build() to finish building the archive"]
 pub struct ArArchiveBuilder<'a> {
     sess: &'a Session,
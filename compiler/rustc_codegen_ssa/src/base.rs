@@ -1074,6 +1074,13 @@ pub fn determine_cgu_reuse<'tcx>(tcx: TyCtxt<'tcx>, cgu: &CodegenUnit<'tcx>) ->
         return CguReuse::No;
     }
 
+    // The MLIR backend writes its modules while generating them, which isn't done for reused ones.
+    if tcx.sess.opts.output_types.contains_key(&OutputType::Mlir)
+        || !tcx.sess.opts.unstable_opts.mlir_dump_after.is_empty()
+    {
+        return CguReuse::No;
+    }
+
     let work_product_id = &cgu.work_product_id();
     if tcx.dep_graph.previous_work_product(work_product_id).is_none() {
         // We don't have anything cached for this CGU. This can happen
@@ -709,6 +709,7 @@ macro_rules! untracked {
     untracked!(macro_backtrace, true);
     untracked!(meta_stats, true);
     untracked!(mir_include_spans, MirIncludeSpans::On);
+    untracked!(mlir_dump_after, vec![String::from("all")]);
     untracked!(nll_facts, true);
     untracked!(no_analysis, true);
     untracked!(no_leak_check, true);
@@ -808,6 +809,10 @@ macro_rules! tracked {
     tracked!(mir_enable_passes, vec![("DestProp".to_string(), false)]);
     tracked!(mir_keep_place_mention, true);
     tracked!(mir_opt_level, Some(4));
+    tracked!(mlir_only_functions, vec![String::from("kernels::saxpy")]);
+    tracked!(mlir_passes, Some(String::from("canonicalize,cse")));
+    tracked!(mlir_raise_loops, true);
+    tracked!(mlir_vectorize, true);
     tracked!(move_size_limit, Some(4096));
     tracked!(mutable_noalias, false);
     tracked!(next_solver, NextSolverConfig { coherence: true, globally: true });
@@ -265,6 +265,9 @@ pub struct CodegenUnit<'tcx> {
     /// True if this is CGU is used to hold code coverage information for dead code,
     /// false otherwise.
     is_code_coverage_dead_code_cgu: bool,
+    /// True if this CGU holds the functions selected with `-Zmlir-only-functions`, which are
+    /// compiled by the MLIR backend while all other CGUs are compiled by LLVM.
+    is_mlir: bool,
 }
 
 /// Auxiliary info about a `MonoItem`.
@@ -325,6 +328,7 @@ pub fn new(name: Symbol) -> CodegenUnit<'tcx> {
             size_estimate: 0,
             primary: false,
             is_code_coverage_dead_code_cgu: false,
+            is_mlir: false,
         }
     }
 
@@ -361,6 +365,15 @@ pub fn make_code_coverage_dead_code_cgu(&mut self) {
         self.is_code_coverage_dead_code_cgu = true;
     }
 
+    pub fn is_mlir(&self) -> bool {
+        self.is_mlir
+    }
+
+    /// Marks this CGU as one to be compiled by the MLIR backend.
+    pub fn make_mlir(&mut self) {
+        self.is_mlir = true;
+    }
+
     pub fn mangle_name(human_readable_name: &str) -> BaseNString {
         let mut hasher = StableHasher::new();
         human_readable_name.hash(&mut hasher);
@@ -9,6 +9,13 @@ monomorphize_large_assignments =
     .label = value moved from here
     .note = The current maximum size is {$limit}, but it can be customized with the move_size_limit attribute: `#![move_size_limit = "..."]`
 
+monomorphize_mlir_only_fn_not_found =
+    no function `{$path}` selected with `-Zmlir-only-functions` is code generated in this crate
+
+monomorphize_mlir_only_fn_not_placed =
+    `{$path}` selected with `-Zmlir-only-functions` is not compiled by the MLIR backend on its own
+    .note = functions that are copied into the codegen units of their users, like `#[inline(always)]` functions, `#[inline]` functions in optimized builds, and shims, are compiled by the backend of each user
+
 monomorphize_no_optimized_mir =
     missing optimized MIR for an item in the crate `{$crate_name}`
     .note = missing optimized MIR for this item (was the crate `{$crate_name}` compiled with `--emit=metadata`?)
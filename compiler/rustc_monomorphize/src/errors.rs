@@ -92,3 +92,16 @@ pub(crate) struct EncounteredErrorWhileInstantiating {
 pub(crate) struct UnknownCguCollectionMode<'a> {
     pub mode: &'a str,
 }
+
+#[derive(Diagnostic)]
+#[diag(monomorphize_mlir_only_fn_not_found)]
+pub(crate) struct MlirOnlyFnNotFound<'a> {
+    pub path: &'a str,
+}
+
+#[derive(Diagnostic)]
+#[diag(monomorphize_mlir_only_fn_not_placed)]
+#[note]
+pub(crate) struct MlirOnlyFnNotPlaced<'a> {
+    pub path: &'a str,
+}
@@ -123,7 +123,10 @@
 use tracing::debug;
 
 use crate::collector::{self, MonoItemCollectionStrategy, UsageMap};
-use crate::errors::{CouldntDumpMonoStats, SymbolAlreadyDefined, UnknownCguCollectionMode};
+use crate::errors::{
+    CouldntDumpMonoStats, MlirOnlyFnNotFound, MlirOnlyFnNotPlaced, SymbolAlreadyDefined,
+    UnknownCguCollectionMode,
+};
 
 struct PartitioningCx<'a, 'tcx> {
     tcx: TyCtxt<'tcx>,
@@ -161,15 +164,26 @@ fn partition<'tcx, I>(
         placed
     };
 
+    // The functions selected with `-Zmlir-only-functions` are compiled by a
+    // different backend than everything else, so their CGU must not be merged
+    // with any other.
+    let mlir_codegen_units: Vec<_>;
+    (mlir_codegen_units, codegen_units) = codegen_units.into_iter().partition(|cgu| cgu.is_mlir());
+
     // Merge until we don't exceed the max CGU count.
     // `merge_codegen_units` is responsible for updating the CGU size
     // estimates.
-    {
+    if !codegen_units.is_empty() {
         let _prof_timer = tcx.prof.generic_activity("cgu_partitioning_merge_cgus");
         merge_codegen_units(cx, &mut codegen_units);
         debug_dump(tcx, "MERGE", &codegen_units);
     }
 
+    if !mlir_codegen_units.is_empty() {
+        codegen_units.extend(mlir_codegen_units);
+        codegen_units.sort_by(|a, b| a.name().as_str().cmp(b.name().as_str()));
+    }
+
     // Make as many symbols "internal" as possible, so LLVM has more freedom to
     // optimize.
     if !tcx.sess.link_dead_code() {
@@ -214,7 +228,18 @@ fn place_mono_items<'tcx, I>(cx: &PartitioningCx<'_, 'tcx>, mono_items: I) -> Pl
     let cgu_name_builder = &mut CodegenUnitNameBuilder::new(cx.tcx);
     let cgu_name_cache = &mut UnordMap::default();
 
+    // For each of the paths selected with `-Zmlir-only-functions`, whether a function at it is
+    // code generated at all, and whether it is placed into the MLIR CGU.
+    let mlir_only_paths = &cx.tcx.sess.opts.unstable_opts.mlir_only_functions;
+    let mut mlir_only_found = vec![false; mlir_only_paths.len()];
+    let mut mlir_only_placed = vec![false; mlir_only_paths.len()];
+
     for mono_item in mono_items {
+        let mlir_only_index = mlir_only_fn_index(cx.tcx, mono_item);
+        if let Some(index) = mlir_only_index {
+            mlir_only_found[index] = true;
+        }
+
         // Handle only root (GloballyShared) items directly here. Inlined (LocalCopy) items
         // are handled at the bottom of the loop based on reachability, with one exception.
         // The #[lang = "start"] item is the program entrypoint, so there are no calls to it in MIR.
@@ -231,7 +256,18 @@ fn place_mono_items<'tcx, I>(cx: &PartitioningCx<'_, 'tcx>, mono_items: I) -> Pl
         let characteristic_def_id = characteristic_def_id_of_mono_item(cx.tcx, mono_item);
         let is_volatile = is_incremental_build && mono_item.is_generic_fn(cx.tcx);
 
+        // Only root items are placed into the MLIR CGU: an inlined copy of a selected function is
+        // compiled along with its user, and so are the shims of a selected function.
+        let is_mlir = match (mlir_only_index, mono_item) {
+            (Some(index), MonoItem::Fn(ty::Instance { def: InstanceKind::Item(_), .. })) => {
+                mlir_only_placed[index] = true;
+                true
+            }
+            _ => false,
+        };
+
         let cgu_name = match characteristic_def_id {
+            _ if is_mlir => mlir_cgu_name(cgu_name_builder),
             Some(def_id) => compute_codegen_unit_name(
                 cx.tcx,
                 cgu_name_builder,
@@ -242,7 +278,13 @@ fn place_mono_items<'tcx, I>(cx: &PartitioningCx<'_, 'tcx>, mono_items: I) -> Pl
             None => fallback_cgu_name(cgu_name_builder),
         };
 
-        let cgu = codegen_units.entry(cgu_name).or_insert_with(|| CodegenUnit::new(cgu_name));
+        let cgu = codegen_units.entry(cgu_name).or_insert_with(|| {
+            let mut cgu = CodegenUnit::new(cgu_name);
+            if is_mlir {
+                cgu.make_mlir();
+            }
+            cgu
+        });
 
         let mut can_be_internalized = true;
         let (linkage, visibility) = mono_item_linkage_and_visibility(
@@ -284,6 +326,14 @@ fn place_mono_items<'tcx, I>(cx: &PartitioningCx<'_, 'tcx>, mono_items: I) -> Pl
         }
     }
 
+    for (index, path) in mlir_only_paths.iter().enumerate() {
+        if !mlir_only_found[index] {
+            cx.tcx.dcx().emit_warn(MlirOnlyFnNotFound { path });
+        } else if !mlir_only_placed[index] {
+            cx.tcx.dcx().emit_warn(MlirOnlyFnNotPlaced { path });
+        }
+    }
+
     // Always ensure we have at least one CGU; otherwise, if we have a
     // crate with just types (for example), we could wind up with no CGU.
     if codegen_units.is_empty() {
@@ -735,6 +785,27 @@ fn fallback_cgu_name(name_builder: &mut CodegenUnitNameBuilder<'_>) -> Symbol {
     name_builder.build_cgu_name(LOCAL_CRATE, &["fallback"], Some("cgu"))
 }
 
+// The functions selected with `-Zmlir-only-functions` go into this.
+fn mlir_cgu_name(name_builder: &mut CodegenUnitNameBuilder<'_>) -> Symbol {
+    name_builder.build_cgu_name(LOCAL_CRATE, &["crate"], Some("mlir"))
+}
+
+/// Returns the index of the path selected with `-Zmlir-only-functions` that `mono_item` is an
+/// instance of, if any.
+fn mlir_only_fn_index<'tcx>(tcx: TyCtxt<'tcx>, mono_item: MonoItem<'tcx>) -> Option<usize> {
+    let paths = &tcx.sess.opts.unstable_opts.mlir_only_functions;
+    if paths.is_empty() {
+        return None;
+    }
+    match mono_item {
+        MonoItem::Fn(instance) => {
+            let path = with_no_trimmed_paths!(tcx.def_path_str(instance.def_id()));
+            paths.iter().position(|selected| *selected == path)
+        }
+        MonoItem::Static(_) | MonoItem::GlobalAsm(_) => None,
+    }
+}
+
 fn mono_item_linkage_and_visibility<'tcx>(
     tcx: TyCtxt<'tcx>,
     mono_item: &MonoItem<'tcx>,
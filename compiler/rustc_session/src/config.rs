@@ -510,6 +510,9 @@ pub enum OutputType {
     Assembly,
     LlvmAssembly,
     Mir,
+    /// Textual MLIR, written alongside MLIR bytecode (`.mlirbc`). Only supported by the MLIR
+    /// codegen backend.
+    Mlir,
     Metadata,
     Object,
     Exe,
@@ -540,6 +543,7 @@ fn is_compatible_with_codegen_units_and_single_output_file(&self) -> bool {
             | OutputType::Assembly
             | OutputType::LlvmAssembly
             | OutputType::Mir
+            | OutputType::Mlir
             | OutputType::Object => false,
         }
     }
@@ -551,6 +555,7 @@ pub fn shorthand(&self) -> &'static str {
             OutputType::Assembly => "asm",
             OutputType::LlvmAssembly => "llvm-ir",
             OutputType::Mir => "mir",
+            OutputType::Mlir => "mlir",
             OutputType::Object => "obj",
             OutputType::Metadata => "metadata",
             OutputType::Exe => "link",
@@ -563,6 +568,7 @@ fn from_shorthand(shorthand: &str) -> Option<Self> {
             "asm" => OutputType::Assembly,
             "llvm-ir" => OutputType::LlvmAssembly,
             "mir" => OutputType::Mir,
+            "mlir" => OutputType::Mlir,
             "llvm-bc" => OutputType::Bitcode,
             "thin-link-bitcode" => OutputType::ThinLinkBitcode,
             "obj" => OutputType::Object,
@@ -575,12 +581,13 @@ fn from_shorthand(shorthand: &str) -> Option<Self> {
 
     fn shorthands_display() -> String {
         format!(
-            "`{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`",
+            "`{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}`",
             OutputType::Bitcode.shorthand(),
             OutputType::ThinLinkBitcode.shorthand(),
             OutputType::Assembly.shorthand(),
             OutputType::LlvmAssembly.shorthand(),
             OutputType::Mir.shorthand(),
+            OutputType::Mlir.shorthand(),
             OutputType::Object.shorthand(),
             OutputType::Metadata.shorthand(),
             OutputType::Exe.shorthand(),
@@ -595,6 +602,7 @@ pub fn extension(&self) -> &'static str {
             OutputType::Assembly => "s",
             OutputType::LlvmAssembly => "ll",
             OutputType::Mir => "mir",
+            OutputType::Mlir => "mlir",
             OutputType::Object => "o",
             OutputType::Metadata => "rmeta",
             OutputType::DepInfo => "d",
@@ -607,6 +615,7 @@ pub fn is_text_output(&self) -> bool {
             OutputType::Assembly
             | OutputType::LlvmAssembly
             | OutputType::Mir
+            | OutputType::Mlir
             | OutputType::DepInfo => true,
             OutputType::Bitcode
             | OutputType::ThinLinkBitcode
@@ -700,6 +709,7 @@ pub fn should_codegen(&self) -> bool {
             | OutputType::Assembly
             | OutputType::LlvmAssembly
             | OutputType::Mir
+            | OutputType::Mlir
             | OutputType::Object
             | OutputType::Exe => true,
             OutputType::Metadata | OutputType::DepInfo => false,
@@ -714,6 +724,7 @@ pub fn should_link(&self) -> bool {
             | OutputType::Assembly
             | OutputType::LlvmAssembly
             | OutputType::Mir
+            | OutputType::Mlir
             | OutputType::Metadata
             | OutputType::Object
             | OutputType::DepInfo => false,
@@ -1512,7 +1523,7 @@ pub fn rustc_short_optgroups() -> Vec<RustcOptGroup> {
             "emit",
             "Comma separated list of types of output for \
              the compiler to emit",
-            "[asm|llvm-bc|llvm-ir|obj|metadata|link|dep-info|mir]",
+            "[asm|llvm-bc|llvm-ir|obj|metadata|link|dep-info|mir|mlir]",
         ),
         opt::multi_s(
             "",
@@ -2568,6 +2579,12 @@ pub fn build_session_options(early_dcx: &mut EarlyDiagCtxt, matches: &getopts::M
         }
     }
 
+    if !unstable_opts.mlir_only_functions.is_empty()
+        && unstable_opts.codegen_backend.as_deref() != Some("mlir")
+    {
+        early_dcx.early_fatal("`-Zmlir-only-functions` requires `-Zcodegen-backend=mlir`")
+    }
+
     if !nightly_options::is_unstable_enabled(matches)
         && cg.force_frame_pointers == FramePointer::NonLeaf
     {
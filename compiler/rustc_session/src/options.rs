@@ -1899,6 +1899,28 @@ pub(crate) fn parse_mir_include_spans(slot: &mut MirIncludeSpans, v: Option<&str
     #[rustc_lint_opt_deny_field_access("use `Session::mir_opt_level` instead of this field")]
     mir_opt_level: Option<usize> = (None, parse_opt_number, [TRACKED],
         "MIR optimization level (0-4; default: 1 in non optimized builds and 2 in optimized builds)"),
+    mlir_dump_after: Vec<String> = (Vec::new(), parse_comma_list, [UNTRACKED],
+        "dump the MLIR of each codegen unit after the given MLIR backend stages or passes \
+        (`rust`, `rust-passes`, `lower`, `raise-loops`, `vectorize`, `mlir-passes`, an MLIR pass \
+        name like `convert-func-to-llvm`, or `all`), as text and bytecode, into the output \
+        directory"),
+    mlir_only_functions: Vec<String> = (Vec::new(), parse_comma_list, [TRACKED],
+        "compile only the functions at the given comma separated paths (e.g. `kernels::saxpy`) \
+        with the MLIR backend, and everything else with LLVM; selected functions that are copied \
+        into the codegen units of their users, like `#[inline(always)]` ones, are compiled by \
+        the backend of each user; requires `-Zcodegen-backend=mlir`"),
+    mlir_passes: Option<String> = (None, parse_opt_string, [TRACKED],
+        "the MLIR pass pipeline the MLIR backend runs on the `rust` dialect before lowering it, \
+        and again before lowering to the LLVM dialect, in MLIR's textual pipeline syntax without \
+        the enclosing `builtin.module(...)`, e.g. `canonicalize,cse,func.func(affine-loop-tile)` \
+        (default: derived from `-Copt-level`)"),
+    mlir_raise_loops: bool = (false, parse_bool, [TRACKED],
+        "raise natural loops to `scf.while`, `scf.for` and `affine.for` in the MLIR backend, so \
+        that MLIR loop transformations apply to them (default: no)"),
+    mlir_vectorize: bool = (false, parse_bool, [TRACKED],
+        "vectorize element-wise loops over slices with the MLIR `vector` dialect in the MLIR \
+        backend, for the vector width of the enabled target features; implies \
+        `-Zmlir-raise-loops` (default: no)"),
     move_size_limit: Option<usize> = (None, parse_opt_number, [TRACKED],
         "the size at which the `large_assignments` lint starts to be emitted"),
     mutable_noalias: bool = (true, parse_bool, [TRACKED],
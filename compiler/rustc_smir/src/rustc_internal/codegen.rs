@@ -0,0 +1,219 @@
+//! Bridge between [`stable_mir::codegen::CodegenBackend`] and the compiler's own
+//! [`CodegenBackend`] trait.
+//!
+//! A backend written against the stable MIR can be registered with the compiler driver by
+//! wrapping it with [`backend`], e.g., from `__rustc_codegen_backend` or
+//! `Config::make_codegen_backend`.
+
+use std::any::Any;
+use std::fs;
+
+use rustc_ast::expand::allocator::{
+    ALLOCATOR_METHODS, AllocatorKind, AllocatorTy, NO_ALLOC_SHIM_IS_UNSTABLE,
+    alloc_error_handler_name, default_fn_name, global_fn_name,
+};
+use rustc_codegen_ssa::back::archive::ArArchiveBuilderBuilder;
+use rustc_codegen_ssa::back::link::link_binary;
+use rustc_codegen_ssa::back::metadata::create_compressed_metadata_file;
+use rustc_codegen_ssa::back::write::{CompiledModules, produce_final_output_artifacts};
+use rustc_codegen_ssa::base::allocator_kind_for_codegen;
+use rustc_codegen_ssa::traits::CodegenBackend;
+use rustc_codegen_ssa::{CodegenResults, CompiledModule, CrateInfo, ModuleKind};
+use rustc_data_structures::fx::FxIndexMap;
+use rustc_errors::ErrorGuaranteed;
+use rustc_hir::def_id::LOCAL_CRATE;
+use rustc_metadata::EncodedMetadata;
+use rustc_middle::bug;
+use rustc_middle::dep_graph::{WorkProduct, WorkProductId};
+use rustc_middle::middle::exported_symbols::metadata_symbol_name;
+use rustc_middle::mir::mono::{CodegenUnitNameBuilder, Visibility};
+use rustc_middle::ty::TyCtxt;
+use rustc_session::Session;
+use rustc_session::config::{OomStrategy, OutputFilenames, OutputType};
+use stable_mir::codegen::{AllocatorShim, ObjectFile, ShimFunction, ShimTy};
+
+use crate::rustc_smir::{Stable, Tables};
+
+/// Turn a code generation backend that is written against the stable MIR into one that can be
+/// used by the compiler.
+pub fn backend<B>(backend: B) -> Box<dyn CodegenBackend>
+where
+    B: stable_mir::codegen::CodegenBackend + 'static,
+{
+    Box::new(StableCodegenBackend(backend))
+}
+
+struct StableCodegenBackend<B>(B);
+
+/// The result of `codegen_crate`, consumed by `join_codegen`.
+struct OngoingCodegen {
+    compiled_modules: CompiledModules,
+    metadata_module: Option<CompiledModule>,
+    metadata: EncodedMetadata,
+    crate_info: CrateInfo,
+}
+
+impl<B> CodegenBackend for StableCodegenBackend<B>
+where
+    B: stable_mir::codegen::CodegenBackend + 'static,
+{
+    fn locale_resource(&self) -> &'static str {
+        // The bridge has no translatable diagnostics of its own, and the ones of linking belong to
+        // `rustc_codegen_ssa`, whose resource is always loaded by the driver.
+        ""
+    }
+
+    fn codegen_crate<'tcx>(
+        &self,
+        tcx: TyCtxt<'tcx>,
+        metadata: EncodedMetadata,
+        need_metadata_module: bool,
+    ) -> Box<dyn Any> {
+        let objects = super::run(tcx, || self.0.codegen_crate(stable_mir::codegen_units()))
+            .unwrap()
+            .unwrap_or_else(|err| tcx.dcx().fatal(format!("stable MIR codegen failed: {err}")));
+        let modules = objects
+            .into_iter()
+            .map(|object| write_object(tcx, object, ModuleKind::Regular))
+            .collect();
+
+        let allocator_module = allocator_kind_for_codegen(tcx).map(|kind| {
+            let object = super::run(tcx, || {
+                let shim = super::with_tables(|tables| allocator_shim(tables, tcx, kind));
+                self.0.codegen_allocator(shim)
+            })
+            .unwrap()
+            .unwrap_or_else(|err| {
+                tcx.dcx().fatal(format!("stable MIR codegen of the allocator shim failed: {err}"))
+            });
+            write_object(tcx, object, ModuleKind::Allocator)
+        });
+        let metadata_module = need_metadata_module.then(|| write_metadata(tcx, &metadata));
+        let target_cpu =
+            tcx.sess.opts.cg.target_cpu.clone().unwrap_or_else(|| tcx.sess.target.cpu.to_string());
+
+        Box::new(OngoingCodegen {
+            compiled_modules: CompiledModules { modules, allocator_module },
+            metadata_module,
+            metadata,
+            crate_info: CrateInfo::new(tcx, target_cpu),
+        })
+    }
+
+    fn join_codegen(
+        &self,
+        ongoing_codegen: Box<dyn Any>,
+        sess: &Session,
+        outputs: &OutputFilenames,
+    ) -> (CodegenResults, FxIndexMap<WorkProductId, WorkProduct>) {
+        let OngoingCodegen { compiled_modules, metadata_module, metadata, crate_info } =
+            *ongoing_codegen
+                .downcast::<OngoingCodegen>()
+                .expect("Expected StableCodegenBackend's OngoingCodegen, found Box<Any>");
+        sess.dcx().abort_if_errors();
+
+        produce_final_output_artifacts(sess, &compiled_modules, outputs);
+
+        let CompiledModules { modules, allocator_module } = compiled_modules;
+        let codegen_results =
+            CodegenResults { modules, allocator_module, metadata_module, metadata, crate_info };
+        (codegen_results, FxIndexMap::default())
+    }
+
+    fn link(
+        &self,
+        sess: &Session,
+        codegen_results: CodegenResults,
+        outputs: &OutputFilenames,
+    ) -> Result<(), ErrorGuaranteed> {
+        link_binary(sess, &ArArchiveBuilderBuilder, &codegen_results, outputs)
+    }
+}
+
+/// Describe the allocator shim the same way `rustc_codegen_llvm` generates it.
+fn allocator_shim(tables: &mut Tables<'_>, tcx: TyCtxt<'_>, kind: AllocatorKind) -> AllocatorShim {
+    let mut functions = vec![];
+    if kind == AllocatorKind::Default {
+        for method in ALLOCATOR_METHODS {
+            let mut inputs = vec![];
+            for input in method.inputs {
+                match input.ty {
+                    AllocatorTy::Layout => inputs.extend([ShimTy::Usize, ShimTy::Usize]),
+                    AllocatorTy::Ptr => inputs.push(ShimTy::Ptr),
+                    AllocatorTy::Usize => inputs.push(ShimTy::Usize),
+                    AllocatorTy::ResultPtr | AllocatorTy::Unit => bug!("invalid allocator arg"),
+                }
+            }
+            let output = match method.output {
+                AllocatorTy::ResultPtr => Some(ShimTy::Ptr),
+                AllocatorTy::Unit => None,
+                AllocatorTy::Layout | AllocatorTy::Usize | AllocatorTy::Ptr => {
+                    bug!("invalid allocator output")
+                }
+            };
+            functions.push(ShimFunction {
+                name: global_fn_name(method.name),
+                callee: default_fn_name(method.name),
+                inputs,
+                output,
+                no_return: false,
+            });
+        }
+    }
+    functions.push(ShimFunction {
+        name: "__rust_alloc_error_handler".to_string(),
+        callee: alloc_error_handler_name(tcx.alloc_error_handler_kind(()).unwrap()).to_string(),
+        // size, align
+        inputs: vec![ShimTy::Usize, ShimTy::Usize],
+        output: None,
+        no_return: true,
+    });
+
+    AllocatorShim {
+        name: CodegenUnitNameBuilder::new(tcx)
+            .build_cgu_name(LOCAL_CRATE, ["crate"], Some("allocator"))
+            .to_string(),
+        functions,
+        statics: vec![
+            (OomStrategy::SYMBOL.to_string(), tcx.sess.opts.unstable_opts.oom.should_panic()),
+            (NO_ALLOC_SHIM_IS_UNSTABLE.to_string(), 0),
+        ],
+        visibility: Visibility::from(tcx.sess.default_visibility()).stable(tables),
+    }
+}
+
+fn write_object(tcx: TyCtxt<'_>, object: ObjectFile, kind: ModuleKind) -> CompiledModule {
+    let path = tcx.output_filenames(()).temp_path(OutputType::Object, Some(&object.name));
+    if let Err(err) = fs::write(&path, object.bytes) {
+        tcx.dcx().fatal(format!("error writing object file `{}`: {err}", path.display()));
+    }
+    CompiledModule {
+        name: object.name,
+        kind,
+        object: Some(path),
+        dwarf_object: None,
+        bytecode: None,
+        assembly: None,
+        llvm_ir: None,
+    }
+}
+
+fn write_metadata(tcx: TyCtxt<'_>, metadata: &EncodedMetadata) -> CompiledModule {
+    let name = CodegenUnitNameBuilder::new(tcx)
+        .build_cgu_name(LOCAL_CRATE, ["crate"], Some("metadata"))
+        .to_string();
+    let path = tcx.output_filenames(()).temp_path(OutputType::Metadata, Some(&name));
+    let object = create_compressed_metadata_file(tcx.sess, metadata, &metadata_symbol_name(tcx));
+    if let Err(err) = fs::write(&path, object) {
+        tcx.dcx().fatal(format!("error writing metadata object file: {err}"));
+    }
+    CompiledModule {
+        name,
+        kind: ModuleKind::Metadata,
+        object: Some(path),
+        dwarf_object: None,
+        bytecode: None,
+        assembly: None,
+        llvm_ir: None,
+    }
+}
@@ -10,7 +10,12 @@
 use stable_mir::abi::Layout;
 use stable_mir::mir::alloc::AllocId;
 use stable_mir::mir::mono::{Instance, MonoItem, StaticDef};
-use stable_mir::mir::{BinOp, Mutability, Place, ProjectionElem, Safety, UnOp};
+use stable_mir::mir::{
+    AggregateKind, AssertMessage, BinOp, BorrowKind, CastKind, ConstOperand, CoroutineDesugaring,
+    CoroutineKind, CoroutineSource, FakeBorrowKind, FakeReadCause, MutBorrowKind, Mutability,
+    NonDivergingIntrinsic, NullOp, Operand, Place, PointerCoercion, ProjectionElem, RetagKind,
+    Safety, UnOp, UnwindAction, UnwindTerminateReason, VarDebugInfoContents, VarDebugInfoFragment,
+};
 use stable_mir::ty::{
     Abi, AdtDef, Binder, BoundRegionKind, BoundTyKind, BoundVariableKind, ClosureKind, DynKind,
     ExistentialPredicate, ExistentialProjection, ExistentialTraitRef, FloatTy, FnSig,
@@ -596,6 +601,331 @@ fn internal<'tcx>(&self, _tables: &mut Tables<'_>, _tcx: TyCtxt<'tcx>) -> Self::
     }
 }
 
+impl RustcInternal for Operand {
+    type T<'tcx> = rustc_middle::mir::Operand<'tcx>;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        match self {
+            Operand::Copy(place) => rustc_middle::mir::Operand::Copy(place.internal(tables, tcx)),
+            Operand::Move(place) => rustc_middle::mir::Operand::Move(place.internal(tables, tcx)),
+            Operand::Constant(constant) => {
+                rustc_middle::mir::Operand::Constant(Box::new(constant.internal(tables, tcx)))
+            }
+        }
+    }
+}
+
+impl RustcInternal for ConstOperand {
+    type T<'tcx> = rustc_middle::mir::ConstOperand<'tcx>;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        rustc_middle::mir::ConstOperand {
+            span: self.span.internal(tables, tcx),
+            user_ty: self.user_ty.map(rustc_ty::UserTypeAnnotationIndex::from_usize),
+            const_: self.const_.internal(tables, tcx),
+        }
+    }
+}
+
+impl RustcInternal for FakeReadCause {
+    type T<'tcx> = rustc_middle::mir::FakeReadCause;
+
+    fn internal<'tcx>(&self, _tables: &mut Tables<'_>, _tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        // The closures and `let` statements are only used for diagnostics, and are not
+        // available in stable MIR.
+        match self {
+            FakeReadCause::ForMatchGuard => rustc_middle::mir::FakeReadCause::ForMatchGuard,
+            FakeReadCause::ForMatchedPlace(_) => {
+                rustc_middle::mir::FakeReadCause::ForMatchedPlace(None)
+            }
+            FakeReadCause::ForGuardBinding => rustc_middle::mir::FakeReadCause::ForGuardBinding,
+            FakeReadCause::ForLet(_) => rustc_middle::mir::FakeReadCause::ForLet(None),
+            FakeReadCause::ForIndex => rustc_middle::mir::FakeReadCause::ForIndex,
+        }
+    }
+}
+
+impl RustcInternal for RetagKind {
+    type T<'tcx> = rustc_middle::mir::RetagKind;
+
+    fn internal<'tcx>(&self, _tables: &mut Tables<'_>, _tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        match self {
+            RetagKind::FnEntry => rustc_middle::mir::RetagKind::FnEntry,
+            RetagKind::TwoPhase => rustc_middle::mir::RetagKind::TwoPhase,
+            RetagKind::Raw => rustc_middle::mir::RetagKind::Raw,
+            RetagKind::Default => rustc_middle::mir::RetagKind::Default,
+        }
+    }
+}
+
+impl RustcInternal for NonDivergingIntrinsic {
+    type T<'tcx> = rustc_middle::mir::NonDivergingIntrinsic<'tcx>;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        match self {
+            NonDivergingIntrinsic::Assume(op) => {
+                rustc_middle::mir::NonDivergingIntrinsic::Assume(op.internal(tables, tcx))
+            }
+            NonDivergingIntrinsic::CopyNonOverlapping(copy) => {
+                rustc_middle::mir::NonDivergingIntrinsic::CopyNonOverlapping(
+                    rustc_middle::mir::CopyNonOverlapping {
+                        src: copy.src.internal(tables, tcx),
+                        dst: copy.dst.internal(tables, tcx),
+                        count: copy.count.internal(tables, tcx),
+                    },
+                )
+            }
+        }
+    }
+}
+
+impl RustcInternal for AggregateKind {
+    type T<'tcx> = rustc_middle::mir::AggregateKind<'tcx>;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        match self {
+            AggregateKind::Array(ty) => {
+                rustc_middle::mir::AggregateKind::Array(ty.internal(tables, tcx))
+            }
+            AggregateKind::Tuple => rustc_middle::mir::AggregateKind::Tuple,
+            AggregateKind::Adt(def, variant, args, user_ty, active_field) => {
+                rustc_middle::mir::AggregateKind::Adt(
+                    def.0.internal(tables, tcx),
+                    variant.internal(tables, tcx),
+                    args.internal(tables, tcx),
+                    user_ty.map(rustc_ty::UserTypeAnnotationIndex::from_usize),
+                    active_field.map(rustc_target::abi::FieldIdx::from_usize),
+                )
+            }
+            AggregateKind::Closure(def, args) => rustc_middle::mir::AggregateKind::Closure(
+                def.0.internal(tables, tcx),
+                args.internal(tables, tcx),
+            ),
+            AggregateKind::Coroutine(def, args, _movability) => {
+                rustc_middle::mir::AggregateKind::Coroutine(
+                    def.0.internal(tables, tcx),
+                    args.internal(tables, tcx),
+                )
+            }
+            AggregateKind::RawPtr(ty, mutability) => rustc_middle::mir::AggregateKind::RawPtr(
+                ty.internal(tables, tcx),
+                mutability.internal(tables, tcx),
+            ),
+        }
+    }
+}
+
+impl RustcInternal for CastKind {
+    type T<'tcx> = rustc_middle::mir::CastKind;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        use rustc_middle::mir::{CastKind as InternalCastKind, CoercionSource};
+        use rustc_middle::ty::adjustment::PointerCoercion as InternalPointerCoercion;
+        // Whether a coercion was written as an `as` cast is only used for diagnostics.
+        match self {
+            CastKind::PointerExposeAddress => InternalCastKind::PointerExposeProvenance,
+            CastKind::PointerWithExposedProvenance => {
+                InternalCastKind::PointerWithExposedProvenance
+            }
+            CastKind::PointerCoercion(coercion) => InternalCastKind::PointerCoercion(
+                coercion.internal(tables, tcx),
+                CoercionSource::Implicit,
+            ),
+            CastKind::DynStar => InternalCastKind::PointerCoercion(
+                InternalPointerCoercion::DynStar,
+                CoercionSource::Implicit,
+            ),
+            CastKind::IntToInt => InternalCastKind::IntToInt,
+            CastKind::FloatToInt => InternalCastKind::FloatToInt,
+            CastKind::FloatToFloat => InternalCastKind::FloatToFloat,
+            CastKind::IntToFloat => InternalCastKind::IntToFloat,
+            CastKind::PtrToPtr => InternalCastKind::PtrToPtr,
+            CastKind::FnPtrToPtr => InternalCastKind::FnPtrToPtr,
+            CastKind::Transmute => InternalCastKind::Transmute,
+        }
+    }
+}
+
+impl RustcInternal for PointerCoercion {
+    type T<'tcx> = rustc_middle::ty::adjustment::PointerCoercion;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        use rustc_middle::ty::adjustment::PointerCoercion as InternalPointerCoercion;
+        match self {
+            PointerCoercion::ReifyFnPointer => InternalPointerCoercion::ReifyFnPointer,
+            PointerCoercion::UnsafeFnPointer => InternalPointerCoercion::UnsafeFnPointer,
+            PointerCoercion::ClosureFnPointer(safety) => {
+                InternalPointerCoercion::ClosureFnPointer(safety.internal(tables, tcx))
+            }
+            PointerCoercion::MutToConstPointer => InternalPointerCoercion::MutToConstPointer,
+            PointerCoercion::ArrayToPointer => InternalPointerCoercion::ArrayToPointer,
+            PointerCoercion::Unsize => InternalPointerCoercion::Unsize,
+        }
+    }
+}
+
+impl RustcInternal for NullOp {
+    type T<'tcx> = rustc_middle::mir::NullOp<'tcx>;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        match self {
+            NullOp::SizeOf => rustc_middle::mir::NullOp::SizeOf,
+            NullOp::AlignOf => rustc_middle::mir::NullOp::AlignOf,
+            NullOp::OffsetOf(indices) => rustc_middle::mir::NullOp::OffsetOf(
+                tcx.mk_offset_of_from_iter(indices.iter().map(|(variant, field)| {
+                    (variant.internal(tables, tcx), rustc_target::abi::FieldIdx::from_usize(*field))
+                })),
+            ),
+            NullOp::UbChecks => rustc_middle::mir::NullOp::UbChecks,
+        }
+    }
+}
+
+impl RustcInternal for BorrowKind {
+    type T<'tcx> = rustc_middle::mir::BorrowKind;
+
+    fn internal<'tcx>(&self, _tables: &mut Tables<'_>, _tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        use rustc_middle::mir::{FakeBorrowKind as InternalFake, MutBorrowKind as InternalMut};
+        match self {
+            BorrowKind::Shared => rustc_middle::mir::BorrowKind::Shared,
+            BorrowKind::Fake(kind) => rustc_middle::mir::BorrowKind::Fake(match kind {
+                FakeBorrowKind::Deep => InternalFake::Deep,
+                FakeBorrowKind::Shallow => InternalFake::Shallow,
+            }),
+            BorrowKind::Mut { kind } => rustc_middle::mir::BorrowKind::Mut {
+                kind: match kind {
+                    MutBorrowKind::Default => InternalMut::Default,
+                    MutBorrowKind::TwoPhaseBorrow => InternalMut::TwoPhaseBorrow,
+                    MutBorrowKind::ClosureCapture => InternalMut::ClosureCapture,
+                },
+            },
+        }
+    }
+}
+
+impl RustcInternal for AssertMessage {
+    type T<'tcx> = rustc_middle::mir::AssertMessage<'tcx>;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        use rustc_middle::mir::AssertKind;
+        match self {
+            AssertMessage::BoundsCheck { len, index } => AssertKind::BoundsCheck {
+                len: len.internal(tables, tcx),
+                index: index.internal(tables, tcx),
+            },
+            AssertMessage::Overflow(op, lhs, rhs) => AssertKind::Overflow(
+                op.internal(tables, tcx),
+                lhs.internal(tables, tcx),
+                rhs.internal(tables, tcx),
+            ),
+            AssertMessage::OverflowNeg(op) => AssertKind::OverflowNeg(op.internal(tables, tcx)),
+            AssertMessage::DivisionByZero(op) => {
+                AssertKind::DivisionByZero(op.internal(tables, tcx))
+            }
+            AssertMessage::RemainderByZero(op) => {
+                AssertKind::RemainderByZero(op.internal(tables, tcx))
+            }
+            AssertMessage::ResumedAfterReturn(kind) => {
+                AssertKind::ResumedAfterReturn(kind.internal(tables, tcx))
+            }
+            AssertMessage::ResumedAfterPanic(kind) => {
+                AssertKind::ResumedAfterPanic(kind.internal(tables, tcx))
+            }
+            AssertMessage::MisalignedPointerDereference { required, found } => {
+                AssertKind::MisalignedPointerDereference {
+                    required: required.internal(tables, tcx),
+                    found: found.internal(tables, tcx),
+                }
+            }
+        }
+    }
+}
+
+impl RustcInternal for CoroutineKind {
+    type T<'tcx> = rustc_hir::CoroutineKind;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        use rustc_hir::{
+            CoroutineDesugaring as InternalDesugaring, CoroutineSource as InternalSource,
+        };
+        match self {
+            CoroutineKind::Desugared(desugaring, source) => rustc_hir::CoroutineKind::Desugared(
+                match desugaring {
+                    CoroutineDesugaring::Async => InternalDesugaring::Async,
+                    CoroutineDesugaring::Gen => InternalDesugaring::Gen,
+                    CoroutineDesugaring::AsyncGen => InternalDesugaring::AsyncGen,
+                },
+                match source {
+                    CoroutineSource::Block => InternalSource::Block,
+                    CoroutineSource::Closure => InternalSource::Closure,
+                    CoroutineSource::Fn => InternalSource::Fn,
+                },
+            ),
+            CoroutineKind::Coroutine(movability) => {
+                rustc_hir::CoroutineKind::Coroutine(movability.internal(tables, tcx))
+            }
+        }
+    }
+}
+
+impl RustcInternal for UnwindAction {
+    type T<'tcx> = rustc_middle::mir::UnwindAction;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        match self {
+            UnwindAction::Continue => rustc_middle::mir::UnwindAction::Continue,
+            UnwindAction::Unreachable => rustc_middle::mir::UnwindAction::Unreachable,
+            UnwindAction::Terminate(reason) => {
+                rustc_middle::mir::UnwindAction::Terminate(reason.internal(tables, tcx))
+            }
+            UnwindAction::Cleanup(bb) => rustc_middle::mir::UnwindAction::Cleanup(
+                rustc_middle::mir::BasicBlock::from_usize(*bb),
+            ),
+        }
+    }
+}
+
+impl RustcInternal for UnwindTerminateReason {
+    type T<'tcx> = rustc_middle::mir::UnwindTerminateReason;
+
+    fn internal<'tcx>(&self, _tables: &mut Tables<'_>, _tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        match self {
+            UnwindTerminateReason::Abi => rustc_middle::mir::UnwindTerminateReason::Abi,
+            UnwindTerminateReason::InCleanup => rustc_middle::mir::UnwindTerminateReason::InCleanup,
+        }
+    }
+}
+
+impl RustcInternal for VarDebugInfoContents {
+    type T<'tcx> = rustc_middle::mir::VarDebugInfoContents<'tcx>;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        match self {
+            VarDebugInfoContents::Place(place) => {
+                rustc_middle::mir::VarDebugInfoContents::Place(place.internal(tables, tcx))
+            }
+            VarDebugInfoContents::Const(constant) => {
+                rustc_middle::mir::VarDebugInfoContents::Const(rustc_middle::mir::ConstOperand {
+                    span: constant.span.internal(tables, tcx),
+                    user_ty: constant.user_ty.map(rustc_ty::UserTypeAnnotationIndex::from_usize),
+                    const_: constant.const_.internal(tables, tcx),
+                })
+            }
+        }
+    }
+}
+
+impl RustcInternal for VarDebugInfoFragment {
+    type T<'tcx> = rustc_middle::mir::VarDebugInfoFragment<'tcx>;
+
+    fn internal<'tcx>(&self, tables: &mut Tables<'_>, tcx: TyCtxt<'tcx>) -> Self::T<'tcx> {
+        rustc_middle::mir::VarDebugInfoFragment {
+            ty: self.ty.internal(tables, tcx),
+            projection: self.projection.internal(tables, tcx),
+        }
+    }
+}
+
 impl<T> RustcInternal for &T
 where
     T: RustcInternal,
@@ -23,8 +23,10 @@
 use crate::rustc_smir::context::TablesWrapper;
 use crate::rustc_smir::{Stable, Tables};
 
+pub mod codegen;
 mod internal;
 pub mod pretty;
+pub mod transform;
 
 /// Convert an internal Rust compiler item into its stable counterpart, if one exists.
 ///
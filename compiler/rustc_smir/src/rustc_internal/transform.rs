@@ -0,0 +1,451 @@
+//! Replace the optimized MIR of the local crate with bodies that were transformed through the
+//! stable MIR.
+//!
+//! The transformation is installed by overriding the `optimized_mir` query, e.g., with
+//! `Config::override_queries`. The transformed bodies are the ones used by code generation.
+
+use std::sync::{LazyLock, RwLock};
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_hir::def_id::LocalDefId;
+use rustc_middle::mir;
+use rustc_middle::ty::TyCtxt;
+use rustc_middle::util::Providers;
+use rustc_session::Session;
+use rustc_span::source_map::Spanned;
+use rustc_span::{Span, Symbol};
+use stable_mir::mir::{
+    Body, Rvalue, Statement, StatementKind, Terminator, TerminatorKind, UnwindAction,
+};
+use stable_mir::{CrateItem, Error};
+
+use super::RustcInternal;
+use crate::rustc_smir::{Stable, Tables};
+
+/// A transformation of the optimized MIR of the functions of the local crate.
+pub trait MirTransform {
+    /// Return the body that should be used instead of `body`, the optimized MIR of `item`, if
+    /// any. All stable MIR APIs can be used from the transformation.
+    fn transform(item: CrateItem, body: &Body) -> Option<Body>;
+}
+
+/// The `optimized_mir` provider that was overridden in each compiler session, keyed by the
+/// address of the session.
+///
+/// The entry of a session is replaced when its queries are overridden, so an entry left by a
+/// session that has ended is never used by the next one that lives at the same address.
+static DEFAULT_OPTIMIZED_MIR: LazyLock<
+    RwLock<FxHashMap<usize, fn(TyCtxt<'_>, LocalDefId) -> &mir::Body<'_>>>,
+> = LazyLock::new(Default::default);
+
+fn session_key(sess: &Session) -> usize {
+    sess as *const Session as usize
+}
+
+/// Return the function that must be used to override the compiler queries so that `T` is applied
+/// to the optimized MIR of every function of the local crate.
+///
+/// The transformation is only applied in the compiler sessions whose queries are overridden with
+/// the returned function, so different compilations of the same process can use different ones.
+///
+/// Bodies that cannot be represented in the stable MIR, e.g., the ones with inline assembly or
+/// coverage instrumentation, are left untouched. Returning a body that cannot be converted back,
+/// e.g., one with new inline assembly, is a fatal error.
+///
+/// The transformation runs in a stable MIR context of its own. Requesting a body that has not been
+/// optimized yet while another stable MIR context is running is a fatal error.
+///
+/// # Example
+///
+/// ```ignore(needs-extern-crate)
+/// config.override_queries = Some(rustc_internal::transform::transform_optimized_mir::<T>());
+/// ```
+pub fn transform_optimized_mir<T: MirTransform>() -> fn(&Session, &mut Providers) {
+    override_queries::<T>
+}
+
+fn override_queries<T: MirTransform>(sess: &Session, providers: &mut Providers) {
+    DEFAULT_OPTIMIZED_MIR.write().unwrap().insert(session_key(sess), providers.optimized_mir);
+    providers.optimized_mir = optimized_mir::<T>;
+}
+
+fn optimized_mir<T: MirTransform>(tcx: TyCtxt<'_>, def: LocalDefId) -> &mir::Body<'_> {
+    let default = DEFAULT_OPTIMIZED_MIR.read().unwrap()[&session_key(tcx.sess)];
+    let body = default(tcx, def);
+    if !is_supported(body) {
+        return body;
+    }
+
+    let transformed = super::run(tcx, || {
+        let (item, stable_body) =
+            super::with_tables(|tables| (tables.crate_item(def.to_def_id()), body.stable(tables)));
+        let Some(new_body) = T::transform(item, &stable_body) else { return Ok(None) };
+        super::with_tables(|tables| lower_body(tables, tcx, body, &new_body)).map(Some)
+    })
+    .and_then(|transformed| transformed)
+    .unwrap_or_else(|err| {
+        tcx.dcx().fatal(format!(
+            "cannot transform the optimized MIR of `{}`: {err}",
+            tcx.def_path_str(def)
+        ))
+    });
+    match transformed {
+        Some(new_body) => tcx.arena.alloc(new_body),
+        None => body,
+    }
+}
+
+/// Whether the body can be converted to the stable MIR and back.
+fn is_supported(body: &mir::Body<'_>) -> bool {
+    body.basic_blocks.iter().all(|block| {
+        let supported_terminator = !matches!(
+            block.terminator().kind,
+            mir::TerminatorKind::InlineAsm { .. } | mir::TerminatorKind::TailCall { .. }
+        );
+        supported_terminator
+            && block.statements.iter().all(|statement| match &statement.kind {
+                mir::StatementKind::Coverage(_) => false,
+                mir::StatementKind::Assign(assign) => !matches!(
+                    &assign.1,
+                    mir::Rvalue::Aggregate(kind, _)
+                        if matches!(**kind, mir::AggregateKind::CoroutineClosure(..))
+                ),
+                _ => true,
+            })
+    })
+}
+
+/// Build the internal body that corresponds to `new_body`.
+///
+/// The parts of the body that the stable MIR does not expose, such as the source scopes or the
+/// coroutine layout, are taken from `original`. The scope of each statement and terminator is
+/// recovered from its span, and falls back to the outermost scope. The blocks that can only be
+/// reached by unwinding are marked as cleanup blocks.
+///
+/// Terminators that the transformation left unchanged are kept as they are. The fields of the
+/// other ones that the stable MIR does not expose, e.g., the span of the function of a call, are
+/// taken from an original terminator of the same kind with the same span, if there is one.
+fn lower_body<'tcx>(
+    tables: &mut Tables<'_>,
+    tcx: TyCtxt<'tcx>,
+    original: &mir::Body<'tcx>,
+    new_body: &Body,
+) -> Result<mir::Body<'tcx>, Error> {
+    let mut scopes = FxHashMap::default();
+    let mut terminators = FxHashMap::<_, Vec<_>>::default();
+    for block in original.basic_blocks.iter() {
+        for statement in &block.statements {
+            scopes.entry(statement.source_info.span).or_insert(statement.source_info.scope);
+        }
+        let terminator = block.terminator();
+        scopes.entry(terminator.source_info.span).or_insert(terminator.source_info.scope);
+        terminators.entry(terminator.source_info.span).or_default().push(terminator.clone());
+    }
+    let mut lowering = BodyLowering { tables, tcx, scopes, terminators };
+    let cleanup = cleanup_blocks(new_body);
+
+    let mut body = original.clone();
+    *body.basic_blocks_mut() = new_body
+        .blocks
+        .iter()
+        .zip(cleanup)
+        .map(|(block, is_cleanup)| lowering.lower_block(block, is_cleanup))
+        .collect::<Result<_, _>>()?;
+    body.local_decls = new_body
+        .locals()
+        .iter()
+        .map(|decl| {
+            let span = decl.span.internal(lowering.tables, tcx);
+            let mut local_decl = mir::LocalDecl::with_source_info(
+                decl.ty.internal(lowering.tables, tcx),
+                mir::SourceInfo { span, scope: lowering.scope(span) },
+            );
+            local_decl.mutability = decl.mutability.internal(lowering.tables, tcx);
+            local_decl
+        })
+        .collect();
+    body.var_debug_info = new_body
+        .var_debug_info
+        .iter()
+        .map(|info| mir::VarDebugInfo {
+            name: Symbol::intern(&info.name),
+            source_info: mir::SourceInfo {
+                span: info.source_info.span.internal(lowering.tables, tcx),
+                scope: mir::SourceScope::from_usize(info.source_info.scope),
+            },
+            composite: info
+                .composite
+                .as_ref()
+                .map(|fragment| Box::new(fragment.internal(lowering.tables, tcx))),
+            value: info.value.internal(lowering.tables, tcx),
+            argument_index: info.argument_index,
+        })
+        .collect();
+    body.spread_arg = new_body.spread_arg().map(mir::Local::from_usize);
+    Ok(body)
+}
+
+/// Find the blocks that are reachable from the unwind edges of the body.
+fn cleanup_blocks(body: &Body) -> Vec<bool> {
+    let mut cleanup = vec![false; body.blocks.len()];
+    let mut worklist: Vec<_> = body
+        .blocks
+        .iter()
+        .filter_map(|block| match &block.terminator.kind {
+            TerminatorKind::Drop { unwind: UnwindAction::Cleanup(bb), .. }
+            | TerminatorKind::Call { unwind: UnwindAction::Cleanup(bb), .. }
+            | TerminatorKind::Assert { unwind: UnwindAction::Cleanup(bb), .. }
+            | TerminatorKind::InlineAsm { unwind: UnwindAction::Cleanup(bb), .. } => Some(*bb),
+            _ => None,
+        })
+        .collect();
+    while let Some(bb) = worklist.pop() {
+        if !std::mem::replace(&mut cleanup[bb], true) {
+            worklist.extend(body.blocks[bb].terminator.successors());
+        }
+    }
+    cleanup
+}
+
+struct BodyLowering<'a, 'b, 'tcx> {
+    tables: &'a mut Tables<'b>,
+    tcx: TyCtxt<'tcx>,
+    scopes: FxHashMap<Span, mir::SourceScope>,
+    /// The terminators of the original body, by span.
+    terminators: FxHashMap<Span, Vec<mir::Terminator<'tcx>>>,
+}
+
+impl<'tcx> BodyLowering<'_, '_, 'tcx> {
+    fn scope(&self, span: Span) -> mir::SourceScope {
+        self.scopes.get(&span).copied().unwrap_or(mir::OUTERMOST_SOURCE_SCOPE)
+    }
+
+    fn source_info(&mut self, span: stable_mir::ty::Span) -> mir::SourceInfo {
+        let span = span.internal(self.tables, self.tcx);
+        mir::SourceInfo { span, scope: self.scope(span) }
+    }
+
+    fn lower_block(
+        &mut self,
+        block: &stable_mir::mir::BasicBlock,
+        is_cleanup: bool,
+    ) -> Result<mir::BasicBlockData<'tcx>, Error> {
+        Ok(mir::BasicBlockData {
+            statements: block
+                .statements
+                .iter()
+                .map(|statement| self.lower_statement(statement))
+                .collect::<Result<_, _>>()?,
+            terminator: Some(self.lower_terminator(&block.terminator, is_cleanup)?),
+            is_cleanup,
+        })
+    }
+
+    fn lower_statement(&mut self, statement: &Statement) -> Result<mir::Statement<'tcx>, Error> {
+        Ok(mir::Statement {
+            source_info: self.source_info(statement.span),
+            kind: self.lower_statement_kind(&statement.kind)?,
+        })
+    }
+
+    fn lower_statement_kind(
+        &mut self,
+        kind: &StatementKind,
+    ) -> Result<mir::StatementKind<'tcx>, Error> {
+        let (tables, tcx) = (&mut *self.tables, self.tcx);
+        Ok(match kind {
+            StatementKind::Assign(place, rvalue) => {
+                let place = place.internal(tables, tcx);
+                mir::StatementKind::Assign(Box::new((place, self.lower_rvalue(rvalue)?)))
+            }
+            StatementKind::FakeRead(cause, place) => mir::StatementKind::FakeRead(Box::new((
+                cause.internal(tables, tcx),
+                place.internal(tables, tcx),
+            ))),
+            StatementKind::SetDiscriminant { place, variant_index } => {
+                mir::StatementKind::SetDiscriminant {
+                    place: Box::new(place.internal(tables, tcx)),
+                    variant_index: variant_index.internal(tables, tcx),
+                }
+            }
+            StatementKind::Deinit(place) => {
+                mir::StatementKind::Deinit(Box::new(place.internal(tables, tcx)))
+            }
+            StatementKind::StorageLive(local) => {
+                mir::StatementKind::StorageLive(mir::Local::from_usize(*local))
+            }
+            StatementKind::StorageDead(local) => {
+                mir::StatementKind::StorageDead(mir::Local::from_usize(*local))
+            }
+            StatementKind::Retag(kind, place) => mir::StatementKind::Retag(
+                kind.internal(tables, tcx),
+                Box::new(place.internal(tables, tcx)),
+            ),
+            StatementKind::PlaceMention(place) => {
+                mir::StatementKind::PlaceMention(Box::new(place.internal(tables, tcx)))
+            }
+            // User type ascriptions are only checked by borrowck, and are removed from the MIR
+            // before it is optimized, so dropping them does not change the body.
+            StatementKind::AscribeUserType { .. } => mir::StatementKind::Nop,
+            StatementKind::Coverage(_) => {
+                return Err(Error::new("coverage statements cannot be converted back".into()));
+            }
+            StatementKind::Intrinsic(intrinsic) => {
+                mir::StatementKind::Intrinsic(Box::new(intrinsic.internal(tables, tcx)))
+            }
+            StatementKind::ConstEvalCounter => mir::StatementKind::ConstEvalCounter,
+            StatementKind::Nop => mir::StatementKind::Nop,
+        })
+    }
+
+    fn lower_rvalue(&mut self, rvalue: &Rvalue) -> Result<mir::Rvalue<'tcx>, Error> {
+        let (tables, tcx) = (&mut *self.tables, self.tcx);
+        Ok(match rvalue {
+            Rvalue::AddressOf(mutability, place) => {
+                mir::Rvalue::RawPtr(mutability.internal(tables, tcx), place.internal(tables, tcx))
+            }
+            Rvalue::Aggregate(kind, operands) => mir::Rvalue::Aggregate(
+                Box::new(kind.internal(tables, tcx)),
+                operands.iter().map(|op| op.internal(tables, tcx)).collect(),
+            ),
+            Rvalue::BinaryOp(op, lhs, rhs) => mir::Rvalue::BinaryOp(
+                op.internal(tables, tcx),
+                Box::new((lhs.internal(tables, tcx), rhs.internal(tables, tcx))),
+            ),
+            Rvalue::Cast(kind, op, ty) => mir::Rvalue::Cast(
+                kind.internal(tables, tcx),
+                op.internal(tables, tcx),
+                ty.internal(tables, tcx),
+            ),
+            Rvalue::CheckedBinaryOp(op, lhs, rhs) => {
+                let Some(checked_op) = op.internal(tables, tcx).wrapping_to_overflowing() else {
+                    return Err(Error::new(format!("`{op:?}` cannot be checked for overflow")));
+                };
+                mir::Rvalue::BinaryOp(
+                    checked_op,
+                    Box::new((lhs.internal(tables, tcx), rhs.internal(tables, tcx))),
+                )
+            }
+            Rvalue::CopyForDeref(place) => mir::Rvalue::CopyForDeref(place.internal(tables, tcx)),
+            Rvalue::Discriminant(place) => mir::Rvalue::Discriminant(place.internal(tables, tcx)),
+            Rvalue::Len(place) => mir::Rvalue::Len(place.internal(tables, tcx)),
+            Rvalue::Ref(region, kind, place) => mir::Rvalue::Ref(
+                region.internal(tables, tcx),
+                kind.internal(tables, tcx),
+                place.internal(tables, tcx),
+            ),
+            Rvalue::Repeat(op, count) => {
+                mir::Rvalue::Repeat(op.internal(tables, tcx), count.internal(tables, tcx))
+            }
+            Rvalue::ShallowInitBox(op, ty) => {
+                mir::Rvalue::ShallowInitBox(op.internal(tables, tcx), ty.internal(tables, tcx))
+            }
+            Rvalue::ThreadLocalRef(item) => mir::Rvalue::ThreadLocalRef(item.internal(tables, tcx)),
+            Rvalue::NullaryOp(op, ty) => {
+                mir::Rvalue::NullaryOp(op.internal(tables, tcx), ty.internal(tables, tcx))
+            }
+            Rvalue::UnaryOp(op, operand) => {
+                mir::Rvalue::UnaryOp(op.internal(tables, tcx), operand.internal(tables, tcx))
+            }
+            Rvalue::Use(op) => mir::Rvalue::Use(op.internal(tables, tcx)),
+        })
+    }
+
+    fn lower_terminator(
+        &mut self,
+        terminator: &Terminator,
+        is_cleanup: bool,
+    ) -> Result<mir::Terminator<'tcx>, Error> {
+        let source_info = self.source_info(terminator.span);
+        let (tables, tcx) = (&mut *self.tables, self.tcx);
+        let originals = self.terminators.get(&source_info.span).map_or(&[][..], Vec::as_slice);
+        if let Some(original) =
+            originals.iter().find(|original| original.stable(tables) == *terminator)
+        {
+            return Ok(original.clone());
+        }
+
+        let block = mir::BasicBlock::from_usize;
+        let kind = match &terminator.kind {
+            TerminatorKind::Goto { target } => mir::TerminatorKind::Goto { target: block(*target) },
+            TerminatorKind::SwitchInt { discr, targets } => mir::TerminatorKind::SwitchInt {
+                discr: discr.internal(tables, tcx),
+                targets: mir::SwitchTargets::new(
+                    targets.branches().map(|(value, target)| (value, block(target))),
+                    block(targets.otherwise()),
+                ),
+            },
+            TerminatorKind::Resume => mir::TerminatorKind::UnwindResume,
+            TerminatorKind::Abort => {
+                // The stable MIR does not tell why the abort terminates unwinding, so take the
+                // reason from the original terminator, or use the one rustc uses for the kind of
+                // block.
+                let reason = originals.iter().find_map(|original| match original.kind {
+                    mir::TerminatorKind::UnwindTerminate(reason) => Some(reason),
+                    _ => None,
+                });
+                mir::TerminatorKind::UnwindTerminate(reason.unwrap_or(if is_cleanup {
+                    mir::UnwindTerminateReason::InCleanup
+                } else {
+                    mir::UnwindTerminateReason::Abi
+                }))
+            }
+            TerminatorKind::Return => mir::TerminatorKind::Return,
+            TerminatorKind::Unreachable => mir::TerminatorKind::Unreachable,
+            TerminatorKind::Drop { place, target, unwind: drop_unwind } => {
+                let replace = originals.iter().any(|original| {
+                    matches!(original.kind, mir::TerminatorKind::Drop { replace: true, .. })
+                });
+                mir::TerminatorKind::Drop {
+                    place: place.internal(tables, tcx),
+                    target: block(*target),
+                    unwind: drop_unwind.internal(tables, tcx),
+                    replace,
+                }
+            }
+            TerminatorKind::Call { func, args, destination, target, unwind: call_unwind } => {
+                let original = originals.iter().find_map(|original| match &original.kind {
+                    mir::TerminatorKind::Call { args, call_source, fn_span, .. } => {
+                        Some((args, *call_source, *fn_span))
+                    }
+                    _ => None,
+                });
+                let (call_source, fn_span) = original
+                    .map_or((mir::CallSource::Normal, source_info.span), |(_, source, span)| {
+                        (source, span)
+                    });
+                mir::TerminatorKind::Call {
+                    func: func.internal(tables, tcx),
+                    args: args
+                        .iter()
+                        .enumerate()
+                        .map(|(i, arg)| Spanned {
+                            node: arg.internal(tables, tcx),
+                            span: original
+                                .and_then(|(args, ..)| args.get(i))
+                                .map_or(fn_span, |arg| arg.span),
+                        })
+                        .collect(),
+                    destination: destination.internal(tables, tcx),
+                    target: target.map(block),
+                    unwind: call_unwind.internal(tables, tcx),
+                    call_source,
+                    fn_span,
+                }
+            }
+            TerminatorKind::Assert { cond, expected, msg, target, unwind: assert_unwind } => {
+                mir::TerminatorKind::Assert {
+                    cond: cond.internal(tables, tcx),
+                    expected: *expected,
+                    msg: Box::new(msg.internal(tables, tcx)),
+                    target: block(*target),
+                    unwind: assert_unwind.internal(tables, tcx),
+                }
+            }
+            TerminatorKind::InlineAsm { .. } => {
+                return Err(Error::new("inline assembly cannot be converted back".into()));
+            }
+        };
+        Ok(mir::Terminator { source_info, kind })
+    }
+}
@@ -9,6 +9,7 @@
 use std::iter;
 
 use rustc_abi::HasDataLayout;
+use rustc_data_structures::fx::FxIndexSet;
 use rustc_hir::LangItem;
 use rustc_middle::ty::layout::{
     FnAbiOf, FnAbiOfHelpers, HasParamEnv, HasTyCtxt, LayoutOf, LayoutOfHelpers,
@@ -58,6 +59,34 @@ fn all_local_items(&self) -> stable_mir::CrateItems {
         tables.tcx.mir_keys(()).iter().map(|item| tables.crate_item(item.to_def_id())).collect()
     }
 
+    fn all_mono_items(&self) -> Vec<stable_mir::mir::mono::MonoItem> {
+        let mut tables = self.0.borrow_mut();
+        let tcx = tables.tcx;
+        let (_, codegen_units) = tcx.collect_and_partition_mono_items(());
+        // Items that are inlined into several codegen units are only listed once.
+        let items: FxIndexSet<_> =
+            codegen_units.iter().flat_map(|cgu| cgu.items().keys().copied()).collect();
+        items.iter().map(|item| item.stable(&mut *tables)).collect()
+    }
+
+    fn codegen_units(&self) -> Vec<stable_mir::mir::mono::CodegenUnit> {
+        let mut tables = self.0.borrow_mut();
+        let tcx = tables.tcx;
+        let (_, codegen_units) = tcx.collect_and_partition_mono_items(());
+        codegen_units
+            .iter()
+            .map(|cgu| stable_mir::mir::mono::CodegenUnit {
+                name: cgu.name().to_string(),
+                is_primary: cgu.is_primary(),
+                items: cgu
+                    .items_in_deterministic_order(tcx)
+                    .iter()
+                    .map(|(item, data)| (item.stable(&mut *tables), data.stable(&mut *tables)))
+                    .collect(),
+            })
+            .collect()
+    }
+
     fn mir_body(&self, item: stable_mir::DefId) -> stable_mir::mir::Body {
         let mut tables = self.0.borrow_mut();
         let def_id = tables[item];
@@ -1,7 +1,7 @@
 //! Conversion of internal Rust compiler `mir` items to stable ones.
 
 use rustc_middle::mir::interpret::alloc_range;
-use rustc_middle::mir::mono::MonoItem;
+use rustc_middle::mir::mono::{Linkage, MonoItem, MonoItemData, Visibility};
 use rustc_middle::{bug, mir};
 use stable_mir::mir::alloc::GlobalAlloc;
 use stable_mir::mir::{ConstOperand, Statement, UserTypeProjection, VarDebugInfoFragment};
@@ -413,17 +413,30 @@ fn stable(&self, _: &mut Tables<'_>) -> Self::T {
 
 impl<'tcx> Stable<'tcx> for mir::UnwindAction {
     type T = stable_mir::mir::UnwindAction;
-    fn stable(&self, _: &mut Tables<'_>) -> Self::T {
+    fn stable(&self, tables: &mut Tables<'_>) -> Self::T {
         use rustc_middle::mir::UnwindAction;
         match self {
             UnwindAction::Continue => stable_mir::mir::UnwindAction::Continue,
             UnwindAction::Unreachable => stable_mir::mir::UnwindAction::Unreachable,
-            UnwindAction::Terminate(_) => stable_mir::mir::UnwindAction::Terminate,
+            UnwindAction::Terminate(reason) => {
+                stable_mir::mir::UnwindAction::Terminate(reason.stable(tables))
+            }
             UnwindAction::Cleanup(bb) => stable_mir::mir::UnwindAction::Cleanup(bb.as_usize()),
         }
     }
 }
 
+impl<'tcx> Stable<'tcx> for mir::UnwindTerminateReason {
+    type T = stable_mir::mir::UnwindTerminateReason;
+    fn stable(&self, _: &mut Tables<'_>) -> Self::T {
+        use rustc_middle::mir::UnwindTerminateReason;
+        match self {
+            UnwindTerminateReason::Abi => stable_mir::mir::UnwindTerminateReason::Abi,
+            UnwindTerminateReason::InCleanup => stable_mir::mir::UnwindTerminateReason::InCleanup,
+        }
+    }
+}
+
 impl<'tcx> Stable<'tcx> for mir::NonDivergingIntrinsic<'tcx> {
     type T = stable_mir::mir::NonDivergingIntrinsic;
 
@@ -782,3 +795,50 @@ fn stable(&self, tables: &mut Tables<'_>) -> Self::T {
         }
     }
 }
+
+impl<'tcx> Stable<'tcx> for Linkage {
+    type T = stable_mir::mir::mono::Linkage;
+
+    fn stable(&self, _: &mut Tables<'_>) -> Self::T {
+        use stable_mir::mir::mono::Linkage as StableLinkage;
+        match self {
+            Linkage::External => StableLinkage::External,
+            Linkage::AvailableExternally => StableLinkage::AvailableExternally,
+            Linkage::LinkOnceAny => StableLinkage::LinkOnceAny,
+            Linkage::LinkOnceODR => StableLinkage::LinkOnceODR,
+            Linkage::WeakAny => StableLinkage::WeakAny,
+            Linkage::WeakODR => StableLinkage::WeakODR,
+            Linkage::Appending => StableLinkage::Appending,
+            Linkage::Internal => StableLinkage::Internal,
+            Linkage::Private => StableLinkage::Private,
+            Linkage::ExternalWeak => StableLinkage::ExternalWeak,
+            Linkage::Common => StableLinkage::Common,
+        }
+    }
+}
+
+impl<'tcx> Stable<'tcx> for Visibility {
+    type T = stable_mir::mir::mono::Visibility;
+
+    fn stable(&self, _: &mut Tables<'_>) -> Self::T {
+        use stable_mir::mir::mono::Visibility as StableVisibility;
+        match self {
+            Visibility::Default => StableVisibility::Default,
+            Visibility::Hidden => StableVisibility::Hidden,
+            Visibility::Protected => StableVisibility::Protected,
+        }
+    }
+}
+
+impl<'tcx> Stable<'tcx> for MonoItemData {
+    type T = stable_mir::mir::mono::MonoItemData;
+
+    fn stable(&self, tables: &mut Tables<'_>) -> Self::T {
+        stable_mir::mir::mono::MonoItemData {
+            inlined: self.inlined,
+            linkage: self.linkage.stable(tables),
+            visibility: self.visibility.stable(tables),
+            size_estimate: self.size_estimate,
+        }
+    }
+}
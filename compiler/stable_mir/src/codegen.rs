@@ -0,0 +1,72 @@
+//! Provide an interface for code generation backends that are written against the stable MIR.
+//!
+//! The compiler side of the bridge, which turns such a backend into one the compiler driver can
+//! load, lives in `rustc_smir::rustc_internal::codegen`.
+
+use crate::mir::mono::{CodegenUnit, Visibility};
+use crate::{Error, Symbol};
+
+/// A code generation backend that only consumes stable MIR.
+///
+/// The backend is invoked once the compiler has collected and partitioned the monomorphized
+/// items of the local crate. All stable MIR APIs can be used while generating code, e.g.,
+/// [`Instance::body`], [`Instance::fn_abi`] and [`Ty::layout`].
+///
+/// The compiler takes care of emitting the crate metadata and of linking. The backend is
+/// responsible for everything else, including the C `main` wrapper of executables (see
+/// [`entry_fn`]) and the allocator shim (see [`AllocatorShim`]).
+///
+/// [`Instance::body`]: crate::mir::mono::Instance::body
+/// [`Instance::fn_abi`]: crate::mir::mono::Instance::fn_abi
+/// [`Ty::layout`]: crate::ty::Ty::layout
+/// [`entry_fn`]: crate::entry_fn
+pub trait CodegenBackend {
+    /// Generate code for the given codegen units, returning one object file per unit.
+    fn codegen_crate(&self, units: Vec<CodegenUnit>) -> Result<Vec<ObjectFile>, Error>;
+
+    /// Generate the allocator shim of the crate. This is only called for crates that need one,
+    /// e.g., executables and dynamic libraries that depend on `std`.
+    fn codegen_allocator(&self, shim: AllocatorShim) -> Result<ObjectFile, Error>;
+}
+
+/// The object file generated for a codegen unit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectFile {
+    /// The name of the codegen unit this object file was generated for.
+    pub name: Symbol,
+    /// The contents of the object file, in the object format of the target.
+    pub bytes: Vec<u8>,
+}
+
+/// The allocator shim of a crate, which forwards the global allocator functions to the
+/// implementation chosen for the crate, e.g., `__rust_alloc` to `__rdl_alloc`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AllocatorShim {
+    /// The name of the module, which is also the name of its object file.
+    pub name: Symbol,
+    /// The functions of the shim.
+    pub functions: Vec<ShimFunction>,
+    /// The `u8` statics of the shim, with their values.
+    pub statics: Vec<(Symbol, u8)>,
+    /// The visibility of all the symbols of the shim.
+    pub visibility: Visibility,
+}
+
+/// A function of the allocator shim, which calls `callee` with its arguments and returns its
+/// result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShimFunction {
+    pub name: Symbol,
+    pub callee: Symbol,
+    pub inputs: Vec<ShimTy>,
+    pub output: Option<ShimTy>,
+    /// Whether the function never returns.
+    pub no_return: bool,
+}
+
+/// The type of an argument or of the result of a [`ShimFunction`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShimTy {
+    Usize,
+    Ptr,
+}
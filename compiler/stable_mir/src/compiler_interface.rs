@@ -8,7 +8,7 @@
 use crate::abi::{FnAbi, Layout, LayoutShape};
 use crate::crate_def::Attribute;
 use crate::mir::alloc::{AllocId, GlobalAlloc};
-use crate::mir::mono::{Instance, InstanceDef, StaticDef};
+use crate::mir::mono::{CodegenUnit, Instance, InstanceDef, MonoItem, StaticDef};
 use crate::mir::{BinOp, Body, Place, UnOp};
 use crate::target::MachineInfo;
 use crate::ty::{
@@ -28,6 +28,10 @@ pub trait Context {
     fn entry_fn(&self) -> Option<CrateItem>;
     /// Retrieve all items of the local crate that have a MIR associated with them.
     fn all_local_items(&self) -> CrateItems;
+    /// Retrieve all monomorphized items that are reachable from the local crate.
+    fn all_mono_items(&self) -> Vec<MonoItem>;
+    /// Retrieve the codegen units the monomorphized items of the local crate are partitioned into.
+    fn codegen_units(&self) -> Vec<CodegenUnit>;
     /// Retrieve the body of a function.
     /// This function will panic if the body is not available.
     fn mir_body(&self, item: DefId) -> mir::Body;
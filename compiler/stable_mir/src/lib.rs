@@ -25,10 +25,12 @@
 use crate::compiler_interface::with;
 pub use crate::crate_def::{CrateDef, CrateDefType, DefId};
 pub use crate::error::*;
+use crate::mir::mono::{CodegenUnit, MonoItem};
 use crate::mir::{Body, Mutability};
 use crate::ty::{ForeignModuleDef, ImplDef, IndexedVal, Span, TraitDef, Ty};
 
 pub mod abi;
+pub mod codegen;
 #[macro_use]
 pub mod crate_def;
 pub mod compiler_interface;
@@ -180,6 +182,18 @@ pub fn all_local_items() -> CrateItems {
     with(|cx| cx.all_local_items())
 }
 
+/// Retrieve all monomorphized items that are reachable from the local crate, i.e., everything
+/// the compiler would emit code for.
+pub fn all_mono_items() -> Vec<MonoItem> {
+    with(|cx| cx.all_mono_items())
+}
+
+/// Retrieve the codegen units the compiler partitioned the monomorphized items of the local
+/// crate into, together with the linkage and visibility of each item.
+pub fn codegen_units() -> Vec<CodegenUnit> {
+    with(|cx| cx.codegen_units())
+}
+
 pub fn all_trait_decls() -> TraitDecls {
     with(|cx| cx.all_trait_decls())
 }
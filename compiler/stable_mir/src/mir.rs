@@ -1,8 +1,10 @@
 pub mod alloc;
 mod body;
+mod builder;
 pub mod mono;
 pub mod pretty;
 pub mod visit;
 
 pub use body::*;
-pub use visit::MirVisitor;
+pub use builder::BodyBuilder;
+pub use visit::{MirVisitor, MutMirVisitor};
@@ -208,6 +208,28 @@ pub fn successors(&self) -> Successors {
         }
     }
 
+    /// Mutable references to the successors of this terminator, in the same order as
+    /// [TerminatorKind::successors].
+    pub fn successors_mut(&mut self) -> Vec<&mut BasicBlockIdx> {
+        use self::TerminatorKind::*;
+        match self {
+            Goto { target } => vec![target],
+            SwitchInt { targets, .. } => targets
+                .branches
+                .iter_mut()
+                .map(|(_, target)| target)
+                .chain(Some(&mut targets.otherwise))
+                .collect(),
+            Return | Resume | Abort | Unreachable => vec![],
+            Drop { target, unwind, .. } | Assert { target, unwind, .. } => {
+                Some(target).into_iter().chain(unwind.cleanup_mut()).collect()
+            }
+            Call { target, unwind, .. } | InlineAsm { destination: target, unwind, .. } => {
+                target.as_mut().into_iter().chain(unwind.cleanup_mut()).collect()
+            }
+        }
+    }
+
     pub fn unwind(&self) -> Option<&UnwindAction> {
         match *self {
             TerminatorKind::Goto { .. }
@@ -237,10 +259,28 @@ pub struct InlineAsmOperand {
 pub enum UnwindAction {
     Continue,
     Unreachable,
-    Terminate,
+    Terminate(UnwindTerminateReason),
     Cleanup(BasicBlockIdx),
 }
 
+impl UnwindAction {
+    fn cleanup_mut(&mut self) -> Option<&mut BasicBlockIdx> {
+        match self {
+            UnwindAction::Cleanup(bb) => Some(bb),
+            UnwindAction::Continue | UnwindAction::Unreachable | UnwindAction::Terminate(_) => None,
+        }
+    }
+}
+
+/// The reason for terminating unwinding, which determines the message printed before aborting.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+pub enum UnwindTerminateReason {
+    /// Unwinding is not allowed by the ABI of the function.
+    Abi,
+    /// Unwinding out of a cleanup block, i.e. a panic while dropping during unwinding.
+    InCleanup,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub enum AssertMessage {
     BoundsCheck { len: Operand, index: Operand },
@@ -0,0 +1,138 @@
+//! Utilities to modify the basic blocks, statements and locals of a [Body].
+
+use crate::Span;
+use crate::mir::visit::{Location, PlaceContext};
+use crate::mir::{
+    BasicBlock, BasicBlockIdx, Body, Local, LocalDecl, MutMirVisitor, Mutability, Statement,
+    Terminator, TerminatorKind,
+};
+use crate::ty::Ty;
+
+/// Modify a [Body] while keeping it consistent.
+///
+/// Inserting or removing a basic block renumbers the blocks that come after it, and every
+/// terminator that refers to one of these blocks is updated accordingly. Likewise, removing a
+/// local renumbers the locals that come after it in every place of the body.
+///
+/// Block indices given to this builder, including the ones in the terminators of inserted blocks,
+/// refer to the body as it is at the time of the call.
+#[derive(Clone, Debug)]
+pub struct BodyBuilder {
+    body: Body,
+}
+
+impl BodyBuilder {
+    /// Start modifying `body`.
+    pub fn new(body: Body) -> Self {
+        BodyBuilder { body }
+    }
+
+    /// The body as modified so far.
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    /// Finish modifying the body.
+    pub fn build(self) -> Body {
+        self.body
+    }
+
+    /// Declare a new local after all existing locals, and return it.
+    pub fn new_local(&mut self, ty: Ty, span: Span, mutability: Mutability) -> Local {
+        self.body.locals.push(LocalDecl { ty, span, mutability });
+        self.body.locals.len() - 1
+    }
+
+    /// Remove the declaration of `local`, which must neither be the return local nor an argument.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `local` is still used in the body, including in `StorageLive` and
+    /// `StorageDead` statements and in debug information.
+    pub fn remove_local(&mut self, local: Local) -> LocalDecl {
+        assert!(
+            local > self.body.arg_count,
+            "Cannot remove the return local or an argument of a function"
+        );
+        let decl = self.body.locals.remove(local);
+        RenumberLocals { removed: local }.visit_body(&mut self.body);
+        decl
+    }
+
+    /// Insert `statement` into `bb` so that it becomes its `index`-th statement.
+    pub fn insert_statement(&mut self, bb: BasicBlockIdx, index: usize, statement: Statement) {
+        self.body.blocks[bb].statements.insert(index, statement);
+    }
+
+    /// Remove the `index`-th statement of `bb`.
+    pub fn remove_statement(&mut self, bb: BasicBlockIdx, index: usize) -> Statement {
+        self.body.blocks[bb].statements.remove(index)
+    }
+
+    /// Append `block` after all existing blocks, and return its index.
+    pub fn push_block(&mut self, block: BasicBlock) -> BasicBlockIdx {
+        self.body.blocks.push(block);
+        self.body.blocks.len() - 1
+    }
+
+    /// Insert `block` so that its index becomes `bb`.
+    ///
+    /// Inserting a block at index 0 makes it the entry block of the function.
+    pub fn insert_block(&mut self, bb: BasicBlockIdx, block: BasicBlock) {
+        self.body.blocks.insert(bb, block);
+        self.renumber_blocks(|target| if target >= bb { target + 1 } else { target });
+    }
+
+    /// Remove the block `bb`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `bb` is still the successor of another block.
+    pub fn remove_block(&mut self, bb: BasicBlockIdx) -> BasicBlock {
+        let block = self.body.blocks.remove(bb);
+        self.renumber_blocks(|target| {
+            assert_ne!(target, bb, "Cannot remove a block that is still a successor");
+            if target > bb { target - 1 } else { target }
+        });
+        block
+    }
+
+    /// Split `bb` before its `index`-th statement.
+    ///
+    /// The statements starting at `index` and the terminator of `bb` are moved to a new block,
+    /// which is appended after all existing blocks and returned. `bb` then ends with a `Goto` to
+    /// the new block.
+    pub fn split_block(&mut self, bb: BasicBlockIdx, index: usize) -> BasicBlockIdx {
+        let new_bb = self.body.blocks.len();
+        let block = &mut self.body.blocks[bb];
+        let statements = block.statements.split_off(index);
+        let goto = Terminator {
+            kind: TerminatorKind::Goto { target: new_bb },
+            span: block.terminator.span,
+        };
+        let terminator = std::mem::replace(&mut block.terminator, goto);
+        self.push_block(BasicBlock { statements, terminator })
+    }
+
+    fn renumber_blocks(&mut self, mut renumber: impl FnMut(BasicBlockIdx) -> BasicBlockIdx) {
+        for block in &mut self.body.blocks {
+            for target in block.terminator.kind.successors_mut() {
+                *target = renumber(*target);
+            }
+        }
+    }
+}
+
+/// Renumbers the locals after a local that was removed.
+struct RenumberLocals {
+    removed: Local,
+}
+
+impl MutMirVisitor for RenumberLocals {
+    fn visit_local(&mut self, local: &mut Local, _ptx: PlaceContext, _location: Location) {
+        assert_ne!(*local, self.removed, "Cannot remove a local that is still used");
+        if *local > self.removed {
+            *local -= 1;
+        }
+    }
+}
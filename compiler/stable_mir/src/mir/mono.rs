@@ -16,6 +16,55 @@ pub enum MonoItem {
     GlobalAsm(Opaque),
 }
 
+/// How a monomorphized item is linked.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum Linkage {
+    External,
+    AvailableExternally,
+    LinkOnceAny,
+    LinkOnceODR,
+    WeakAny,
+    WeakODR,
+    Appending,
+    Internal,
+    Private,
+    ExternalWeak,
+    Common,
+}
+
+/// The symbol visibility of a monomorphized item.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum Visibility {
+    Default,
+    Hidden,
+    Protected,
+}
+
+/// Information about how a monomorphized item is emitted into a codegen unit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct MonoItemData {
+    /// Whether this is a copy of an item that is emitted into every codegen unit using it,
+    /// e.g. a function marked `#[inline]`, rather than into a single codegen unit.
+    pub inlined: bool,
+    pub linkage: Linkage,
+    pub visibility: Visibility,
+    /// An estimate of the size of the item, used to balance the size of codegen units.
+    pub size_estimate: usize,
+}
+
+/// A unit of code generation, as produced by the compiler's partitioning of the
+/// monomorphized items of the local crate.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct CodegenUnit {
+    /// The name of the codegen unit, which is unique among all crates.
+    pub name: Symbol,
+    /// Whether this is the primary codegen unit, which holds items such as the entry point
+    /// wrapper if the local crate has no better place for them.
+    pub is_primary: bool,
+    /// The items emitted into this codegen unit, in a deterministic order.
+    pub items: Vec<(MonoItem, MonoItemData)>,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize)]
 pub struct Instance {
     /// The type of instance.
@@ -123,7 +123,7 @@ fn pretty_terminator<W: Write>(writer: &mut W, terminator: &TerminatorKind) -> i
             None | Some(UnwindAction::Cleanup(_)) => unreachable!(),
             Some(UnwindAction::Continue) => write!(w, "continue"),
             Some(UnwindAction::Unreachable) => write!(w, "unreachable"),
-            Some(UnwindAction::Terminate) => write!(w, "terminate"),
+            Some(UnwindAction::Terminate(_)) => write!(w, "terminate"),
         }
     };
 
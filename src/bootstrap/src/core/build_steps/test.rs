@@ -1457,6 +1457,95 @@ fn run(self, builder: &Builder<'_>) {
 
 default_test!(Assembly { path: "tests/assembly", mode: "assembly", suite: "assembly" });
 
+/// FileCheck tests of the MLIR emitted by `rustc_codegen_mlir`, which only run when `mlir` is in
+/// `rust.codegen-backends`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Mlir {
+    pub compiler: Compiler,
+    pub target: TargetSelection,
+}
+
+impl Step for Mlir {
+    type Output = ();
+    const DEFAULT: bool = true;
+    const ONLY_HOSTS: bool = false;
+
+    fn should_run(run: ShouldRun<'_>) -> ShouldRun<'_> {
+        run.suite_path("tests/mlir")
+    }
+
+    fn make_run(run: RunConfig<'_>) {
+        let builder = run.builder;
+        if !builder.config.codegen_backends(run.target).contains(&"mlir".to_owned()) {
+            builder.info("mlir not in rust.codegen-backends. skipping");
+            return;
+        }
+
+        let compiler = builder.compiler(builder.top_stage, run.build_triple());
+        builder.ensure(Mlir { compiler, target: run.target });
+    }
+
+    fn run(self, builder: &Builder<'_>) {
+        builder.ensure(Compiletest {
+            compiler: self.compiler,
+            target: self.target,
+            mode: "mlir",
+            suite: "mlir",
+            path: "tests/mlir",
+            compare_mode: None,
+        });
+    }
+}
+
+/// Runs the run-pass tests of `tests/ui` with the MLIR backend, which only happens when `mlir` is
+/// in `rust.codegen-backends`. The tests listed in
+/// `compiler/rustc_codegen_mlir/ui-known-failures.txt` are expected to fail.
+///
+/// Not run by default until that list has been generated from an actual run; use
+/// `x test compiler/rustc_codegen_mlir` to run it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CodegenMlir {
+    pub compiler: Compiler,
+    pub target: TargetSelection,
+}
+
+impl Step for CodegenMlir {
+    type Output = ();
+    const DEFAULT: bool = false;
+    const ONLY_HOSTS: bool = false;
+
+    fn should_run(run: ShouldRun<'_>) -> ShouldRun<'_> {
+        run.paths(&["compiler/rustc_codegen_mlir"])
+    }
+
+    fn make_run(run: RunConfig<'_>) {
+        let builder = run.builder;
+        if builder.download_rustc() {
+            builder.info("CI rustc uses the default codegen backend. skipping");
+            return;
+        }
+
+        if !builder.config.codegen_backends(run.target).contains(&"mlir".to_owned()) {
+            builder.info("mlir not in rust.codegen-backends. skipping");
+            return;
+        }
+
+        let compiler = builder.compiler(builder.top_stage, run.build_triple());
+        builder.ensure(CodegenMlir { compiler, target: run.target });
+    }
+
+    fn run(self, builder: &Builder<'_>) {
+        builder.ensure(Compiletest {
+            compiler: self.compiler,
+            target: self.target,
+            mode: "ui",
+            suite: "ui",
+            path: "tests/ui",
+            compare_mode: Some("mlir"),
+        });
+    }
+}
+
 /// Coverage tests are a bit more complicated than other test suites, because
 /// we want to run the same set of test files in multiple different modes,
 /// in a way that's convenient and flexible when invoked manually.
@@ -1802,11 +1891,20 @@ fn run(self, builder: &Builder<'_>) {
             cmd.arg("--force-rerun");
         }
 
-        let compare_mode =
+        let mut compare_mode =
             builder.config.cmd.compare_mode().or_else(|| {
                 if builder.config.test_compare_mode { self.compare_mode } else { None }
             });
 
+        // The MLIR backend is tested by running the suite in its compare mode only, with the
+        // tests it is known to fail expected to fail.
+        if self.compare_mode == Some("mlir") {
+            let known_failures = "compiler/rustc_codegen_mlir/ui-known-failures.txt";
+            cmd.arg("--compare-mode").arg("mlir");
+            cmd.arg("--known-failures").arg(builder.src.join(known_failures));
+            compare_mode = None;
+        }
+
         if let Some(ref pass) = builder.config.cmd.pass() {
             cmd.arg("--pass");
             cmd.arg(pass);
@@ -325,6 +325,7 @@ pub fn assert_single_path(&self) -> &TaskPath {
         "tests/debuginfo",
         "tests/incremental",
         "tests/mir-opt",
+        "tests/mlir",
         "tests/pretty",
         "tests/run-make",
         "tests/rustdoc",
@@ -867,11 +868,13 @@ macro_rules! describe {
                 test::Codegen,
                 test::CodegenUnits,
                 test::Assembly,
+                test::Mlir,
                 test::Incremental,
                 test::Debuginfo,
                 test::UiFullDeps,
                 test::CodegenCranelift,
                 test::CodegenGCC,
+                test::CodegenMlir,
                 test::Rustdoc,
                 test::CoverageRunRustdoc,
                 test::Pretty,
@@ -121,6 +121,7 @@
     "min-lldb-version",
     "min-llvm-version",
     "min-system-llvm-version",
+    "mlir-check-stage",
     "needs-asm-support",
     "needs-deterministic-layouts",
     "needs-dlltool",
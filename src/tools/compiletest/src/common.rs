@@ -65,6 +65,7 @@ pub enum Mode {
         JsDocTest => "js-doc-test",
         MirOpt => "mir-opt",
         Assembly => "assembly",
+        Mlir => "mlir",
         CoverageMap => "coverage-map",
         CoverageRun => "coverage-run",
         Crashes => "crashes",
@@ -121,6 +122,7 @@ pub enum CompareMode {
         NextSolverCoherence => "next-solver-coherence",
         SplitDwarf => "split-dwarf",
         SplitDwarfSingle => "split-dwarf-single",
+        Mlir => "mlir",
     }
 }
 
@@ -333,6 +335,11 @@ pub struct Config {
     /// mode describing what file the actual ui output will be compared to
     pub compare_mode: Option<CompareMode>,
 
+    /// Tests that are expected to fail, as paths relative to the repository root with an optional
+    /// `#revision` suffix. Known failures pass when they fail and fail when they pass, so that
+    /// both regressions and fixed tests get noticed.
+    pub known_failures: HashSet<String>,
+
     /// If true, this will generate a coverage file with UI test files that run `MachineApplicable`
     /// diagnostics but are missing `run-rustfix` annotations. The generated coverage file is
     /// created in `/<build_base>/rustfix_missing_coverage.txt`
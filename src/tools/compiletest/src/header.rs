@@ -8,7 +8,7 @@
 
 use tracing::*;
 
-use crate::common::{Config, Debugger, FailMode, Mode, PassMode};
+use crate::common::{CompareMode, Config, Debugger, FailMode, Mode, PassMode};
 use crate::debuggers::{extract_cdb_version, extract_gdb_version};
 use crate::header::auxiliary::{AuxProps, parse_and_update_aux};
 use crate::header::cfg::{MatchOutcome, parse_cfg_name_directive};
@@ -193,6 +193,9 @@ pub struct TestProps {
     pub llvm_cov_flags: Vec<String>,
     /// Extra flags to pass to LLVM's `filecheck` tool, in tests that use it.
     pub filecheck_flags: Vec<String>,
+    /// The MLIR backend stage whose `-Zmlir-dump-after` dumps are checked with FileCheck, instead
+    /// of the `--emit=mlir` output. Only used by the "mlir" test mode.
+    pub mlir_check_stage: Option<String>,
     /// Don't automatically insert any `--check-cfg` args
     pub no_auto_check_cfg: bool,
     /// Run tests which require enzyme being build
@@ -241,6 +244,7 @@ mod directives {
     pub const COMPARE_OUTPUT_LINES_BY_SUBSET: &'static str = "compare-output-lines-by-subset";
     pub const LLVM_COV_FLAGS: &'static str = "llvm-cov-flags";
     pub const FILECHECK_FLAGS: &'static str = "filecheck-flags";
+    pub const MLIR_CHECK_STAGE: &'static str = "mlir-check-stage";
     pub const NO_AUTO_CHECK_CFG: &'static str = "no-auto-check-cfg";
     // This isn't a real directive, just one that is probably mistyped often
     pub const INCORRECT_COMPILER_FLAGS: &'static str = "compiler-flags";
@@ -297,6 +301,7 @@ pub fn new() -> Self {
             remap_src_base: false,
             llvm_cov_flags: vec![],
             filecheck_flags: vec![],
+            mlir_check_stage: None,
             no_auto_check_cfg: false,
             has_enzyme: false,
         }
@@ -562,6 +567,13 @@ fn split_flags(flags: &str) -> Vec<String> {
                         self.filecheck_flags.extend(split_flags(&flags));
                     }
 
+                    config.set_name_value_directive(
+                        ln,
+                        MLIR_CHECK_STAGE,
+                        &mut self.mlir_check_stage,
+                        |s| s.trim().to_string(),
+                    );
+
                     config.set_name_directive(ln, NO_AUTO_CHECK_CFG, &mut self.no_auto_check_cfg);
                 },
             );
@@ -637,6 +649,7 @@ fn update_pass_mode(&mut self, ln: &str, revision: Option<&str>, config: &Config
             (Mode::Ui, _) => (),
             (Mode::Crashes, _) => (),
             (Mode::Codegen, "build-pass") => (),
+            (Mode::Mlir, "run-pass") => (),
             (Mode::Incremental, _) => {
                 if revision.is_some() && !self.revisions.iter().all(|r| r.starts_with("cfail")) {
                     panic!("`{s}` header is only supported in `cfail` incremental tests")
@@ -1282,6 +1295,7 @@ pub fn make_test_description<R: Read>(
     let mut ignore = false;
     let mut ignore_message = None;
     let mut should_fail = false;
+    let mut run_pass = false;
 
     let mut local_poisoned = false;
 
@@ -1334,9 +1348,26 @@ macro_rules! decision {
             }
 
             should_fail |= config.parse_name_directive(ln, "should-fail");
+            run_pass |= config.parse_name_directive(ln, "run-pass");
         },
     );
 
+    // Alternative codegen backends are only checked on whether the tests they compile run
+    // correctly, as diagnostics are all emitted before codegen.
+    if config.compare_mode == Some(CompareMode::Mlir) && config.mode == Mode::Ui && !run_pass {
+        ignore = true;
+        ignore_message = Some("only run-pass tests are run with the MLIR backend");
+    }
+
+    // A known failure is expected to fail, and has to be removed from the list once it passes.
+    let root_directory = config.src_base.parent().unwrap().parent().unwrap();
+    let relative_path =
+        path.strip_prefix(root_directory).unwrap_or(path).display().to_string().replace('\\', "/");
+    let known_failure = config.known_failures.contains(&relative_path)
+        || test_revision.is_some_and(|revision| {
+            config.known_failures.contains(&format!("{relative_path}#{revision}"))
+        });
+
     if local_poisoned {
         eprintln!("errors encountered when trying to make test description: {}", path.display());
         panic!("errors encountered when trying to make test description");
@@ -1347,7 +1378,7 @@ macro_rules! decision {
     // If desired, we could add a `should-fail-pretty` annotation.
     let should_panic = match config.mode {
         crate::common::Pretty => test::ShouldPanic::No,
-        _ if should_fail => test::ShouldPanic::Yes,
+        _ if should_fail || known_failure => test::ShouldPanic::Yes,
         _ => test::ShouldPanic::No,
     };
 
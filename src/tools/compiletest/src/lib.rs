@@ -71,7 +71,7 @@ pub fn parse_config(args: Vec<String>) -> Config {
             "which sort of compile tests to run",
             "pretty | debug-info | codegen | rustdoc \
             | rustdoc-json | codegen-units | incremental | run-make | ui \
-            | js-doc-test | mir-opt | assembly | crashes",
+            | js-doc-test | mir-opt | assembly | mlir | crashes",
         )
         .reqopt(
             "",
@@ -150,6 +150,13 @@ pub fn parse_config(args: Vec<String>) -> Config {
             "mode describing what file the actual ui output will be compared to",
             "COMPARE MODE",
         )
+        .optopt(
+            "",
+            "known-failures",
+            "file listing the tests expected to fail, one path relative to the repository root \
+            per line",
+            "PATH",
+        )
         .optflag(
             "",
             "rustfix-coverage",
@@ -241,6 +248,18 @@ fn make_absolute(path: PathBuf) -> PathBuf {
         false
     };
     let has_enzyme = matches.opt_present("has-enzyme");
+    let known_failures = matches
+        .opt_str("known-failures")
+        .map(|path| {
+            let list = fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("failed to read known failures `{path}`: {e}"));
+            list.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default();
     let filters = if mode == Mode::RunMake {
         matches
             .free
@@ -335,6 +354,7 @@ fn make_absolute(path: PathBuf) -> PathBuf {
         compare_mode: matches
             .opt_str("compare-mode")
             .map(|s| s.parse().expect("invalid --compare-mode provided")),
+        known_failures,
         rustfix_coverage: matches.opt_present("rustfix-coverage"),
         has_html_tidy,
         has_enzyme,
@@ -17,7 +17,7 @@
 
 use crate::common::{
     Assembly, Codegen, CodegenUnits, CompareMode, Config, CoverageMap, CoverageRun, Crashes,
-    DebugInfo, Debugger, FailMode, Incremental, JsDocTest, MirOpt, PassMode, Pretty, RunMake,
+    DebugInfo, Debugger, FailMode, Incremental, JsDocTest, MirOpt, Mlir, PassMode, Pretty, RunMake,
     Rustdoc, RustdocJson, TestPaths, UI_EXTENSIONS, UI_FIXED, UI_RUN_STDERR, UI_RUN_STDOUT,
     UI_STDERR, UI_STDOUT, UI_SVG, UI_WINDOWS_SVG, Ui, expected_output_path, incremental_dir,
     output_base_dir, output_base_name, output_testname_unique,
@@ -42,6 +42,7 @@
 mod incremental;
 mod js_doc;
 mod mir_opt;
+mod mlir;
 mod pretty;
 mod run_make;
 mod rustdoc;
@@ -240,6 +241,7 @@ enum Emit {
     Mir,
     Asm,
     LinkArgsAsm,
+    Mlir,
 }
 
 impl<'test> TestCx<'test> {
@@ -261,6 +263,7 @@ fn run_revision(&self) {
             Ui => self.run_ui_test(),
             MirOpt => self.run_mir_opt_test(),
             Assembly => self.run_assembly_test(),
+            Mlir => self.run_mlir_test(),
             JsDocTest => self.run_js_doc_test(),
             CoverageMap => self.run_coverage_map_test(), // see self::coverage
             CoverageRun => self.run_coverage_run_test(), // see self::coverage
@@ -275,8 +278,8 @@ fn pass_mode(&self) -> Option<PassMode> {
     fn should_run(&self, pm: Option<PassMode>) -> WillExecute {
         let test_should_run = match self.config.mode {
             Ui if pm == Some(PassMode::Run) || self.props.fail_mode == Some(FailMode::Run) => true,
-            MirOpt if pm == Some(PassMode::Run) => true,
-            Ui | MirOpt => false,
+            MirOpt | Mlir if pm == Some(PassMode::Run) => true,
+            Ui | MirOpt | Mlir => false,
             mode => panic!("unimplemented for mode {:?}", mode),
         };
         if test_should_run { self.run_if_enabled() } else { WillExecute::No }
@@ -1524,6 +1527,10 @@ fn make_compile_args(
             Assembly | Codegen => {
                 rustc.arg("-Cdebug-assertions=no");
             }
+            Mlir => {
+                rustc.arg("-Cdebug-assertions=no");
+                rustc.arg("-Zcodegen-backend=mlir");
+            }
             Crashes => {
                 set_mir_dump_dir(&mut rustc);
             }
@@ -1558,6 +1565,9 @@ fn make_compile_args(
             Emit::LinkArgsAsm => {
                 rustc.args(&["-Clink-args=--emit=asm"]);
             }
+            Emit::Mlir => {
+                rustc.args(&["--emit", "mlir"]);
+            }
         }
 
         if !is_rustdoc {
@@ -1604,6 +1614,9 @@ fn make_compile_args(
             Some(CompareMode::SplitDwarfSingle) => {
                 rustc.args(&["-Csplit-debuginfo=packed"]);
             }
+            Some(CompareMode::Mlir) => {
+                rustc.args(&["-Zcodegen-backend=mlir"]);
+            }
             None => {}
         }
 
@@ -1857,6 +1870,25 @@ fn compile_test_and_save_assembly(&self) -> (ProcRes, PathBuf) {
         (proc_res, output_path)
     }
 
+    fn compile_test_and_save_mlir(&self) -> (ProcRes, PathBuf) {
+        let output_path = self.output_base_name().with_extension("mlir");
+        let input_file = &self.testpaths.file;
+        let mut rustc = self.make_compile_args(
+            input_file,
+            TargetLocation::ThisFile(output_path.clone()),
+            Emit::Mlir,
+            AllowUnused::No,
+            LinkToAux::Yes,
+            Vec::new(),
+        );
+        if let Some(stage) = &self.props.mlir_check_stage {
+            rustc.arg(format!("-Zmlir-dump-after={stage}"));
+        }
+
+        let proc_res = self.compose_and_run_compiler(rustc, None, self.testpaths);
+        (proc_res, output_path)
+    }
+
     fn verify_with_filecheck(&self, output: &Path) -> ProcRes {
         let mut filecheck = Command::new(self.config.llvm_filecheck.as_ref().unwrap());
         filecheck.arg("--input-file").arg(output).arg(&self.testpaths.file);
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{Emit, TestCx, WillExecute};
+
+impl TestCx<'_> {
+    pub(super) fn run_mlir_test(&self) {
+        if self.config.llvm_filecheck.is_none() {
+            self.fatal("missing --llvm-filecheck");
+        }
+
+        let (proc_res, output_path) = self.compile_test_and_save_mlir();
+        if !proc_res.status.success() {
+            self.fatal_proc_rec("compilation failed!", &proc_res);
+        }
+
+        let output_path = match &self.props.mlir_check_stage {
+            Some(stage) => self.concat_mlir_dumps(&output_path, stage),
+            None => output_path,
+        };
+        let proc_res = self.verify_with_filecheck(&output_path);
+        if !proc_res.status.success() {
+            self.fatal_proc_rec("verification with 'FileCheck' failed", &proc_res);
+        }
+
+        // `run-pass` tests are also built into an executable with the MLIR backend and run.
+        if let WillExecute::Yes = self.should_run(self.pass_mode()) {
+            let proc_res = self.compile_test(WillExecute::Yes, Emit::None);
+            if !proc_res.status.success() {
+                self.fatal_proc_rec("compilation failed!", &proc_res);
+            }
+            let proc_res = self.exec_compiled_test();
+            if !proc_res.status.success() {
+                self.fatal_proc_rec("test run failed!", &proc_res);
+            }
+        }
+    }
+
+    /// Concatenates the dumps after `stage` of every codegen unit, which the compiler writes next
+    /// to `output_path` as `<name>.<cgu>.<stage>.mlir`, into a single file to check.
+    fn concat_mlir_dumps(&self, output_path: &Path, stage: &str) -> PathBuf {
+        let dir = output_path.parent().unwrap();
+        let prefix = format!("{}.", output_path.file_stem().unwrap().to_str().unwrap());
+        let suffix = format!(".{stage}.mlir");
+        let mut dumps = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                name.starts_with(&prefix) && name.ends_with(&suffix)
+            })
+            .collect::<Vec<_>>();
+        if dumps.is_empty() {
+            self.fatal(&format!("no MLIR dumps after `{stage}` in {}", dir.display()));
+        }
+        dumps.sort();
+
+        let mut contents = String::new();
+        for dump in dumps {
+            contents.push_str(&fs::read_to_string(dump).unwrap());
+        }
+        let concatenated = output_path.with_extension("dumps");
+        fs::write(&concatenated, contents).unwrap();
+        concatenated
+    }
+}
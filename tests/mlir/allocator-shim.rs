@@ -0,0 +1,25 @@
+// Checks that the allocator shim forwards `__rust_alloc` and friends to the default allocator of
+// std, and that `-Zoom=panic` makes the alloc error handler panic.
+//@ run-pass
+//@ compile-flags: -Copt-level=0 -Zoom=panic
+//@ mlir-check-stage: lower
+//@ needs-unwind
+
+#![crate_type = "bin"]
+
+use std::alloc::{Layout, handle_alloc_error};
+use std::panic;
+
+// CHECK-LABEL: func.func @__rust_alloc(
+// CHECK: call @__rdl_alloc(
+// CHECK-LABEL: func.func @__rust_alloc_error_handler(
+// CHECK: call @__rdl_oom(
+// CHECK: llvm.mlir.global {{.*}}@__rust_alloc_error_handler_should_panic(1 : i8)
+
+fn main() {
+    let v: Vec<u64> = (0..100).collect();
+    assert_eq!(v.iter().sum::<u64>(), 4950);
+
+    let oom = panic::catch_unwind(|| handle_alloc_error(Layout::new::<u64>()));
+    assert!(oom.is_err());
+}
@@ -0,0 +1,26 @@
+// Checks that integer and float arithmetic lowers to `arith` ops.
+//@ compile-flags: -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: func.func @wrapping_add
+// CHECK: arith.addi
+// CHECK: return
+#[no_mangle]
+pub fn wrapping_add(a: u32, b: u32) -> u32 {
+    a.wrapping_add(b)
+}
+
+// CHECK-LABEL: func.func @unsigned_less_than
+// CHECK: arith.cmpi ult
+#[no_mangle]
+pub fn unsigned_less_than(a: u64, b: u64) -> bool {
+    a < b
+}
+
+// CHECK-LABEL: func.func @float_mul
+// CHECK: arith.mulf
+#[no_mangle]
+pub fn float_mul(a: f64, b: f64) -> f64 {
+    a * b
+}
@@ -0,0 +1,36 @@
+// Checks that `asm!` lowers to `llvm.inline_asm` in the Intel dialect, and that functions defined
+// with `global_asm!`, which is appended to the LLVM module after translation, can be called.
+//@ run-pass
+//@ compile-flags: -Copt-level=0
+//@ only-x86_64
+
+#![crate_type = "bin"]
+
+use std::arch::{asm, global_asm};
+use std::hint::black_box;
+
+global_asm!(
+    ".globl mlir_global_asm_triple",
+    "mlir_global_asm_triple:",
+    "    lea rax, [rdi + rdi*2]",
+    "    ret",
+);
+
+extern "C" {
+    fn mlir_global_asm_triple(x: u64) -> u64;
+}
+
+// CHECK-LABEL: func.func @add_asm
+// CHECK: llvm.inline_asm asm_dialect = intel "add ${{[0-9]}}, ${{[0-9]}}"
+#[no_mangle]
+#[inline(never)]
+fn add_asm(a: u64, b: u64) -> u64 {
+    let mut x = a;
+    unsafe { asm!("add {0}, {1}", inout(reg) x, in(reg) b, options(pure, nomem, nostack)) };
+    x
+}
+
+fn main() {
+    assert_eq!(add_asm(black_box(40), black_box(2)), 42);
+    assert_eq!(unsafe { mlir_global_asm_triple(black_box(14)) }, 42);
+}
@@ -0,0 +1,23 @@
+// Checks that conditional control flow lowers to `cf` branches.
+//@ compile-flags: -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: func.func @max
+// CHECK: cf.cond_br
+#[no_mangle]
+pub fn max(a: i32, b: i32) -> i32 {
+    if a > b { a } else { b }
+}
+
+// CHECK-LABEL: func.func @classify
+// CHECK: cf.switch
+#[no_mangle]
+pub fn classify(x: u8) -> u32 {
+    match x {
+        0 => 10,
+        1 => 20,
+        2 => 30,
+        _ => 0,
+    }
+}
@@ -0,0 +1,15 @@
+// Checks that direct calls lower to `func.call` and callees are declared in the module.
+//@ compile-flags: -Copt-level=0
+
+#![crate_type = "lib"]
+
+extern "C" {
+    fn external(x: i32) -> i32;
+}
+
+// CHECK-LABEL: func.func @caller
+// CHECK: call @external
+#[no_mangle]
+pub fn caller(x: i32) -> i32 {
+    unsafe { external(x) }
+}
@@ -0,0 +1,42 @@
+// Checks that a failed overflow check calls the panic lang item through its unwind edge, and that
+// the panic can be caught with `catch_unwind`.
+//@ run-pass
+//@ needs-unwind
+//@ compile-flags: -Copt-level=0 -Coverflow-checks=on
+//@ only-x86_64
+
+#![crate_type = "bin"]
+
+use std::cell::Cell;
+use std::hint::black_box;
+use std::panic::{self, AssertUnwindSafe};
+
+struct Guard<'a>(&'a Cell<u32>);
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+// CHECK-LABEL: func.func @add
+// CHECK: func.constant @{{.*}}panic_const_add_overflow
+// CHECK: llvm.invoke
+#[no_mangle]
+#[inline(never)]
+fn add(x: u8, y: u8, drops: &Cell<u32>) -> u8 {
+    let _guard = Guard(drops);
+    x + y
+}
+
+fn main() {
+    panic::set_hook(Box::new(|_| {}));
+
+    let drops = Cell::new(0);
+    assert_eq!(add(black_box(1), 2, &drops), 3);
+    assert_eq!(drops.get(), 1);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| add(black_box(255), 1, &drops)));
+    assert!(result.is_err());
+    assert_eq!(drops.get(), 2);
+}
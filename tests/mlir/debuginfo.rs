@@ -0,0 +1,28 @@
+// Checks that with `-Cdebuginfo=2` user variables are declared with `llvm.intr.dbg.declare`, and
+// that the DWARF line info of the ops lets a backtrace resolve the line of a call.
+//@ run-pass
+//@ compile-flags: -Copt-level=0 -Cdebuginfo=2
+//@ only-linux
+//@ ignore-cross-compile
+
+#![crate_type = "bin"]
+
+use std::backtrace::Backtrace;
+use std::hint::black_box;
+
+// CHECK: #llvm.di_local_variable<{{.*}}name = "answer"
+// CHECK-LABEL: func.func @capture
+// CHECK: llvm.intr.dbg.declare
+#[no_mangle]
+#[inline(never)]
+fn capture(x: u32) -> (Backtrace, u32) {
+    let answer = black_box(x) * 2;
+    assert_eq!(answer, 42);
+    (Backtrace::force_capture(), line!())
+}
+
+fn main() {
+    let (backtrace, line) = capture(black_box(21));
+    let backtrace = backtrace.to_string();
+    assert!(backtrace.contains(&format!("debuginfo.rs:{line}:")), "{backtrace}");
+}
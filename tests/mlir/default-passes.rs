@@ -0,0 +1,18 @@
+// Checks that without `-Zmlir-passes`, `-Copt-level=2` runs the default pipeline, which includes
+// `loop-invariant-code-motion`. Its dump is only written if the pass ran.
+//@ compile-flags: -Copt-level=2
+//@ mlir-check-stage: loop-invariant-code-motion
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: func.func @sum_scaled
+// CHECK: arith.muli
+#[no_mangle]
+pub fn sum_scaled(mut x: u32, n: u32) -> u32 {
+    let mut sum = 0u32;
+    while x != 0 {
+        sum = sum.wrapping_add(n.wrapping_mul(3));
+        x -= 1;
+    }
+    sum
+}
@@ -0,0 +1,64 @@
+// Checks that unsizing to a trait object takes the address of a vtable global, and that calls
+// through the trait object load the method from the vtable and call it indirectly. Dropping the
+// `Box<dyn Shape>` runs the drop glue found in the vtable.
+//@ run-pass
+//@ compile-flags: -Copt-level=0
+
+#![crate_type = "bin"]
+
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static DROPS: AtomicU32 = AtomicU32::new(0);
+
+trait Shape {
+    fn area(&self) -> u32;
+}
+
+struct Square(u32);
+
+impl Shape for Square {
+    fn area(&self) -> u32 {
+        self.0 * self.0
+    }
+}
+
+struct Rect(u32, u32);
+
+impl Shape for Rect {
+    fn area(&self) -> u32 {
+        self.0 * self.1
+    }
+}
+
+impl Drop for Rect {
+    fn drop(&mut self) {
+        DROPS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// CHECK-LABEL: func.func @as_shape
+// CHECK: llvm.mlir.addressof @vtable.
+#[no_mangle]
+#[inline(never)]
+fn as_shape(rect: Box<Rect>) -> Box<dyn Shape> {
+    rect
+}
+
+// CHECK-LABEL: func.func @call_area
+// CHECK: llvm.load
+// CHECK: llvm.call %
+#[no_mangle]
+#[inline(never)]
+fn call_area(shape: &dyn Shape) -> u32 {
+    shape.area()
+}
+
+fn main() {
+    assert_eq!(call_area(black_box(&Square(3))), 9);
+
+    let shape = as_shape(Box::new(Rect(2, 5)));
+    assert_eq!(call_area(black_box(&*shape)), 10);
+    drop(shape);
+    assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+}
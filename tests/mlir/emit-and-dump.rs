@@ -0,0 +1,50 @@
+// Checks that `--emit=mlir` writes the module as text and bytecode, and that `-Zmlir-dump-after`
+// dumps the requested stages of every codegen unit into the output directory.
+//@ run-pass
+//@ compile-flags: -Copt-level=0 -Zmlir-dump-after=rust,lower
+//@ ignore-cross-compile
+
+#![crate_type = "bin"]
+
+use std::fs;
+use std::path::Path;
+
+const BYTECODE_MAGIC: &[u8] = b"ML\xefR";
+
+// CHECK-LABEL: func.func @double
+// CHECK: arith.addi
+#[no_mangle]
+#[inline(never)]
+fn double(x: u32) -> u32 {
+    x.wrapping_add(x)
+}
+
+/// Checks the dumps after `stage` written while compiling this executable, `a`. The directory it is
+/// in also holds the `--emit=mlir` output of this test.
+fn check_dumps(dir: &Path, stage: &str, expected: &str) {
+    let suffix = format!(".{stage}.mlir");
+    let mut dumps = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        if !name.starts_with("a.") || !name.ends_with(&suffix) {
+            continue;
+        }
+        dumps += 1;
+        assert!(fs::read_to_string(&path).unwrap().contains(expected), "{name}");
+        assert!(fs::read(path.with_extension("mlirbc")).unwrap().starts_with(BYTECODE_MAGIC));
+    }
+    assert!(dumps > 0, "no dumps after `{stage}`");
+}
+
+fn main() {
+    assert_eq!(double(std::hint::black_box(21)), 42);
+
+    let exe = std::env::current_exe().unwrap();
+    let dir = exe.parent().unwrap();
+    check_dumps(dir, "rust", "rust.fn");
+    check_dumps(dir, "lower", "func.func");
+
+    let emitted = dir.join("emit-and-dump.mlirbc");
+    assert!(fs::read(&emitted).unwrap().starts_with(BYTECODE_MAGIC));
+}
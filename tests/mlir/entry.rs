@@ -0,0 +1,11 @@
+// Checks that executables get a C `main` that calls the `start` lang item with the Rust `main`.
+//@ compile-flags: -Copt-level=0 -Ccodegen-units=1
+//@ only-x86_64
+
+#![crate_type = "bin"]
+
+// CHECK-LABEL: func.func @main(%{{.*}}: i32, %{{.*}}: !llvm.ptr) -> i32
+// CHECK: arith.extsi %{{.*}} : i32 to i64
+// CHECK: call @{{.*}}lang_start{{.*}}(%{{.*}}, %{{.*}}, %{{.*}}, %{{.*}}) : (!llvm.ptr, i64, !llvm.ptr, i8) -> i64
+// CHECK: arith.trunci %{{.*}} : i64 to i32
+fn main() {}
@@ -0,0 +1,42 @@
+// Checks that codegen attributes end up on the `func.func`, and that `#[track_caller]` functions
+// receive the caller location as an extra argument.
+//@ compile-flags: -Copt-level=0
+//@ only-x86_64
+
+#![crate_type = "lib"]
+
+use std::panic::Location;
+
+// CHECK-LABEL: func.func @cold_fn
+// CHECK-SAME: passthrough = [{{.*}}"cold"
+#[no_mangle]
+#[cold]
+pub fn cold_fn() {}
+
+// CHECK-LABEL: func.func @never_inlined
+// CHECK-SAME: passthrough = [{{.*}}"noinline"
+#[no_mangle]
+#[inline(never)]
+pub fn never_inlined() {}
+
+// CHECK-LABEL: func.func @with_avx2
+// CHECK-SAME: ["target-features", "{{.*}}+avx2{{.*}}"]
+#[no_mangle]
+#[target_feature(enable = "avx2")]
+pub unsafe fn with_avx2() {}
+
+// CHECK-LABEL: func.func @tracked
+// CHECK-SAME: !llvm.ptr
+#[no_mangle]
+#[track_caller]
+pub fn tracked() -> &'static Location<'static> {
+    Location::caller()
+}
+
+// CHECK-LABEL: func.func @untracked
+// CHECK: llvm.mlir.addressof
+// CHECK: call @tracked
+#[no_mangle]
+pub fn untracked() -> u32 {
+    tracked().line()
+}
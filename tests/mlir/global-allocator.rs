@@ -0,0 +1,34 @@
+// Checks that allocations go through a `#[global_allocator]`, which defines `__rust_alloc` and
+// friends itself, so that the allocator shim only forwards the alloc error handler.
+//@ run-pass
+//@ compile-flags: -Copt-level=0
+
+#![crate_type = "bin"]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let boxed = Box::new([7u8; 64]);
+    assert_eq!(boxed.iter().map(|&b| b as u32).sum::<u32>(), 448);
+    assert!(ALLOCATIONS.load(Ordering::Relaxed) > before);
+}
@@ -0,0 +1,95 @@
+// Checks that intrinsics lower to `math`, `arith`, `vector` and `llvm` dialect ops. The MIR
+// inliner, which runs from `-Copt-level=1`, inlines the wrappers around `copy_nonoverlapping`,
+// `write_bytes` and `black_box`.
+//@ compile-flags: -Copt-level=1
+
+#![crate_type = "lib"]
+#![feature(core_intrinsics, repr_simd)]
+#![allow(internal_features)]
+
+use std::intrinsics::simd::simd_add;
+use std::intrinsics::{
+    atomic_xadd_seqcst, copy_nonoverlapping, ctlz, ctpop, fmaf64, rotate_left, saturating_add,
+    sqrtf32, write_bytes,
+};
+
+#[repr(simd)]
+#[derive(Copy, Clone)]
+pub struct I32x4([i32; 4]);
+
+// CHECK-LABEL: func.func @sqrt
+// CHECK: math.sqrt
+#[no_mangle]
+pub fn sqrt(x: f32) -> f32 {
+    unsafe { sqrtf32(x) }
+}
+
+// CHECK-LABEL: func.func @fma
+// CHECK: math.fma
+#[no_mangle]
+pub fn fma(a: f64, b: f64, c: f64) -> f64 {
+    unsafe { fmaf64(a, b, c) }
+}
+
+// CHECK-LABEL: func.func @count_ones
+// CHECK: math.ctpop
+#[no_mangle]
+pub fn count_ones(x: u64) -> u32 {
+    ctpop(x)
+}
+
+// CHECK-LABEL: func.func @leading_zeros
+// CHECK: math.ctlz
+#[no_mangle]
+pub fn leading_zeros(x: u16) -> u32 {
+    ctlz(x)
+}
+
+// CHECK-LABEL: func.func @rotl
+// CHECK: llvm.intr.fshl
+#[no_mangle]
+pub fn rotl(x: u32, n: u32) -> u32 {
+    rotate_left(x, n)
+}
+
+// CHECK-LABEL: func.func @saturate
+// CHECK: llvm.intr.uadd.sat
+#[no_mangle]
+pub fn saturate(a: u8, b: u8) -> u8 {
+    saturating_add(a, b)
+}
+
+// CHECK-LABEL: func.func @copy
+// CHECK: llvm.intr.memcpy
+#[no_mangle]
+pub unsafe fn copy(src: *const u32, dst: *mut u32, count: usize) {
+    copy_nonoverlapping(src, dst, count)
+}
+
+// CHECK-LABEL: func.func @fill
+// CHECK: llvm.intr.memset
+#[no_mangle]
+pub unsafe fn fill(dst: *mut u32, count: usize) {
+    write_bytes(dst, 0xab, count)
+}
+
+// CHECK-LABEL: func.func @fetch_add
+// CHECK: llvm.atomicrmw add {{.*}} seq_cst
+#[no_mangle]
+pub unsafe fn fetch_add(dst: *mut u32) -> u32 {
+    atomic_xadd_seqcst(dst, 1)
+}
+
+// CHECK-LABEL: func.func @opaque
+// CHECK: llvm.inline_asm has_side_effects
+#[no_mangle]
+pub fn opaque(x: u32) -> u32 {
+    std::hint::black_box(x)
+}
+
+// CHECK-LABEL: func.func @add_lanes
+// CHECK: arith.addi {{.*}} : vector<4xi32>
+#[no_mangle]
+pub fn add_lanes(a: I32x4, b: I32x4) -> I32x4 {
+    unsafe { simd_add(a, b) }
+}
@@ -0,0 +1,72 @@
+// Checks the lowering of niche-encoded enum discriminants and of projections to `dyn` fields,
+// whose offset depends on the alignment in the vtable.
+//@ run-pass
+//@ compile-flags: -Copt-level=0
+
+#![crate_type = "bin"]
+
+use std::fmt::Debug;
+use std::hint::black_box;
+
+// The niche is the null pointer, so the tag is compared as an integer.
+// CHECK-LABEL: func.func @is_none
+// CHECK: llvm.ptrtoint
+// CHECK: arith.cmpi eq
+#[no_mangle]
+#[inline(never)]
+fn is_none(x: Option<&u32>) -> bool {
+    matches!(x, None)
+}
+
+enum Niches {
+    Untagged(bool),
+    A,
+    B,
+    C,
+}
+
+// The variants other than `Untagged` are the values 2 to 4 of the `bool`.
+// CHECK-LABEL: func.func @variant
+// CHECK: arith.cmpi ule
+// CHECK: arith.select
+#[no_mangle]
+#[inline(never)]
+fn variant(x: Niches) -> u32 {
+    match x {
+        Niches::Untagged(b) => b as u32 * 10,
+        Niches::A => 1,
+        Niches::B => 2,
+        Niches::C => 3,
+    }
+}
+
+struct Wrapper<T: ?Sized> {
+    tag: u8,
+    value: T,
+}
+
+// The offset of `value` is 1 rounded up to the alignment of the erased type.
+// CHECK-LABEL: func.func @value_addr
+// CHECK: llvm.load
+// CHECK: llvm.getelementptr
+#[no_mangle]
+#[inline(never)]
+fn value_addr(w: &Wrapper<dyn Debug>) -> usize {
+    &w.value as *const dyn Debug as *const u8 as usize
+}
+
+fn main() {
+    let x = 5;
+    assert!(is_none(black_box(None)));
+    assert!(!is_none(black_box(Some(&x))));
+
+    assert_eq!(variant(black_box(Niches::Untagged(true))), 10);
+    assert_eq!(variant(black_box(Niches::A)), 1);
+    assert_eq!(variant(black_box(Niches::C)), 3);
+
+    let w = Wrapper { tag: 1, value: 7u64 };
+    assert_eq!(value_addr(black_box(&w)), &w.value as *const u64 as usize);
+    let w = Wrapper { tag: 2, value: 7u8 };
+    assert_eq!(value_addr(black_box(&w)), &w.value as *const u8 as usize);
+    assert_eq!(w.tag, 2);
+}
@@ -0,0 +1,16 @@
+// Checks that functions get the linkage chosen by the partitioner.
+//@ compile-flags: -Copt-level=0 -Ccodegen-units=1
+
+#![crate_type = "lib"]
+
+// CHECK-DAG: func.func @exported(){{.*}}llvm.linkage = #llvm.linkage<external>
+#[no_mangle]
+pub fn exported() -> u32 {
+    helper()
+}
+
+// CHECK-DAG: func.func @{{.*}}helper{{.*}}llvm.linkage = #llvm.linkage<internal>
+#[inline(never)]
+fn helper() -> u32 {
+    2
+}
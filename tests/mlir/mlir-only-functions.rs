@@ -0,0 +1,29 @@
+// Checks that only the functions selected with `-Zmlir-only-functions` are placed into the MLIR
+// codegen unit, and that the ones that are copied into their users stay with them.
+//@ compile-flags: -Copt-level=0 -Zmlir-only-functions=kernels::saxpy,kernels::scale
+
+#![crate_type = "lib"]
+
+// CHECK-NOT: func.func @caller
+// CHECK-NOT: scale
+// CHECK-LABEL: func.func @saxpy
+// CHECK-NOT: func.func @caller
+// CHECK-NOT: scale
+
+pub mod kernels {
+    #[no_mangle]
+    pub fn saxpy(a: f32, x: f32, y: f32) -> f32 {
+        a * x + y
+    }
+
+    // Inlined into `caller`, so it is compiled by LLVM.
+    #[inline(always)]
+    pub fn scale(a: f32, x: f32) -> f32 {
+        a * x
+    }
+}
+
+#[no_mangle]
+pub fn caller(a: f32, x: f32) -> f32 {
+    kernels::saxpy(a, x, kernels::scale(a, x))
+}
@@ -0,0 +1,15 @@
+// Checks that the `-Zmlir-passes` pipeline runs on every function before it is lowered to the
+// LLVM dialect: `cse` leaves only one of the two identical `arith.xori` ops.
+//@ compile-flags: -Copt-level=0 -Zmlir-passes=mem2reg,cse
+//@ mlir-check-stage: mlir-passes
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: func.func @same_xor
+// CHECK: arith.xori
+// CHECK-NOT: arith.xori
+// CHECK: arith.ori
+#[no_mangle]
+pub fn same_xor(a: u32, b: u32) -> u32 {
+    (a ^ b) | (a ^ b)
+}
@@ -0,0 +1,21 @@
+// Checks that `&mut` and `&` arguments the ABI marks `noalias` keep that in the `func.func`.
+//@ compile-flags: -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: func.func @scale
+// CHECK-SAME: llvm.noalias
+#[no_mangle]
+pub fn scale(x: &mut [f32], factor: f32) {
+    for v in x {
+        *v *= factor;
+    }
+}
+
+// CHECK-LABEL: func.func @sum
+// CHECK-SAME: llvm.noalias
+// CHECK-SAME: llvm.readonly
+#[no_mangle]
+pub fn sum(x: &[u32]) -> u32 {
+    x.iter().copied().fold(0, u32::wrapping_add)
+}
@@ -0,0 +1,93 @@
+// Checks the structured loops `-Zmlir-raise-loops` raises loops to: `scf.while` for loops that
+// don't count, `scf.for` for counting loops, and `affine.for` for counting loops whose bounds are
+// affine symbols and whose counter fits into a signed `index`.
+//@ run-pass
+//@ compile-flags: -Copt-level=2 -Zmlir-raise-loops
+//@ mlir-check-stage: raise-loops
+
+#![crate_type = "bin"]
+
+use std::hint::black_box;
+
+// CHECK-LABEL: func.func @bit_length
+// CHECK: scf.while
+// CHECK-NOT: scf.for
+// CHECK-NOT: affine.for
+#[no_mangle]
+#[inline(never)]
+fn bit_length(mut x: u32) -> u32 {
+    let mut n = 0;
+    while x != 0 {
+        x >>= 1;
+        n += 1;
+    }
+    n
+}
+
+// CHECK-LABEL: func.func @sum_i32
+// CHECK: affine.for
+// CHECK-NOT: scf.while
+#[no_mangle]
+#[inline(never)]
+fn sum_i32(n: i32) -> i32 {
+    let mut sum = 0i32;
+    for i in 0..n {
+        sum = sum.wrapping_add(i);
+    }
+    sum
+}
+
+// Unsigned counters narrower than `index` are zero-extended to it.
+// CHECK-LABEL: func.func @sum_u32
+// CHECK: arith.index_castui
+// CHECK: affine.for
+#[no_mangle]
+#[inline(never)]
+fn sum_u32(n: u32) -> u32 {
+    let mut sum = 0u32;
+    for i in 0..n {
+        sum = sum.wrapping_add(i);
+    }
+    sum
+}
+
+// A `usize` counter can't be an `index`, so the loop stays an `scf.for`.
+// CHECK-LABEL: func.func @sum_usize
+// CHECK: scf.for unsigned
+// CHECK-NOT: affine.for
+#[no_mangle]
+#[inline(never)]
+fn sum_usize(n: usize) -> usize {
+    let mut sum = 0usize;
+    for i in 0..n {
+        sum = sum.wrapping_add(i);
+    }
+    sum
+}
+
+// The bound of the inner loop is the counter of the outer one, which isn't an affine symbol of
+// the function, so the inner loop stays an `scf.for`.
+// CHECK-LABEL: func.func @triangle
+// CHECK: scf.for
+// CHECK-NOT: affine.for
+#[no_mangle]
+#[inline(never)]
+fn triangle(n: i32) -> i32 {
+    let mut count = 0i32;
+    for i in 0..n {
+        for _ in 0..i {
+            count = count.wrapping_add(1);
+        }
+    }
+    count
+}
+
+fn main() {
+    assert_eq!(bit_length(black_box(0)), 0);
+    assert_eq!(bit_length(black_box(5)), 3);
+    assert_eq!(sum_i32(black_box(5)), 10);
+    assert_eq!(sum_i32(black_box(-3)), 0);
+    assert_eq!(sum_u32(black_box(5)), 10);
+    assert_eq!(sum_usize(black_box(5)), 10);
+    assert_eq!(triangle(black_box(4)), 6);
+}
@@ -0,0 +1,15 @@
+// Checks that the index of the loop initializing an array by repetition is accessed with the
+// alignment of its stack slot.
+//@ compile-flags: -Copt-level=0
+//@ only-x86_64
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: func.func @fill
+// CHECK: llvm.store {{.*}}alignment = 8 : i64{{.*}} : i64, !llvm.ptr
+// CHECK: llvm.load {{.*}}alignment = 8 : i64{{.*}} : !llvm.ptr -> i64
+// CHECK: llvm.store {{.*}}alignment = 8 : i64{{.*}} : i64, !llvm.ptr
+#[no_mangle]
+pub fn fill(x: u32) -> [u32; 8] {
+    [x; 8]
+}
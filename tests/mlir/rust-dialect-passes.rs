@@ -0,0 +1,42 @@
+// Checks that a `-Zmlir-passes` pipeline, which also runs on the `rust` dialect before it is
+// lowered, keeps the lowered functions working when it erases or merges blocks.
+//@ run-pass
+//@ compile-flags: -Copt-level=0 -Zmlir-passes=canonicalize,cse
+
+#![crate_type = "bin"]
+
+use std::hint::black_box;
+
+// CHECK-LABEL: func.func @classify
+// CHECK: cf.switch
+#[no_mangle]
+#[inline(never)]
+fn classify(x: u8) -> u32 {
+    match x {
+        0 => 10,
+        1 | 2 => 20,
+        3 => 20,
+        _ => 0,
+    }
+}
+
+// CHECK-LABEL: func.func @sum_to
+#[no_mangle]
+#[inline(never)]
+fn sum_to(n: u32) -> u32 {
+    let mut sum = 0;
+    let mut i = 0;
+    while i < n {
+        sum += i;
+        i += 1;
+    }
+    sum
+}
+
+fn main() {
+    assert_eq!(classify(black_box(0)), 10);
+    assert_eq!(classify(black_box(2)), 20);
+    assert_eq!(classify(black_box(3)), 20);
+    assert_eq!(classify(black_box(7)), 0);
+    assert_eq!(sum_to(black_box(5)), 10);
+}
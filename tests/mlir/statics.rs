@@ -0,0 +1,15 @@
+// Checks that statics lower to LLVM dialect globals.
+//@ compile-flags: -Copt-level=0
+
+#![crate_type = "lib"]
+
+// CHECK: llvm.mlir.global {{.*}}@COUNTER
+#[no_mangle]
+pub static mut COUNTER: u32 = 0;
+
+// CHECK-LABEL: func.func @bump
+// CHECK: llvm.mlir.addressof @COUNTER
+#[no_mangle]
+pub fn bump() {
+    unsafe { COUNTER += 1 };
+}
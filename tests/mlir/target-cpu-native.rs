@@ -0,0 +1,11 @@
+// Checks that `-Ctarget-cpu=native` is resolved to the host CPU, and that the features enabled for
+// it are visible to `cfg(target_feature)` like with the LLVM backend.
+//@ compile-flags: -Copt-level=0 -Ctarget-cpu=native
+//@ only-x86_64
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: func.func @sse2_enabled
+#[cfg(target_feature = "sse2")]
+#[no_mangle]
+pub fn sse2_enabled() {}
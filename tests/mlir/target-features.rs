@@ -0,0 +1,13 @@
+// Checks that `-Ctarget-feature` is mapped to LLVM features, and that the features only rustc
+// knows about are not passed to LLVM.
+//@ compile-flags: -Copt-level=0 -Ctarget-feature=+crt-static,+bmi1
+//@ only-x86_64
+//@ only-linux
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: func.func @with_avx2
+// CHECK-SAME: ["target-features", "+bmi,+avx2"]
+#[no_mangle]
+#[target_feature(enable = "avx2")]
+pub unsafe fn with_avx2() {}
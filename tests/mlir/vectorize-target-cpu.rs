@@ -0,0 +1,16 @@
+// Checks that the width of the vectors `-Zmlir-vectorize` uses follows the features enabled by
+// `-Ctarget-cpu`, here the 256-bit vectors of AVX2.
+//@ compile-flags: -Copt-level=3 -Zmlir-vectorize -Ctarget-cpu=haswell
+//@ mlir-check-stage: vectorize
+//@ only-x86_64
+
+#![crate_type = "lib"]
+
+// CHECK-LABEL: func.func @scale
+// CHECK: arith.mulf {{.*}} : vector<8xf32>
+#[no_mangle]
+pub fn scale(x: &mut [f32], factor: f32) {
+    for v in x {
+        *v *= factor;
+    }
+}
@@ -0,0 +1,59 @@
+// Checks that `-Zmlir-vectorize` vectorizes element-wise loops over slices, including the loops
+// over slice iterators, with the 128-bit vectors of the baseline x86_64 target.
+//@ run-pass
+//@ compile-flags: -Copt-level=3 -Zmlir-vectorize
+//@ mlir-check-stage: vectorize
+//@ only-x86_64
+
+#![crate_type = "bin"]
+
+use std::hint::black_box;
+
+// CHECK-LABEL: func.func @scale
+// CHECK: vector.broadcast
+// CHECK: arith.mulf {{.*}} : vector<4xf32>
+#[no_mangle]
+#[inline(never)]
+fn scale(x: &mut [f32], factor: f32) {
+    for v in x {
+        *v *= factor;
+    }
+}
+
+// CHECK-LABEL: func.func @add_assign
+// CHECK: arith.addf {{.*}} : vector<4xf32>
+#[no_mangle]
+#[inline(never)]
+fn add_assign(x: &mut [f32], y: &[f32]) {
+    for (a, b) in x.iter_mut().zip(y) {
+        *a += b;
+    }
+}
+
+// CHECK-LABEL: func.func @double_unchecked
+// CHECK: arith.addi {{.*}} : vector<4xi32>
+#[no_mangle]
+#[inline(never)]
+fn double_unchecked(x: &mut [u32]) {
+    for i in 0..x.len() {
+        unsafe {
+            let v = x.get_unchecked_mut(i);
+            *v = v.wrapping_add(*v);
+        }
+    }
+}
+
+fn main() {
+    let mut x: Vec<f32> = (0..11).map(|i| i as f32).collect();
+    scale(black_box(&mut x), black_box(2.0));
+    assert_eq!(x, (0..11).map(|i| (2 * i) as f32).collect::<Vec<_>>());
+
+    let y = vec![1.0; 9];
+    add_assign(black_box(&mut x), black_box(&y));
+    assert_eq!(x[..9], (0..9).map(|i| (2 * i + 1) as f32).collect::<Vec<_>>()[..]);
+    assert_eq!(x[9..], [18.0, 20.0]);
+
+    let mut z: Vec<u32> = (0..7).collect();
+    double_unchecked(black_box(&mut z));
+    assert_eq!(z, [0, 2, 4, 6, 8, 10, 12]);
+}
@@ -0,0 +1,157 @@
+//@ run-pass
+//! Test that a code generation backend written against the stable MIR can be driven by the
+//! compiler.
+
+//@ ignore-stage1
+//@ ignore-cross-compile
+//@ ignore-remote
+//@ only-x86_64
+//@ only-linux
+//@ edition: 2021
+
+#![feature(rustc_private)]
+
+extern crate rustc_driver;
+extern crate rustc_interface;
+extern crate rustc_smir;
+extern crate stable_mir;
+
+use std::io::Write;
+use std::sync::Mutex;
+
+use rustc_driver::{Callbacks, RunCompiler};
+use rustc_interface::interface;
+use rustc_smir::rustc_internal;
+use stable_mir::Error;
+use stable_mir::codegen::{AllocatorShim, CodegenBackend, ObjectFile, ShimTy};
+use stable_mir::mir::mono::MonoItem;
+
+const CRATE_NAME: &str = "input";
+
+/// The functions the backend was asked to generate code for.
+static FUNCTIONS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The allocator shim the backend was asked to generate.
+static ALLOCATOR_SHIM: Mutex<Option<AllocatorShim>> = Mutex::new(None);
+
+struct TestBackend;
+
+impl CodegenBackend for TestBackend {
+    fn codegen_crate(
+        &self,
+        units: Vec<stable_mir::mir::mono::CodegenUnit>,
+    ) -> Result<Vec<ObjectFile>, Error> {
+        let mut functions = FUNCTIONS.lock().unwrap();
+        for unit in &units {
+            for (item, _) in &unit.items {
+                let MonoItem::Fn(instance) = item else { continue };
+                let body = instance.body().ok_or_else(|| Error::from("missing body"))?;
+                let fn_abi = instance.fn_abi()?;
+                assert_eq!(body.arg_locals().len(), fn_abi.args.len());
+                for local in body.locals() {
+                    let _shape = local.ty.layout()?.shape();
+                }
+                functions.push(instance.name());
+            }
+        }
+        // The object files are never linked, since only `--emit=obj` is requested.
+        Ok(units
+            .into_iter()
+            .map(|unit| ObjectFile { name: unit.name, bytes: empty_object() })
+            .collect())
+    }
+
+    fn codegen_allocator(&self, shim: AllocatorShim) -> Result<ObjectFile, Error> {
+        let name = shim.name.clone();
+        *ALLOCATOR_SHIM.lock().unwrap() = Some(shim);
+        Ok(ObjectFile { name, bytes: empty_object() })
+    }
+}
+
+/// An x86_64 ELF relocatable object file without any sections.
+fn empty_object() -> Vec<u8> {
+    let mut object = vec![];
+    // e_ident: magic, 64-bit, little-endian, version 1, System V ABI, padding
+    object.extend_from_slice(b"\x7fELF");
+    object.extend_from_slice(&[2, 1, 1, 0]);
+    object.extend_from_slice(&[0; 8]);
+    object.extend_from_slice(&1u16.to_le_bytes()); // e_type: ET_REL
+    object.extend_from_slice(&62u16.to_le_bytes()); // e_machine: EM_X86_64
+    object.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    object.extend_from_slice(&[0; 24]); // e_entry, e_phoff, e_shoff
+    object.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    object.extend_from_slice(&64u16.to_le_bytes()); // e_ehsize
+    object.extend_from_slice(&[0; 4]); // e_phentsize, e_phnum
+    object.extend_from_slice(&64u16.to_le_bytes()); // e_shentsize
+    object.extend_from_slice(&[0; 4]); // e_shnum, e_shstrndx
+    assert_eq!(object.len(), 64);
+    object
+}
+
+struct BackendCallbacks;
+
+impl Callbacks for BackendCallbacks {
+    fn config(&mut self, config: &mut interface::Config) {
+        config.make_codegen_backend =
+            Some(Box::new(|_| rustc_internal::codegen::backend(TestBackend)));
+    }
+}
+
+/// This test will generate a dummy crate and compile it with a stable MIR codegen backend.
+fn main() {
+    let path = "codegen_backend_input.rs";
+    generate_input(&path).unwrap();
+    let args = vec![
+        "rustc".to_string(),
+        "-Cpanic=abort".to_string(),
+        "--crate-type=bin".to_string(),
+        "--emit=obj".to_string(),
+        "-Ccodegen-units=1".to_string(),
+        "--crate-name".to_string(),
+        CRATE_NAME.to_string(),
+        path.to_string(),
+    ];
+    rustc_driver::catch_fatal_errors(|| RunCompiler::new(&args, &mut BackendCallbacks).run())
+        .unwrap()
+        .unwrap();
+
+    // The object file of the only codegen unit is the crate's `--emit=obj` output.
+    let object = std::fs::read(format!("{CRATE_NAME}.o")).expect("missing object file");
+    assert_eq!(object, empty_object());
+
+    let functions = FUNCTIONS.lock().unwrap();
+    assert!(functions.iter().any(|name| name == "input::add"), "{functions:?}");
+    assert!(functions.iter().any(|name| name == "input::id::<u32>"), "{functions:?}");
+    assert!(!functions.iter().any(|name| name == "input::id"), "{functions:?}");
+
+    // Executables need an allocator shim, which forwards to the default allocator here.
+    let shim = ALLOCATOR_SHIM.lock().unwrap().take().expect("missing allocator shim");
+    let alloc = shim.functions.iter().find(|function| function.name == "__rust_alloc").unwrap();
+    assert_eq!(alloc.callee, "__rdl_alloc");
+    assert_eq!(alloc.inputs, [ShimTy::Usize, ShimTy::Usize]);
+    assert_eq!(alloc.output, Some(ShimTy::Ptr));
+    let handler =
+        shim.functions.iter().find(|function| function.name == "__rust_alloc_error_handler");
+    assert!(handler.unwrap().no_return);
+}
+
+fn generate_input(path: &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    write!(
+        file,
+        r#"
+    fn id<T>(t: T) -> T {{
+        t
+    }}
+
+    pub fn add(a: u32, b: u32) -> u32 {{
+        id(a).wrapping_add(b)
+    }}
+
+    fn main() {{
+        std::process::exit(add(1, 2) as i32);
+    }}
+    "#
+    )?;
+    Ok(())
+}
@@ -0,0 +1,109 @@
+//@ run-pass
+//! Test that users are able to retrieve the mono items and codegen units of a crate.
+
+//@ ignore-stage1
+//@ ignore-cross-compile
+//@ ignore-remote
+//@ ignore-windows-gnu mingw has troubles with linking https://github.com/rust-lang/rust/pull/116837
+//@ edition: 2021
+
+#![feature(rustc_private)]
+#![feature(assert_matches)]
+
+#[macro_use]
+extern crate rustc_smir;
+extern crate rustc_driver;
+extern crate rustc_interface;
+extern crate stable_mir;
+
+use std::io::Write;
+use std::ops::ControlFlow;
+
+use stable_mir::mir::mono::{Linkage, MonoItem};
+use stable_mir::*;
+
+const CRATE_NAME: &str = "input";
+
+/// This function uses the Stable MIR APIs to get information about the test crate.
+fn test_stable_mir() -> ControlFlow<()> {
+    let items = stable_mir::all_mono_items();
+    let fn_names = items
+        .iter()
+        .filter_map(|item| match item {
+            MonoItem::Fn(instance) => Some(instance.name()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert!(fn_names.iter().any(|name| name == "input::entry"), "{fn_names:?}");
+    assert!(fn_names.iter().any(|name| name == "input::helper"), "{fn_names:?}");
+    // Generic functions only show up through their instantiations.
+    assert!(!fn_names.iter().any(|name| name == "input::generic"), "{fn_names:?}");
+    assert!(fn_names.iter().any(|name| name == "input::generic::<u8>"), "{fn_names:?}");
+    assert!(items.iter().any(|item| matches!(item, MonoItem::Static(_))));
+
+    let cgus = stable_mir::codegen_units();
+    assert!(!cgus.is_empty());
+    assert_eq!(cgus.iter().filter(|cgu| cgu.is_primary).count(), 1);
+
+    // Every mono item is emitted into at least one codegen unit.
+    for item in &items {
+        assert!(
+            cgus.iter().any(|cgu| cgu.items.iter().any(|(cgu_item, _)| cgu_item == item)),
+            "{item:?} is not in any codegen unit"
+        );
+    }
+
+    let (_, entry_data) = cgus
+        .iter()
+        .flat_map(|cgu| cgu.items.iter())
+        .find(
+            |(item, _)| matches!(item, MonoItem::Fn(instance) if instance.name() == "input::entry"),
+        )
+        .unwrap();
+    assert_eq!(entry_data.linkage, Linkage::External);
+    assert!(!entry_data.inlined);
+    ControlFlow::Continue(())
+}
+
+/// This test will generate and analyze a dummy crate using the stable mir.
+/// For that, it will first write the dummy crate into a file.
+/// Then it will create a `StableMir` using custom arguments and then
+/// it will run the compiler.
+fn main() {
+    let path = "mono_items_input.rs";
+    generate_input(&path).unwrap();
+    let args = vec![
+        "rustc".to_string(),
+        "-Cpanic=abort".to_string(),
+        "--crate-type=lib".to_string(),
+        "--crate-name".to_string(),
+        CRATE_NAME.to_string(),
+        path.to_string(),
+    ];
+    run!(args, test_stable_mir).unwrap();
+}
+
+fn generate_input(path: &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    write!(
+        file,
+        r#"
+    pub static COUNTER: u32 = 10;
+
+    pub fn generic<T: Copy>(t: T) -> T {{
+        t
+    }}
+
+    #[inline(never)]
+    fn helper() -> u32 {{
+        COUNTER + 1
+    }}
+
+    #[no_mangle]
+    pub fn entry() -> u8 {{
+        helper() as u8 + generic(1u8)
+    }}
+    "#
+    )?;
+    Ok(())
+}
@@ -0,0 +1,155 @@
+//@ run-pass
+//! Test that the optimized MIR can be transformed through the stable MIR, that the transformed
+//! body is the one used for code generation, and that the transformation is only applied in the
+//! compiler session that installs it.
+
+//@ ignore-stage1
+//@ ignore-cross-compile
+//@ ignore-remote
+//@ ignore-windows-gnu mingw has troubles with linking https://github.com/rust-lang/rust/pull/116837
+//@ edition: 2021
+
+#![feature(rustc_private)]
+#![feature(assert_matches)]
+
+extern crate rustc_driver;
+extern crate rustc_interface;
+extern crate rustc_smir;
+extern crate stable_mir;
+
+use std::assert_matches::assert_matches;
+use std::io::Write;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rustc_driver::{Callbacks, RunCompiler};
+use rustc_interface::interface;
+use rustc_smir::rustc_internal;
+use rustc_smir::rustc_internal::transform::MirTransform;
+use stable_mir::mir::visit::Location;
+use stable_mir::mir::{
+    BasicBlock, Body, BodyBuilder, MutMirVisitor, Mutability, Terminator, TerminatorKind,
+};
+use stable_mir::ty::{MirConst, Ty, UintTy};
+use stable_mir::{CrateDef, CrateItem};
+
+const CRATE_NAME: &str = "input";
+
+static TRANSFORMED: AtomicBool = AtomicBool::new(false);
+
+/// Replaces every `u32` constant by `43`.
+struct ReplaceConst;
+
+impl MutMirVisitor for ReplaceConst {
+    fn visit_mir_const(&mut self, constant: &mut MirConst, _location: Location) {
+        if constant.ty() == Ty::unsigned_ty(UintTy::U32) {
+            *constant = MirConst::try_from_uint(43, UintTy::U32).unwrap();
+        }
+    }
+}
+
+struct ReplaceFortyTwo;
+
+impl MirTransform for ReplaceFortyTwo {
+    fn transform(item: CrateItem, body: &Body) -> Option<Body> {
+        transform(item, body)
+    }
+}
+
+fn transform(item: CrateItem, body: &Body) -> Option<Body> {
+    // Round-trip `main` unchanged, which must keep the caller location of its calls.
+    if item.trimmed_name() == "main" {
+        return Some(body.clone());
+    }
+    if item.trimmed_name() != "forty_two" {
+        return None;
+    }
+    let mut body = body.clone();
+    ReplaceConst.visit_body(&mut body);
+
+    let mut builder = BodyBuilder::new(body);
+    let entry = Terminator { kind: TerminatorKind::Goto { target: 0 }, span: item.span() };
+    builder.insert_block(0, BasicBlock { statements: vec![], terminator: entry });
+    let exit = builder.split_block(1, 1);
+    let unused = builder.new_local(Ty::bool_ty(), item.span(), Mutability::Not);
+    builder.remove_local(unused);
+    let body = builder.build();
+
+    assert_eq!(exit, 2);
+    assert_eq!(body.blocks.len(), 3);
+    assert_matches!(body.blocks[0].terminator.kind, TerminatorKind::Goto { target: 1 });
+    assert_matches!(body.blocks[1].terminator.kind, TerminatorKind::Goto { target: 2 });
+    assert_matches!(body.blocks[2].terminator.kind, TerminatorKind::Return);
+    TRANSFORMED.store(true, Ordering::Relaxed);
+    Some(body)
+}
+
+struct TransformCallbacks {
+    transform: bool,
+}
+
+impl Callbacks for TransformCallbacks {
+    fn config(&mut self, config: &mut interface::Config) {
+        if self.transform {
+            config.override_queries =
+                Some(rustc_internal::transform::transform_optimized_mir::<ReplaceFortyTwo>());
+        }
+    }
+}
+
+/// Compile `path` into `output`, transforming its MIR if `transform` is set, and return the exit
+/// code of the resulting binary.
+fn compile_and_run(path: &str, output: &str, transform: bool) -> Option<i32> {
+    let args = vec![
+        "rustc".to_string(),
+        "--crate-type=bin".to_string(),
+        "--crate-name".to_string(),
+        CRATE_NAME.to_string(),
+        "-o".to_string(),
+        output.to_string(),
+        path.to_string(),
+    ];
+    let mut callbacks = TransformCallbacks { transform };
+    rustc_driver::catch_fatal_errors(|| RunCompiler::new(&args, &mut callbacks).run())
+        .unwrap()
+        .unwrap();
+    Command::new(format!("./{output}")).status().unwrap().code()
+}
+
+/// This test will generate a dummy binary, compile it while transforming its MIR, and run it. It
+/// then compiles it again in the same process without the transformation.
+fn main() {
+    let path = "optimized_mir_transform_input.rs";
+    generate_input(&path).unwrap();
+
+    assert_eq!(compile_and_run(path, "optimized_mir_transform_output", true), Some(43));
+    assert!(TRANSFORMED.swap(false, Ordering::Relaxed));
+
+    assert_eq!(compile_and_run(path, "optimized_mir_transform_untouched", false), Some(42));
+    assert!(!TRANSFORMED.load(Ordering::Relaxed));
+}
+
+fn generate_input(path: &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    write!(
+        file,
+        r#"
+    #[inline(never)]
+    fn forty_two() -> u32 {{
+        42
+    }}
+
+    #[track_caller]
+    fn caller_line() -> u32 {{
+        std::panic::Location::caller().line()
+    }}
+
+    fn main() {{
+        let line = caller_line();
+        assert_eq!(line, line!() - 1);
+        std::process::exit(forty_two() as i32);
+    }}
+    "#
+    )?;
+    Ok(())
+}